@@ -0,0 +1,17 @@
+use std::env;
+
+fn main() {
+    // Plugins resolve `roc_alloc` and the other host functions against the executable that loads
+    // them, but Linux executables only export their symbols when linked with `-rdynamic`. This
+    // covers the CLI and the integration tests; hosts built on the library need the same link
+    // argument.
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux") {
+        println!("cargo:rustc-link-arg-bins=-rdynamic");
+        println!("cargo:rustc-link-arg-tests=-rdynamic");
+    }
+
+    // The gRPC service is generated from its definition, which requires `protoc`.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/roc_plugin.proto")
+        .unwrap_or_else(|error| panic!("failed to compile proto/roc_plugin.proto: {error}"));
+}
@@ -0,0 +1,73 @@
+/*
+ * The C API of roc-plugin, for hosting Roc plugins in C and C++ applications.
+ *
+ * Build the library with `cargo rustc --lib --release --features capi --crate-type cdylib`,
+ * or `--crate-type staticlib`. Functions that fail return NULL or -1, and leave a message that
+ * rocplug_last_error returns. Values are passed as JSON.
+ */
+
+#ifndef ROC_PLUGIN_H
+#define ROC_PLUGIN_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+typedef struct RocplugManager RocplugManager;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a manager without plugins, which must be freed with [`rocplug_manager_free`].
+RocplugManager *rocplug_manager_new(void);
+
+// Frees a manager created by [`rocplug_manager_new`], unloading its plugins.
+//
+// # Safety
+//
+// `manager` must be `NULL` or a manager created by [`rocplug_manager_new`] that wasn't freed
+// before, and which isn't used by other threads.
+void rocplug_manager_free(RocplugManager *manager);
+
+// Loads the plugins in the directory at `dir`, with the default options, see
+// [`PluginManager::scan`].
+//
+// Returns the number of plugins loaded, or `-1` if any failed to load, in which case the
+// others are loaded regardless and the last error lists the failures.
+//
+// # Safety
+//
+// `manager` must be a manager created by [`rocplug_manager_new`] which isn't used by other
+// threads, and `dir` a NUL-terminated string.
+int rocplug_load_dir(RocplugManager *manager, const char *dir);
+
+// Invokes the first function of the plugin named `plugin`, with the arguments in
+// `args_json`, which is a JSON array, or a JSON object for functions taking a single record.
+//
+// Returns the result as JSON, which must be freed with [`rocplug_free`], or `NULL` if the
+// invocation failed.
+//
+// # Safety
+//
+// `manager` must be a manager created by [`rocplug_manager_new`], and `plugin` and
+// `args_json` NUL-terminated strings. Managers may be used by several threads at once, as long
+// as none of them loads plugins or frees it meanwhile.
+char *rocplug_invoke_json(const RocplugManager *manager, const char *plugin, const char *args_json);
+
+// Frees a string returned by [`rocplug_invoke_json`].
+//
+// # Safety
+//
+// `s` must be `NULL` or a string returned by [`rocplug_invoke_json`] that wasn't freed before.
+void rocplug_free(char *s);
+
+// Returns the message of the last error on the calling thread, or `NULL` if there was none.
+//
+// The message stays valid until the next call that fails on the same thread, and must not be
+// freed.
+const char *rocplug_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif // __cplusplus
+
+#endif  /* ROC_PLUGIN_H */
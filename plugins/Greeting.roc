@@ -0,0 +1,4 @@
+module [formal]
+
+formal : Str -> Str
+formal = \name -> "Good day, $(name)."
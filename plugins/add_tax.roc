@@ -0,0 +1,4 @@
+#[plugin] addTax : Dec, Dec -> Dec
+
+addTax : Dec, Dec -> Dec
+addTax = \price, rate -> price + price * rate
@@ -0,0 +1,6 @@
+#[plugin] checkedDiv : I64, I64 -> Result I64 Str
+
+checkedDiv : I64, I64 -> Result I64 Str
+checkedDiv = \x, y ->
+    Num.divTruncChecked x y
+    |> Result.mapErr \DivByZero -> "division by zero"
@@ -0,0 +1,4 @@
+#[plugin] concat3 : Str, Str, Str -> Str
+
+concat3 : Str, Str, Str -> Str
+concat3 = \a, b, c -> Str.joinWith [a, b, c] "-"
@@ -0,0 +1,9 @@
+#[config] { greeting : Str, excited : Bool }
+#[plugin] configured_greeting : Str -> Str
+
+import pf.Config
+
+configured_greeting : Str -> Str
+configured_greeting = \name ->
+    punctuation = if Config.config.excited then "!" else "."
+    "$(Config.config.greeting), $(name)$(punctuation)"
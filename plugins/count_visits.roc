@@ -0,0 +1,12 @@
+#[plugin] count_visits : Str -> Task Str Str
+
+import pf.Host
+
+count_visits : Str -> Task Str Str
+count_visits = \name ->
+    previous = Host.kvGet name |> Task.onErr! \NotFound -> Task.ok "0"
+    count = Str.toU64 previous |> Result.withDefault 0 |> Num.add 1
+
+    Host.kvSet name (Num.toStr count)
+    |> Task.map \{} -> "$(name) visited $(Num.toStr count) times"
+    |> Task.mapErr \KvErr msg -> msg
@@ -0,0 +1,4 @@
+#[plugin] describe5 : Str, U64, Str, U64, Str -> Str
+
+describe5 : Str, U64, Str, U64, Str -> Str
+describe5 = \a, b, c, d, e -> "$(a) $(Num.toStr b) $(c) $(Num.toStr d) $(e)"
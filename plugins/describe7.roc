@@ -0,0 +1,4 @@
+#[plugin] describe7 : Str, Str, Str, U64, Str, Str, Str -> Str
+
+describe7 : Str, Str, Str, U64, Str, Str, Str -> Str
+describe7 = \a, b, c, d, e, f, g -> Str.joinWith [a, b, c, Num.toStr d, e, f, g] " "
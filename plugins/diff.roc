@@ -0,0 +1,4 @@
+#[plugin] diff : I64, I64 -> I64
+
+diff : I64, I64 -> I64
+diff = \x, y -> x - y
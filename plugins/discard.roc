@@ -0,0 +1,4 @@
+#[plugin] discard : Str -> {}
+
+discard : Str -> {}
+discard = \_ -> {}
@@ -0,0 +1,7 @@
+#[plugin] double : I64 -> I64
+
+double : I64 -> I64
+double = \n ->
+    dbg n
+
+    n * 2
@@ -0,0 +1,4 @@
+#[plugin] exclaimAll : List Str -> List Str
+
+exclaimAll : List Str -> List Str
+exclaimAll = \strs -> List.map strs \s -> Str.concat s "!"
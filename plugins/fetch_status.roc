@@ -0,0 +1,8 @@
+#[plugin] fetch_status : Str -> Task Str Str
+
+import pf.Host
+
+fetch_status : Str -> Task Str Str
+fetch_status = \url ->
+    Host.httpGet url
+    |> Task.mapErr \HttpErr msg -> msg
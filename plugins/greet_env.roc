@@ -0,0 +1,8 @@
+#[plugin] greet_env : Str -> Task Str {}
+
+import pf.Host
+
+greet_env : Str -> Task Str {}
+greet_env = \fallback ->
+    name = Host.envVar "USER" |> Task.onErr! \NotFound -> Task.ok fallback
+    Task.ok "Hello, $(name)!"
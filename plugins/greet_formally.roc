@@ -0,0 +1,6 @@
+#[plugin] greetFormally : Str -> Str
+
+import Greeting
+
+greetFormally : Str -> Str
+greetFormally = \name -> Greeting.formal name
@@ -0,0 +1,16 @@
+#[plugin] greet_lifecycle : Str -> Str
+
+import pf.Host
+
+onLoad : Task {} Str
+onLoad =
+    Host.log! "loaded"
+    Task.ok {}
+
+onUnload : Task {} Str
+onUnload =
+    Host.log! "unloading"
+    Task.ok {}
+
+greet_lifecycle : Str -> Str
+greet_lifecycle = \name -> "Hello, $(name)!"
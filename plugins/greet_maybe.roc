@@ -0,0 +1,7 @@
+#[plugin] greetMaybe : [Some Str, None] -> Str
+
+greetMaybe : [Some Str, None] -> Str
+greetMaybe = \name ->
+    when name is
+        Some n -> "Hello, $(n)!"
+        None -> "Hello, stranger!"
@@ -0,0 +1,4 @@
+#[plugin(version = "1.0", description = "greets a user", tags = "text, example")] greetUser : { name : Str, count : U64 } -> Str
+
+greetUser : { name : Str, count : U64 } -> Str
+greetUser = \{ name, count } -> "Hello $(name), you have $(Num.toStr count) new messages"
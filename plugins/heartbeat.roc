@@ -0,0 +1,8 @@
+#[plugin(schedule = "*/5 * * * *")] heartbeat : Task {} {}
+
+import pf.Host
+
+heartbeat : Task {} {}
+heartbeat =
+    now = Host.now!
+    Host.log "alive at $(Num.toStr now)"
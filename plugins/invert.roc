@@ -0,0 +1,4 @@
+#[plugin] invert : List U8 -> List U8
+
+invert : List U8 -> List U8
+invert = \bytes -> List.map bytes \b -> 255 - b
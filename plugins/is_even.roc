@@ -0,0 +1,4 @@
+#[plugin] isEven : U64 -> Bool
+
+isEven : U64 -> Bool
+isEven = \n -> Num.isEven n
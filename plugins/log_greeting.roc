@@ -0,0 +1,8 @@
+#[plugin] log_greeting : Str -> Task Str {}
+
+import pf.Host
+
+log_greeting : Str -> Task Str {}
+log_greeting = \name ->
+    Host.log! "greeting $(name)"
+    Task.ok "Hello, $(name)!"
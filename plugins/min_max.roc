@@ -0,0 +1,7 @@
+#[plugin] minMax : List I64 -> (I64, I64)
+
+minMax : List I64 -> (I64, I64)
+minMax = \numbers -> (
+    List.min numbers |> Result.withDefault 0,
+    List.max numbers |> Result.withDefault 0,
+)
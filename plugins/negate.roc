@@ -0,0 +1,4 @@
+#[plugin] negate : I8 -> I8
+
+negate : I8 -> I8
+negate = \n -> -n
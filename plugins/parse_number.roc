@@ -0,0 +1,6 @@
+#[plugin] parseNumber : Str -> Result U64 Str
+
+parseNumber : Str -> Result U64 Str
+parseNumber = \s ->
+    Str.toU64 s
+    |> Result.mapErr \_ -> "not a number: $(s)"
@@ -0,0 +1,4 @@
+#[plugin] pi : F64
+
+pi : F64
+pi = Num.pi
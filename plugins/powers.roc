@@ -0,0 +1,8 @@
+#[plugin] square : U64 -> U64
+#[plugin] cube : U64 -> U64
+
+square : U64 -> U64
+square = \n -> n * n
+
+cube : U64 -> U64
+cube = \n -> n * n * n
@@ -0,0 +1,10 @@
+#[plugin] quadruple : I64 -> Task Str Str
+
+import pf.Host
+
+# Calls the `double` plugin twice, which requires `call-allowlist = ["double"]`.
+quadruple : I64 -> Task Str Str
+quadruple = \n ->
+    Host.call "double" "[$(Num.toStr n)]"
+    |> Task.await \twice -> Host.call "double" "[$(twice)]"
+    |> Task.mapErr \CallErr msg -> msg
@@ -0,0 +1,7 @@
+#[plugin] renderConfig : Dict Str Str -> Str
+
+renderConfig : Dict Str Str -> Str
+renderConfig = \config ->
+    Dict.toList config
+    |> List.map \(key, value) -> "$(key)=$(value)"
+    |> Str.joinWith ", "
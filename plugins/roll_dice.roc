@@ -0,0 +1,9 @@
+#[plugin] roll_dice : U64 -> Task (List U64) {}
+
+import pf.Host
+
+roll_dice : U64 -> Task (List U64) {}
+roll_dice = \count ->
+    List.range { start: At 0, end: Before count }
+    |> List.map \_ -> Host.randomU64 |> Task.map \n -> n % 6 + 1
+    |> Task.seq
@@ -0,0 +1,9 @@
+#[plugin] save_note : Str -> Task Str Str
+
+import pf.Host
+
+save_note : Str -> Task Str Str
+save_note = \note ->
+    Host.writeFile "note.txt" note
+    |> Task.await \{} -> Host.readFile "note.txt"
+    |> Task.mapErr \FileErr msg -> msg
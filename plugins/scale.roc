@@ -0,0 +1,4 @@
+#[plugin] scale : F64, F32 -> F32
+
+scale : F64, F32 -> F32
+scale = \x, factor -> Num.toF32 x * factor
@@ -0,0 +1,2 @@
+shout : Str -> Str
+shout = \s -> "$(s)!"
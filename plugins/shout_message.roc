@@ -0,0 +1,4 @@
+#[plugin(hook = "on_message")] shout_message : Str -> Str
+
+shout_message : Str -> Str
+shout_message = \message -> "$(message)!"
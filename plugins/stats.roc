@@ -0,0 +1,8 @@
+#[plugin] stats : List U64 -> { count : U64, total : U64, label : Str }
+
+stats : List U64 -> { count : U64, total : U64, label : Str }
+stats = \numbers -> {
+    count: List.len numbers,
+    total: List.sum numbers,
+    label: "stats",
+}
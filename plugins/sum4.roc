@@ -0,0 +1,4 @@
+#[plugin] sum4 : U64, U64, U64, U64 -> U64
+
+sum4 : U64, U64, U64, U64 -> U64
+sum4 = \a, b, c, d -> a + b + c + d
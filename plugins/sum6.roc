@@ -0,0 +1,4 @@
+#[plugin] sum6 : U64, U64, U64, U64, U64, U64 -> U64
+
+sum6 : U64, U64, U64, U64, U64, U64 -> U64
+sum6 = \a, b, c, d, e, f -> a + b + c + d + e + f
@@ -0,0 +1,4 @@
+#[plugin] sum8 : U64, U64, U64, U64, U64, U64, U64, U64 -> U64
+
+sum8 : U64, U64, U64, U64, U64, U64, U64, U64 -> U64
+sum8 = \a, b, c, d, e, f, g, h -> a + b + c + d + e + f + g + h
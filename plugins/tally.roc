@@ -0,0 +1,12 @@
+#[state] { count : U64, words : List Str }
+#[plugin] tally : State, Str -> (State, U64)
+
+State : { count : U64, words : List Str }
+
+init : {} -> State
+init = \{} -> { count: 0, words: [] }
+
+tally : State, Str -> (State, U64)
+tally = \state, word ->
+    count = state.count + 1
+    ({ count, words: List.append state.words word }, count)
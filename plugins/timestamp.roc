@@ -0,0 +1,10 @@
+#[plugin] timestamp : Str -> Task Str {}
+
+import pf.Host
+
+timestamp : Str -> Task Str {}
+timestamp = \msg ->
+    start = Host.monotonicMillis!
+    now = Host.now!
+    end = Host.monotonicMillis!
+    Task.ok "[$(Num.toStr now)] $(msg) (took $(Num.toStr (end - start)) ms)"
@@ -0,0 +1,4 @@
+#[plugin] toUtf8 : Str -> List U8
+
+toUtf8 : Str -> List U8
+toUtf8 = \s -> Str.toUtf8 s
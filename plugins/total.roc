@@ -0,0 +1,4 @@
+#[plugin] total : List U64 -> U64
+
+total : List U64 -> U64
+total = \numbers -> List.sum numbers
@@ -0,0 +1,7 @@
+#[plugin(subscribe = "user_created")] welcome_user : Str -> Task {} {}
+
+import pf.Host
+
+welcome_user : Str -> Task {} {}
+welcome_user = \name ->
+    Host.log "welcome, $(name)"
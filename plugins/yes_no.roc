@@ -0,0 +1,4 @@
+#[plugin] yesNo : Bool -> Str
+
+yesNo : Bool -> Str
+yesNo = \b -> if b then "yes" else "no"
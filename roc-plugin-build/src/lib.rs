@@ -0,0 +1,52 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use roc_plugin::{LoadOptions, Profile};
+
+/// Compiles the plugins in `dir` and prepares them for embedding into the crate being built.
+///
+/// Call this from a build script; the plugins are then available at runtime through
+/// `roc_plugin::embedded!()`, and can be loaded with `Embedded::load` without a Roc compiler.
+/// The build is rerun whenever a file in `dir` changes. Plugins are optimized in release builds
+/// of the crate.
+///
+/// # Panics
+///
+/// Panics if a plugin fails to compile, failing the build.
+pub fn embed<P: AsRef<Path>>(dir: P) {
+    let dir = dir.as_ref();
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR not set"));
+    let artifacts = out_dir.join("roc-plugins");
+    let paths = roc_plugin::discover(dir)
+        .unwrap_or_else(|error| panic!("failed to read {}: {error}", dir.display()));
+
+    let profile = match env::var("PROFILE").as_deref() {
+        Ok("release") => Profile::Release,
+        _ => Profile::Dev,
+    };
+    let options = LoadOptions {
+        profile,
+        ..LoadOptions::default()
+    };
+
+    let mut code = String::from("&[\n");
+    for path in paths {
+        let manifest = roc_plugin::precompile(&path, &artifacts, &options)
+            .unwrap_or_else(|error| panic!("failed to build {}: {error}", path.display()));
+        let library = manifest.with_extension(env::consts::DLL_EXTENSION);
+        writeln!(
+            code,
+            "    ::roc_plugin::Embedded {{ manifest: include_str!({:?}), library: include_bytes!({:?}) }},",
+            manifest.display().to_string(),
+            library.display().to_string(),
+        )
+        .unwrap();
+    }
+    code.push(']');
+
+    fs::write(out_dir.join("roc_plugins.rs"), code).expect("failed to write roc_plugins.rs");
+}
@@ -0,0 +1,207 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, ToTokens};
+use syn::{FnArg, ItemTrait, Pat, ReturnType, TraitItem, Type};
+
+/// A Rust type that can cross the boundary between an effect and its Roc caller.
+struct Mapping {
+    /// The type in the Roc signature of the effect.
+    roc_type: &'static str,
+    /// The type the `roc_fx_*` shim receives or returns.
+    ffi_type: TokenStream2,
+}
+
+fn map_arg(ty: &Type) -> syn::Result<Mapping> {
+    match ty.to_token_stream().to_string().as_str() {
+        "& str" => Ok(Mapping {
+            roc_type: "Str",
+            ffi_type: quote!(&::roc_plugin::__private::RocStr),
+        }),
+        name => map_scalar(name).ok_or_else(|| unsupported(ty)),
+    }
+}
+
+fn map_return(ty: &Type) -> syn::Result<Mapping> {
+    match ty.to_token_stream().to_string().as_str() {
+        "String" => Ok(Mapping {
+            roc_type: "Str",
+            ffi_type: quote!(::roc_plugin::__private::RocStr),
+        }),
+        "()" => Ok(unit()),
+        name => map_scalar(name).ok_or_else(|| unsupported(ty)),
+    }
+}
+
+fn map_scalar(name: &str) -> Option<Mapping> {
+    let roc_type = match name {
+        "bool" => "Bool",
+        "u8" => "U8",
+        "u64" => "U64",
+        "i8" => "I8",
+        "i16" => "I16",
+        "i32" => "I32",
+        "i64" => "I64",
+        "f32" => "F32",
+        "f64" => "F64",
+        _ => return None,
+    };
+    let ffi_type = format_ident!("{name}").into_token_stream();
+    Some(Mapping { roc_type, ffi_type })
+}
+
+fn unit() -> Mapping {
+    Mapping {
+        roc_type: "{}",
+        ffi_type: quote!(()),
+    }
+}
+
+fn unsupported(ty: &Type) -> syn::Error {
+    syn::Error::new_spanned(
+        ty,
+        "unsupported effect type; expected `&str`, `String`, `bool` or a number type",
+    )
+}
+
+/// Converts a Rust method name to the name of the effect in Roc, e.g. `fetch_data` to
+/// `fetchData`.
+fn roc_name(name: &str) -> String {
+    let mut parts = name.split('_').filter(|p| !p.is_empty());
+    let mut roc_name = parts.next().unwrap_or_default().to_owned();
+    for part in parts {
+        let mut chars = part.chars();
+        roc_name.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+        roc_name.extend(chars);
+    }
+    roc_name
+}
+
+pub(crate) fn expand(item: ItemTrait) -> syn::Result<TokenStream2> {
+    let trait_name = &item.ident;
+
+    let mut effects = Vec::new();
+    let mut shims = Vec::new();
+    let mut shim_names = Vec::new();
+    for trait_item in &item.items {
+        let TraitItem::Fn(method) = trait_item else {
+            return Err(syn::Error::new_spanned(
+                trait_item,
+                "host API traits may only contain methods",
+            ));
+        };
+        let sig = &method.sig;
+
+        let mut inputs = sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(receiver))
+                if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &sig.ident,
+                    "effect methods must take `&self`",
+                ))
+            }
+        }
+
+        let mut arg_names = Vec::new();
+        let mut arg_types = Vec::new();
+        let mut roc_args = Vec::new();
+        let mut call_args = Vec::new();
+        for input in inputs {
+            let FnArg::Typed(arg) = input else {
+                unreachable!("only the first argument can be a receiver");
+            };
+            let Pat::Ident(pat) = &*arg.pat else {
+                return Err(syn::Error::new_spanned(
+                    &arg.pat,
+                    "effect arguments must be plain identifiers",
+                ));
+            };
+            let name = &pat.ident;
+            let mapping = map_arg(&arg.ty)?;
+            call_args.push(match mapping.roc_type {
+                "Str" => quote!(#name.as_str()),
+                _ => quote!(#name),
+            });
+            arg_names.push(name);
+            arg_types.push(mapping.ffi_type);
+            roc_args.push(mapping.roc_type);
+        }
+
+        let ret = match &sig.output {
+            ReturnType::Default => unit(),
+            ReturnType::Type(_, ty) => map_return(ty)?,
+        };
+        let ret_type = &ret.ffi_type;
+        let ret_value = match ret.roc_type {
+            "Str" => quote!(::roc_plugin::__private::RocStr::from(value.as_str())),
+            _ => quote!(value),
+        };
+
+        let name = roc_name(&sig.ident.to_string());
+        let task = format!("Task {} {{}}", ret.roc_type);
+        let signature = if roc_args.is_empty() {
+            task
+        } else {
+            format!("{} -> {task}", roc_args.join(", "))
+        };
+        effects.push(quote! {
+            ::roc_plugin::HostEffect {
+                name: #name,
+                signature: #signature,
+            }
+        });
+
+        let method_name = &sig.ident;
+        let shim = format_ident!("roc_fx_{name}");
+        shims.push(quote! {
+            #[no_mangle]
+            #[allow(non_snake_case)]
+            #[doc(hidden)]
+            pub extern "C-unwind" fn #shim(
+                #(#arg_names: #arg_types),*
+            ) -> ::roc_plugin::__private::RocResult<#ret_type, ()> {
+                let value = ::roc_plugin::__private::with_host_api::<
+                    dyn #trait_name + ::std::marker::Send + ::std::marker::Sync,
+                    _,
+                >(::std::stringify!(#trait_name), |api| {
+                    api.#method_name(#(#call_args),*)
+                });
+                ::roc_plugin::__private::RocResult::ok(#ret_value)
+            }
+        });
+        shim_names.push(shim);
+    }
+
+    let vis = &item.vis;
+    Ok(quote! {
+        #item
+
+        impl dyn #trait_name {
+            /// The effects declared by this trait, as they appear in the `Host` module.
+            #vis const EFFECTS: &'static [::roc_plugin::HostEffect] = &[#(#effects),*];
+
+            /// Makes `api` implement the effects declared by this trait for the plugins loaded
+            /// with `options`, and declares them in their `Host` module. This replaces the
+            /// implementation provided before, if any.
+            #vis fn provide<T>(options: &mut ::roc_plugin::LoadOptions, api: T)
+            where
+                T: #trait_name + ::std::marker::Send + ::std::marker::Sync + 'static,
+            {
+                options.host_apis.insert::<
+                    dyn #trait_name + ::std::marker::Send + ::std::marker::Sync,
+                >(::std::boxed::Box::new(api));
+                for effect in Self::EFFECTS {
+                    if !options.effects.contains(effect) {
+                        options.effects.push(*effect);
+                    }
+                }
+                // Keep the shims from being discarded by the linker, like `roc_plugin::init`
+                // does for the built-in host functions.
+                let shims: &[*const ()] = &[#(#shim_names as *const ()),*];
+                ::std::hint::black_box(shims);
+            }
+        }
+
+        #(#shims)*
+    })
+}
@@ -0,0 +1,134 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, ItemTrait};
+
+mod host_api;
+
+/// Derives `IntoRocArg` and `FromRocReturn` for a struct with named fields, mapping it to a Roc
+/// record with the same field names.
+///
+/// The Roc type annotation of the record is available as
+/// `<T as IntoRocArg>::roc_type().to_string()`.
+#[proc_macro_derive(RocValue)]
+pub fn derive_roc_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Turns a trait into host effects that plugins can perform through the `Host` module.
+///
+/// Each method becomes an effect named like the method in camel case, which returns a `Task`
+/// that never fails. Methods must take `&self`, arguments may be `&str`, `bool` or number types,
+/// and return values `String`, `()`, `bool` or number types. The generated
+/// `<dyn Trait>::provide` function sets the implementation used by the plugins loaded with some
+/// `LoadOptions`, so managers can provide different ones:
+///
+/// ```ignore
+/// #[roc_plugin::host_api]
+/// trait HostApi {
+///     fn fetch(&self, url: &str) -> String;
+/// }
+///
+/// let mut options = roc_plugin::LoadOptions::default();
+/// <dyn HostApi>::provide(&mut options, MyApi);
+/// ```
+///
+/// Plugins loaded with `options` can then call `Host.fetch : Str -> Task Str {}`. Effect names
+/// must not clash with the built-in effects. Performing an effect in a plugin loaded without an
+/// implementation panics.
+#[proc_macro_attribute]
+pub fn host_api(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let error = syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`host_api` takes no arguments",
+        );
+        return error.into_compile_error().into();
+    }
+    let item = parse_macro_input!(item as ItemTrait);
+    host_api::expand(item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "`RocValue` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`RocValue` can only be derived for structs",
+            ))
+        }
+    };
+    if fields.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "empty records are not supported",
+        ));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let idents: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let names: Vec<_> = idents.iter().map(|i| i.to_string()).collect();
+    let types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+
+    Ok(quote! {
+        impl #impl_generics ::roc_plugin::IntoRocArg for #name #ty_generics #where_clause {
+            fn roc_type() -> ::roc_plugin::DType {
+                ::roc_plugin::DType::Record(::std::vec![
+                    #((
+                        ::std::string::String::from(#names),
+                        <#types as ::roc_plugin::IntoRocArg>::roc_type(),
+                    )),*
+                ])
+            }
+
+            fn into_roc_value(self) -> ::roc_plugin::Value {
+                ::roc_plugin::Value::Record(::std::vec![
+                    #((
+                        ::std::string::String::from(#names),
+                        ::roc_plugin::IntoRocArg::into_roc_value(self.#idents),
+                    )),*
+                ])
+            }
+        }
+
+        impl #impl_generics ::roc_plugin::FromRocReturn for #name #ty_generics #where_clause {
+            fn roc_type() -> ::roc_plugin::DType {
+                ::roc_plugin::DType::Record(::std::vec![
+                    #((
+                        ::std::string::String::from(#names),
+                        <#types as ::roc_plugin::FromRocReturn>::roc_type(),
+                    )),*
+                ])
+            }
+
+            fn from_roc_value(value: ::roc_plugin::Value) -> ::std::option::Option<Self> {
+                let ::roc_plugin::Value::Record(mut fields) = value else {
+                    return ::std::option::Option::None;
+                };
+                ::std::option::Option::Some(Self {
+                    #(#idents: {
+                        let i = fields.iter().position(|(n, _)| n == #names)?;
+                        <#types as ::roc_plugin::FromRocReturn>::from_roc_value(
+                            fields.swap_remove(i).1,
+                        )?
+                    },)*
+                })
+            }
+        }
+    })
+}
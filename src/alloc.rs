@@ -0,0 +1,267 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+/// Allocations are prefixed with a header holding their size, so that `roc_dealloc` knows how
+/// much memory it releases, and where they came from. The header is a multiple of the
+/// alignment Roc values require.
+const HEADER: usize = 32;
+/// The alignment of allocations, which is the largest one Roc values require.
+const ALIGN: usize = 16;
+
+const _: () = assert!(mem::size_of::<usize>() + mem::size_of::<Origin>() <= HEADER);
+
+/// The allocator backing the memory plugins allocate through `roc_alloc`, see
+/// [`LoadOptions::allocator`].
+///
+/// Each allocation records the allocator it came from, so plugins with different allocators
+/// can pass values to each other, and values returned by plugins can be dropped anywhere.
+///
+/// [`LoadOptions::allocator`]: crate::LoadOptions::allocator
+#[derive(Clone, Copy, Default)]
+pub enum Allocator {
+    /// The system allocator, i.e. `malloc` and `free`.
+    #[default]
+    System,
+    /// A bump arena per thread, which the code of plugins allocates from during an invocation,
+    /// and which is reset once the invocation returns.
+    ///
+    /// Freeing arena memory does nothing, which makes this much faster for plugins that
+    /// allocate many short-lived values, like strings they build up, at the cost of holding on
+    /// to all memory an invocation allocates until it returns. The arena grows by chunks of
+    /// `chunk_size` bytes, of which it keeps the first between invocations.
+    ///
+    /// The host allocates the arguments and copies the results with the system allocator, so
+    /// that they don't refer to arena memory. Effects implemented by the host must not keep the
+    /// Roc values that plugins pass them either.
+    Arena { chunk_size: usize },
+    /// An allocator of the host's choosing, like `Custom(&mimalloc::MiMalloc)`.
+    Custom(&'static (dyn GlobalAlloc + Sync)),
+}
+
+impl Allocator {
+    /// Returns the allocator for memory that isn't allocated from the arena.
+    fn base(&self) -> &'static (dyn GlobalAlloc + Sync) {
+        match self {
+            Self::System | Self::Arena { .. } => &System,
+            Self::Custom(allocator) => *allocator,
+        }
+    }
+}
+
+impl fmt::Debug for Allocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::System => f.write_str("System"),
+            Self::Arena { chunk_size } => f
+                .debug_struct("Arena")
+                .field("chunk_size", chunk_size)
+                .finish(),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Returns whether the running plugin allocates from the arena, see [`Allocator::Arena`].
+pub(crate) fn is_arena() -> bool {
+    matches!(ALLOCATOR.get(), Allocator::Arena { .. })
+}
+
+/// Runs `f` with the allocator of the plugin it invokes, see [`LoadOptions::allocator`].
+///
+/// [`LoadOptions::allocator`]: crate::LoadOptions::allocator
+pub(crate) fn with_allocator<R>(allocator: Allocator, f: impl FnOnce() -> R) -> R {
+    struct Reset(Allocator);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            ALLOCATOR.set(self.0);
+        }
+    }
+
+    let _reset = Reset(ALLOCATOR.replace(allocator));
+    f()
+}
+
+/// Where an allocation came from, as recorded in its header.
+#[derive(Clone, Copy)]
+enum Origin {
+    Base(&'static (dyn GlobalAlloc + Sync)),
+    Arena,
+}
+
+thread_local! {
+    /// The allocator of the plugin running on this thread, or of the host outside of plugins.
+    static ALLOCATOR: Cell<Allocator> = const { Cell::new(Allocator::System) };
+    /// Whether the code of a plugin is running on this thread, rather than the host's.
+    static IN_ROC: Cell<bool> = const { Cell::new(false) };
+    /// The number of invocations running on this thread, see [`scope`].
+    static INVOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static ARENA: RefCell<Arena> = const { RefCell::new(Arena::new()) };
+}
+
+/// Runs the code of a plugin, which allocates from the arena if there is one.
+pub(crate) fn in_roc<R>(f: impl FnOnce() -> R) -> R {
+    with_in_roc(true, f)
+}
+
+/// Runs host code called by a plugin, like an effect, which doesn't allocate from the arena.
+pub(crate) fn in_host<R>(f: impl FnOnce() -> R) -> R {
+    with_in_roc(false, f)
+}
+
+fn with_in_roc<R>(in_roc: bool, f: impl FnOnce() -> R) -> R {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            IN_ROC.set(self.0);
+        }
+    }
+
+    let _reset = Reset(IN_ROC.replace(in_roc));
+    f()
+}
+
+/// Runs an invocation, resetting the arena once no invocations run on this thread anymore,
+/// since plugins that call each other share it.
+pub(crate) fn scope<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            let invocations = INVOCATIONS.get() - 1;
+            INVOCATIONS.set(invocations);
+            if invocations == 0 {
+                ARENA.with_borrow_mut(Arena::reset);
+            }
+        }
+    }
+
+    INVOCATIONS.set(INVOCATIONS.get() + 1);
+    let _reset = Reset;
+    f()
+}
+
+/// Allocates `size` bytes, returning null if that fails.
+pub(crate) unsafe fn alloc(size: usize) -> *mut u8 {
+    let Some(layout) = layout(size) else {
+        return ptr::null_mut();
+    };
+    let (base, origin) = match ALLOCATOR.get() {
+        Allocator::Arena { chunk_size } if IN_ROC.get() => (
+            ARENA.with_borrow_mut(|arena| arena.alloc(layout.size(), chunk_size)),
+            Origin::Arena,
+        ),
+        allocator => {
+            let base = allocator.base();
+            (base.alloc(layout), Origin::Base(base))
+        }
+    };
+    if base.is_null() {
+        return base;
+    }
+    write_header(base, size, origin)
+}
+
+/// Resizes the allocation at `ptr` to `new_size` bytes, returning null if that fails.
+pub(crate) unsafe fn realloc(ptr: *mut u8, new_size: usize) -> *mut u8 {
+    let (base, old_size, origin) = read_header(ptr);
+    match origin {
+        // Arena memory can't grow in place, so it is copied to a new allocation.
+        Origin::Arena => {
+            let new = alloc(new_size);
+            if !new.is_null() {
+                ptr::copy_nonoverlapping(ptr, new, old_size.min(new_size));
+            }
+            new
+        }
+        Origin::Base(allocator) => {
+            let Some(new_layout) = layout(new_size) else {
+                return ptr::null_mut();
+            };
+            let old_layout = layout(old_size).expect("allocated sizes have a layout");
+            let base = allocator.realloc(base, old_layout, new_layout.size());
+            if base.is_null() {
+                return base;
+            }
+            write_header(base, new_size, origin)
+        }
+    }
+}
+
+/// Frees the allocation at `ptr`, unless it is arena memory, which is freed when the arena is
+/// reset.
+pub(crate) unsafe fn dealloc(ptr: *mut u8) {
+    let (base, size, origin) = read_header(ptr);
+    if let Origin::Base(allocator) = origin {
+        let layout = layout(size).expect("allocated sizes have a layout");
+        allocator.dealloc(base, layout);
+    }
+}
+
+/// Returns the size of the allocation at `ptr`.
+pub(crate) unsafe fn size_of(ptr: *mut u8) -> usize {
+    read_header(ptr).1
+}
+
+/// Returns the layout of an allocation of `size` bytes with its header.
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, ALIGN).ok()
+}
+
+unsafe fn write_header(base: *mut u8, size: usize, origin: Origin) -> *mut u8 {
+    base.cast::<usize>().write(size);
+    base.cast::<usize>().add(1).cast::<Origin>().write(origin);
+    base.add(HEADER)
+}
+
+unsafe fn read_header(ptr: *mut u8) -> (*mut u8, usize, Origin) {
+    let base = ptr.sub(HEADER);
+    let size = base.cast::<usize>().read();
+    let origin = base.cast::<usize>().add(1).cast::<Origin>().read();
+    (base, size, origin)
+}
+
+/// A bump arena, see [`Allocator::Arena`].
+struct Arena {
+    /// The chunks allocations are bumped from, which are only ever used through their spare
+    /// capacity, since the allocations are handed out as pointers.
+    chunks: Vec<Vec<u128>>,
+    /// The number of bytes allocated from the last chunk.
+    used: usize,
+}
+
+impl Arena {
+    const fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            used: 0,
+        }
+    }
+
+    /// Allocates `size` bytes, which is a multiple of [`HEADER`], adding a chunk of at least
+    /// `chunk_size` bytes if the last one is full.
+    fn alloc(&mut self, size: usize, chunk_size: usize) -> *mut u8 {
+        let size = size.next_multiple_of(mem::size_of::<u128>());
+        let fits = self
+            .chunks
+            .last()
+            .is_some_and(|chunk| chunk.capacity() * mem::size_of::<u128>() - self.used >= size);
+        if !fits {
+            let len = size.max(chunk_size).div_ceil(mem::size_of::<u128>());
+            self.chunks.push(Vec::with_capacity(len));
+            self.used = 0;
+        }
+        let chunk = self.chunks.last_mut().expect("a chunk was just added");
+        // SAFETY: The allocation fits into the chunk's capacity.
+        let ptr = unsafe { chunk.as_mut_ptr().cast::<u8>().add(self.used) };
+        self.used += size;
+        ptr
+    }
+
+    /// Frees all allocations, keeping the first chunk for the next invocation.
+    fn reset(&mut self) {
+        self.chunks.truncate(1);
+        self.used = 0;
+    }
+}
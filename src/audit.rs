@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::PluginError;
+use crate::logging::{plugin_log, LogLevel};
+use crate::plugin::Meta;
+use crate::roc_host;
+use crate::value::Value;
+
+thread_local! {
+    /// Who the invocations on this thread are made on behalf of, see [`with_caller`].
+    static CALLER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, recording `caller` as the caller of the invocations it makes in the audit logs
+/// of the invoked plugins, see [`LoadOptions::audit_log`].
+///
+/// This is meant for hosts serving requests, which identify the user or service that made a
+/// request, like `user:alice`. Plugins calling each other through `Host.call` are recorded as
+/// `plugin:<name>` instead.
+///
+/// [`LoadOptions::audit_log`]: crate::LoadOptions::audit_log
+pub fn with_caller<R>(caller: &str, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<String>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CALLER.set(self.0.take());
+        }
+    }
+
+    let _reset = Reset(CALLER.replace(Some(caller.into())));
+    f()
+}
+
+/// Returns the caller of an invocation starting on this thread, see [`with_caller`].
+fn caller() -> Option<String> {
+    match roc_host::current_plugin() {
+        Some(plugin) => Some(format!("plugin:{plugin}")),
+        None => CALLER.with_borrow(Clone::clone),
+    }
+}
+
+/// An invocation, as recorded by [`AuditLog::record`].
+pub(crate) struct Entry<'a> {
+    /// When the invocation started, in milliseconds since the Unix epoch.
+    pub(crate) timestamp_ms: u64,
+    pub(crate) plugin: &'a str,
+    /// The hash of the plugin's source.
+    pub(crate) sha256: &'a str,
+    pub(crate) function: &'a str,
+    /// The invoked function, if it exists.
+    pub(crate) meta: Option<&'a Meta>,
+    pub(crate) args: &'a [Value],
+    pub(crate) result: &'a Result<Value, PluginError>,
+    pub(crate) duration: Duration,
+}
+
+/// An append-only log of invocations, with a JSON object per line, see
+/// [`LoadOptions::audit_log`].
+///
+/// Entries look like this, with the arguments replaced by their types if they are redacted, and
+/// `result` holding either the type of the returned value or the error:
+///
+/// ```json
+/// {"timestamp_ms": 1718000000000, "plugin": "slugify", "sha256": "9f86d0...",
+///  "function": "slugify", "args": ["Hello"], "result": {"ok": true, "type": "Str"},
+///  "duration_ms": 1.2, "caller": "user:alice"}
+/// ```
+///
+/// `sha256` is the hash of the plugin's source, as in the `<name>@sha256:<hash>` entries of
+/// [`LoadOptions::allow`], or of its manifest if it was precompiled. Clones share the same
+/// file, so a log can be passed to many plugins.
+///
+/// [`LoadOptions::audit_log`]: crate::LoadOptions::audit_log
+/// [`LoadOptions::allow`]: crate::LoadOptions::allow
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    file: Arc<Mutex<File>>,
+    redact_args: bool,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, creating it if it doesn't exist, and replacing the arguments of
+    /// invocations with their types if `redact_args` is set.
+    pub fn open<P: AsRef<Path>>(path: P, redact_args: bool) -> Result<Self, PluginError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            redact_args,
+        })
+    }
+
+    /// Appends the entry of an invocation.
+    ///
+    /// Failing to write is reported as an error message of the plugin, but doesn't fail the
+    /// invocation.
+    pub(crate) fn record(&self, entry: Entry<'_>) {
+        let Entry {
+            timestamp_ms,
+            plugin,
+            sha256,
+            function,
+            meta,
+            args,
+            result,
+            duration,
+        } = entry;
+        let args: Vec<_> = match meta {
+            Some(meta) if self.redact_args => meta
+                .arg_types
+                .iter()
+                .map(|dtype| serde_json::Value::from(format!("<{dtype}>")))
+                .collect(),
+            Some(_) => args.iter().map(Value::to_json).collect(),
+            None => Vec::new(),
+        };
+        let result = match result {
+            Ok(value) => serde_json::json!({ "ok": true, "type": value.type_name() }),
+            Err(error) => serde_json::json!({ "ok": false, "error": error.to_string() }),
+        };
+        let entry = serde_json::json!({
+            "timestamp_ms": timestamp_ms,
+            "plugin": plugin,
+            "sha256": sha256,
+            "function": function,
+            "args": args,
+            "result": result,
+            "duration_ms": duration.as_secs_f64() * 1000.0,
+            "caller": caller(),
+        });
+
+        // Entries are written at once, so that those of concurrent invocations don't mix.
+        let line = format!("{entry}\n");
+        let mut file = self.file.lock().unwrap();
+        if let Err(error) = file.write_all(line.as_bytes()).and_then(|()| file.flush()) {
+            plugin_log!(
+                "roc_plugin::audit",
+                LogLevel::Error,
+                plugin,
+                "failed to write audit log: {error}",
+            );
+        }
+    }
+}
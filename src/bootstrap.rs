@@ -0,0 +1,142 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::config::ToolchainPin;
+use crate::error::PluginError;
+use crate::toolchain::{build_date, toolchain, OLDEST_SUPPORTED, UNSUPPORTED_SINCE};
+
+/// Where nightlies are downloaded from, unless [`ToolchainPin::url`] is set.
+const DEFAULT_URL: &str = concat!(
+    "https://github.com/roc-lang/roc/releases/download/nightly/",
+    "roc_nightly-{platform}-{version}.tar.gz"
+);
+/// How long downloads wait for a connection, or for the next chunk of the archive. There is no
+/// limit on the whole download, since the archives are large.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the compiler to use instead of `bin`, downloading the pinned nightly into
+/// `cache_dir` if `bin` can't be run or its version isn't supported.
+///
+/// Downloaded nightlies are kept, and only downloaded again if they are removed.
+pub(crate) fn resolve(
+    bin: &Path,
+    pin: &ToolchainPin,
+    cache_dir: &Path,
+) -> Result<PathBuf, PluginError> {
+    let supported = build_date(&toolchain(bin).version)
+        .is_some_and(|date| (OLDEST_SUPPORTED..UNSUPPORTED_SINCE).contains(&date));
+    if supported {
+        return Ok(bin.to_owned());
+    }
+
+    let dir = cache_dir.join("toolchains").join(&pin.version);
+    if let Some(bin) = find_compiler(&dir)? {
+        return Ok(bin);
+    }
+    download(pin, &dir)?;
+    find_compiler(&dir)?.ok_or_else(|| {
+        PluginError::Bootstrap(format!(
+            "the archive of roc nightly {} contains no compiler",
+            pin.version
+        ))
+    })
+}
+
+/// Returns the platform name roc nightlies are published for, like `linux_x86_64`.
+fn platform() -> String {
+    let arch = match (env::consts::OS, env::consts::ARCH) {
+        ("macos", "aarch64") => "apple_silicon",
+        ("linux", "aarch64") => "arm64",
+        (_, arch) => arch,
+    };
+    format!("{}_{arch}", env::consts::OS)
+}
+
+/// Downloads and verifies the pinned nightly, then unpacks it into `dir`.
+fn download(pin: &ToolchainPin, dir: &Path) -> Result<(), PluginError> {
+    let platform = platform();
+    let expected = pin.sha256.get(&platform).ok_or_else(|| {
+        PluginError::Bootstrap(format!(
+            "no SHA-256 hash of roc nightly {} is pinned for {platform}",
+            pin.version
+        ))
+    })?;
+    let url = pin
+        .url
+        .as_deref()
+        .unwrap_or(DEFAULT_URL)
+        .replace("{platform}", &platform)
+        .replace("{version}", &pin.version);
+
+    let parent = dir.parent().expect("toolchain directories have a parent");
+    fs::create_dir_all(parent)?;
+    let archive = tempfile::NamedTempFile::new_in(parent)?;
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(DOWNLOAD_TIMEOUT)
+        .timeout_read(DOWNLOAD_TIMEOUT)
+        .build();
+    let response = agent.get(&url).call().map_err(|error| {
+        PluginError::Bootstrap(format!("failed to download roc from {url}: {error}"))
+    })?;
+    let mut reader = response.into_reader();
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        archive.as_file().write_all(&buf[..n])?;
+    }
+    let found = format!("{:x}", hasher.finalize());
+    if !found.eq_ignore_ascii_case(expected) {
+        return Err(PluginError::Bootstrap(format!(
+            "the roc archive downloaded from {url} has SHA-256 {found}, expected {expected}"
+        )));
+    }
+
+    // Unpack next to the final location, so that an interrupted download leaves no compiler.
+    let unpacked = tempfile::tempdir_in(parent)?;
+    let status = Command::new("tar")
+        .arg("-xzf")
+        .arg(archive.path())
+        .arg("-C")
+        .arg(unpacked.path())
+        .status()?;
+    if !status.success() {
+        return Err(PluginError::Bootstrap(format!(
+            "failed to unpack the roc archive downloaded from {url}: tar exited with {status}"
+        )));
+    }
+    match fs::rename(unpacked.path(), dir) {
+        // Another process unpacked the same nightly in the meantime.
+        Err(_) if dir.exists() => Ok(()),
+        result => result.map_err(Into::into),
+    }
+}
+
+/// Returns the compiler in `dir`, or in one of its immediate subdirectories, which is how
+/// nightly archives are laid out.
+fn find_compiler(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let name = format!("roc{}", env::consts::EXE_SUFFIX);
+    if !dir.exists() {
+        return Ok(None);
+    }
+    if dir.join(&name).is_file() {
+        return Ok(Some(dir.join(&name)));
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path().join(&name);
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,95 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::error::PluginError;
+use crate::plugin::LoadOptions;
+use crate::value::Value;
+
+/// Marks a plugin unhealthy after too many consecutive failures, see
+/// [`LoadOptions::failure_threshold`].
+///
+/// Once the cooldown has passed, the next invocation is let through as a probe, which closes
+/// the breaker again if it succeeds and keeps it open for another cooldown if it fails.
+#[derive(Debug)]
+pub(crate) struct Breaker {
+    threshold: Option<u32>,
+    cooldown: Duration,
+    clock: Clock,
+    health: Mutex<Health>,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// The number of consecutive failures.
+    failures: u32,
+    /// When the breaker opened, in the milliseconds of [`Clock::monotonic_millis`].
+    opened_at: Option<u64>,
+    /// Whether an invocation was let through after the cooldown and hasn't finished yet.
+    probing: bool,
+}
+
+impl Breaker {
+    pub(crate) fn new(options: &LoadOptions) -> Self {
+        Self {
+            threshold: options.failure_threshold,
+            cooldown: options.cooldown,
+            clock: options.clock.clone(),
+            health: Mutex::default(),
+        }
+    }
+
+    /// Returns an error if the breaker is open, unless its cooldown has passed and no other
+    /// invocation is probing the plugin.
+    pub(crate) fn check(&self, plugin: &str) -> Result<(), PluginError> {
+        let mut health = self.health.lock().unwrap();
+        let Some(opened_at) = health.opened_at else {
+            return Ok(());
+        };
+        let open_for = Duration::from_millis(self.clock.monotonic_millis() - opened_at);
+        if open_for >= self.cooldown && !health.probing {
+            health.probing = true;
+            return Ok(());
+        }
+        Err(PluginError::Unhealthy {
+            plugin: plugin.into(),
+            failures: health.failures,
+            retry_in: self.cooldown.saturating_sub(open_for),
+        })
+    }
+
+    /// Records the outcome of an invocation that [`Breaker::check`] let through.
+    ///
+    /// Only panics, timeouts, exceeded memory limits and failed workers count as failures, and
+    /// other errors, like invalid arguments, don't reset the count either.
+    pub(crate) fn record(&self, result: &Result<Value, PluginError>) {
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+        let mut health = self.health.lock().unwrap();
+        match result {
+            Ok(_) => *health = Health::default(),
+            Err(
+                PluginError::Panic { .. }
+                | PluginError::Timeout(_)
+                | PluginError::MemoryLimit(_)
+                | PluginError::WorkerFailed(_),
+            ) => {
+                health.failures = health.failures.saturating_add(1);
+                if health.probing || health.failures >= threshold {
+                    health.opened_at = Some(self.clock.monotonic_millis());
+                }
+                health.probing = false;
+            }
+            Err(_) => health.probing = false,
+        }
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.health.lock().unwrap().opened_at.is_some()
+    }
+
+    pub(crate) fn reset(&self) {
+        *self.health.lock().unwrap() = Health::default();
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+use std::ops::Deref;
+
+use roc_std::RocList;
+
+/// A byte buffer that is passed to and returned from plugins as a `List U8` without copying.
+///
+/// The bytes live in Roc-managed memory, so cloning a `Bytes` or passing it to a plugin only
+/// bumps a reference count. Buffers returned by plugins, including seamless slices of other
+/// lists, are taken over as they are.
+#[derive(Clone, Default)]
+pub struct Bytes(RocList<u8>);
+
+impl Bytes {
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    pub(crate) fn as_roc_list(&self) -> &RocList<u8> {
+        &self.0
+    }
+
+    pub(crate) fn from_roc_list(list: RocList<u8>) -> Self {
+        Self(list)
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    /// Copies the bytes into Roc-managed memory.
+    fn from(bytes: &[u8]) -> Self {
+        Self(RocList::from_slice(bytes))
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::from(bytes.as_slice())
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Bytes {}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bytes({} bytes)", self.len())
+    }
+}
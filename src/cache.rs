@@ -0,0 +1,32 @@
+use std::env;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+/// Returns the default directory for cached plugin builds.
+///
+/// This is `$XDG_CACHE_HOME/roc-plugins`, falling back to `~/.cache/roc-plugins`.
+pub(crate) fn default_dir() -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir);
+    base.join("roc-plugins")
+}
+
+/// Returns the SHA-256 hash of a plugin's source, as printed by `sha256sum`.
+pub(crate) fn source_hash(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code))
+}
+
+/// Returns a key identifying a plugin build, derived from everything that affects its output.
+pub(crate) fn key<T: AsRef<[u8]>>(parts: &[T]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts.iter().map(AsRef::as_ref) {
+        // Hash the lengths too, so that moving text between parts changes the key.
+        hasher.update(part.len().to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
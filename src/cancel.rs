@@ -0,0 +1,92 @@
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+/// A handle for cancelling plugin invocations.
+///
+/// Cancelling a token makes invocations that haven't started yet fail with
+/// [`PluginError::Cancelled`](crate::PluginError::Cancelled). Running invocations are killed
+/// if they run in a forked worker process or under the wasm backend. Invocations running
+/// natively in the host process can't be interrupted, and finish normally.
+///
+/// Clones share the same state, so a token can be cancelled from another thread than the one
+/// waiting for the invocation.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    next_id: u64,
+    /// Functions that interrupt running invocations, keyed by registration.
+    handlers: Vec<(u64, Box<dyn FnOnce() + Send>)>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the invocations using this token, including those started afterwards.
+    pub fn cancel(&self) {
+        let handlers = {
+            let mut state = self.0.lock().unwrap();
+            state.cancelled = true;
+            mem::take(&mut state.handlers)
+        };
+        for (_, handler) in handlers {
+            handler();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.lock().unwrap().cancelled
+    }
+
+    /// Registers `handler` to interrupt a running invocation once the token is cancelled,
+    /// calling it right away if it already is.
+    ///
+    /// The handler is unregistered when the returned guard is dropped.
+    pub(crate) fn on_cancel<F: FnOnce() + Send + 'static>(&self, handler: F) -> OnCancel<'_> {
+        let mut state = self.0.lock().unwrap();
+        if state.cancelled {
+            drop(state);
+            handler();
+            return OnCancel {
+                token: self,
+                id: None,
+            };
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.handlers.push((id, Box::new(handler)));
+        OnCancel {
+            token: self,
+            id: Some(id),
+        }
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Unregisters a handler added by [`CancellationToken::on_cancel`] when dropped.
+pub(crate) struct OnCancel<'a> {
+    token: &'a CancellationToken,
+    id: Option<u64>,
+}
+
+impl Drop for OnCancel<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.token.0.lock().unwrap();
+            state.handlers.retain(|(i, _)| *i != id);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::error::{self, PluginError};
+use crate::manager::PluginManager;
+use crate::plugin::LoadOptions;
+use crate::value::Value;
+
+thread_local! {
+    /// The message of the last error on this thread, see [`rocplug_last_error`].
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, recording its error or panic as the last error, which makes it return `failed`.
+///
+/// Panics must not unwind into C, so they are caught like errors.
+fn catch<T>(failed: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(format!("panicked: {}", error::panic_message(&*payload))));
+    match result {
+        Ok(value) => value,
+        Err(error) => {
+            // Messages can't contain NUL bytes in C.
+            let error = CString::new(error.replace('\0', "\\0")).expect("NUL bytes were replaced");
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+            failed
+        }
+    }
+}
+
+/// Borrows the string at `s`, which must be a valid, NUL-terminated C string or `NULL`.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("`{name}` is NULL"));
+    }
+    // SAFETY: The caller passes a valid C string.
+    let s = unsafe { CStr::from_ptr(s) };
+    s.to_str().map_err(|_| format!("`{name}` is not UTF-8"))
+}
+
+/// Creates a manager without plugins, which must be freed with [`rocplug_manager_free`].
+#[no_mangle]
+pub extern "C" fn rocplug_manager_new() -> *mut PluginManager {
+    catch(ptr::null_mut(), || {
+        Ok(Box::into_raw(Box::new(PluginManager::new())))
+    })
+}
+
+/// Frees a manager created by [`rocplug_manager_new`], unloading its plugins.
+///
+/// # Safety
+///
+/// `manager` must be `NULL` or a manager created by [`rocplug_manager_new`] that wasn't freed
+/// before, and which isn't used by other threads.
+#[no_mangle]
+pub unsafe extern "C" fn rocplug_manager_free(manager: *mut PluginManager) {
+    if !manager.is_null() {
+        // SAFETY: The caller passes a manager created by `rocplug_manager_new`.
+        let manager = unsafe { Box::from_raw(manager) };
+        catch((), || {
+            drop(manager);
+            Ok(())
+        });
+    }
+}
+
+/// Loads the plugins in the directory at `dir`, with the default options, see
+/// [`PluginManager::scan`].
+///
+/// Returns the number of plugins loaded, or `-1` if any failed to load, in which case the
+/// others are loaded regardless and the last error lists the failures.
+///
+/// # Safety
+///
+/// `manager` must be a manager created by [`rocplug_manager_new`] which isn't used by other
+/// threads, and `dir` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rocplug_load_dir(
+    manager: *mut PluginManager,
+    dir: *const c_char,
+) -> c_int {
+    catch(-1, || {
+        // SAFETY: The caller passes a manager and a C string.
+        let manager = unsafe { manager.as_mut() }.ok_or("`manager` is NULL")?;
+        let dir = unsafe { str_arg(dir, "dir") }?;
+        let report = manager.load_all([dir], &LoadOptions::default());
+        if !report.is_ok() {
+            return Err(report.to_string());
+        }
+        Ok(c_int::try_from(report.loaded.len()).unwrap_or(c_int::MAX))
+    })
+}
+
+/// Invokes the first function of the plugin named `plugin`, with the arguments in
+/// `args_json`, which is a JSON array, or a JSON object for functions taking a single record.
+///
+/// Returns the result as JSON, which must be freed with [`rocplug_free`], or `NULL` if the
+/// invocation failed.
+///
+/// # Safety
+///
+/// `manager` must be a manager created by [`rocplug_manager_new`], and `plugin` and
+/// `args_json` NUL-terminated strings. Managers may be used by several threads at once, as long
+/// as none of them loads plugins or frees it meanwhile.
+#[no_mangle]
+pub unsafe extern "C" fn rocplug_invoke_json(
+    manager: *const PluginManager,
+    plugin: *const c_char,
+    args_json: *const c_char,
+) -> *mut c_char {
+    catch(ptr::null_mut(), || {
+        // SAFETY: The caller passes a manager and C strings.
+        let manager = unsafe { manager.as_ref() }.ok_or("`manager` is NULL")?;
+        let name = unsafe { str_arg(plugin, "plugin") }?;
+        let args_json = unsafe { str_arg(args_json, "args_json") }?;
+
+        let plugin = manager
+            .get(name)
+            .ok_or_else(|| PluginError::PluginNotFound(name.into()).to_string())?;
+        let json = serde_json::from_str(args_json)
+            .map_err(|error| format!("malformed arguments: {error}"))?;
+        let args = Value::args_from_json(&json, &plugin.meta().arg_types)?;
+        let value = plugin
+            .invoke_with(&args)
+            .map_err(|error| error.to_string())?;
+        let json =
+            CString::new(value.to_json().to_string()).expect("JSON escapes NUL bytes in strings");
+        Ok(json.into_raw())
+    })
+}
+
+/// Frees a string returned by [`rocplug_invoke_json`].
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a string returned by [`rocplug_invoke_json`] that wasn't freed before.
+#[no_mangle]
+pub unsafe extern "C" fn rocplug_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: The caller passes a string created by `CString::into_raw`.
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Returns the message of the last error on the calling thread, or `NULL` if there was none.
+///
+/// The message stays valid until the next call that fails on the same thread, and must not be
+/// freed.
+#[no_mangle]
+pub extern "C" fn rocplug_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The clock plugins read through `Host.now` and `Host.monotonicMillis`.
+#[derive(Clone, Debug, Default)]
+pub enum Clock {
+    /// The host's real clocks.
+    #[default]
+    System,
+    /// A clock controlled by the host, for deterministic plugin tests.
+    Fake(FakeClock),
+}
+
+impl Clock {
+    /// Returns the wall-clock time in milliseconds since the Unix epoch.
+    pub(crate) fn now_millis(&self) -> u64 {
+        match self {
+            Self::System => {
+                let since_epoch = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                since_epoch.as_millis() as u64
+            }
+            Self::Fake(fake) => fake.start_millis + fake.elapsed_millis(),
+        }
+    }
+
+    /// Returns the milliseconds elapsed since an arbitrary, fixed point in time.
+    pub(crate) fn monotonic_millis(&self) -> u64 {
+        static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+        match self {
+            Self::System => START.elapsed().as_millis() as u64,
+            Self::Fake(fake) => fake.elapsed_millis(),
+        }
+    }
+}
+
+/// A clock that only advances when told to.
+///
+/// Clones share the same time, so a test can keep one to advance the clock of the plugins it
+/// was passed to.
+#[derive(Clone, Debug)]
+pub struct FakeClock {
+    start_millis: u64,
+    elapsed: Arc<AtomicU64>,
+}
+
+impl FakeClock {
+    /// Creates a clock that starts at `start`.
+    pub fn new(start: SystemTime) -> Self {
+        let since_epoch = start.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            start_millis: since_epoch.as_millis() as u64,
+            elapsed: Arc::default(),
+        }
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        self.elapsed
+            .fetch_add(by.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn elapsed_millis(&self) -> u64 {
+        self.elapsed.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use crate::cache;
+use crate::error::PluginError;
+use crate::metrics::Metrics;
+use crate::pipeline::Pipeline;
+use crate::plugin::{parse_duration, LoadOptions, Profile};
+
+/// The name of the configuration file the CLI reads from the working directory.
+pub const CONFIG_FILE: &str = "roc-plugins.toml";
+
+/// Host settings, as read from a `roc-plugins.toml` file.
+///
+/// ```toml
+/// plugins-dirs = ["plugins", "vendor/plugins"]
+/// timeout = "5s"
+/// profile = "release"
+/// env-allowlist = ["API_KEY"]
+/// deny = ["panic"]
+///
+/// [plugins.slugify]
+/// isolated = true
+/// memoize = 1000
+///
+/// [plugins.fetch.config]
+/// apiUrl = "https://example.com"
+///
+/// [pipelines]
+/// slug = "slugify | truncate(80)"
+/// ```
+///
+/// Missing settings keep their defaults, and settings of individual plugins under `[plugins]`
+/// are described by [`PluginOverrides`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The directories plugins are loaded from.
+    pub plugins_dirs: Vec<PathBuf>,
+    /// See [`LoadOptions::cache`].
+    pub cache: bool,
+    /// See [`LoadOptions::cache_dir`], which is used if this is `None`.
+    pub cache_dir: Option<PathBuf>,
+    /// See [`LoadOptions::keep_artifacts`].
+    pub keep_artifacts: bool,
+    /// See [`LoadOptions::profile`].
+    pub profile: Profile,
+    /// See [`LoadOptions::roc_bin`], which is `roc` if this is `None`.
+    pub roc_bin: Option<PathBuf>,
+    /// See [`LoadOptions::platform_template`].
+    pub platform_template: Option<PathBuf>,
+    /// See [`LoadOptions::lazy`].
+    pub lazy: bool,
+    /// See [`LoadOptions::timeout`].
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+    /// See [`LoadOptions::isolated`].
+    pub isolated: bool,
+    /// See [`LoadOptions::memory_limit`].
+    pub memory_limit: Option<usize>,
+    /// See [`LoadOptions::memoize`].
+    pub memoize: usize,
+    /// See [`LoadOptions::retries`].
+    pub retries: u32,
+    /// See [`LoadOptions::retry_backoff`], which is 100ms if this is `None`.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub retry_backoff: Option<Duration>,
+    /// See [`LoadOptions::failure_threshold`].
+    pub failure_threshold: Option<u32>,
+    /// See [`LoadOptions::cooldown`], which is 30s if this is `None`.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub cooldown: Option<Duration>,
+    /// See [`LoadOptions::data_dir`].
+    pub data_dir: Option<PathBuf>,
+    /// The file the plugins' key-value store is persisted in, or `None` to keep it in memory.
+    pub store: Option<PathBuf>,
+    /// See [`LoadOptions::env_allowlist`].
+    pub env_allowlist: Vec<String>,
+    /// The hosts plugins may send requests to, which requires the `http` feature.
+    pub http_allowlist: Vec<String>,
+    /// See [`LoadOptions::call_allowlist`].
+    pub call_allowlist: Vec<String>,
+    /// See [`LoadOptions::max_call_depth`], which is 8 if this is `None`.
+    pub max_call_depth: Option<usize>,
+    /// See [`LoadOptions::include`].
+    pub include: Vec<String>,
+    /// See [`LoadOptions::exclude`].
+    pub exclude: Vec<String>,
+    /// See [`LoadOptions::allow`].
+    pub allow: Vec<String>,
+    /// See [`LoadOptions::deny`].
+    pub deny: Vec<String>,
+    /// The Roc nightly to download if `roc_bin` can't be run or isn't supported, which requires
+    /// the `bootstrap` feature. See [`Config::bootstrap`].
+    pub toolchain: Option<ToolchainPin>,
+    /// Settings of individual plugins, keyed by plugin name.
+    pub plugins: BTreeMap<String, PluginOverrides>,
+    /// Pipelines of plugins, keyed by name, see [`Pipeline`].
+    pub pipelines: BTreeMap<String, String>,
+    /// See [`PluginManager::set_event_concurrency`].
+    ///
+    /// [`PluginManager::set_event_concurrency`]: crate::PluginManager::set_event_concurrency
+    pub event_concurrency: usize,
+    /// The file every invocation is recorded in, see [`LoadOptions::audit_log`].
+    pub audit_log: Option<PathBuf>,
+    /// Whether the arguments of invocations are replaced by their types in the audit log, see
+    /// [`AuditLog::open`](crate::AuditLog::open).
+    pub redact_audit_args: bool,
+    /// See [`LoadOptions::metrics`], which can't be set in the file.
+    #[serde(skip)]
+    pub metrics: Option<Arc<dyn Metrics>>,
+}
+
+/// A Roc nightly pinned in the `[toolchain]` table of a configuration file.
+///
+/// ```toml
+/// [toolchain]
+/// version = "2024-08-26-1a2b3c4"
+/// sha256 = { linux_x86_64 = "...", macos_apple_silicon = "..." }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ToolchainPin {
+    /// The nightly's version, as in the names of its archives.
+    pub version: String,
+    /// The SHA-256 hashes of the nightly's archives, keyed by platform like `linux_x86_64`.
+    ///
+    /// Nightlies are only downloaded for platforms with a pinned hash.
+    pub sha256: BTreeMap<String, String>,
+    /// The URL of the archives, in which `{version}` and `{platform}` are replaced, if they
+    /// aren't downloaded from roc's GitHub releases.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// Settings of a single plugin that take precedence over the [`LoadOptions`] it is loaded with.
+///
+/// A `timeout` attribute in the plugin's header still takes precedence over `timeout`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PluginOverrides {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+    pub isolated: Option<bool>,
+    pub memory_limit: Option<usize>,
+    pub memoize: Option<usize>,
+    pub retries: Option<u32>,
+    pub failure_threshold: Option<u32>,
+    pub data_dir: Option<PathBuf>,
+    pub env_allowlist: Option<Vec<String>>,
+    /// Only has an effect with the `http` feature.
+    pub http_allowlist: Option<Vec<String>>,
+    pub call_allowlist: Option<Vec<String>>,
+    /// See [`LoadOptions::config`].
+    pub config: Option<serde_json::Value>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            plugins_dirs: vec!["plugins".into()],
+            cache: true,
+            cache_dir: None,
+            keep_artifacts: false,
+            profile: Profile::Dev,
+            roc_bin: None,
+            platform_template: None,
+            lazy: false,
+            timeout: None,
+            isolated: false,
+            memory_limit: None,
+            memoize: 0,
+            retries: 0,
+            retry_backoff: None,
+            failure_threshold: None,
+            cooldown: None,
+            data_dir: None,
+            store: None,
+            env_allowlist: Vec::new(),
+            http_allowlist: Vec::new(),
+            call_allowlist: Vec::new(),
+            max_call_depth: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+            toolchain: None,
+            plugins: BTreeMap::new(),
+            pipelines: BTreeMap::new(),
+            event_concurrency: 1,
+            audit_log: None,
+            redact_audit_args: false,
+            metrics: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file at `path`, then applies the overrides from the environment,
+    /// see [`Config::apply_env`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+        let text = fs::read_to_string(path)?;
+        let mut config: Self =
+            toml::from_str(&text).map_err(|error| PluginError::Config(error.to_string()))?;
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// Returns the default configuration with the overrides from the environment applied.
+    pub fn from_env() -> Result<Self, PluginError> {
+        let mut config = Self::default();
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// Overrides settings with the environment variables that are set:
+    ///
+    /// - `ROC_PLUGINS_DIRS`: the plugin directories, separated like in `PATH`
+    /// - `ROC_PLUGINS_CACHE_DIR`, `ROC_PLUGINS_DATA_DIR`, `ROC_PLUGINS_STORE`,
+    ///   `ROC_PLUGINS_ROC_BIN` and `ROC_PLUGINS_AUDIT_LOG`
+    /// - `ROC_PLUGINS_TIMEOUT`: a duration like `5s`
+    /// - `ROC_PLUGINS_PROFILE`: `dev` or `release`
+    /// - `ROC_PLUGINS_MEMORY_LIMIT`: a number of bytes
+    /// - `ROC_PLUGINS_ENV_ALLOWLIST`, `ROC_PLUGINS_HTTP_ALLOWLIST` and
+    ///   `ROC_PLUGINS_CALL_ALLOWLIST`: comma-separated lists
+    pub fn apply_env(&mut self) -> Result<(), PluginError> {
+        let invalid = |name: &str, value: &str| {
+            PluginError::Config(format!("invalid value of {name}: `{value}`"))
+        };
+
+        if let Some(dirs) = env::var_os("ROC_PLUGINS_DIRS") {
+            self.plugins_dirs = env::split_paths(&dirs).collect();
+        }
+        if let Some(dir) = env::var_os("ROC_PLUGINS_CACHE_DIR") {
+            self.cache_dir = Some(dir.into());
+        }
+        if let Some(dir) = env::var_os("ROC_PLUGINS_DATA_DIR") {
+            self.data_dir = Some(dir.into());
+        }
+        if let Some(path) = env::var_os("ROC_PLUGINS_STORE") {
+            self.store = Some(path.into());
+        }
+        if let Some(path) = env::var_os("ROC_PLUGINS_ROC_BIN") {
+            self.roc_bin = Some(path.into());
+        }
+        if let Some(path) = env::var_os("ROC_PLUGINS_AUDIT_LOG") {
+            self.audit_log = Some(path.into());
+        }
+        if let Ok(value) = env::var("ROC_PLUGINS_TIMEOUT") {
+            let timeout = parse_duration(&value);
+            self.timeout = Some(timeout.ok_or_else(|| invalid("ROC_PLUGINS_TIMEOUT", &value))?);
+        }
+        if let Ok(value) = env::var("ROC_PLUGINS_PROFILE") {
+            let profile = value.parse();
+            self.profile = profile.map_err(|_| invalid("ROC_PLUGINS_PROFILE", &value))?;
+        }
+        if let Ok(value) = env::var("ROC_PLUGINS_MEMORY_LIMIT") {
+            let limit = value.parse();
+            self.memory_limit =
+                Some(limit.map_err(|_| invalid("ROC_PLUGINS_MEMORY_LIMIT", &value))?);
+        }
+        if let Ok(value) = env::var("ROC_PLUGINS_ENV_ALLOWLIST") {
+            self.env_allowlist = split_list(&value);
+        }
+        if let Ok(value) = env::var("ROC_PLUGINS_HTTP_ALLOWLIST") {
+            self.http_allowlist = split_list(&value);
+        }
+        if let Ok(value) = env::var("ROC_PLUGINS_CALL_ALLOWLIST") {
+            self.call_allowlist = split_list(&value);
+        }
+        Ok(())
+    }
+
+    /// Downloads the pinned [`ToolchainPin`] into the cache directory, unless it was downloaded
+    /// before, if the configured compiler can't be run or its version isn't supported. Plugins
+    /// are then built with the downloaded compiler.
+    #[cfg(feature = "bootstrap")]
+    pub fn bootstrap(&mut self) -> Result<(), PluginError> {
+        let Some(pin) = &self.toolchain else {
+            return Ok(());
+        };
+        let options = self.load_options();
+        let bin = crate::bootstrap::resolve(&options.roc_bin, pin, &options.cache_dir)?;
+        self.roc_bin = Some(bin);
+        Ok(())
+    }
+
+    /// Returns the configured pipeline with the given name.
+    pub fn pipeline(&self, name: &str) -> Result<Pipeline, PluginError> {
+        self.pipelines
+            .get(name)
+            .ok_or_else(|| PluginError::Config(format!("no pipeline named `{name}`")))?
+            .parse()
+    }
+
+    /// Returns the options plugins are loaded with.
+    pub fn load_options(&self) -> LoadOptions {
+        let defaults = LoadOptions::default();
+        LoadOptions {
+            cache: self.cache,
+            cache_dir: self.cache_dir.clone().unwrap_or_else(cache::default_dir),
+            keep_artifacts: self.keep_artifacts,
+            profile: self.profile,
+            roc_bin: self.roc_bin.clone().unwrap_or(defaults.roc_bin),
+            platform_template: self.platform_template.clone(),
+            lazy: self.lazy,
+            timeout: self.timeout,
+            isolated: self.isolated,
+            memory_limit: self.memory_limit,
+            memoize: self.memoize,
+            retries: self.retries,
+            retry_backoff: self.retry_backoff.unwrap_or(defaults.retry_backoff),
+            failure_threshold: self.failure_threshold,
+            cooldown: self.cooldown.unwrap_or(defaults.cooldown),
+            data_dir: self.data_dir.clone(),
+            #[cfg(feature = "http")]
+            http_allowlist: self.http_allowlist.clone(),
+            env_allowlist: self.env_allowlist.clone(),
+            call_allowlist: self.call_allowlist.clone(),
+            max_call_depth: self.max_call_depth.unwrap_or(defaults.max_call_depth),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            allow: self.allow.clone(),
+            deny: self.deny.clone(),
+            overrides: self.plugins.clone(),
+            metrics: self.metrics.clone(),
+            ..defaults
+        }
+    }
+}
+
+impl PluginOverrides {
+    /// Applies the overrides to the options a plugin is loaded with.
+    pub(crate) fn apply(&self, options: &mut LoadOptions) {
+        if let Some(timeout) = self.timeout {
+            options.timeout = Some(timeout);
+        }
+        if let Some(isolated) = self.isolated {
+            options.isolated = isolated;
+        }
+        if let Some(limit) = self.memory_limit {
+            options.memory_limit = Some(limit);
+        }
+        if let Some(capacity) = self.memoize {
+            options.memoize = capacity;
+        }
+        if let Some(retries) = self.retries {
+            options.retries = retries;
+        }
+        if let Some(threshold) = self.failure_threshold {
+            options.failure_threshold = Some(threshold);
+        }
+        if let Some(dir) = &self.data_dir {
+            options.data_dir = Some(dir.clone());
+        }
+        if let Some(allowlist) = &self.env_allowlist {
+            options.env_allowlist = allowlist.clone();
+        }
+        #[cfg(feature = "http")]
+        if let Some(allowlist) = &self.http_allowlist {
+            options.http_allowlist = allowlist.clone();
+        }
+        if let Some(allowlist) = &self.call_allowlist {
+            options.call_allowlist = allowlist.clone();
+        }
+        if let Some(config) = &self.config {
+            options.config = Some(config.clone());
+        }
+    }
+}
+
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(Into::into)
+        .collect()
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let Some(s) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    parse_duration(&s).map(Some).ok_or_else(|| {
+        D::Error::custom(format!(
+            "invalid duration `{s}`, expected one like `500ms`, `5s`, `2m` or `1h`"
+        ))
+    })
+}
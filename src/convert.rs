@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::bytes::Bytes;
+use crate::dec::Dec;
+use crate::value::{DType, Value};
+
+/// A Rust type that can be passed to a plugin function as a single argument.
+pub trait IntoRocArg {
+    /// Returns the Roc type this Rust type is passed as.
+    fn roc_type() -> DType;
+
+    fn into_roc_value(self) -> Value;
+}
+
+/// A Rust type that can be converted from a plugin function's return value.
+pub trait FromRocReturn: Sized {
+    /// Returns the Roc type this Rust type is converted from.
+    fn roc_type() -> DType;
+
+    /// Converts the returned value, returning `None` if it has the wrong shape.
+    fn from_roc_value(value: Value) -> Option<Self>;
+}
+
+/// A tuple of Rust values that can be passed to a plugin function as its arguments.
+pub trait IntoRocArgs {
+    fn roc_types() -> Vec<DType>;
+
+    fn into_roc_values(self) -> Vec<Value>;
+}
+
+macro_rules! scalar {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl IntoRocArg for $ty {
+                fn roc_type() -> DType {
+                    DType::$variant
+                }
+
+                fn into_roc_value(self) -> Value {
+                    Value::$variant(self)
+                }
+            }
+
+            impl FromRocReturn for $ty {
+                fn roc_type() -> DType {
+                    DType::$variant
+                }
+
+                fn from_roc_value(value: Value) -> Option<Self> {
+                    match value {
+                        Value::$variant(x) => Some(x),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+scalar! {
+    bool => Bool,
+    String => Str,
+    u8 => U8,
+    u64 => U64,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+    Dec => Dec,
+    Bytes => Bytes,
+}
+
+impl FromRocReturn for () {
+    fn roc_type() -> DType {
+        DType::Unit
+    }
+
+    fn from_roc_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Unit => Some(()),
+            _ => None,
+        }
+    }
+}
+
+impl IntoRocArg for &str {
+    fn roc_type() -> DType {
+        DType::Str
+    }
+
+    fn into_roc_value(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+impl<T: IntoRocArg> IntoRocArg for Vec<T> {
+    fn roc_type() -> DType {
+        DType::List(Box::new(T::roc_type()))
+    }
+
+    fn into_roc_value(self) -> Value {
+        Value::List(self.into_iter().map(T::into_roc_value).collect())
+    }
+}
+
+impl<T: FromRocReturn> FromRocReturn for Vec<T> {
+    fn roc_type() -> DType {
+        DType::List(Box::new(T::roc_type()))
+    }
+
+    fn from_roc_value(value: Value) -> Option<Self> {
+        match value {
+            Value::List(items) => items.into_iter().map(T::from_roc_value).collect(),
+            Value::Bytes(bytes) => bytes
+                .iter()
+                .map(|&b| T::from_roc_value(Value::U8(b)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: IntoRocArg> IntoRocArg for Option<T> {
+    fn roc_type() -> DType {
+        DType::Option(Box::new(T::roc_type()))
+    }
+
+    fn into_roc_value(self) -> Value {
+        Value::Option(self.map(|v| Box::new(v.into_roc_value())))
+    }
+}
+
+impl<T: FromRocReturn> FromRocReturn for Option<T> {
+    fn roc_type() -> DType {
+        DType::Option(Box::new(T::roc_type()))
+    }
+
+    fn from_roc_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Option(Some(value)) => T::from_roc_value(*value).map(Some),
+            Value::Option(None) => Some(None),
+            _ => None,
+        }
+    }
+}
+
+impl<K: IntoRocArg, V: IntoRocArg> IntoRocArg for HashMap<K, V> {
+    fn roc_type() -> DType {
+        DType::Dict(Box::new(K::roc_type()), Box::new(V::roc_type()))
+    }
+
+    fn into_roc_value(self) -> Value {
+        let entries = self
+            .into_iter()
+            .map(|(k, v)| (k.into_roc_value(), v.into_roc_value()))
+            .collect();
+        Value::Dict(entries)
+    }
+}
+
+impl<K: FromRocReturn + Eq + Hash, V: FromRocReturn> FromRocReturn for HashMap<K, V> {
+    fn roc_type() -> DType {
+        DType::Dict(Box::new(K::roc_type()), Box::new(V::roc_type()))
+    }
+
+    fn from_roc_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Dict(entries) => entries
+                .into_iter()
+                .map(|(k, v)| Some((K::from_roc_value(k)?, V::from_roc_value(v)?)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! args_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: IntoRocArg),*> IntoRocArgs for ($($name,)*) {
+            fn roc_types() -> Vec<DType> {
+                vec![$($name::roc_type()),*]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_roc_values(self) -> Vec<Value> {
+                let ($($name,)*) = self;
+                vec![$($name.into_roc_value()),*]
+            }
+        }
+    };
+}
+
+args_tuple!();
+args_tuple!(A);
+args_tuple!(A, B);
+args_tuple!(A, B, C);
+args_tuple!(A, B, C, D);
+args_tuple!(A, B, C, D, E);
+args_tuple!(A, B, C, D, E, F);
+args_tuple!(A, B, C, D, E, F, G);
+args_tuple!(A, B, C, D, E, F, G, H);
+
+macro_rules! value_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: IntoRocArg),*> IntoRocArg for ($($name,)*) {
+            fn roc_type() -> DType {
+                DType::Tuple(vec![$(<$name as IntoRocArg>::roc_type()),*])
+            }
+
+            #[allow(non_snake_case)]
+            fn into_roc_value(self) -> Value {
+                let ($($name,)*) = self;
+                Value::Tuple(vec![$($name.into_roc_value()),*])
+            }
+        }
+
+        impl<$($name: FromRocReturn),*> FromRocReturn for ($($name,)*) {
+            fn roc_type() -> DType {
+                DType::Tuple(vec![$(<$name as FromRocReturn>::roc_type()),*])
+            }
+
+            fn from_roc_value(value: Value) -> Option<Self> {
+                let Value::Tuple(values) = value else {
+                    return None;
+                };
+                let mut values = values.into_iter();
+                let tuple = ($($name::from_roc_value(values.next()?)?,)*);
+                values.next().is_none().then_some(tuple)
+            }
+        }
+    };
+}
+
+value_tuple!(A, B);
+value_tuple!(A, B, C);
+value_tuple!(A, B, C, D);
+value_tuple!(A, B, C, D, E);
+value_tuple!(A, B, C, D, E, F);
+value_tuple!(A, B, C, D, E, F, G);
+value_tuple!(A, B, C, D, E, F, G, H);
@@ -0,0 +1,178 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A Roc `Dec`: a 128-bit fixed-point decimal number with 18 decimal places.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Dec(i128);
+
+impl Dec {
+    pub const DECIMAL_PLACES: u32 = 18;
+
+    const ONE: i128 = 10_i128.pow(Self::DECIMAL_PLACES);
+
+    /// Creates a `Dec` from its raw representation, i.e. the value multiplied by 10^18.
+    pub const fn from_raw(raw: i128) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw representation of the `Dec`, i.e. the value multiplied by 10^18.
+    pub const fn to_raw(self) -> i128 {
+        self.0
+    }
+
+    /// Creates a `Dec` from a whole number, returning `None` if it is out of range.
+    pub fn from_int(n: i128) -> Option<Self> {
+        n.checked_mul(Self::ONE).map(Self)
+    }
+}
+
+impl From<i64> for Dec {
+    fn from(n: i64) -> Self {
+        Self(i128::from(n) * Self::ONE)
+    }
+}
+
+impl From<u64> for Dec {
+    fn from(n: u64) -> Self {
+        Self(i128::from(n) * Self::ONE)
+    }
+}
+
+impl fmt::Display for Dec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let one = Self::ONE.unsigned_abs();
+        let abs = self.0.unsigned_abs();
+        let (int, frac) = (abs / one, abs % one);
+
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+        write!(f, "{int}")?;
+        if frac != 0 {
+            let frac = format!("{frac:018}");
+            write!(f, ".{}", frac.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Dec {
+    type Err = ParseDecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if (int.is_empty() && frac.is_empty())
+            || !is_digits(int)
+            || !is_digits(frac)
+            || frac.len() > Self::DECIMAL_PLACES as usize
+        {
+            return Err(ParseDecError);
+        }
+
+        let int: i128 = match int {
+            "" => 0,
+            int => int.parse().map_err(|_| ParseDecError)?,
+        };
+        let frac: i128 = format!("{frac:0<18}").parse().map_err(|_| ParseDecError)?;
+        let raw = int
+            .checked_mul(Self::ONE)
+            .and_then(|n| n.checked_add(frac))
+            .ok_or(ParseDecError)?;
+
+        Ok(Self(if negative { -raw } else { raw }))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseDecError;
+
+impl fmt::Display for ParseDecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid decimal literal")
+    }
+}
+
+impl std::error::Error for ParseDecError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!(Dec::from(0_u64).to_string(), "0");
+        assert_eq!(Dec::from(-3_i64).to_string(), "-3");
+        assert_eq!(
+            Dec::from_raw(-1_500_000_000_000_000_000).to_string(),
+            "-1.5"
+        );
+        assert_eq!(Dec::from_raw(1).to_string(), "0.000000000000000001");
+        assert_eq!(Dec::from_raw(-1).to_string(), "-0.000000000000000001");
+        assert_eq!(
+            Dec::from_raw(i128::MAX).to_string(),
+            "170141183460469231731.687303715884105727"
+        );
+        assert_eq!(
+            Dec::from_raw(i128::MIN).to_string(),
+            "-170141183460469231731.687303715884105728"
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("12".parse(), Ok(Dec::from(12_u64)));
+        assert_eq!(
+            "-1.5".parse(),
+            Ok(Dec::from_raw(-1_500_000_000_000_000_000))
+        );
+        assert_eq!(".5".parse(), Ok(Dec::from_raw(500_000_000_000_000_000)));
+        assert_eq!("2.".parse(), Ok(Dec::from(2_u64)));
+        assert_eq!("0.000000000000000001".parse(), Ok(Dec::from_raw(1)));
+        assert_eq!("-0.000000000000000001".parse(), Ok(Dec::from_raw(-1)));
+        assert_eq!(
+            "170141183460469231731.687303715884105727".parse(),
+            Ok(Dec::from_raw(i128::MAX))
+        );
+    }
+
+    #[test]
+    fn parse_round_trips_display() {
+        for raw in [0, 1, -1, 123_456_789, -10_i128.pow(20), i128::MAX] {
+            let dec = Dec::from_raw(raw);
+            assert_eq!(dec.to_string().parse(), Ok(dec));
+        }
+    }
+
+    #[test]
+    fn parse_rejects_invalid_literals() {
+        for s in [
+            "", "-", ".", "-.", "1.2.3", "+1", "1e3", "1_000", " 1", "0x10", "--1",
+        ] {
+            assert_eq!(s.parse::<Dec>(), Err(ParseDecError), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_rejects_more_than_18_fractional_digits() {
+        assert_eq!("0.0000000000000000001".parse::<Dec>(), Err(ParseDecError));
+    }
+
+    #[test]
+    fn parse_rejects_overflow() {
+        for s in [
+            "170141183460469231732",
+            "170141183460469231731.687303715884105728",
+            "-170141183460469231732",
+            "999999999999999999999999999999999999999999",
+        ] {
+            assert_eq!(s.parse::<Dec>(), Err(ParseDecError), "{s:?}");
+        }
+    }
+}
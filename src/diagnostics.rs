@@ -0,0 +1,101 @@
+use std::path::{Path, MAIN_SEPARATOR};
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// The file a plugin's source is compiled as, in its build directory.
+pub(crate) const APP_FILE: &str = "plugin.roc";
+
+/// How many lines of the compiled app precede the plugin's source, i.e. the `app` header.
+const HEADER_LINES: usize = 1;
+
+/// Rewrites the output of the Roc compiler for a plugin built in `build_dir` to refer to the
+/// plugin's source file, `source`, instead of the app generated from it.
+///
+/// Line numbers in reports about the app are shifted back by the injected `app` header, and
+/// the plugin's helper modules, the `siblings` copied into `build_dir`, are referred to by
+/// their original paths. Other generated files are referred to by name only. Colors are
+/// stripped.
+pub(crate) fn map(output: &str, build_dir: &Path, source: &Path, siblings: &[&str]) -> String {
+    static ANSI: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap());
+    // Reports start with a heading like `── TYPE MISMATCH in plugin.roc ───`.
+    static HEADING: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^── .* in (?P<file>\S+) ─").unwrap());
+    // Code snippets are prefixed with their line number, like `5│  add = \x, y -> x + y`.
+    static GUTTER: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(?P<indent>\s*)(?P<line>\d+)(?P<rest>│.*)$").unwrap());
+
+    let output = ANSI.replace_all(output, "");
+    let app = build_dir.join(APP_FILE).display().to_string();
+    let generated = format!("{}{MAIN_SEPARATOR}", build_dir.display());
+    let source_dir = source.parent().unwrap_or(Path::new(""));
+    let siblings: Vec<_> = siblings
+        .iter()
+        .map(|name| {
+            let copied = build_dir.join(name).display().to_string();
+            (copied, source_dir.join(name).display().to_string())
+        })
+        .collect();
+    let source = source.display().to_string();
+
+    let mut in_app = false;
+    let mut lines = Vec::new();
+    for line in output.lines() {
+        let mut line = line.to_owned();
+        if let Some(caps) = HEADING.captures(&line) {
+            let file = caps.name("file").unwrap();
+            in_app = Path::new(file.as_str()).ends_with(APP_FILE);
+            if in_app {
+                line.replace_range(file.start()..file.end(), &source);
+            }
+        } else if let Some(caps) = GUTTER.captures(&line).filter(|_| in_app) {
+            let number = &caps["line"];
+            let shifted = shift(number);
+            line = format!(
+                "{}{shifted:>width$}{}",
+                &caps["indent"],
+                &caps["rest"],
+                width = number.len()
+            );
+        }
+
+        let mut line = map_locations(&line, &app, &source);
+        for (copied, original) in &siblings {
+            line = line.replace(copied, original);
+        }
+        lines.push(line.replace(&generated, ""));
+    }
+    lines.join("\n")
+}
+
+/// Rewrites locations like `<app>:5:3` to the corresponding line of `source`.
+fn map_locations(line: &str, app: &str, source: &str) -> String {
+    let mut mapped = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(app) {
+        mapped.push_str(&rest[..start]);
+        mapped.push_str(source);
+        rest = &rest[start + app.len()..];
+
+        if let Some(location) = rest.strip_prefix(':') {
+            let end = location
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(location.len());
+            if end > 0 {
+                mapped.push(':');
+                mapped.push_str(&shift(&location[..end]));
+                rest = &location[end..];
+            }
+        }
+    }
+    mapped.push_str(rest);
+    mapped
+}
+
+/// Shifts a line number of the app to the corresponding line of the plugin's source.
+fn shift(number: &str) -> String {
+    match number.parse::<usize>() {
+        Ok(n) if n > HEADER_LINES => (n - HEADER_LINES).to_string(),
+        _ => number.to_owned(),
+    }
+}
@@ -0,0 +1,123 @@
+use std::env;
+use std::fs;
+
+use crate::plugin::{LoadOptions, Plugin};
+use crate::toolchain::{build_date, toolchain, OLDEST_SUPPORTED, UNSUPPORTED_SINCE};
+use crate::value::Value;
+
+/// The outcome of one of the checks run by [`doctor`].
+#[derive(Debug)]
+pub struct Check {
+    /// What was checked, like `roc compiler`.
+    pub name: &'static str,
+    /// What was found if the check passed, or why it failed.
+    pub result: Result<String, String>,
+    /// How to fix the problem, if the check failed.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn passed(name: &'static str, found: String) -> Self {
+        Self {
+            name,
+            result: Ok(found),
+            fix: None,
+        }
+    }
+
+    fn failed(name: &'static str, problem: String, fix: String) -> Self {
+        Self {
+            name,
+            result: Err(problem),
+            fix: Some(fix),
+        }
+    }
+}
+
+/// Checks whether plugins can be built with `options` on this machine: that the compiler is
+/// found, that its version is supported, and that it can build a plugin that is then invoked.
+///
+/// Checks that depend on a failed one are skipped.
+pub fn doctor(options: &LoadOptions) -> Vec<Check> {
+    let bin = options.roc_bin.display();
+    let toolchain = toolchain(&options.roc_bin);
+    if toolchain.version.is_empty() {
+        return vec![Check::failed(
+            "roc compiler",
+            format!("`{bin} version` failed"),
+            "install a Roc nightly as described on https://www.roc-lang.org/install, or \
+             point `--roc-bin`, `roc-bin` in roc-plugins.toml or ROC_PLUGINS_ROC_BIN at it"
+                .into(),
+        )];
+    }
+    let mut checks = vec![Check::passed(
+        "roc compiler",
+        format!("{bin}: {}", toolchain.version),
+    )];
+
+    let date = |(year, month, day)| format!("{year}-{month:02}-{day:02}");
+    let supported = format!(
+        "nightlies from {} up to {} are supported",
+        date(OLDEST_SUPPORTED),
+        date(UNSUPPORTED_SINCE)
+    );
+    checks.push(match build_date(&toolchain.version) {
+        Some(built) if (OLDEST_SUPPORTED..UNSUPPORTED_SINCE).contains(&built) => {
+            Check::passed("roc version", format!("built on {}", date(built)))
+        }
+        Some(built) => Check::failed(
+            "roc version",
+            format!("the compiler was built on {}, but {supported}", date(built)),
+            "install a supported nightly from https://github.com/roc-lang/roc/releases".into(),
+        ),
+        None => Check::failed(
+            "roc version",
+            format!("the compiler's build date is unknown, but {supported}"),
+            "install a nightly from https://github.com/roc-lang/roc/releases".into(),
+        ),
+    });
+
+    checks.push(match build_sample(options) {
+        Ok(()) => Check::passed(
+            "plugin build",
+            format!("built and invoked a plugin with `{bin} build --lib`"),
+        ),
+        Err(error) => Check::failed(
+            "plugin build",
+            error,
+            format!(
+                "make sure a C toolchain with a linker is installed, and that the compiler \
+                 supports `--lib` builds for {}-{}",
+                env::consts::OS,
+                env::consts::ARCH
+            ),
+        ),
+    });
+    checks
+}
+
+/// Builds a minimal plugin from scratch and invokes it.
+fn build_sample(options: &LoadOptions) -> Result<(), String> {
+    let dir = tempfile::tempdir().map_err(|error| error.to_string())?;
+    let path = dir.path().join("doctor.roc");
+    fs::write(
+        &path,
+        "#[plugin] doctor : U64\n\ndoctor : U64\ndoctor = 42\n",
+    )
+    .map_err(|error| error.to_string())?;
+
+    let options = LoadOptions {
+        cache: false,
+        cache_dir: dir.path().join("cache"),
+        lazy: false,
+        allow: Vec::new(),
+        deny: Vec::new(),
+        ..options.clone()
+    };
+    let plugin = Plugin::load_with(&path, &options).map_err(|error| error.to_string())?;
+    match plugin.invoke() {
+        Ok(Value::U64(42)) => Ok(()),
+        Ok(value) => Err(format!("the plugin returned {value} instead of 42")),
+        Err(error) => Err(error.to_string()),
+    }
+}
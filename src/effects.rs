@@ -0,0 +1,726 @@
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "http")]
+use std::time::Duration;
+
+use roc_std::{RocResult, RocStr};
+
+use crate::alloc::{self, Allocator};
+use crate::clock::Clock;
+use crate::error::PluginError;
+use crate::logging::{plugin_log, LogLevel};
+use crate::manager::Peers;
+use crate::plugin::LoadOptions;
+use crate::roc_host::{with_current_plugin, DbgSink};
+use crate::store::Store;
+use crate::toolchain::Syntax;
+use crate::value::Value;
+
+/// An effect that plugins can perform through the `Host` module.
+///
+/// Embedders can add their own effects to plugins with [`LoadOptions::effects`], usually
+/// through the code generated by the `host_api` attribute of the `derive` feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostEffect {
+    /// The name of the effect in Roc. The host implements it as `roc_fx_<name>`.
+    pub name: &'static str,
+    /// The Roc type of the effect, like `Str -> Task Str {}`.
+    pub signature: &'static str,
+}
+
+/// The effects implemented by the `roc_fx_*` functions below.
+const BUILTIN_EFFECTS: &[HostEffect] = &[
+    HostEffect {
+        name: "log",
+        signature: "Str -> Task {} {}",
+    },
+    HostEffect {
+        name: "readFile",
+        signature: "Str -> Task Str [FileErr Str]",
+    },
+    HostEffect {
+        name: "writeFile",
+        signature: "Str, Str -> Task {} [FileErr Str]",
+    },
+    HostEffect {
+        name: "httpGet",
+        signature: "Str -> Task Str [HttpErr Str]",
+    },
+    HostEffect {
+        name: "kvGet",
+        signature: "Str -> Task Str [NotFound]",
+    },
+    HostEffect {
+        name: "kvSet",
+        signature: "Str, Str -> Task {} [KvErr Str]",
+    },
+    HostEffect {
+        name: "envVar",
+        signature: "Str -> Task Str [NotFound]",
+    },
+    HostEffect {
+        name: "now",
+        signature: "Task U64 {}",
+    },
+    HostEffect {
+        name: "monotonicMillis",
+        signature: "Task U64 {}",
+    },
+    HostEffect {
+        name: "randomU64",
+        signature: "Task U64 {}",
+    },
+    HostEffect {
+        name: "call",
+        signature: "Str, Str -> Task Str [CallErr Str]",
+    },
+];
+
+/// Returns the built-in effects, followed by those of `extra` that aren't built in.
+fn all(extra: &[HostEffect]) -> Vec<HostEffect> {
+    let mut effects = BUILTIN_EFFECTS.to_vec();
+    for effect in extra {
+        if !effects.contains(effect) {
+            effects.push(*effect);
+        }
+    }
+    effects
+}
+
+/// The implementations of host API traits that the effects of plugins call, see
+/// [`LoadOptions::host_apis`].
+///
+/// These are usually provided through the code generated by the `host_api` attribute of the
+/// `derive` feature, as `<dyn Trait>::provide(&mut options, api)`.
+#[derive(Clone, Default)]
+pub struct HostApis(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl HostApis {
+    /// Makes `api` the implementation of the host API `A`, usually `dyn Trait + Send + Sync`,
+    /// replacing the one inserted before.
+    pub fn insert<A: ?Sized + Send + Sync + 'static>(&mut self, api: Box<A>) {
+        self.0.insert(TypeId::of::<A>(), Arc::new(api));
+    }
+
+    /// Returns the implementation of the host API `A`, if one was inserted.
+    pub fn get<A: ?Sized + 'static>(&self) -> Option<&A> {
+        let api = self.0.get(&TypeId::of::<A>())?;
+        api.downcast_ref::<Box<A>>().map(|api| &**api)
+    }
+}
+
+impl fmt::Debug for HostApis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HostApis(..)")
+    }
+}
+/// Returns the qualified names of the built-in effects and those of `extra`,
+/// like `Host.log`.
+pub(crate) fn names(extra: &[HostEffect]) -> Vec<String> {
+    all(extra)
+        .iter()
+        .map(|e| format!("Host.{}", e.name))
+        .collect()
+}
+
+/// Returns the code of the hosted `Host` module, which declares the built-in effects and those
+/// of `extra`, see [`LoadOptions::effects`].
+pub(crate) fn host_module(syntax: Syntax, extra: &[HostEffect]) -> String {
+    let imports = match syntax {
+        Syntax::Legacy => "\n    imports []",
+        Syntax::Modern => "",
+    };
+
+    let effects = all(extra);
+    let exposes: Vec<_> = effects
+        .iter()
+        .map(|e| format!("        {},\n", e.name))
+        .collect();
+    let declarations: Vec<_> = effects
+        .iter()
+        .map(|e| format!("{} : {}\n", e.name, e.signature))
+        .collect();
+
+    format!(
+        "\nhosted Host\n    exposes [\n{exposes}    ]{imports}\n\n{declarations}",
+        exposes = exposes.concat(),
+        declarations = declarations.join("\n"),
+    )
+}
+
+/// What the effects performed by a plugin may access.
+#[derive(Debug, Default)]
+pub(crate) struct Capabilities {
+    /// The store behind `Host.kvGet` and `Host.kvSet`, which is provided by the
+    /// [`PluginManager`](crate::PluginManager) the plugin is added to.
+    pub(crate) store: OnceLock<Arc<Store>>,
+    /// The directory `Host.readFile` and `Host.writeFile` are confined to, or `None` if the
+    /// plugin has no file access.
+    pub(crate) data_dir: Option<PathBuf>,
+    /// The hosts `Host.httpGet` may send requests to, see [`LoadOptions::http_allowlist`].
+    ///
+    /// [`LoadOptions::http_allowlist`]: crate::LoadOptions::http_allowlist
+    #[cfg(feature = "http")]
+    pub(crate) http_allowlist: Vec<String>,
+    /// The environment variables `Host.envVar` may read.
+    pub(crate) env_allowlist: Vec<String>,
+    /// The plugins `Host.call` may invoke, which are those of the
+    /// [`PluginManager`](crate::PluginManager) the plugin is added to.
+    pub(crate) peers: OnceLock<Arc<Peers>>,
+    /// The names of the plugins `Host.call` may invoke.
+    pub(crate) call_allowlist: Vec<String>,
+    /// How many `Host.call`s may be nested.
+    pub(crate) max_call_depth: usize,
+    /// The clock behind `Host.now` and `Host.monotonicMillis`.
+    pub(crate) clock: Clock,
+    /// The seed `Host.randomU64` starts from in every invocation, or `None` for a random one.
+    pub(crate) random_seed: Option<u64>,
+    /// The sink of the plugin's `dbg` output, see [`LoadOptions::dbg_sink`].
+    pub(crate) dbg_sink: Option<DbgSink>,
+    /// The data effects read through [`host_data`], see [`LoadOptions::host_data`].
+    pub(crate) host_data: Option<Arc<dyn Any + Send + Sync>>,
+    /// The implementations of host API traits, see [`LoadOptions::host_apis`].
+    pub(crate) host_apis: HostApis,
+    /// The allocator behind `roc_alloc`, see [`LoadOptions::allocator`].
+    pub(crate) allocator: Allocator,
+}
+
+impl Capabilities {
+    /// Returns the capabilities `options` grant the plugin named `plugin`.
+    pub(crate) fn new(plugin: &str, options: &LoadOptions) -> Self {
+        Self {
+            store: OnceLock::new(),
+            data_dir: options.data_dir.as_ref().map(|dir| dir.join(plugin)),
+            #[cfg(feature = "http")]
+            http_allowlist: options.http_allowlist.clone(),
+            env_allowlist: options.env_allowlist.clone(),
+            peers: OnceLock::new(),
+            call_allowlist: options.call_allowlist.clone(),
+            max_call_depth: options.max_call_depth,
+            clock: options.clock.clone(),
+            random_seed: options.random_seed,
+            dbg_sink: options.dbg_sink.clone(),
+            host_data: options.host_data.clone(),
+            host_apis: options.host_apis.clone(),
+            allocator: options.allocator,
+        }
+    }
+}
+
+thread_local! {
+    /// The capabilities of the plugin running on this thread, see [`with_capabilities`].
+    static CAPABILITIES: RefCell<Option<Arc<Capabilities>>> = const { RefCell::new(None) };
+    /// The state of the generator behind `Host.randomU64`, which is reset for every invocation.
+    static RNG: Cell<u64> = const { Cell::new(0) };
+    /// The seed of the invocations on this thread, see [`with_random_seed`].
+    static SEED: Cell<Option<u64>> = const { Cell::new(None) };
+    /// The context passed to the invocation running on this thread, see [`with_context`].
+    static CONTEXT: Cell<Option<*const dyn Any>> = const { Cell::new(None) };
+    /// The plugins that made the `Host.call`s running on this thread, outermost first.
+    static CALLS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f`, granting the effects of the plugin code it calls `capabilities`.
+pub(crate) fn with_capabilities<R>(capabilities: &Arc<Capabilities>, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<Arc<Capabilities>>, u64);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CAPABILITIES.set(self.0.take());
+            RNG.set(self.1);
+        }
+    }
+
+    let seed = SEED
+        .get()
+        .or(capabilities.random_seed)
+        .unwrap_or_else(|| RandomState::new().build_hasher().finish());
+    let _reset = Reset(
+        CAPABILITIES.replace(Some(Arc::clone(capabilities))),
+        RNG.replace(seed),
+    );
+    alloc::with_allocator(capabilities.allocator, f)
+}
+
+/// Runs `f`, starting `Host.randomU64` from `seed` in the invocations it makes, instead of
+/// from the plugins' [`LoadOptions::random_seed`].
+///
+/// This allows reproducing a single invocation, like one recorded in a test, while other
+/// invocations of the same plugin are seeded as usual. Plugins invoked through `Host.call`
+/// start from the same seed.
+pub fn with_random_seed<R>(seed: u64, f: impl FnOnce() -> R) -> R {
+    with_seed(Some(seed), f)
+}
+
+/// Returns the seed of the invocations on this thread, see [`with_random_seed`].
+pub(crate) fn seed() -> Option<u64> {
+    SEED.get()
+}
+
+/// Runs `f` with the seed returned by [`seed`], which worker threads continue.
+pub(crate) fn with_seed<R>(seed: Option<u64>, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<u64>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SEED.set(self.0);
+        }
+    }
+
+    let _reset = Reset(SEED.replace(seed));
+    f()
+}
+
+/// Runs `f`, making `ctx` available to the effects of the plugin code it calls through
+/// [`invocation_context`].
+pub(crate) fn with_context<R>(ctx: &dyn Any, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<*const dyn Any>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CONTEXT.set(self.0);
+        }
+    }
+
+    let _reset = Reset(CONTEXT.replace(Some(ctx)));
+    f()
+}
+
+/// Calls `f` with the context passed to the running invocation by
+/// [`Plugin::invoke_with_ctx`](crate::Plugin::invoke_with_ctx), if it is a `T`.
+///
+/// This is meant for effect implementations that need per-request state, like a database
+/// handle. Outside of effects, or in invocations without a context, `f` receives `None`.
+pub fn invocation_context<T: Any, R>(f: impl FnOnce(Option<&T>) -> R) -> R {
+    // The context outlives the invocation, and is only reachable from its thread.
+    let ctx = CONTEXT.get().map(|ctx| unsafe { &*ctx });
+    f(ctx.and_then(|ctx| ctx.downcast_ref()))
+}
+
+/// Calls `f` with the [`LoadOptions::host_data`] of the plugin running on this thread, if it
+/// is a `T`.
+///
+/// This is meant for effect implementations that behave differently depending on how the
+/// plugin was loaded, like ones backed by the services of the [`PluginManager`] the plugin
+/// belongs to, for embedders with several managers. Outside of effects, or for plugins loaded
+/// without host data, `f` receives `None`.
+///
+/// [`PluginManager`]: crate::PluginManager
+pub fn host_data<T: Any, R>(f: impl FnOnce(Option<&T>) -> R) -> R {
+    let capabilities = capabilities();
+    let data = capabilities.host_data.as_deref();
+    f(data.and_then(|data| data.downcast_ref()))
+}
+
+/// Passes the implementation of the host API `A` of the plugin running on this thread to `f`,
+/// see [`HostApis`]. This backs the effects generated by the `host_api` attribute.
+///
+/// # Panics
+///
+/// Panics if the plugin was loaded without an implementation of `A`, which is named `name`.
+#[doc(hidden)]
+pub fn with_host_api<A: ?Sized + 'static, R>(name: &str, f: impl FnOnce(&A) -> R) -> R {
+    let capabilities = capabilities();
+    match capabilities.host_apis.get::<A>() {
+        Some(api) => f(api),
+        None => panic!("the plugin was loaded without an implementation of `{name}`"),
+    }
+}
+
+/// Returns the sink of the `dbg` output of the plugin running on this thread, if it has one.
+pub(crate) fn dbg_sink() -> Option<DbgSink> {
+    CAPABILITIES.with_borrow(|c| c.as_ref()?.dbg_sink.clone())
+}
+
+/// Returns the plugins that made the `Host.call`s running on this thread, see [`with_calls`].
+pub(crate) fn calls() -> Vec<String> {
+    CALLS.with_borrow(Vec::clone)
+}
+
+/// Runs `f` as part of the `Host.call`s made by `calls`, which count towards the plugins'
+/// [`LoadOptions::max_call_depth`].
+pub(crate) fn with_calls<R>(calls: Vec<String>, f: impl FnOnce() -> R) -> R {
+    struct Reset(Vec<String>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CALLS.set(std::mem::take(&mut self.0));
+        }
+    }
+
+    let _reset = Reset(CALLS.replace(calls));
+    f()
+}
+
+/// Returns the capabilities of the plugin running on this thread.
+fn capabilities() -> Arc<Capabilities> {
+    CAPABILITIES.with_borrow(|c| c.clone().unwrap_or_default())
+}
+
+/// Implements `Host.log`, which writes a message on behalf of the plugin.
+#[no_mangle]
+pub extern "C" fn roc_fx_log(msg: &RocStr) -> RocResult<(), ()> {
+    with_current_plugin(|plugin| {
+        plugin_log!(
+            "roc_plugin::log",
+            LogLevel::Info,
+            plugin,
+            "{}",
+            msg.as_str()
+        );
+    });
+    RocResult::ok(())
+}
+
+/// Implements `Host.readFile`, which reads a file from the plugin's data directory.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_readFile(path: &RocStr) -> RocResult<RocStr, RocStr> {
+    let result = sandboxed_path(path.as_str()).and_then(|path| {
+        let contents = fs::read_to_string(&path)?;
+        // Check where the file actually is only after reading it, so that symlinks swapped in
+        // between the checks can't be used to escape the sandbox.
+        check_contained(&path)?;
+        Ok(contents)
+    });
+    match result {
+        Ok(contents) => RocResult::ok(contents.as_str().into()),
+        Err(error) => RocResult::err(file_error(path, error)),
+    }
+}
+
+/// Implements `Host.writeFile`, which writes a file in the plugin's data directory.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_writeFile(path: &RocStr, contents: &RocStr) -> RocResult<(), RocStr> {
+    let result = sandboxed_path(path.as_str()).and_then(|path| {
+        if let Some(parent) = path.parent() {
+            check_contained(parent)?;
+        }
+        if path
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            return Err(outside_sandbox());
+        }
+        fs::write(&path, contents.as_str())
+    });
+    match result {
+        Ok(()) => RocResult::ok(()),
+        Err(error) => RocResult::err(file_error(path, error)),
+    }
+}
+
+/// Resolves `path` relative to the data directory of the running plugin, creating the
+/// directory if necessary.
+///
+/// Only plain relative paths are accepted, so that plugins can't refer to files outside their
+/// data directory through absolute paths or `..`.
+fn sandboxed_path(path: &str) -> io::Result<PathBuf> {
+    let Some(dir) = capabilities().data_dir.clone() else {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the plugin has no file access",
+        ));
+    };
+
+    let path = Path::new(path);
+    let plain = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if path.as_os_str().is_empty() || !plain {
+        return Err(outside_sandbox());
+    }
+
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(path))
+}
+
+/// Checks that `path`, with all symlinks resolved, lies within the running plugin's data
+/// directory.
+fn check_contained(path: &Path) -> io::Result<()> {
+    let dir = capabilities()
+        .data_dir
+        .clone()
+        .ok_or_else(outside_sandbox)?;
+    if fs::canonicalize(path)?.starts_with(fs::canonicalize(dir)?) {
+        Ok(())
+    } else {
+        Err(outside_sandbox())
+    }
+}
+
+fn outside_sandbox() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "path is outside the plugin's data directory",
+    )
+}
+
+/// Implements `Host.httpGet`, which fetches a URL on an allowlisted host and returns the
+/// response body.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_httpGet(url: &RocStr) -> RocResult<RocStr, RocStr> {
+    match http_get(url.as_str()) {
+        Ok(body) => RocResult::ok(body.as_str().into()),
+        Err(msg) => RocResult::err(format!("{}: {msg}", url.as_str()).as_str().into()),
+    }
+}
+
+/// How long `Host.httpGet` waits for a connection to be established.
+#[cfg(feature = "http")]
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long `Host.httpGet` waits for a whole request, including reading the response.
+#[cfg(feature = "http")]
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[cfg(feature = "http")]
+fn http_get(url: &str) -> Result<String, String> {
+    // Redirects aren't followed, since they could lead to hosts that aren't allowed.
+    let agent = ureq::AgentBuilder::new()
+        .redirects(0)
+        .timeout_connect(HTTP_CONNECT_TIMEOUT)
+        .timeout(HTTP_TIMEOUT)
+        .build();
+    let request = agent.get(url);
+    let host = request
+        .request_url()
+        .map_err(|e| e.to_string())?
+        .host()
+        .to_owned();
+    if !host_allowed(&host, &capabilities().http_allowlist) {
+        return Err(format!("host `{host}` is not allowed"));
+    }
+
+    let response = request.call().map_err(|e| e.to_string())?;
+    response.into_string().map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "http"))]
+fn http_get(_url: &str) -> Result<String, String> {
+    Err("HTTP requests require the `http` feature of the host".into())
+}
+
+/// Returns whether `host` matches an entry of `allowlist`, either exactly or, for entries
+/// like `*.example.com`, as a subdomain.
+#[cfg(feature = "http")]
+fn host_allowed(host: &str, allowlist: &[String]) -> bool {
+    let host = host.to_ascii_lowercase();
+    allowlist.iter().any(|entry| {
+        let entry = entry.to_ascii_lowercase();
+        match entry.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.ends_with('.')),
+            None => host == entry,
+        }
+    })
+}
+
+/// Implements `Host.kvGet`, which looks up a key in the plugin's namespace of the store.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_kvGet(key: &RocStr) -> RocResult<RocStr, ()> {
+    let value = capabilities()
+        .store
+        .get()
+        .and_then(|store| with_current_plugin(|plugin| store.get(plugin, key.as_str())));
+    match value {
+        Some(value) => RocResult::ok(value.as_str().into()),
+        None => RocResult::err(()),
+    }
+}
+
+/// Implements `Host.kvSet`, which sets a key in the plugin's namespace of the store.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_kvSet(key: &RocStr, value: &RocStr) -> RocResult<(), RocStr> {
+    let result = match capabilities().store.get() {
+        Some(store) => with_current_plugin(|plugin| {
+            store
+                .set(plugin, key.as_str(), value.as_str())
+                .map_err(|e| format!("failed to persist key-value store: {e}"))
+        }),
+        None => Err("no key-value store is available outside of a plugin manager".into()),
+    };
+    match result {
+        Ok(()) => RocResult::ok(()),
+        Err(msg) => RocResult::err(msg.as_str().into()),
+    }
+}
+
+/// Implements `Host.envVar`, which reads an allowlisted environment variable of the host.
+///
+/// Variables that aren't allowlisted are reported as not found, so that plugins can't probe
+/// which variables exist.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_envVar(name: &RocStr) -> RocResult<RocStr, ()> {
+    let name = name.as_str();
+    let allowed = capabilities().env_allowlist.iter().any(|n| n == name);
+    match env::var(name) {
+        Ok(value) if allowed => RocResult::ok(value.as_str().into()),
+        _ => RocResult::err(()),
+    }
+}
+
+/// Implements `Host.now`.
+#[no_mangle]
+pub extern "C" fn roc_fx_now() -> RocResult<u64, ()> {
+    RocResult::ok(capabilities().clock.now_millis())
+}
+
+/// Implements `Host.monotonicMillis`.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_monotonicMillis() -> RocResult<u64, ()> {
+    RocResult::ok(capabilities().clock.monotonic_millis())
+}
+
+/// Implements `Host.randomU64`.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn roc_fx_randomU64() -> RocResult<u64, ()> {
+    // SplitMix64, which is good enough for plugins and fully determined by the seed.
+    let state = RNG.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+    RNG.set(state);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    RocResult::ok(z ^ (z >> 31))
+}
+
+/// Implements `Host.call`, which invokes another plugin of the same manager by name.
+///
+/// Plugins can't pass Roc values of arbitrary types through the host, so the arguments are a
+/// JSON array, and the result is returned as JSON, like by the CLI.
+#[no_mangle]
+pub extern "C" fn roc_fx_call(name: &RocStr, args: &RocStr) -> RocResult<RocStr, RocStr> {
+    // The invocation's arguments and results may outlive the calling plugin's arena.
+    match alloc::in_host(|| call(name.as_str(), args.as_str())) {
+        Ok(result) => RocResult::ok(result.as_str().into()),
+        Err(msg) => RocResult::err(format!("{}: {msg}", name.as_str()).as_str().into()),
+    }
+}
+
+fn call(name: &str, args: &str) -> Result<String, String> {
+    let capabilities = capabilities();
+    if !capabilities.call_allowlist.iter().any(|n| n == name) {
+        return Err("calling the plugin is not allowed".into());
+    }
+    let plugin = capabilities
+        .peers
+        .get()
+        .ok_or("plugins can only call each other within a plugin manager")?
+        .get(name)
+        .ok_or_else(|| PluginError::PluginNotFound(name.into()).to_string())?;
+
+    let mut calls = calls();
+    calls.push(with_current_plugin(str::to_owned));
+    if calls.len() > capabilities.max_call_depth {
+        let max = capabilities.max_call_depth;
+        return Err(format!("more than {max} nested plugin calls"));
+    }
+    // The state of a stateful plugin is held until its invocation returns.
+    if plugin.is_stateful() && calls.iter().any(|n| n == name) {
+        return Err("the plugin is stateful and already running".into());
+    }
+
+    let json = serde_json::from_str(args).map_err(|e| format!("malformed arguments: {e}"))?;
+    let args = Value::args_from_json(&json, &plugin.meta().arg_types)?;
+
+    let result = with_calls(calls, || plugin.invoke_with(&args));
+    Ok(result.map_err(|e| e.to_string())?.to_json().to_string())
+}
+
+fn file_error(path: &RocStr, error: io::Error) -> RocStr {
+    format!("{}: {error}", path.as_str()).as_str().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `f` as a plugin named `sandboxed` with the data directory `data_dir`.
+    fn with_data_dir<R>(data_dir: Option<PathBuf>, f: impl FnOnce() -> R) -> R {
+        let options = LoadOptions {
+            data_dir,
+            ..LoadOptions::default()
+        };
+        let capabilities = Arc::new(Capabilities::new("sandboxed", &options));
+        with_capabilities(&capabilities, f)
+    }
+
+    #[test]
+    fn sandboxed_path_accepts_relative_paths() {
+        let data_dir = env::temp_dir().join("roc-plugin-sandboxed-path");
+        let plugin_dir = data_dir.join("sandboxed");
+        with_data_dir(Some(data_dir.clone()), || {
+            assert_eq!(sandboxed_path("a").unwrap(), plugin_dir.join("a"));
+            assert_eq!(
+                sandboxed_path("a/b.txt").unwrap(),
+                plugin_dir.join("a/b.txt")
+            );
+            assert_eq!(sandboxed_path("a/./b").unwrap(), plugin_dir.join("a/b"));
+            assert_eq!(sandboxed_path("./a").unwrap(), plugin_dir.join("a"));
+        });
+        assert!(plugin_dir.is_dir());
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn sandboxed_path_rejects_paths_outside_the_data_dir() {
+        let data_dir = env::temp_dir().join("roc-plugin-sandboxed-path-outside");
+        with_data_dir(Some(data_dir.clone()), || {
+            for path in ["", "..", "../a", "a/../b", "a/..", "/etc/passwd", "/"] {
+                let error = sandboxed_path(path).unwrap_err();
+                assert_eq!(error.kind(), io::ErrorKind::PermissionDenied, "{path:?}");
+            }
+        });
+        assert!(!data_dir.exists());
+    }
+
+    #[test]
+    fn sandboxed_path_requires_file_access() {
+        with_data_dir(None, || {
+            let error = sandboxed_path("a").unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn host_allowed_matches_exact_hosts() {
+        let allowlist = ["example.com".to_owned()];
+        assert!(host_allowed("example.com", &allowlist));
+        assert!(host_allowed("EXAMPLE.com", &allowlist));
+        assert!(!host_allowed("api.example.com", &allowlist));
+        assert!(!host_allowed("example.org", &allowlist));
+        assert!(!host_allowed("", &allowlist));
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn host_allowed_matches_subdomains_of_wildcards() {
+        let allowlist = ["*.Example.com".to_owned()];
+        assert!(host_allowed("api.example.com", &allowlist));
+        assert!(host_allowed("a.b.example.com", &allowlist));
+        assert!(host_allowed("API.EXAMPLE.COM", &allowlist));
+        assert!(!host_allowed("example.com", &allowlist));
+        assert!(!host_allowed("badexample.com", &allowlist));
+        assert!(!host_allowed("api.example.com.evil.org", &allowlist));
+        assert!(!host_allowed("api.example.org", &allowlist));
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn host_allowed_denies_everything_without_entries() {
+        assert!(!host_allowed("example.com", &[]));
+    }
+}
@@ -0,0 +1,33 @@
+use std::env;
+use std::fs;
+
+use crate::cache;
+use crate::error::PluginError;
+use crate::plugin::{LoadOptions, Plugin};
+
+/// A precompiled plugin embedded into the host binary, see [`embedded!`](crate::embedded).
+#[derive(Clone, Copy, Debug)]
+pub struct Embedded {
+    pub manifest: &'static str,
+    pub library: &'static [u8],
+}
+
+impl Embedded {
+    /// Extracts the plugin's library into the cache directory and loads it.
+    ///
+    /// Libraries are extracted to a directory named after their content, so that extracting the
+    /// same plugin again reuses the existing files.
+    pub fn load(&self, options: &LoadOptions) -> Result<Plugin, PluginError> {
+        let key = cache::key(&[self.manifest.as_bytes(), self.library]);
+        let dir = options.cache_dir.join("embedded").join(key);
+        let manifest_path = dir.join("plugin.manifest");
+        if !manifest_path.exists() {
+            fs::create_dir_all(&dir)?;
+            let library_path = manifest_path.with_extension(env::consts::DLL_EXTENSION);
+            fs::write(library_path, self.library)?;
+            // Written last, since its presence marks the extraction as complete.
+            fs::write(&manifest_path, self.manifest)?;
+        }
+        Plugin::load_precompiled(manifest_path)
+    }
+}
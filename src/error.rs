@@ -0,0 +1,212 @@
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum PluginError {
+    Io(io::Error),
+    HeaderParse(String),
+    Compile {
+        stderr: String,
+        /// The build directory, if its generated files were kept, see
+        /// [`LoadOptions::keep_artifacts`](crate::LoadOptions::keep_artifacts).
+        artifacts: Option<PathBuf>,
+    },
+    Load(libloading::Error),
+    SymbolNotFound {
+        name: String,
+        found: Vec<String>,
+    },
+    /// The compiled code of a function returns a value of another size than its signature says,
+    /// so calling it would corrupt memory.
+    AbiMismatch {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    FunctionNotFound(String),
+    PluginNotFound(String),
+    HookNotFound(String),
+    Hook(String),
+    DuplicatePlugin(String),
+    PluginDisabled(String),
+    Unhealthy {
+        plugin: String,
+        failures: u32,
+        /// How long until the plugin is invoked again.
+        retry_in: Duration,
+    },
+    Panic {
+        plugin: String,
+        /// The exported symbol of the function that panicked.
+        entrypoint: String,
+        message: String,
+        kind: PanicKind,
+    },
+    PluginFailed(String),
+    TypeMismatch {
+        expected: String,
+        found: String,
+    },
+    ArgumentCount {
+        expected: usize,
+        found: usize,
+    },
+    Timeout(Duration),
+    QueueFull,
+    Cancelled,
+    MemoryLimit(usize),
+    WorkerFailed(String),
+    Watch(notify::Error),
+    InvalidPattern {
+        pattern: String,
+        error: glob::PatternError,
+    },
+    Config(String),
+    Pipeline(String),
+    #[cfg(feature = "bootstrap")]
+    Bootstrap(String),
+    #[cfg(feature = "wasm")]
+    Wasm(wasmtime::Error),
+}
+
+/// What made a plugin panic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicKind {
+    /// A runtime error detected by Roc, like an integer overflow.
+    Runtime,
+    /// An explicit `crash` in the plugin's code.
+    Crash,
+}
+
+impl PanicKind {
+    /// Returns the kind of panic identified by the `tag_id` passed to `roc_panic`.
+    pub(crate) fn from_tag(tag_id: u32) -> Self {
+        match tag_id {
+            1 => Self::Crash,
+            _ => Self::Runtime,
+        }
+    }
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "I/O error: {error}"),
+            Self::HeaderParse(msg) => write!(f, "invalid plugin header: {msg}"),
+            Self::Compile {
+                stderr,
+                artifacts: None,
+            } => write!(f, "roc compile failed:\n{stderr}"),
+            Self::Compile {
+                stderr,
+                artifacts: Some(dir),
+            } => write!(
+                f,
+                "roc compile failed, the generated files are kept in {}:\n{stderr}",
+                dir.display()
+            ),
+            Self::Load(error) => write!(f, "failed to load plugin library: {error}"),
+            Self::SymbolNotFound { name, found } if found.is_empty() => {
+                write!(f, "symbol not found: {name}")
+            }
+            Self::SymbolNotFound { name, found } => {
+                write!(f, "symbol not found: {name} (found: {})", found.join(", "))
+            }
+            Self::AbiMismatch {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "ABI mismatch in {function}: its signature returns {expected} bytes, but the \
+                 compiled function returns {found} bytes"
+            ),
+            Self::FunctionNotFound(name) => write!(f, "plugin function not found: {name}"),
+            Self::PluginNotFound(name) => write!(f, "plugin not found: {name}"),
+            Self::HookNotFound(name) => write!(f, "hook not found: {name}"),
+            Self::Hook(msg) => write!(f, "invalid hook: {msg}"),
+            Self::DuplicatePlugin(name) => write!(f, "a plugin named {name} is already loaded"),
+            Self::PluginDisabled(name) => write!(f, "plugin {name} is disabled"),
+            Self::Unhealthy {
+                plugin,
+                failures,
+                retry_in,
+            } => write!(
+                f,
+                "plugin {plugin} is unhealthy after {failures} consecutive failures, \
+                 retrying in {retry_in:?}"
+            ),
+            Self::Panic {
+                plugin,
+                entrypoint,
+                message,
+                kind: PanicKind::Runtime,
+            } => write!(f, "plugin {plugin} panicked in {entrypoint}: {message}"),
+            Self::Panic {
+                plugin,
+                entrypoint,
+                message,
+                kind: PanicKind::Crash,
+            } => write!(f, "plugin {plugin} crashed in {entrypoint}: {message}"),
+            Self::PluginFailed(msg) => write!(f, "plugin failed: {msg}"),
+            Self::TypeMismatch { expected, found } => {
+                write!(f, "type mismatch: expected {expected}, found {found}")
+            }
+            Self::ArgumentCount { expected, found } => {
+                write!(f, "expected {expected} arguments, found {found}")
+            }
+            Self::Timeout(timeout) => write!(f, "plugin timed out after {timeout:?}"),
+            Self::QueueFull => write!(f, "the invocation queue is full"),
+            Self::Cancelled => write!(f, "the invocation was cancelled"),
+            Self::MemoryLimit(limit) => {
+                write!(f, "plugin exceeded its memory limit of {limit} bytes")
+            }
+            Self::WorkerFailed(msg) => write!(f, "plugin worker process failed: {msg}"),
+            Self::Watch(error) => write!(f, "failed to watch plugin sources: {error}"),
+            Self::InvalidPattern { pattern, error } => {
+                write!(f, "invalid glob pattern `{pattern}`: {error}")
+            }
+            Self::Config(msg) => write!(f, "invalid configuration: {msg}"),
+            Self::Pipeline(msg) => write!(f, "invalid pipeline: {msg}"),
+            #[cfg(feature = "bootstrap")]
+            Self::Bootstrap(msg) => write!(f, "failed to set up the roc compiler: {msg}"),
+            #[cfg(feature = "wasm")]
+            Self::Wasm(error) => write!(f, "wasm error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Load(error) => Some(error),
+            Self::Watch(error) => Some(error),
+            Self::InvalidPattern { error, .. } => Some(error),
+            #[cfg(feature = "wasm")]
+            Self::Wasm(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the message of a Rust panic, whose payload is a `String` or `&str` unless it was
+/// raised with `panic_any`.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else {
+        "panicked with a payload that isn't a string".into()
+    }
+}
+
+impl From<io::Error> for PluginError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
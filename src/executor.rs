@@ -0,0 +1,178 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::cancel::CancellationToken;
+use crate::effects;
+use crate::error::PluginError;
+use crate::plugin::{Detached, Plugin};
+use crate::value::Value;
+
+/// Invokes a plugin on a fixed pool of worker threads.
+///
+/// Invocations are queued until a worker is free. The queue is bounded, so that callers are
+/// slowed down by a busy plugin instead of piling up work: [`PluginExecutor::submit`] blocks
+/// while the queue is full, and [`PluginExecutor::try_submit`] fails with
+/// [`PluginError::QueueFull`].
+///
+/// Dropping the executor waits for the queued invocations to finish.
+#[derive(Debug)]
+pub struct PluginExecutor {
+    plugin: Arc<Plugin>,
+    queue: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// A queued invocation.
+struct Job {
+    function: String,
+    args: Detached<Vec<Value>>,
+    token: CancellationToken,
+    /// The seed of the thread that queued the job, see [`with_random_seed`].
+    ///
+    /// [`with_random_seed`]: crate::with_random_seed
+    seed: Option<u64>,
+    result: mpsc::Sender<Detached<Result<Value, PluginError>>>,
+}
+
+impl PluginExecutor {
+    /// Starts `workers` threads invoking `plugin`, with room for `queue_depth` invocations
+    /// waiting for a free worker.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is zero.
+    pub fn new(plugin: Arc<Plugin>, workers: usize, queue_depth: usize) -> Self {
+        assert!(workers > 0, "executors need at least one worker");
+
+        let (queue, jobs) = mpsc::sync_channel::<Job>(queue_depth);
+        let jobs = Arc::new(Mutex::new(jobs));
+        let workers = (0..workers)
+            .map(|_| {
+                let plugin = Arc::clone(&plugin);
+                let jobs = Arc::clone(&jobs);
+                thread::spawn(move || work(&plugin, &jobs))
+            })
+            .collect();
+
+        Self {
+            plugin,
+            queue: Some(queue),
+            workers,
+        }
+    }
+
+    pub fn plugin(&self) -> &Arc<Plugin> {
+        &self.plugin
+    }
+
+    /// Queues an invocation of the plugin's function `name`, blocking while the queue is full.
+    pub fn submit(&self, name: &str, args: &[Value]) -> Result<Invocation, PluginError> {
+        let (job, invocation) = self.job(name, args)?;
+        self.queue().send(job).expect("workers outlive the queue");
+        Ok(invocation)
+    }
+
+    /// Queues an invocation of the plugin's function `name`, failing if the queue is full.
+    pub fn try_submit(&self, name: &str, args: &[Value]) -> Result<Invocation, PluginError> {
+        let (job, invocation) = self.job(name, args)?;
+        match self.queue().try_send(job) {
+            Ok(()) => Ok(invocation),
+            Err(TrySendError::Full(_)) => Err(PluginError::QueueFull),
+            Err(TrySendError::Disconnected(_)) => unreachable!("workers outlive the queue"),
+        }
+    }
+
+    fn job(&self, name: &str, args: &[Value]) -> Result<(Job, Invocation), PluginError> {
+        // Check the function here, so that callers learn about typos without waiting in line.
+        if !self.plugin.functions().any(|f| f == name) {
+            return Err(PluginError::FunctionNotFound(name.into()));
+        }
+
+        let (result, receiver) = mpsc::channel();
+        let token = CancellationToken::new();
+        let job = Job {
+            function: name.into(),
+            args: Detached(args.iter().map(Value::detach).collect()),
+            token: token.clone(),
+            seed: effects::seed(),
+            result,
+        };
+        let invocation = Invocation {
+            result: receiver,
+            token,
+        };
+        Ok((job, invocation))
+    }
+
+    fn queue(&self) -> &SyncSender<Job> {
+        self.queue
+            .as_ref()
+            .expect("the queue is only closed on drop")
+    }
+}
+
+impl Drop for PluginExecutor {
+    fn drop(&mut self) {
+        // Closing the queue makes the workers exit once it is drained.
+        self.queue = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(plugin: &Plugin, jobs: &Mutex<Receiver<Job>>) {
+    loop {
+        // Only hold the lock while waiting, so that other workers can take the next job.
+        let Ok(job) = jobs.lock().unwrap().recv() else {
+            return;
+        };
+        let Job {
+            function,
+            args,
+            token,
+            seed,
+            result: sender,
+        } = job;
+        let result = effects::with_seed(seed, || {
+            plugin.invoke_function_cancellable(&function, &args.0, &token)
+        });
+        // The result may share buffers with the arguments, see `Detached`.
+        drop(args);
+        // The caller may have stopped waiting for the result.
+        let _ = sender.send(Detached(result));
+    }
+}
+
+/// An invocation queued on a [`PluginExecutor`].
+#[derive(Debug)]
+pub struct Invocation {
+    result: Receiver<Detached<Result<Value, PluginError>>>,
+    token: CancellationToken,
+}
+
+impl Invocation {
+    /// Cancels the invocation, see [`CancellationToken`].
+    ///
+    /// Queued invocations are skipped by the workers, and fail with
+    /// [`PluginError::Cancelled`].
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Returns the token that cancels the invocation, e.g. to cancel it from another thread.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Waits for the invocation to finish, returning its result.
+    pub fn wait(self) -> Result<Value, PluginError> {
+        match self.result.recv() {
+            Ok(Detached(result)) => result,
+            Err(_) => Err(PluginError::WorkerFailed(
+                "the worker stopped without a result".into(),
+            )),
+        }
+    }
+}
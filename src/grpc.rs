@@ -0,0 +1,170 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use crate::error::PluginError;
+use crate::manager::PluginManager;
+use crate::plugin::Plugin;
+use crate::value::Value;
+
+/// The messages and service generated from `proto/roc_plugin.proto`.
+#[allow(clippy::all)]
+mod proto {
+    tonic::include_proto!("roc_plugin.v1");
+}
+
+use proto::plugin_host_server::{PluginHost, PluginHostServer};
+
+/// The `PluginHost` service of a manager's plugins, see [`PluginManager::serve_grpc`].
+struct Service {
+    plugins: Vec<Arc<Plugin>>,
+}
+
+impl PluginManager {
+    /// Serves the manager's plugins over gRPC at `addr`, with the `PluginHost` service defined
+    /// in `proto/roc_plugin.proto`, until serving fails.
+    ///
+    /// The RPCs are:
+    ///
+    /// - `Invoke`, which invokes the plugin's first function, or the one named by `function`,
+    ///   with the arguments in `args_json`, and responds with the result as JSON
+    /// - `ListPlugins`, which responds with the plugins and their functions
+    /// - `Reload`, which reloads the plugin, or all plugins if `plugin` is empty, see
+    ///   [`Plugin::reload`]
+    ///
+    /// The arguments are a JSON array, or a JSON object for functions taking a single record,
+    /// see [`Value::to_json`] for how values are represented. This must be called from within a
+    /// tokio runtime, and invocations and reloads run on its blocking thread pool.
+    pub async fn serve_grpc(&self, addr: SocketAddr) -> Result<(), PluginError> {
+        let service = Service {
+            plugins: self.plugins().to_vec(),
+        };
+        tonic::transport::Server::builder()
+            .add_service(PluginHostServer::new(service))
+            .serve(addr)
+            .await
+            .map_err(|error| PluginError::Io(io::Error::other(error)))
+    }
+}
+
+impl Service {
+    fn get(&self, name: &str) -> Result<&Arc<Plugin>, Status> {
+        self.plugins
+            .iter()
+            .find(|p| p.name() == name)
+            .ok_or_else(|| status(PluginError::PluginNotFound(name.into())))
+    }
+}
+
+#[tonic::async_trait]
+impl PluginHost for Service {
+    async fn invoke(
+        &self,
+        request: Request<proto::InvokeRequest>,
+    ) -> Result<Response<proto::InvokeResponse>, Status> {
+        let request = request.into_inner();
+        let plugin = self.get(&request.plugin)?;
+        let function = match request.function.as_str() {
+            "" => plugin.meta().name.clone(),
+            function => function.to_owned(),
+        };
+
+        // The arguments aren't `Send`, so they must be gone before the invocation is awaited.
+        let invocation = {
+            let meta = plugin.function(&function).map_err(status)?;
+            let json = match request.args_json.trim() {
+                "" => serde_json::Value::Array(Vec::new()),
+                json => serde_json::from_str(json).map_err(|error| {
+                    Status::invalid_argument(format!("malformed arguments: {error}"))
+                })?,
+            };
+            let args =
+                Value::args_from_json(&json, &meta.arg_types).map_err(Status::invalid_argument)?;
+            plugin.invoke_function_async(&function, &args)
+        };
+        let value = invocation.await.map_err(status)?;
+        Ok(Response::new(proto::InvokeResponse {
+            value_json: value.to_json().to_string(),
+        }))
+    }
+
+    async fn list_plugins(
+        &self,
+        _request: Request<proto::ListPluginsRequest>,
+    ) -> Result<Response<proto::ListPluginsResponse>, Status> {
+        let plugins = self.plugins.iter().map(|plugin| describe(plugin)).collect();
+        Ok(Response::new(proto::ListPluginsResponse { plugins }))
+    }
+
+    async fn reload(
+        &self,
+        request: Request<proto::ReloadRequest>,
+    ) -> Result<Response<proto::ReloadResponse>, Status> {
+        let name = request.into_inner().plugin;
+        let plugins = match name.as_str() {
+            "" => self.plugins.clone(),
+            name => vec![Arc::clone(self.get(name)?)],
+        };
+
+        // Reloading compiles the plugins, which blocks.
+        let failures = tokio::task::spawn_blocking({
+            let plugins = plugins.clone();
+            move || {
+                plugins
+                    .iter()
+                    .filter_map(|plugin| {
+                        let error = plugin.reload().err()?;
+                        Some(format!("{}: {error}", plugin.name()))
+                    })
+                    .collect::<Vec<_>>()
+            }
+        })
+        .await
+        .map_err(|error| Status::internal(error.to_string()))?;
+        if !failures.is_empty() {
+            return Err(Status::internal(failures.join("\n")));
+        }
+        let reloaded = plugins.iter().map(|p| p.name().to_owned()).collect();
+        Ok(Response::new(proto::ReloadResponse { reloaded }))
+    }
+}
+
+/// Describes `plugin` in the response to `ListPlugins`.
+fn describe(plugin: &Plugin) -> proto::PluginInfo {
+    let functions = plugin
+        .functions()
+        .filter_map(|name| plugin.function(name).ok())
+        .map(|meta| proto::FunctionInfo {
+            name: meta.name.clone(),
+            arguments: meta.arg_types.iter().map(ToString::to_string).collect(),
+            returns: meta.return_type.to_string(),
+            description: meta.description.clone().unwrap_or_default(),
+        })
+        .collect();
+    proto::PluginInfo {
+        name: plugin.name().into(),
+        path: plugin.path().display().to_string(),
+        disabled: plugin.is_disabled(),
+        stateful: plugin.is_stateful(),
+        functions,
+    }
+}
+
+/// Returns the status of a request that failed with `error`.
+fn status(error: PluginError) -> Status {
+    let msg = error.to_string();
+    match error {
+        PluginError::PluginNotFound(_) | PluginError::FunctionNotFound(_) => Status::not_found(msg),
+        PluginError::TypeMismatch { .. } | PluginError::ArgumentCount { .. } => {
+            Status::invalid_argument(msg)
+        }
+        PluginError::PluginDisabled(_) => Status::failed_precondition(msg),
+        PluginError::Unhealthy { .. } => Status::unavailable(msg),
+        PluginError::Timeout(_) => Status::deadline_exceeded(msg),
+        PluginError::QueueFull | PluginError::MemoryLimit(_) => Status::resource_exhausted(msg),
+        PluginError::Cancelled => Status::cancelled(msg),
+        _ => Status::internal(msg),
+    }
+}
@@ -0,0 +1,354 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::fd::FromRawFd;
+use std::time::{Duration, Instant};
+
+use crate::bytes::Bytes;
+use crate::cancel::CancellationToken;
+use crate::dec::Dec;
+use crate::error::{PanicKind, PluginError};
+use crate::value::Value;
+
+/// Runs `f` in a forked child process and returns its result, which is sent back over a pipe.
+///
+/// Arguments don't need to be serialized, since the child inherits a copy of the host's memory.
+/// If the child crashes, e.g. because the plugin segfaults or runs out of memory, the host
+/// survives and gets a [`PluginError::WorkerFailed`]. Cancelling `token`, or running for longer
+/// than `timeout`, kills the child.
+///
+/// The child only gets a copy of the forking thread. Locks held by other threads, like the one
+/// of the allocator, would stay locked forever in the child, which deadlocks as soon as it takes
+/// one. So this fails unless the host runs a single thread, which is checked right before
+/// forking. This is also why timeouts are enforced here, rather than by a worker thread.
+pub(crate) fn run<F>(
+    f: F,
+    timeout: Option<Duration>,
+    token: &CancellationToken,
+) -> Result<Value, PluginError>
+where
+    F: FnOnce() -> Result<Value, PluginError>,
+{
+    match thread_count() {
+        Some(1) => {}
+        Some(threads) => {
+            return Err(PluginError::WorkerFailed(format!(
+                "isolated invocations fork the host, which is only safe while it runs a single \
+                 thread, but {threads} threads are running"
+            )))
+        }
+        None => {
+            return Err(PluginError::WorkerFailed(
+                "isolated invocations fork the host, which is only safe while it runs a single \
+                 thread, but the threads of this process can't be counted"
+                    .into(),
+            ))
+        }
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let [read_fd, write_fd] = fds;
+
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        let error = io::Error::last_os_error();
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+        return Err(error.into());
+    }
+
+    if pid == 0 {
+        unsafe { libc::close(read_fd) };
+        let mut buf = Vec::new();
+        encode_result(&f(), &mut buf);
+        let mut pipe = unsafe { File::from_raw_fd(write_fd) };
+        let status = if pipe.write_all(&buf).is_ok() { 0 } else { 1 };
+        // Skip destructors and exit handlers, which belong to the host.
+        unsafe { libc::_exit(status) };
+    }
+
+    unsafe { libc::close(write_fd) };
+    let on_cancel = token.on_cancel(move || unsafe {
+        libc::kill(pid, libc::SIGKILL);
+    });
+    let mut buf = Vec::new();
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let read = read_until(read_fd, deadline, &mut buf);
+    let timed_out = matches!(read, Ok(false));
+    if timed_out {
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+    }
+    // Stop killing the child before reaping it, after which its pid may be reused.
+    drop(on_cancel);
+
+    let mut status = 0;
+    if unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    if let (true, Some(timeout)) = (timed_out, timeout) {
+        return Err(PluginError::Timeout(timeout));
+    }
+    if libc::WIFSIGNALED(status) && token.is_cancelled() {
+        return Err(PluginError::Cancelled);
+    }
+    if libc::WIFSIGNALED(status) {
+        let msg = format!("killed by signal {}", libc::WTERMSIG(status));
+        return Err(PluginError::WorkerFailed(msg));
+    }
+    if !libc::WIFEXITED(status) || libc::WEXITSTATUS(status) != 0 {
+        return Err(PluginError::WorkerFailed("exited unsuccessfully".into()));
+    }
+    read?;
+
+    decode_result(&mut &buf[..])
+        .unwrap_or_else(|| Err(PluginError::WorkerFailed("malformed result".into())))
+}
+
+/// Returns how many threads this process runs, if that can be determined, which it only can on
+/// platforms with a `/proc` filesystem.
+fn thread_count() -> Option<usize> {
+    Some(fs::read_dir("/proc/self/task").ok()?.count())
+}
+
+/// Reads from `fd` into `buf` until the writer closes it, returning whether it did so before
+/// `deadline`.
+fn read_until(fd: i32, deadline: Option<Instant>, buf: &mut Vec<u8>) -> io::Result<bool> {
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut chunk = [0; 64 * 1024];
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // Round up, so that the deadline has passed once `poll` times out.
+            let ms = remaining
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(i32::MAX as u128) as i32;
+            match unsafe { libc::poll(&mut pollfd, 1, ms) } {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+                -1 => return Err(io::Error::last_os_error()),
+                0 => continue,
+                _ => {}
+            }
+        }
+        match file.read(&mut chunk) {
+            Ok(0) => return Ok(true),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn encode_result(result: &Result<Value, PluginError>, buf: &mut Vec<u8>) {
+    match result {
+        Ok(value) => {
+            buf.push(0);
+            encode(value, buf);
+        }
+        Err(PluginError::PluginFailed(msg)) => {
+            buf.push(1);
+            encode_bytes(msg.as_bytes(), buf);
+        }
+        Err(PluginError::Panic {
+            plugin,
+            entrypoint,
+            message,
+            kind,
+        }) => {
+            buf.push(2);
+            encode_bytes(plugin.as_bytes(), buf);
+            encode_bytes(entrypoint.as_bytes(), buf);
+            encode_bytes(message.as_bytes(), buf);
+            buf.push(match kind {
+                PanicKind::Runtime => 0,
+                PanicKind::Crash => 1,
+            });
+        }
+        // Other errors can't be reconstructed in the host, so only their message is kept.
+        Err(error) => {
+            buf.push(3);
+            encode_bytes(error.to_string().as_bytes(), buf);
+        }
+    }
+}
+
+fn decode_result(buf: &mut &[u8]) -> Option<Result<Value, PluginError>> {
+    let result = match take::<1>(buf)?[0] {
+        0 => Ok(decode(buf)?),
+        1 => Err(PluginError::PluginFailed(decode_string(buf)?)),
+        2 => Err(PluginError::Panic {
+            plugin: decode_string(buf)?,
+            entrypoint: decode_string(buf)?,
+            message: decode_string(buf)?,
+            kind: PanicKind::from_tag(u32::from(take::<1>(buf)?[0])),
+        }),
+        3 => Err(PluginError::WorkerFailed(decode_string(buf)?)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Encodes a value along with its type, so that it can be decoded without a `DType`.
+fn encode(value: &Value, buf: &mut Vec<u8>) {
+    match value {
+        Value::Unit => buf.push(0),
+        Value::Bool(b) => buf.extend([1, u8::from(*b)]),
+        Value::Str(s) => {
+            buf.push(2);
+            encode_bytes(s.as_bytes(), buf);
+        }
+        Value::U8(n) => buf.extend([3, *n]),
+        Value::U64(n) => {
+            buf.push(4);
+            buf.extend(n.to_le_bytes());
+        }
+        Value::I8(n) => buf.extend([5, *n as u8]),
+        Value::I16(n) => {
+            buf.push(6);
+            buf.extend(n.to_le_bytes());
+        }
+        Value::I32(n) => {
+            buf.push(7);
+            buf.extend(n.to_le_bytes());
+        }
+        Value::I64(n) => {
+            buf.push(8);
+            buf.extend(n.to_le_bytes());
+        }
+        Value::F32(n) => {
+            buf.push(9);
+            buf.extend(n.to_le_bytes());
+        }
+        Value::F64(n) => {
+            buf.push(10);
+            buf.extend(n.to_le_bytes());
+        }
+        Value::Dec(d) => {
+            buf.push(11);
+            buf.extend(d.to_raw().to_le_bytes());
+        }
+        Value::Bytes(bytes) => {
+            buf.push(12);
+            encode_bytes(bytes, buf);
+        }
+        Value::List(items) => {
+            buf.push(13);
+            encode_len(items.len(), buf);
+            items.iter().for_each(|item| encode(item, buf));
+        }
+        Value::Record(fields) => {
+            buf.push(14);
+            encode_len(fields.len(), buf);
+            for (name, value) in fields {
+                encode_bytes(name.as_bytes(), buf);
+                encode(value, buf);
+            }
+        }
+        Value::Tuple(items) => {
+            buf.push(15);
+            encode_len(items.len(), buf);
+            items.iter().for_each(|item| encode(item, buf));
+        }
+        Value::Dict(entries) => {
+            buf.push(16);
+            encode_len(entries.len(), buf);
+            for (key, value) in entries {
+                encode(key, buf);
+                encode(value, buf);
+            }
+        }
+        Value::Option(None) => buf.push(17),
+        Value::Option(Some(value)) => {
+            buf.push(18);
+            encode(value, buf);
+        }
+    }
+}
+
+fn decode(buf: &mut &[u8]) -> Option<Value> {
+    let value = match take::<1>(buf)?[0] {
+        0 => Value::Unit,
+        1 => Value::Bool(take::<1>(buf)?[0] != 0),
+        2 => Value::Str(decode_string(buf)?),
+        3 => Value::U8(take::<1>(buf)?[0]),
+        4 => Value::U64(u64::from_le_bytes(take(buf)?)),
+        5 => Value::I8(take::<1>(buf)?[0] as i8),
+        6 => Value::I16(i16::from_le_bytes(take(buf)?)),
+        7 => Value::I32(i32::from_le_bytes(take(buf)?)),
+        8 => Value::I64(i64::from_le_bytes(take(buf)?)),
+        9 => Value::F32(f32::from_le_bytes(take(buf)?)),
+        10 => Value::F64(f64::from_le_bytes(take(buf)?)),
+        11 => Value::Dec(Dec::from_raw(i128::from_le_bytes(take(buf)?))),
+        12 => Value::Bytes(Bytes::from(decode_bytes(buf)?)),
+        13 => Value::List(decode_items(buf)?),
+        14 => {
+            let len = decode_len(buf)?;
+            let fields = (0..len)
+                .map(|_| Some((decode_string(buf)?, decode(buf)?)))
+                .collect::<Option<_>>()?;
+            Value::Record(fields)
+        }
+        15 => Value::Tuple(decode_items(buf)?),
+        16 => {
+            let len = decode_len(buf)?;
+            let entries = (0..len)
+                .map(|_| Some((decode(buf)?, decode(buf)?)))
+                .collect::<Option<_>>()?;
+            Value::Dict(entries)
+        }
+        17 => Value::Option(None),
+        18 => Value::Option(Some(Box::new(decode(buf)?))),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn encode_len(len: usize, buf: &mut Vec<u8>) {
+    buf.extend((len as u64).to_le_bytes());
+}
+
+fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    encode_len(bytes.len(), buf);
+    buf.extend_from_slice(bytes);
+}
+
+fn take<const N: usize>(buf: &mut &[u8]) -> Option<[u8; N]> {
+    let (head, rest) = buf.split_first_chunk()?;
+    *buf = rest;
+    Some(*head)
+}
+
+fn decode_len(buf: &mut &[u8]) -> Option<usize> {
+    usize::try_from(u64::from_le_bytes(take(buf)?)).ok()
+}
+
+fn decode_bytes<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = decode_len(buf)?;
+    if len > buf.len() {
+        return None;
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(bytes)
+}
+
+fn decode_string(buf: &mut &[u8]) -> Option<String> {
+    String::from_utf8(decode_bytes(buf)?.to_vec()).ok()
+}
+
+fn decode_items(buf: &mut &[u8]) -> Option<Vec<Value>> {
+    let len = decode_len(buf)?;
+    (0..len).map(|_| decode(buf)).collect()
+}
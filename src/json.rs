@@ -0,0 +1,171 @@
+use serde_json::{Map, Number};
+
+use crate::error::PluginError;
+use crate::value::{DType, Value};
+
+impl Value {
+    /// Converts the value to JSON.
+    ///
+    /// Records and dicts become objects, tuples and lists arrays, and `None` becomes `null`.
+    /// `Dec`s become strings, so that they keep their precision, and floats that aren't finite
+    /// become `null`. `{}` becomes an empty object.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value as Json;
+
+        match self {
+            Value::Unit => Json::Object(Map::new()),
+            Value::Bool(b) => Json::Bool(*b),
+            Value::Str(s) => Json::String(s.clone()),
+            Value::U8(n) => Json::from(*n),
+            Value::U64(n) => Json::from(*n),
+            Value::I8(n) => Json::from(*n),
+            Value::I16(n) => Json::from(*n),
+            Value::I32(n) => Json::from(*n),
+            Value::I64(n) => Json::from(*n),
+            Value::F32(x) => Number::from_f64(f64::from(*x)).map_or(Json::Null, Json::Number),
+            Value::F64(x) => Number::from_f64(*x).map_or(Json::Null, Json::Number),
+            Value::Dec(d) => Json::String(d.to_string()),
+            Value::Bytes(bytes) => Json::Array(bytes.iter().map(|&b| Json::from(b)).collect()),
+            Value::List(items) | Value::Tuple(items) => {
+                Json::Array(items.iter().map(Value::to_json).collect())
+            }
+            Value::Record(fields) => Json::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_json()))
+                    .collect(),
+            ),
+            Value::Dict(entries) => Json::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            Value::Str(s) => s.clone(),
+                            key => key.to_string(),
+                        };
+                        (key, value.to_json())
+                    })
+                    .collect(),
+            ),
+            Value::Option(value) => value.as_ref().map_or(Json::Null, |v| v.to_json()),
+        }
+    }
+
+    /// Converts the arguments of a function taking `types` from JSON, which are given as an
+    /// array, or as an object for functions taking a single record.
+    pub(crate) fn args_from_json(
+        json: &serde_json::Value,
+        types: &[DType],
+    ) -> Result<Vec<Self>, String> {
+        let args = match json {
+            serde_json::Value::Array(args) => args,
+            serde_json::Value::Object(_) if types.len() == 1 => {
+                return Ok(vec![Value::from_json(json, &types[0])?]);
+            }
+            json => {
+                return Err(format!(
+                    "expected a JSON array of arguments, found `{json}`"
+                ))
+            }
+        };
+        if args.len() != types.len() {
+            let error = PluginError::ArgumentCount {
+                expected: types.len(),
+                found: args.len(),
+            };
+            return Err(error.to_string());
+        }
+        args.iter()
+            .zip(types)
+            .enumerate()
+            .map(|(i, (arg, dtype))| {
+                Value::from_json(arg, dtype)
+                    .map_err(|error| format!("invalid argument {}: {error}", i + 1))
+            })
+            .collect()
+    }
+
+    /// Converts JSON to a value of type `dtype`, the inverse of [`Value::to_json`].
+    ///
+    /// `Dec`s may also be given as numbers.
+    pub fn from_json(json: &serde_json::Value, dtype: &DType) -> Result<Self, String> {
+        use serde_json::Value as Json;
+
+        let mismatch = || format!("expected a value of type {dtype}, found `{json}`");
+        let int = |json: &Json| json.as_i64().ok_or_else(mismatch);
+        let uint = |json: &Json| json.as_u64().ok_or_else(mismatch);
+        let value = match (dtype, json) {
+            (DType::Unit, Json::Null) => Value::Unit,
+            (DType::Unit, Json::Object(fields)) if fields.is_empty() => Value::Unit,
+            (DType::Bool, Json::Bool(b)) => Value::Bool(*b),
+            (DType::Str, Json::String(s)) => Value::Str(s.clone()),
+            (DType::U8, json) => Value::U8(uint(json)?.try_into().map_err(|_| mismatch())?),
+            (DType::U64, json) => Value::U64(uint(json)?),
+            (DType::I8, json) => Value::I8(int(json)?.try_into().map_err(|_| mismatch())?),
+            (DType::I16, json) => Value::I16(int(json)?.try_into().map_err(|_| mismatch())?),
+            (DType::I32, json) => Value::I32(int(json)?.try_into().map_err(|_| mismatch())?),
+            (DType::I64, json) => Value::I64(int(json)?),
+            (DType::F32, Json::Number(n)) => Value::F32(n.as_f64().ok_or_else(mismatch)? as f32),
+            (DType::F64, Json::Number(n)) => Value::F64(n.as_f64().ok_or_else(mismatch)?),
+            (DType::Dec, Json::String(s)) => Value::Dec(s.parse().map_err(|_| mismatch())?),
+            (DType::Dec, Json::Number(n)) => {
+                Value::Dec(n.to_string().parse().map_err(|_| mismatch())?)
+            }
+            (DType::Bytes, Json::Array(items)) => {
+                let bytes = items
+                    .iter()
+                    .map(|item| uint(item)?.try_into().map_err(|_| mismatch()))
+                    .collect::<Result<Vec<u8>, _>>()?;
+                Value::Bytes(bytes.into())
+            }
+            (DType::List(elem), Json::Array(items)) => Value::List(
+                items
+                    .iter()
+                    .map(|item| Value::from_json(item, elem))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (DType::Tuple(elems), Json::Array(items)) if elems.len() == items.len() => {
+                Value::Tuple(
+                    items
+                        .iter()
+                        .zip(elems)
+                        .map(|(item, elem)| Value::from_json(item, elem))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (DType::Record(fields), Json::Object(object)) => {
+                if let Some(name) = object.keys().find(|k| !fields.iter().any(|(n, _)| n == *k)) {
+                    return Err(format!("unknown record field `{name}`"));
+                }
+                let values = fields
+                    .iter()
+                    .map(|(name, dtype)| {
+                        let json = object
+                            .get(name)
+                            .ok_or_else(|| format!("missing record field `{name}`"))?;
+                        Ok((name.clone(), Value::from_json(json, dtype)?))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Value::Record(values)
+            }
+            (DType::Dict(key, value), Json::Object(object)) => Value::Dict(
+                object
+                    .iter()
+                    .map(|(k, v)| {
+                        let k = Value::from_json(&Json::String(k.clone()), key)?;
+                        Ok((k, Value::from_json(v, value)?))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            (DType::Option(_), Json::Null) => Value::Option(None),
+            (DType::Option(inner), json) => Some(Value::from_json(json, inner)?).into(),
+            (DType::Result(..) | DType::Task(..), _) => {
+                return Err(format!(
+                    "values of type {dtype} can't be converted from JSON"
+                ));
+            }
+            _ => return Err(mismatch()),
+        };
+        Ok(value)
+    }
+}
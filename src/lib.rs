@@ -0,0 +1,206 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+pub use crate::alloc::Allocator;
+pub use crate::audit::{with_caller, AuditLog};
+pub use crate::bytes::Bytes;
+pub use crate::cancel::CancellationToken;
+pub use crate::clock::{Clock, FakeClock};
+pub use crate::config::{Config, PluginOverrides, ToolchainPin, CONFIG_FILE};
+pub use crate::convert::{FromRocReturn, IntoRocArg, IntoRocArgs};
+pub use crate::dec::{Dec, ParseDecError};
+pub use crate::doctor::{doctor, Check};
+pub use crate::effects::{host_data, invocation_context, with_random_seed, HostApis, HostEffect};
+pub use crate::embed::Embedded;
+pub use crate::error::{PanicKind, PluginError};
+pub use crate::executor::{Invocation, PluginExecutor};
+pub use crate::logging::{log, set_log_level, LogLevel};
+pub use crate::manager::{
+    EventMetrics, HookResult, LoadReport, PluginManager, PublishReport, Watcher,
+};
+pub use crate::metrics::{Metrics, PrometheusMetrics};
+pub use crate::pipeline::Pipeline;
+pub use crate::plugin::{
+    precompile, Backend, LoadOptions, Meta, Plugin, Profile, PLATFORM_TEMPLATE,
+};
+pub use crate::report::InvocationReport;
+pub use crate::roc_host::{init, AllocReport, Dbg, DbgSink};
+pub use crate::schedule::Scheduler;
+pub use crate::value::{DType, Value};
+#[cfg(feature = "wasm")]
+pub use crate::wasm::WasmLimits;
+#[cfg(feature = "derive")]
+pub use roc_plugin_derive::{host_api, RocValue};
+
+mod alloc;
+mod audit;
+#[cfg(feature = "bootstrap")]
+mod bootstrap;
+mod breaker;
+mod bytes;
+mod cache;
+mod cancel;
+#[cfg(feature = "capi")]
+mod capi;
+mod clock;
+mod config;
+mod convert;
+mod dec;
+mod diagnostics;
+mod doctor;
+mod effects;
+mod embed;
+mod error;
+mod executor;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(unix)]
+mod isolate;
+mod json;
+mod literal;
+mod logging;
+mod manager;
+mod memo;
+mod metrics;
+mod pipeline;
+mod plugin;
+#[cfg(feature = "python")]
+mod python;
+mod report;
+mod roc_host;
+mod schedule;
+#[cfg(feature = "server")]
+mod server;
+mod sidecar;
+#[cfg(unix)]
+mod socket;
+mod spans;
+mod store;
+mod toolchain;
+mod type_expr;
+mod value;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+/// Items used by the code generated by `roc-plugin-derive`.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::effects::with_host_api;
+    pub use roc_std::{RocResult, RocStr};
+}
+
+/// Expands to the plugins embedded by `roc_plugin_build::embed`, as a `&'static [Embedded]`.
+///
+/// This must be used in the crate whose build script called `embed`.
+#[macro_export]
+macro_rules! embedded {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/roc_plugins.rs"))
+    };
+}
+
+/// Returns the paths of all plugin files, i.e. `.roc` files, in `dir` and its subdirectories.
+///
+/// Helper modules, which are named like Roc modules, aren't plugins, see [`discover_matching`].
+pub fn discover<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, PluginError> {
+    discover_matching(dir, &[], &[])
+}
+
+/// Returns the paths of the plugin files in `dir` and its subdirectories that match any of the
+/// `include` glob patterns, or all if there are none, and none of the `exclude` patterns.
+///
+/// Patterns are matched against paths relative to `dir`, and `*` doesn't match across
+/// directories, so `tools/*.roc` only matches files directly in `tools`. Hidden files and
+/// directories are skipped.
+///
+/// Files named like Roc modules, starting with an uppercase letter as in `Helpers.roc`, are
+/// skipped too. They are helper modules, which the plugins in the same directory can import.
+pub fn discover_matching<P: AsRef<Path>>(
+    dir: P,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Vec<PathBuf>, PluginError> {
+    let compile = |patterns: &[String]| {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Pattern::new(pattern).map_err(|error| PluginError::InvalidPattern {
+                    pattern: pattern.clone(),
+                    error,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let (include, exclude) = (compile(include)?, compile(exclude)?);
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let matches = |patterns: &[Pattern], path: &Path| {
+        patterns.iter().any(|p| p.matches_path_with(path, options))
+    };
+
+    let dir = dir.as_ref();
+    let mut paths = Vec::new();
+    let mut pending = vec![dir.to_owned()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(current)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if path.extension() != Some(OsStr::new("roc")) || is_module(&path) {
+                continue;
+            }
+
+            let relative = path
+                .strip_prefix(dir)
+                .expect("discovered paths are in `dir`");
+            if (include.is_empty() || matches(&include, relative)) && !matches(&exclude, relative) {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Returns whether `path` is a helper module rather than a plugin, see [`discover_matching`].
+pub(crate) fn is_module(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("roc"))
+        && path.file_name().is_some_and(|name| {
+            name.to_string_lossy()
+                .starts_with(|c: char| c.is_uppercase())
+        })
+}
+
+/// Returns the namespace of the plugin at `path` found in `dir`, which is the subdirectory it
+/// is in, like `tools` for `tools/slugify.roc`.
+pub(crate) fn namespace(dir: &Path, path: &Path) -> Option<String> {
+    let parent = path.strip_prefix(dir).ok()?.parent()?;
+    let components: Vec<_> = parent
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    (!components.is_empty()).then(|| components.join("/"))
+}
+
+/// Loads all plugins found in `dir`.
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Plugin>, PluginError> {
+    let dir = dir.as_ref();
+    discover(dir)?
+        .into_iter()
+        .map(|path| {
+            let namespace = namespace(dir, &path);
+            Plugin::load_namespaced(&path, namespace.as_deref(), &LoadOptions::default())
+        })
+        .collect()
+}
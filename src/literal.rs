@@ -0,0 +1,342 @@
+use crate::value::{DType, Value};
+
+impl Value {
+    /// Parses a value of type `dtype` written as a Roc literal, like `"hello"`, `[1, 2]` or
+    /// `{ name: "Ada", age: 36 }`.
+    ///
+    /// Optional values are written as `None` or `Some x`, and dicts like records but with quoted
+    /// keys. This accepts everything values are displayed as, strings nested in collections
+    /// included.
+    pub fn parse(s: &str, dtype: &DType) -> Result<Self, String> {
+        let mut parser = Parser::new(s);
+        let value = parser.value(dtype)?;
+        parser.finish()?;
+        Ok(value)
+    }
+
+    /// Parses the arguments of a function taking `types`, written as whitespace-separated Roc
+    /// literals like `"world" 42`.
+    pub fn parse_args(s: &str, types: &[DType]) -> Result<Vec<Self>, String> {
+        let mut parser = Parser::new(s);
+        let mut args = Vec::new();
+        for dtype in types {
+            if parser.at_end() {
+                return Err(format!(
+                    "expected {} arguments, found {}",
+                    types.len(),
+                    args.len()
+                ));
+            }
+            args.push(parser.value(dtype)?);
+        }
+        parser.finish()?;
+        Ok(args)
+    }
+
+    /// Writes the value as Roc source code, which has the value's type if annotated with it.
+    pub(crate) fn to_roc_literal(&self) -> String {
+        match self {
+            Value::Unit => "{}".into(),
+            Value::Bool(b) => format!("Bool.{b}"),
+            Value::Str(s) => {
+                let mut literal = String::from("\"");
+                for c in s.chars() {
+                    match c {
+                        '"' => literal.push_str("\\\""),
+                        '\\' => literal.push_str("\\\\"),
+                        // Escape interpolation.
+                        '$' => literal.push_str("\\$"),
+                        '\n' => literal.push_str("\\n"),
+                        '\r' => literal.push_str("\\r"),
+                        '\t' => literal.push_str("\\t"),
+                        c if c.is_control() => literal.push_str(&format!("\\u({:X})", c as u32)),
+                        c => literal.push(c),
+                    }
+                }
+                literal.push('"');
+                literal
+            }
+            Value::U8(n) => n.to_string(),
+            Value::U64(n) => n.to_string(),
+            Value::I8(n) => n.to_string(),
+            Value::I16(n) => n.to_string(),
+            Value::I32(n) => n.to_string(),
+            Value::I64(n) => n.to_string(),
+            Value::F32(x) => format!("{x:?}"),
+            Value::F64(x) => format!("{x:?}"),
+            Value::Dec(d) => d.to_string(),
+            Value::Bytes(bytes) => format!("[{}]", join(bytes.iter().map(u8::to_string))),
+            Value::List(items) => format!("[{}]", join(items.iter().map(Value::to_roc_literal))),
+            Value::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {}", value.to_roc_literal()));
+                format!("{{ {} }}", join(fields))
+            }
+            Value::Tuple(items) => format!("({})", join(items.iter().map(Value::to_roc_literal))),
+            Value::Dict(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(k, v)| format!("({}, {})", k.to_roc_literal(), v.to_roc_literal()));
+                format!("Dict.fromList [{}]", join(entries))
+            }
+            Value::Option(Some(value)) => format!("Some ({})", value.to_roc_literal()),
+            Value::Option(None) => "None".into(),
+        }
+    }
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn new(s: &'a str) -> Self {
+        Self {
+            rest: s.trim_start(),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    fn finish(&self) -> Result<(), String> {
+        match self.rest {
+            "" => Ok(()),
+            rest => Err(format!("unexpected `{rest}`")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    /// Consumes `token` if the input continues with it.
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                self.skip_whitespace();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{token}`")))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.rest {
+            "" => format!("expected {expected}, found the end of the input"),
+            rest => format!("expected {expected}, found `{rest}`"),
+        }
+    }
+
+    /// Consumes a bare token, like a number or a tag name.
+    fn word(&mut self) -> &'a str {
+        let end = self
+            .rest
+            .find(|c: char| c.is_whitespace() || ",:()[]{}\"".contains(c))
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        self.skip_whitespace();
+        word
+    }
+
+    fn value(&mut self, dtype: &DType) -> Result<Value, String> {
+        let start = self.rest;
+        let value = match dtype {
+            DType::Unit => {
+                self.expect("{")?;
+                self.expect("}")?;
+                Some(Value::Unit)
+            }
+            DType::Str => Some(Value::Str(self.string()?)),
+            DType::Bool => match self.word() {
+                "true" | "Bool.true" => Some(Value::Bool(true)),
+                "false" | "Bool.false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            DType::U8 => self.number().map(Value::U8),
+            DType::U64 => self.number().map(Value::U64),
+            DType::I8 => self.number().map(Value::I8),
+            DType::I16 => self.number().map(Value::I16),
+            DType::I32 => self.number().map(Value::I32),
+            DType::I64 => self.number().map(Value::I64),
+            DType::F32 => self.number().map(Value::F32),
+            DType::F64 => self.number().map(Value::F64),
+            DType::Dec => self.number().map(Value::Dec),
+            DType::Bytes => {
+                let bytes = self.list(&DType::U8)?.into_iter().map(|byte| match byte {
+                    Value::U8(byte) => byte,
+                    _ => unreachable!("list elements are parsed as `U8`"),
+                });
+                Some(Value::Bytes(bytes.collect::<Vec<_>>().into()))
+            }
+            DType::List(elem) => Some(Value::List(self.list(elem)?)),
+            DType::Record(fields) => Some(self.record(fields)?),
+            DType::Tuple(elems) => {
+                self.expect("(")?;
+                let mut values = Vec::new();
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        self.expect(",")?;
+                    }
+                    values.push(self.value(elem)?);
+                }
+                self.expect(")")?;
+                Some(Value::Tuple(values))
+            }
+            DType::Dict(key, value) => Some(self.dict(key, value)?),
+            DType::Option(inner) => match self.word() {
+                "None" => Some(Value::Option(None)),
+                "Some" => Some(Some(self.value(inner)?).into()),
+                _ => None,
+            },
+            DType::Result(..) | DType::Task(..) => {
+                return Err(format!(
+                    "values of type {dtype} can't be written as literals"
+                ));
+            }
+        };
+
+        value.ok_or_else(|| {
+            self.rest = start;
+            self.unexpected(&format!("a value of type {dtype}"))
+        })
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Option<T> {
+        self.word().replace('_', "").parse().ok()
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let Some(rest) = self.rest.strip_prefix('"') else {
+            return Err(self.unexpected("a string"));
+        };
+
+        let mut string = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &rest[i + 1..];
+                    self.skip_whitespace();
+                    return Ok(string);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('$') => '$',
+                        Some('\'') => '\'',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        // Roc writes `\u(1F600)`, Rust `\u{1F600}`.
+                        Some('u') => {
+                            let close = match chars.next().map(|(_, c)| c) {
+                                Some('(') => ')',
+                                Some('{') => '}',
+                                _ => return Err("malformed unicode escape".into()),
+                            };
+                            let hex: String = chars
+                                .by_ref()
+                                .map(|(_, c)| c)
+                                .take_while(|&c| c != close)
+                                .collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("invalid unicode escape `{hex}`"))?
+                        }
+                        Some(c) => return Err(format!("unknown escape sequence `\\{c}`")),
+                        None => break,
+                    };
+                    string.push(escaped);
+                }
+                c => string.push(c),
+            }
+        }
+        Err("unterminated string".into())
+    }
+
+    fn list(&mut self, elem: &DType) -> Result<Vec<Value>, String> {
+        self.expect("[")?;
+        let mut items = Vec::new();
+        while !self.eat("]") {
+            if !items.is_empty() {
+                self.expect(",")?;
+                // Allow a trailing comma.
+                if self.eat("]") {
+                    break;
+                }
+            }
+            items.push(self.value(elem)?);
+        }
+        Ok(items)
+    }
+
+    fn record(&mut self, fields: &[(String, DType)]) -> Result<Value, String> {
+        self.expect("{")?;
+        let mut values: Vec<(String, Value)> = Vec::new();
+        while !self.eat("}") {
+            if !values.is_empty() {
+                self.expect(",")?;
+                if self.eat("}") {
+                    break;
+                }
+            }
+
+            let name = self.word();
+            let Some((_, dtype)) = fields.iter().find(|(n, _)| n == name) else {
+                return Err(format!("unknown record field `{name}`"));
+            };
+            if values.iter().any(|(n, _)| n == name) {
+                return Err(format!("duplicate record field `{name}`"));
+            }
+            self.expect(":")?;
+            values.push((name.into(), self.value(dtype)?));
+        }
+
+        if let Some((name, _)) = fields
+            .iter()
+            .find(|(n, _)| !values.iter().any(|(v, _)| v == n))
+        {
+            return Err(format!("missing record field `{name}`"));
+        }
+        // Keep the order of the declaration, regardless of how the fields were written.
+        values.sort_by_key(|(name, _)| fields.iter().position(|(n, _)| n == name));
+        Ok(Value::Record(values))
+    }
+
+    fn dict(&mut self, key: &DType, value: &DType) -> Result<Value, String> {
+        self.expect("{")?;
+        let mut entries = Vec::new();
+        while !self.eat("}") {
+            if !entries.is_empty() {
+                self.expect(",")?;
+                if self.eat("}") {
+                    break;
+                }
+            }
+            let k = self.value(key)?;
+            self.expect(":")?;
+            entries.push((k, self.value(value)?));
+        }
+        Ok(Value::Dict(entries))
+    }
+}
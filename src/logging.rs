@@ -0,0 +1,128 @@
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How important a message about a plugin is, see [`log`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    /// Returns the level set by the `RUST_LOG` environment variable, like `debug` or
+    /// `roc_plugin=warn`, if it sets one for this crate.
+    pub fn from_env() -> Option<Self> {
+        let directives = env::var("RUST_LOG").ok()?;
+        directives.split(',').rev().find_map(|directive| {
+            let level = match directive.trim().split_once('=') {
+                Some((target, level)) if target.trim().starts_with("roc_plugin") => level,
+                Some(_) => return None,
+                None => directive,
+            };
+            level.trim().parse().ok()
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" | "trace" => Ok(Self::Debug),
+            _ => Err(format!("unknown log level `{s}`")),
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Sets the least important level of the messages that are printed, which is
+/// [`LogLevel::Info`] by default.
+///
+/// Messages emitted as `tracing` events are filtered by the subscriber instead, see [`log`].
+pub fn set_log_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+fn is_enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Writes a message about a plugin to the `tracing` target `$target`, see [`log`].
+macro_rules! plugin_log {
+    ($target:literal, $level:expr, $plugin:expr, $($arg:tt)+) => {{
+        let level: $crate::logging::LogLevel = $level;
+        let plugin: &str = $plugin;
+        #[cfg(feature = "tracing")]
+        let traced = $crate::logging::has_subscriber();
+        #[cfg(not(feature = "tracing"))]
+        let traced = false;
+        #[cfg(feature = "tracing")]
+        if traced {
+            match level {
+                $crate::logging::LogLevel::Error => {
+                    tracing::error!(target: $target, plugin, $($arg)+)
+                }
+                $crate::logging::LogLevel::Warn => {
+                    tracing::warn!(target: $target, plugin, $($arg)+)
+                }
+                $crate::logging::LogLevel::Info => {
+                    tracing::info!(target: $target, plugin, $($arg)+)
+                }
+                $crate::logging::LogLevel::Debug => {
+                    tracing::debug!(target: $target, plugin, $($arg)+)
+                }
+            }
+        }
+        if !traced {
+            $crate::logging::print(level, plugin, format_args!($($arg)+));
+        }
+    }};
+}
+
+pub(crate) use plugin_log;
+
+/// Writes a message about `plugin`, like the ones plugins write with `Host.log`.
+///
+/// Messages are printed to stderr as `[<plugin>] <level>: <message>`, if their level is
+/// enabled, see [`set_log_level`]. If the `tracing` feature is enabled and a subscriber is
+/// set, they are emitted as `tracing` events with a `plugin` field instead.
+pub fn log(level: LogLevel, plugin: &str, message: impl fmt::Display) {
+    plugin_log!("roc_plugin::log", level, plugin, "{message}");
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn has_subscriber() -> bool {
+    tracing::dispatcher::has_been_set()
+}
+
+/// Prints a message about `plugin` to stderr, if its level is enabled.
+pub(crate) fn print(level: LogLevel, plugin: &str, message: fmt::Arguments<'_>) {
+    if is_enabled(level) {
+        eprintln!("[{plugin}] {level}: {message}");
+    }
+}
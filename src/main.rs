@@ -1,24 +1,929 @@
-use std::fs;
+use std::io::{self, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
 
-use crate::plugin::Plugin;
+use clap::{Parser, Subcommand, ValueEnum};
+use roc_plugin::{
+    log, Config, DType, LoadReport, LogLevel, Plugin, PluginError, PluginManager, Profile,
+    Scheduler, Value, CONFIG_FILE,
+};
 
-mod plugin;
-mod roc_host;
+/// Compiles Roc plugins and invokes their functions.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// The configuration file to read, instead of `roc-plugins.toml` in the working directory.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// The directory plugins are loaded from, instead of the configured ones.
+    #[arg(long, global = true)]
+    plugins_dir: Option<PathBuf>,
+    /// Recompile plugins instead of reusing libraries built earlier.
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// The Roc compiler to build plugins with, instead of `roc` from `PATH`.
+    #[arg(long, global = true)]
+    roc_bin: Option<PathBuf>,
+    /// How optimized plugins are built, `dev` or `release`. `build` uses `release` unless this is
+    /// given, other commands the configured profile.
+    #[arg(long, global = true)]
+    profile: Option<Profile>,
+    /// Keep the platform and app generated for each plugin in its build directory.
+    #[arg(long, global = true)]
+    keep_artifacts: bool,
+    /// The directory plugins may read and write files in.
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Only load plugin files matching this glob pattern, relative to the plugins directory.
+    #[arg(long, global = true)]
+    include: Vec<String>,
+    /// Skip plugin files matching this glob pattern, relative to the plugins directory.
+    #[arg(long, global = true)]
+    exclude: Vec<String>,
+    /// Serve Prometheus metrics of the plugins at `/metrics` on this address, like
+    /// `127.0.0.1:9090`, while plugins are kept loaded.
+    #[cfg(feature = "server")]
+    #[arg(long, global = true)]
+    metrics_addr: Option<String>,
+    /// Only print errors about plugins, instead of all messages from the `RUST_LOG` level on.
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print debug messages about plugins too.
+    #[arg(long, short, global = true)]
+    verbose: bool,
+    /// How invocation results are printed.
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Plain text, with errors printed to stderr.
+    Text,
+    /// A JSON object per invocation, errors included.
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Invokes a plugin, or every function of all plugins with generated arguments.
+    Run {
+        /// The name of the plugin to invoke, like `tools/slugify` for plugins in subdirectories.
+        name: Option<String>,
+        /// The function to invoke, if not the plugin's first one.
+        #[arg(long, requires = "name")]
+        function: Option<String>,
+        /// An argument to pass, converted to the type declared in the plugin's header.
+        #[arg(long = "arg", requires = "name", allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Read the arguments from stdin as a JSON array, or as a JSON object for a function
+        /// taking a single record.
+        #[arg(long, requires = "name", conflicts_with = "args")]
+        stdin: bool,
+        /// Invoke plugins again whenever their source changes.
+        #[arg(long)]
+        watch: bool,
+        /// Report the memory plugins allocate, running them in this process and without
+        /// timeouts.
+        #[arg(long)]
+        trace: bool,
+    },
+    /// Lists the signatures of all plugin functions, without compiling the plugins.
+    List {
+        /// Print the signatures as JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Reads invocations like `greet "world"` from stdin and prints their results.
+    Repl {
+        /// Reload plugins whenever their source changes.
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Precompiles all plugins into a directory.
+    Build {
+        /// The directory the libraries and manifests are written to.
+        out_dir: PathBuf,
+    },
+    /// Type checks all plugins with `roc check`, without building them.
+    Check,
+    /// Checks that the Roc compiler is set up to build plugins, suggesting fixes if it isn't.
+    Doctor,
+    /// Keeps all plugins loaded and runs their scheduled functions on time.
+    Daemon,
+    /// Keeps all plugins loaded and serves requests to them over a Unix domain socket.
+    ///
+    /// Requests and responses are JSON objects preceded by their length as a 4-byte big-endian
+    /// integer, like `{"op": "invoke", "plugin": "slugify", "args": ["Hello"]}`. The `reload`
+    /// and `status` ops reload plugins and describe them.
+    #[cfg(unix)]
+    Serve {
+        /// The path of the socket.
+        #[arg(long, default_value = "roc-plugins.sock")]
+        socket: PathBuf,
+    },
+    /// Keeps all plugins loaded and serves them over HTTP.
+    ///
+    /// `POST /plugins/<name>` invokes a plugin with the JSON array of arguments in the body,
+    /// and `GET /plugins` lists the plugins and their functions.
+    #[cfg(feature = "server")]
+    Http {
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Keeps all plugins loaded and serves them over gRPC, with the `PluginHost` service
+    /// defined in `proto/roc_plugin.proto`.
+    #[cfg(feature = "grpc")]
+    Grpc {
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        addr: std::net::SocketAddr,
+    },
+    /// Runs the pipelines of plugins defined in the configuration.
+    Pipeline {
+        #[command(subcommand)]
+        command: PipelineCommand,
+    },
+    /// Removes all cached libraries.
+    Clean,
+}
+
+#[derive(Subcommand)]
+enum PipelineCommand {
+    /// Feeds an input through a pipeline and prints the result of its last stage.
+    Run {
+        /// The name of the pipeline in the configuration.
+        name: String,
+        /// The input, converted to the type the pipeline's first stage takes.
+        #[arg(allow_hyphen_values = true)]
+        input: String,
+    },
+}
 
 fn main() {
-    roc_host::init();
+    roc_plugin::init();
+
+    let cli = Cli::parse();
+    let level = if cli.quiet {
+        Some(LogLevel::Error)
+    } else if cli.verbose {
+        Some(LogLevel::Debug)
+    } else {
+        LogLevel::from_env()
+    };
+    if let Some(level) = level {
+        roc_plugin::set_log_level(level);
+    }
+    let config = config(&cli);
+
+    match cli.command {
+        Command::Run {
+            name: None,
+            watch,
+            trace,
+            ..
+        } => run_all(&config, cli.format, watch, trace),
+        Command::Run {
+            name: Some(name),
+            function,
+            args,
+            stdin,
+            watch,
+            trace,
+        } => {
+            let args = if stdin {
+                Args::Json(read_json_args())
+            } else {
+                Args::Cli(args)
+            };
+            run(&config, cli.format, &name, function, args, watch, trace);
+        }
+        Command::List { json } => {
+            let json = json || cli.format == Format::Json;
+            list(&config, json);
+        }
+        Command::Repl { watch } => repl(&config, watch),
+        Command::Build { out_dir } => build_all(&config, &out_dir),
+        Command::Check => check_all(&config),
+        Command::Doctor => doctor(&config),
+        Command::Daemon => daemon(&config),
+        #[cfg(unix)]
+        Command::Serve { socket } => serve(&config, &socket),
+        #[cfg(feature = "server")]
+        Command::Http { addr } => serve_http(&config, &addr),
+        #[cfg(feature = "grpc")]
+        Command::Grpc { addr } => serve_grpc(&config, addr),
+        Command::Pipeline {
+            command: PipelineCommand::Run { name, input },
+        } => run_pipeline(&config, cli.format, &name, &input),
+        Command::Clean => {
+            if let Err(error) = config.load_options().clean() {
+                eprintln!("failed to clean cache: {error}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// The arguments of an invocation, before they are converted to the function's argument types.
+enum Args {
+    /// Arguments given with `--arg`.
+    Cli(Vec<String>),
+    /// Arguments read from stdin.
+    Json(serde_json::Value),
+}
+
+fn read_json_args() -> serde_json::Value {
+    match serde_json::from_reader(io::stdin().lock()) {
+        Ok(json) => json,
+        Err(error) => {
+            eprintln!("failed to read arguments from stdin: {error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the configuration file, if there is one, and applies the command line options to it.
+fn config(cli: &Cli) -> Config {
+    let config = match &cli.config {
+        Some(path) => Config::load(path),
+        None if Path::new(CONFIG_FILE).exists() => Config::load(CONFIG_FILE),
+        None => Config::from_env(),
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("failed to load configuration: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(dir) = &cli.plugins_dir {
+        config.plugins_dirs = vec![dir.clone()];
+    }
+    if cli.no_cache {
+        config.cache = false;
+    }
+    if let Some(bin) = &cli.roc_bin {
+        config.roc_bin = Some(bin.clone());
+    }
+    if let Some(profile) = cli.profile {
+        config.profile = profile;
+    } else if let Command::Build { .. } = cli.command {
+        // Precompiled plugins are meant for production.
+        config.profile = Profile::Release;
+    }
+    if cli.keep_artifacts {
+        config.keep_artifacts = true;
+    }
+    if let Some(dir) = &cli.data_dir {
+        config.data_dir = Some(dir.clone());
+    }
+    if !cli.include.is_empty() {
+        config.include = cli.include.clone();
+    }
+    config.exclude.extend(cli.exclude.iter().cloned());
+    #[cfg(feature = "bootstrap")]
+    if let Err(error) = config.bootstrap() {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+    #[cfg(feature = "server")]
+    if let Some(addr) = &cli.metrics_addr {
+        config.metrics = Some(serve_metrics(addr.clone()));
+    }
+    config
+}
+
+/// Serves the metrics of the plugins at `addr` on a background thread.
+#[cfg(feature = "server")]
+fn serve_metrics(addr: String) -> std::sync::Arc<roc_plugin::PrometheusMetrics> {
+    let metrics = std::sync::Arc::new(roc_plugin::PrometheusMetrics::new());
+    eprintln!("serving metrics on http://{addr}/metrics");
+    std::thread::spawn({
+        let metrics = std::sync::Arc::clone(&metrics);
+        move || {
+            if let Err(error) = metrics.serve_http(&addr) {
+                eprintln!("failed to serve metrics: {error}");
+                std::process::exit(1);
+            }
+        }
+    });
+    metrics
+}
+
+/// Loads all configured plugins, continuing past the ones that fail to load.
+fn load(config: &Config) -> (PluginManager, LoadReport) {
+    match PluginManager::from_config(config) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("failed to create plugin manager: {error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Loads all configured plugins, reporting the ones that fail to load right away.
+fn load_reporting(config: &Config) -> PluginManager {
+    let (manager, report) = load(config);
+    for (path, error) in &report.failures {
+        // Plugins that fail to load are named after their file.
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let path = path.display();
+        log(
+            LogLevel::Error,
+            &name,
+            format_args!("failed to load from {path}: {error}"),
+        );
+    }
+    manager
+}
+
+/// Prints the summary of a load with failures, and exits with an error if there were any.
+fn finish(report: &LoadReport) {
+    if !report.is_ok() {
+        eprintln!("\n{report}");
+        std::process::exit(1);
+    }
+}
+
+fn run_all(config: &Config, format: Format, watch: bool, trace: bool) {
+    let (manager, report) = load(config);
+    if format == Format::Text {
+        println!();
+    }
+
+    for plugin in manager.plugins_by_priority() {
+        let path = plugin.path().display();
+        log(
+            LogLevel::Info,
+            plugin.name(),
+            format_args!("loaded from {path}"),
+        );
+        invoke_all(format, plugin, trace);
+    }
+
+    if watch {
+        if !report.is_ok() {
+            eprintln!("{report}");
+        }
+        watch_plugins(&manager, move |plugin| invoke_all(format, plugin, trace));
+    }
+    finish(&report);
+}
+
+/// Invokes `function` of the plugin called `name`, or its first function, with `args`, exiting
+/// with an error if the invocation fails.
+fn run(
+    config: &Config,
+    format: Format,
+    name: &str,
+    function: Option<String>,
+    args: Args,
+    watch: bool,
+    trace: bool,
+) {
+    // Only the invoked plugin needs to be compiled.
+    let config = Config {
+        lazy: true,
+        ..config.clone()
+    };
+    let manager = load_reporting(&config);
+    let Some(plugin) = manager.get(name) else {
+        log(
+            LogLevel::Error,
+            name,
+            PluginError::PluginNotFound(name.into()),
+        );
+        std::process::exit(1);
+    };
+
+    let function = function.unwrap_or_else(|| plugin.meta().name.clone());
+    let succeeded = invoke(format, plugin, &function, &args, trace);
+    if watch {
+        let name = name.to_owned();
+        watch_plugins(&manager, move |plugin| {
+            if plugin.name() == name {
+                invoke(format, plugin, &function, &args, trace);
+            }
+        });
+    }
+    if !succeeded {
+        std::process::exit(1);
+    }
+}
+
+/// Runs the scheduled functions of all plugins until the process is killed, logging each run.
+fn daemon(config: &Config) {
+    let manager = load_reporting(config);
+    let _scheduler = Scheduler::start(&manager);
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Serves requests to all plugins over the socket at `path` until the process is killed.
+#[cfg(unix)]
+fn serve(config: &Config, path: &Path) {
+    let manager = load_reporting(config);
+    eprintln!(
+        "serving {} plugins on {}",
+        manager.plugins().len(),
+        path.display()
+    );
+    if let Err(error) = manager.serve_socket(path) {
+        eprintln!("failed to serve plugins: {error}");
+        std::process::exit(1);
+    }
+}
+
+/// Serves all plugins over HTTP at `addr` until the process is killed.
+#[cfg(feature = "server")]
+fn serve_http(config: &Config, addr: &str) {
+    let manager = load_reporting(config);
+    eprintln!(
+        "serving {} plugins on http://{addr}",
+        manager.plugins().len()
+    );
+    if let Err(error) = manager.serve_http(addr) {
+        eprintln!("failed to serve plugins: {error}");
+        std::process::exit(1);
+    }
+}
+
+/// Serves all plugins over gRPC at `addr` until the process is killed.
+#[cfg(feature = "grpc")]
+fn serve_grpc(config: &Config, addr: std::net::SocketAddr) {
+    let manager = load_reporting(config);
+    eprintln!("serving {} plugins on {addr}", manager.plugins().len());
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap_or_else(|error| {
+            eprintln!("failed to start the async runtime: {error}");
+            std::process::exit(1);
+        });
+    if let Err(error) = runtime.block_on(manager.serve_grpc(addr)) {
+        eprintln!("failed to serve plugins: {error}");
+        std::process::exit(1);
+    }
+}
 
-    let dir = fs::read_dir("plugins").unwrap();
-    for entry in dir {
-        let entry = entry.unwrap();
-        let plugin_path = entry.path();
+/// Feeds `input` through the configured pipeline called `name`, exiting with an error if a
+/// stage fails.
+fn run_pipeline(config: &Config, format: Format, name: &str, input: &str) {
+    let pipeline = config.pipeline(name).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    // Only the plugins of the pipeline need to be compiled.
+    let config = Config {
+        lazy: true,
+        ..config.clone()
+    };
+    let manager = load_reporting(&config);
 
-        println!("loading plugin from {}", plugin_path.to_str().unwrap());
-        let plugin = Plugin::load(plugin_path);
+    let result = pipeline
+        .input_type(&manager)
+        .map_err(|error| error.to_string())
+        .and_then(|dtype| coerce(input, dtype).map_err(|error| format!("invalid input: {error}")))
+        .and_then(|input| {
+            pipeline
+                .run(&manager, input)
+                .map_err(|error| error.to_string())
+        });
+    match (format, &result) {
+        (Format::Text, Ok(value)) => println!("{value}"),
+        (Format::Text, Err(error)) => eprintln!("{error}"),
+        (Format::Json, result) => {
+            let json = match result {
+                Ok(value) => serde_json::json!({
+                    "pipeline": name,
+                    "ok": true,
+                    "value": value.to_json(),
+                }),
+                Err(error) => serde_json::json!({
+                    "pipeline": name,
+                    "ok": false,
+                    "error": error,
+                }),
+            };
+            println!("{json}");
+        }
+    }
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
+
+/// Invokes `function` with `args` converted to its argument types, printing the result.
+///
+/// Returns whether the invocation succeeded.
+fn invoke(format: Format, plugin: &Plugin, function: &str, args: &Args, trace: bool) -> bool {
+    let args = plugin
+        .function(function)
+        .map_err(|error| error.to_string())
+        .and_then(|meta| coerce_args(args, &meta.arg_types));
+    let args = match args {
+        Ok(args) => args,
+        Err(error) => {
+            match format {
+                Format::Text => eprintln!("{error}"),
+                Format::Json => println!(
+                    "{}",
+                    serde_json::json!({
+                        "plugin": plugin.name(),
+                        "function": function,
+                        "ok": false,
+                        "error": error,
+                    })
+                ),
+            }
+            return false;
+        }
+    };
+
+    let report = plugin.invoke_reported(function, &args, trace);
+    match (format, &report.result) {
+        (Format::Text, Ok(value)) => println!("{value}"),
+        (Format::Text, Err(error)) => log(LogLevel::Error, plugin.name(), error),
+        (Format::Json, _) => println!("{}", report.to_json()),
+    }
+    if format == Format::Text {
+        // Only the result goes to stdout, so that it can be piped.
+        if let Some(allocations) = &report.allocations {
+            let message = format!(
+                "allocated {} bytes in {} allocations, at most {} bytes at once",
+                allocations.allocated_bytes, allocations.allocations, allocations.peak_bytes
+            );
+            log(LogLevel::Info, plugin.name(), message);
+        }
+        for warning in &report.warnings {
+            log(LogLevel::Warn, plugin.name(), warning);
+        }
+    }
+    report.is_ok()
+}
+
+fn invoke_all(format: Format, plugin: &Plugin, trace: bool) {
+    if plugin.is_disabled() {
+        log(
+            LogLevel::Info,
+            plugin.name(),
+            "skipped, since it is disabled",
+        );
+        return;
+    }
 
-        println!("invoking plugin: {}", plugin.name());
-        plugin.invoke();
+    for function in plugin.functions() {
+        // Functions are listed by the plugin, so they exist.
+        let args = plugin.generated_args(function).unwrap_or_default();
+        let report = plugin.invoke_reported(function, &args, trace);
+        match format {
+            Format::Text => println!("{report}"),
+            Format::Json => println!("{}", report.to_json()),
+        }
+    }
 
+    if format == Format::Text {
         println!();
     }
 }
+
+/// Converts the arguments of an invocation to values of the given types.
+///
+/// JSON arguments are given as an array, or as an object for functions taking a single record.
+fn coerce_args(args: &Args, types: &[DType]) -> Result<Vec<Value>, String> {
+    let check_count = |found: usize| {
+        if found == types.len() {
+            return Ok(());
+        }
+        let error = PluginError::ArgumentCount {
+            expected: types.len(),
+            found,
+        };
+        Err(error.to_string())
+    };
+
+    match args {
+        Args::Cli(args) => {
+            check_count(args.len())?;
+            args.iter()
+                .zip(types)
+                .enumerate()
+                .map(|(i, (arg, dtype))| {
+                    coerce(arg, dtype)
+                        .map_err(|error| format!("invalid argument {}: {error}", i + 1))
+                })
+                .collect()
+        }
+        Args::Json(serde_json::Value::Array(args)) => {
+            check_count(args.len())?;
+            args.iter()
+                .zip(types)
+                .enumerate()
+                .map(|(i, (arg, dtype))| {
+                    Value::from_json(arg, dtype)
+                        .map_err(|error| format!("invalid argument {}: {error}", i + 1))
+                })
+                .collect()
+        }
+        Args::Json(arg @ serde_json::Value::Object(_)) if types.len() == 1 => {
+            Ok(vec![Value::from_json(arg, &types[0])?])
+        }
+        Args::Json(json) => Err(format!(
+            "expected a JSON array of arguments, found `{json}`"
+        )),
+    }
+}
+
+/// Converts a command line argument to a value of type `dtype`.
+///
+/// Strings and bytes are taken as they are, so that they don't need to be quoted. Everything
+/// else is written as a Roc literal, see [`Value::parse`].
+fn coerce(arg: &str, dtype: &DType) -> Result<Value, String> {
+    match dtype {
+        DType::Str => Ok(Value::Str(arg.into())),
+        DType::Bytes => Ok(Value::Bytes(arg.as_bytes().into())),
+        dtype => Value::parse(arg, dtype),
+    }
+}
+
+/// Prints the signatures of all plugin functions, as a table or as JSON.
+fn list(config: &Config, json: bool) {
+    // Listing only needs the headers, so don't compile or load the plugins.
+    let config = Config {
+        lazy: true,
+        ..config.clone()
+    };
+    let (manager, report) = load(&config);
+    print_signatures(&manager, json);
+    finish(&report);
+}
+
+fn print_signatures(manager: &PluginManager, json: bool) {
+    let functions: Vec<_> = manager
+        .plugins()
+        .iter()
+        .flat_map(|plugin| {
+            plugin.functions().map(move |function| {
+                let meta = plugin.function(function).expect("listed functions exist");
+                (plugin, meta)
+            })
+        })
+        .collect();
+
+    if json {
+        let functions: Vec<_> = functions
+            .iter()
+            .map(|(plugin, meta)| {
+                let arg_types: Vec<_> = meta.arg_types.iter().map(ToString::to_string).collect();
+                serde_json::json!({
+                    "plugin": plugin.name(),
+                    "function": meta.name,
+                    "disabled": plugin.is_disabled(),
+                    "arguments": arg_types,
+                    "returns": meta.return_type.to_string(),
+                    "description": meta.description,
+                    "version": meta.version,
+                    "tags": meta.tags,
+                    "hook": meta.hook,
+                    "subscribe": meta.subscribe,
+                    "priority": meta.priority,
+                    "schedule": meta.schedule,
+                    "stateful": meta.state.is_some(),
+                    "timeout_ms": meta.timeout.map(|timeout| timeout.as_millis() as u64),
+                })
+            })
+            .collect();
+        println!("{:#}", serde_json::Value::Array(functions));
+        return;
+    }
+
+    let header = [
+        "PLUGIN",
+        "FUNCTION",
+        "VERSION",
+        "ARGUMENTS",
+        "RETURNS",
+        "DESCRIPTION",
+    ]
+    .map(String::from);
+    let rows: Vec<_> = functions
+        .iter()
+        .map(|(plugin, meta)| {
+            let arg_types: Vec<_> = meta.arg_types.iter().map(ToString::to_string).collect();
+            let name = if plugin.is_disabled() {
+                format!("{} (disabled)", plugin.name())
+            } else {
+                plugin.name().into()
+            };
+            [
+                name,
+                meta.name.clone(),
+                meta.version.clone().unwrap_or_default(),
+                arg_types.join(", "),
+                meta.return_type.to_string(),
+                meta.description.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 6];
+    for row in iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in iter::once(&header).chain(&rows) {
+        let [plugin, function, version, arg_types, return_type, description] = row;
+        let [w0, w1, w2, w3, w4, _] = widths;
+        let line = format!(
+            "{plugin:<w0$}  {function:<w1$}  {version:<w2$}  {arg_types:<w3$}  \
+             {return_type:<w4$}  {description}"
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Invokes plugin functions written like `add 1 2`, until the input ends or `:quit` is entered.
+fn repl(config: &Config, watch: bool) {
+    let manager = load_reporting(config);
+    let watcher = watch
+        .then(|| manager.watch(|plugin, result| log_reload(plugin, &result)))
+        .transpose();
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            eprintln!("failed to watch plugins: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    println!("enter `<function> <args>...` to invoke a function, `:list` to list the functions,");
+    println!("`:reset <plugin>` to reset the state of a stateful plugin, or `:quit` to exit");
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+
+        line.clear();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("failed to read input: {error}");
+                std::process::exit(1);
+            }
+        }
+
+        match line.trim() {
+            "" => {}
+            ":quit" | ":q" => break,
+            ":list" => print_signatures(&manager, false),
+            line if line.starts_with(":reset ") => {
+                let name = line[":reset ".len()..].trim();
+                match manager.get(name) {
+                    Some(plugin) => plugin.reset_state(),
+                    None => eprintln!("{}", PluginError::PluginNotFound(name.into())),
+                }
+            }
+            line => eval(&manager, line),
+        }
+    }
+}
+
+/// Invokes the function or plugin named by the first word of `line`, passing the rest of the
+/// line parsed as literals, and prints the result together with its type.
+fn eval(manager: &PluginManager, line: &str) {
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let found = match manager.get(name) {
+        Some(plugin) => Some((plugin, plugin.meta())),
+        None => manager
+            .plugins()
+            .iter()
+            .find_map(|plugin| Some((plugin, plugin.function(name).ok()?))),
+    };
+    let Some((plugin, meta)) = found else {
+        eprintln!("{}", PluginError::FunctionNotFound(name.into()));
+        return;
+    };
+
+    let result = Value::parse_args(args, &meta.arg_types).and_then(|args| {
+        plugin
+            .invoke_function_with(&meta.name, &args)
+            .map_err(|error| error.to_string())
+    });
+    match result {
+        // Quote strings, so that results read like the literals they are entered as.
+        Ok(Value::Str(s)) => println!("{s:?} : {}", meta.ok_type()),
+        Ok(value) => println!("{value} : {}", meta.ok_type()),
+        Err(error) => eprintln!("{error}"),
+    }
+}
+
+fn log_reload(plugin: &Plugin, result: &Result<(), PluginError>) {
+    match result {
+        Ok(()) => {
+            let path = plugin.path().display();
+            log(
+                LogLevel::Info,
+                plugin.name(),
+                format_args!("reloaded from {path}"),
+            );
+        }
+        Err(error) => log(
+            LogLevel::Error,
+            plugin.name(),
+            format_args!("failed to reload: {error}"),
+        ),
+    }
+}
+
+/// Watches the plugins for changes, calling `on_reload` with every plugin that was reloaded.
+fn watch_plugins<F>(manager: &PluginManager, mut on_reload: F) -> !
+where
+    F: FnMut(&Plugin) + Send + 'static,
+{
+    let watcher = manager.watch(move |plugin, result| {
+        log_reload(plugin, &result);
+        if result.is_ok() {
+            on_reload(plugin);
+        }
+    });
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            eprintln!("failed to watch plugins: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    eprintln!("watching plugins for changes");
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Precompiles all plugins into `out_dir`, exiting with an error if any of them fail to build.
+fn build_all(config: &Config, out_dir: &Path) {
+    let (manager, mut report) = load(config);
+    for plugin in manager.plugins().iter().filter(|p| !p.is_disabled()) {
+        match plugin.precompile(out_dir) {
+            Ok(manifest) => {
+                let manifest = manifest.display();
+                log(
+                    LogLevel::Info,
+                    plugin.name(),
+                    format_args!("built into {manifest}"),
+                );
+            }
+            Err(error) => {
+                report.loaded.retain(|name| name != plugin.name());
+                report.failures.push((plugin.path().to_owned(), error));
+            }
+        }
+    }
+    finish(&report);
+}
+
+fn check_all(config: &Config) {
+    // Headers are parsed when loading, and checking replaces compiling.
+    let config = Config {
+        lazy: true,
+        ..config.clone()
+    };
+    let (manager, mut report) = load(&config);
+    for plugin in manager.plugins().iter().filter(|p| !p.is_disabled()) {
+        match plugin.check() {
+            Ok(()) => log(LogLevel::Info, plugin.name(), "checked"),
+            Err(error) => {
+                report.loaded.retain(|name| name != plugin.name());
+                report.failures.push((plugin.path().to_owned(), error));
+            }
+        }
+    }
+    finish(&report);
+}
+
+fn doctor(config: &Config) {
+    let mut healthy = true;
+    for check in roc_plugin::doctor(&config.load_options()) {
+        match &check.result {
+            Ok(found) => println!("ok    {}: {found}", check.name),
+            Err(problem) => {
+                healthy = false;
+                println!("error {}: {problem}", check.name);
+            }
+        }
+        if let Some(fix) = &check.fix {
+            println!("      fix: {fix}");
+        }
+    }
+    if !healthy {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,527 @@
+use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::audit::AuditLog;
+use crate::config::Config;
+use crate::error::PluginError;
+use crate::plugin::{self, Detached, LoadOptions, Meta, Plugin};
+use crate::sidecar;
+use crate::store::Store;
+use crate::value::Value;
+
+/// A collection of loaded plugins.
+///
+/// Managers can be shared between threads to invoke their plugins concurrently, see [`Plugin`]
+/// for the details. Adding plugins requires exclusive access.
+#[derive(Debug)]
+pub struct PluginManager {
+    plugins: Vec<Arc<Plugin>>,
+    /// The key-value store shared by the plugins, in which each has its own namespace.
+    store: Arc<Store>,
+    /// The plugins, as seen by the `Host.call` effects of each other.
+    peers: Arc<Peers>,
+    /// The hook points defined by the host, as the signature of a function named like the hook.
+    hooks: Vec<Meta>,
+    /// How many subscribers of an event are invoked at once, see [`PluginManager::publish`].
+    event_concurrency: usize,
+    events: Mutex<BTreeMap<String, EventMetrics>>,
+}
+
+impl Default for PluginManager {
+    fn default() -> Self {
+        Self {
+            plugins: Vec::new(),
+            store: Arc::default(),
+            peers: Arc::default(),
+            hooks: Vec::new(),
+            event_concurrency: 1,
+            events: Mutex::default(),
+        }
+    }
+}
+
+impl PluginManager {
+    /// Creates a manager whose plugins share an in-memory key-value store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a manager whose plugins share a key-value store persisted at `path`.
+    ///
+    /// Entries stored by earlier runs are available to the plugins again.
+    pub fn with_store<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+        Ok(Self {
+            store: Arc::new(Store::open(path.as_ref())?),
+            ..Self::default()
+        })
+    }
+
+    /// Creates a manager as described by `config`, and loads the plugins in its plugin
+    /// directories.
+    ///
+    /// Plugins that fail to load are skipped and reported, like by [`PluginManager::load_all`].
+    pub fn from_config(config: &Config) -> Result<(Self, LoadReport), PluginError> {
+        let mut manager = match &config.store {
+            Some(path) => Self::with_store(path)?,
+            None => Self::new(),
+        };
+        manager.set_event_concurrency(config.event_concurrency);
+        let mut options = config.load_options();
+        if let Some(path) = &config.audit_log {
+            options.audit_log = Some(AuditLog::open(path, config.redact_audit_args)?);
+        }
+        let report = manager.load_all(&config.plugins_dirs, &options);
+        Ok((manager, report))
+    }
+
+    /// Adds a plugin, unless a plugin with the same name was added before.
+    ///
+    /// Plugins implementing a hook point that is defined must have its signature, see
+    /// [`PluginManager::define_hook`].
+    pub fn add(&mut self, plugin: Plugin) -> Result<Arc<Plugin>, PluginError> {
+        if self.get(plugin.name()).is_some() {
+            return Err(PluginError::DuplicatePlugin(plugin.name().into()));
+        }
+        for hook in &self.hooks {
+            check_hook(&plugin, hook)?;
+        }
+
+        plugin.set_store(Arc::clone(&self.store));
+        plugin.set_peers(Arc::clone(&self.peers));
+        let plugin = Arc::new(plugin);
+        self.plugins.push(Arc::clone(&plugin));
+        self.peers.0.write().unwrap().push(Arc::downgrade(&plugin));
+        Ok(plugin)
+    }
+
+    /// Loads and adds all plugins found in `dir` and its subdirectories, filtered by
+    /// [`LoadOptions::include`] and [`LoadOptions::exclude`].
+    ///
+    /// Plugins in subdirectories are namespaced by them, see [`Plugin::name`]. Plugins that fail
+    /// to load, or are named like an already added plugin, are skipped. Their paths are returned
+    /// together with the reason, so that one broken plugin doesn't keep the others from loading.
+    /// Scanning several directories keeps the first plugin of each name.
+    ///
+    /// Plugins disabled by [`LoadOptions::allow`] or [`LoadOptions::deny`] are added without
+    /// being compiled.
+    pub fn scan<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        options: &LoadOptions,
+    ) -> Vec<(PathBuf, PluginError)> {
+        let dir = dir.as_ref();
+        let paths = match crate::discover_matching(dir, &options.include, &options.exclude) {
+            Ok(paths) => paths,
+            Err(error) => return vec![(dir.to_owned(), error)],
+        };
+
+        let mut failures = Vec::new();
+        for path in paths {
+            let namespace = crate::namespace(dir, &path);
+            let result = Plugin::load_namespaced(&path, namespace.as_deref(), options)
+                .and_then(|plugin| self.add(plugin));
+            if let Err(error) = result {
+                failures.push((path, error));
+            }
+        }
+        failures
+    }
+
+    /// Loads and adds the plugins in all of `dirs`, see [`PluginManager::scan`].
+    ///
+    /// Plugins that fail to load or compile don't keep the others from loading, and are listed
+    /// in the returned report instead.
+    pub fn load_all<I>(&mut self, dirs: I, options: &LoadOptions) -> LoadReport
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let added = self.plugins.len();
+        let failures = dirs
+            .into_iter()
+            .flat_map(|dir| self.scan(dir, options))
+            .collect();
+        let loaded = self.plugins[added..]
+            .iter()
+            .map(|p| p.name().to_owned())
+            .collect();
+        LoadReport { loaded, failures }
+    }
+
+    pub fn plugins(&self) -> &[Arc<Plugin>] {
+        &self.plugins
+    }
+
+    /// Returns the plugin with the given name.
+    pub fn get(&self, name: &str) -> Option<&Arc<Plugin>> {
+        self.plugins.iter().find(|p| p.name() == name)
+    }
+
+    /// Returns the names of all plugins, in the order they were added.
+    pub fn list(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|p| p.name())
+    }
+
+    /// Returns all plugins in the order they run in batches: by [`Plugin::priority`], highest
+    /// first, then by name.
+    ///
+    /// Unlike the order plugins were added in, this doesn't depend on which directories they
+    /// were loaded from.
+    pub fn plugins_by_priority(&self) -> Vec<&Arc<Plugin>> {
+        let mut plugins: Vec<_> = self.plugins.iter().collect();
+        plugins.sort_by_key(|p| (Reverse(p.priority()), p.name()));
+        plugins
+    }
+
+    /// Sets whether the plugin with the given name is invoked in a forked worker process, see
+    /// [`Plugin::set_isolated`].
+    pub fn set_isolated(&self, name: &str, isolated: bool) -> Result<(), PluginError> {
+        self.get(name)
+            .ok_or_else(|| PluginError::PluginNotFound(name.into()))?
+            .set_isolated(isolated);
+        Ok(())
+    }
+
+    /// Invokes the plugin with the given name, see [`Plugin::invoke_with`].
+    pub fn invoke(&self, name: &str, args: &[Value]) -> Result<Value, PluginError> {
+        self.get(name)
+            .ok_or_else(|| PluginError::PluginNotFound(name.into()))?
+            .invoke_with(args)
+    }
+
+    /// Invokes the plugin with the given name, see [`Plugin::invoke_with_ctx`].
+    pub fn invoke_with_ctx(
+        &self,
+        name: &str,
+        ctx: &dyn Any,
+        args: &[Value],
+    ) -> Result<Value, PluginError> {
+        self.get(name)
+            .ok_or_else(|| PluginError::PluginNotFound(name.into()))?
+            .invoke_with_ctx(ctx, args)
+    }
+
+    /// Invokes the plugin with the given name on tokio's blocking thread pool, see
+    /// [`Plugin::invoke_async`].
+    #[cfg(feature = "tokio")]
+    pub fn invoke_async(
+        &self,
+        name: &str,
+        args: &[Value],
+    ) -> impl Future<Output = Result<Value, PluginError>> + Send {
+        let plugin = self
+            .get(name)
+            .ok_or_else(|| PluginError::PluginNotFound(name.into()));
+        let invocation = plugin.map(|plugin| plugin.invoke_async(args));
+        async move { invocation?.await }
+    }
+
+    /// Defines a hook point named `name`, which plugins implement with functions declared like
+    /// `#[plugin(hook = "on_message")] handle : Str -> Str`.
+    ///
+    /// The functions implementing the hook must have `signature`, like `Str -> Str`, which is
+    /// checked for the plugins already added and those added later. Plugins may implement hooks
+    /// the host doesn't define, which are never run.
+    pub fn define_hook(&mut self, name: &str, signature: &str) -> Result<(), PluginError> {
+        if self.hooks.iter().any(|h| h.name == name) {
+            return Err(PluginError::Hook(format!("`{name}` is already defined")));
+        }
+        let hook = plugin::parse_hook(name, signature)
+            .map_err(|error| PluginError::Hook(format!("`{name} : {signature}`: {error}")))?;
+        for plugin in &self.plugins {
+            check_hook(plugin, &hook)?;
+        }
+        self.hooks.push(hook);
+        Ok(())
+    }
+
+    /// Runs the hook point named `name` with `args`, invoking the function of every enabled
+    /// plugin that implements it, ordered by [`Meta::priority`].
+    ///
+    /// A failing plugin doesn't keep the others from running.
+    pub fn run_hook(&self, name: &str, args: &[Value]) -> Result<Vec<HookResult>, PluginError> {
+        let hook = self
+            .hooks
+            .iter()
+            .find(|h| h.name == name)
+            .ok_or_else(|| PluginError::HookNotFound(name.into()))?;
+        if args.len() != hook.arg_types.len() {
+            return Err(PluginError::ArgumentCount {
+                expected: hook.arg_types.len(),
+                found: args.len(),
+            });
+        }
+
+        let mut implementations: Vec<_> = self
+            .plugins
+            .iter()
+            .filter(|p| !p.is_disabled())
+            .filter_map(|plugin| Some((plugin, plugin.hook_function(name)?)))
+            .collect();
+        implementations
+            .sort_by_key(|(plugin, function)| (Reverse(function.priority), plugin.name()));
+
+        let results = implementations
+            .into_iter()
+            .map(|(plugin, function)| HookResult {
+                plugin: plugin.name().to_owned(),
+                result: plugin.invoke_function_with(&function.name, args),
+            })
+            .collect();
+        Ok(results)
+    }
+
+    /// Sets how many subscribers of an event [`PluginManager::publish`] invokes at once, which
+    /// is one by default.
+    pub fn set_event_concurrency(&mut self, concurrency: usize) {
+        self.event_concurrency = concurrency.max(1);
+    }
+
+    /// Publishes the event named `event`, invoking every function of an enabled plugin that
+    /// subscribes to it with `payload`, see [`Meta::subscribe`].
+    ///
+    /// Subscribers are invoked in the order of their [`Meta::priority`], and events are
+    /// delivered at most once: subscribers that fail or time out aren't invoked
+    /// again, and a failing subscriber doesn't keep the others from receiving the event.
+    /// Several subscribers are invoked at once on separate threads if the event concurrency is
+    /// raised with [`PluginManager::set_event_concurrency`].
+    pub fn publish(&self, event: &str, payload: &Value) -> PublishReport {
+        let mut subscribers: Vec<_> = self
+            .plugins
+            .iter()
+            .filter(|p| !p.is_disabled())
+            .flat_map(|plugin| plugin.subscriptions(event).map(move |m| (plugin, m)))
+            .collect();
+        subscribers.sort_by_key(|(plugin, m)| (Reverse(m.priority), plugin.name(), &m.name));
+        let subscribers: Vec<_> = subscribers
+            .into_iter()
+            .map(|(plugin, m)| (plugin, &m.name))
+            .collect();
+
+        let deliver = |(plugin, function): &(&Arc<Plugin>, &String), payload: &Value| {
+            let started = Instant::now();
+            let result = plugin.invoke_function_with(function, std::slice::from_ref(payload));
+            (started.elapsed(), result.map(drop))
+        };
+        let results: Vec<_> = if self.event_concurrency == 1 || subscribers.len() <= 1 {
+            subscribers.iter().map(|s| deliver(s, payload)).collect()
+        } else {
+            // Workers take the next subscriber until all received the event.
+            let next = AtomicUsize::new(0);
+            let results = Mutex::new(Vec::new());
+            thread::scope(|scope| {
+                for _ in 0..self.event_concurrency.min(subscribers.len()) {
+                    let payload = Detached(payload.detach());
+                    let (next, results, subscribers) = (&next, &results, &subscribers);
+                    scope.spawn(move || {
+                        // Capture the wrapper as a whole, rather than just its contents.
+                        let payload = payload;
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(subscriber) = subscribers.get(i) else {
+                                break;
+                            };
+                            let delivery = deliver(subscriber, &payload.0);
+                            results.lock().unwrap().push((i, delivery));
+                        }
+                    });
+                }
+            });
+            let mut results = results.into_inner().unwrap();
+            results.sort_by_key(|(i, _)| *i);
+            results.into_iter().map(|(_, delivery)| delivery).collect()
+        };
+
+        let mut report = PublishReport::default();
+        let mut events = self.events.lock().unwrap();
+        let metrics = events.entry(event.to_owned()).or_default();
+        metrics.published += 1;
+        for ((plugin, _), (elapsed, result)) in subscribers.iter().zip(results) {
+            metrics.handling_time += elapsed;
+            match result {
+                Ok(()) => {
+                    metrics.delivered += 1;
+                    report.delivered.push(plugin.name().to_owned());
+                }
+                Err(error) => {
+                    metrics.failed += 1;
+                    report.failures.push((plugin.name().to_owned(), error));
+                }
+            }
+        }
+        report
+    }
+
+    /// Returns the metrics of the event named `event`, or `None` if it was never published.
+    pub fn event_metrics(&self, event: &str) -> Option<EventMetrics> {
+        self.events.lock().unwrap().get(event).copied()
+    }
+
+    /// Watches the source files of all plugins, recompiling plugins whose source changes.
+    ///
+    /// Plugins are recompiled on a background thread, which calls `on_reload` with the result of
+    /// each reload. Watching stops when the returned `Watcher` is dropped.
+    pub fn watch<F>(&self, mut on_reload: F) -> Result<Watcher, PluginError>
+    where
+        F: FnMut(&Plugin, Result<(), PluginError>) + Send + 'static,
+    {
+        let plugins = self.plugins.clone();
+        let handler = move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if !event.kind.is_create() && !event.kind.is_modify() {
+                return;
+            }
+
+            for path in event.paths.iter().filter_map(|p| fs::canonicalize(p).ok()) {
+                // Plugins are also rebuilt when their sidecar or a helper module changes.
+                let changed = |p: &&Arc<Plugin>| {
+                    p.path() == path
+                        || sidecar::path(p.path()) == path
+                        || (crate::is_module(&path) && p.path().parent() == path.parent())
+                };
+                for plugin in plugins.iter().filter(changed) {
+                    on_reload(plugin, plugin.refresh());
+                }
+            }
+        };
+        let mut watcher = notify::recommended_watcher(handler).map_err(PluginError::Watch)?;
+
+        // Watch the containing directories rather than the files themselves, so that editors
+        // that replace files on save don't end the watch.
+        let dirs: BTreeSet<_> = self
+            .plugins
+            .iter()
+            .filter_map(|p| p.path().parent())
+            .collect();
+        for dir in dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(PluginError::Watch)?;
+        }
+        Ok(Watcher(watcher))
+    }
+}
+
+/// The outcome of publishing an event with [`PluginManager::publish`].
+#[derive(Debug, Default)]
+pub struct PublishReport {
+    /// The names of the plugins that handled the event, once per subscribing function.
+    pub delivered: Vec<String>,
+    /// The plugins that failed to handle the event, with the reason.
+    pub failures: Vec<(String, PluginError)>,
+}
+
+impl PublishReport {
+    /// Returns whether all subscribers handled the event.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Counts of the deliveries of an event, see [`PluginManager::event_metrics`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EventMetrics {
+    /// How often the event was published.
+    pub published: u64,
+    /// How many deliveries to subscribers succeeded.
+    pub delivered: u64,
+    /// How many deliveries failed.
+    pub failed: u64,
+    /// The time subscribers took to handle the event, summed over all deliveries.
+    pub handling_time: Duration,
+}
+
+/// The result of a plugin's implementation of a hook point, see [`PluginManager::run_hook`].
+#[derive(Debug)]
+pub struct HookResult {
+    /// The name of the plugin.
+    pub plugin: String,
+    pub result: Result<Value, PluginError>,
+}
+
+/// Checks that the function with which `plugin` implements `hook`, if any, has its signature.
+fn check_hook(plugin: &Plugin, hook: &Meta) -> Result<(), PluginError> {
+    let Some(function) = plugin.hook_function(&hook.name) else {
+        return Ok(());
+    };
+    if function.arg_types == hook.arg_types && function.return_type == hook.return_type {
+        return Ok(());
+    }
+    Err(PluginError::Hook(format!(
+        "`{}` of {} implements `{} : {}` as `{}`",
+        function.name,
+        plugin.name(),
+        hook.name,
+        hook.signature(),
+        function.signature()
+    )))
+}
+
+/// The plugins of a manager, which they can invoke through `Host.call`.
+///
+/// Plugins only hold weak references to each other, so that they are still dropped with their
+/// manager.
+#[derive(Debug, Default)]
+pub(crate) struct Peers(RwLock<Vec<Weak<Plugin>>>);
+
+impl Peers {
+    /// Returns the plugin with the given name, unless it was dropped.
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<Plugin>> {
+        let peers = self.0.read().unwrap();
+        peers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|p| p.name() == name)
+    }
+}
+
+/// The outcome of loading plugins with [`PluginManager::load_all`].
+///
+/// Displaying the report summarizes it, listing every failure.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    /// The names of the plugins that were added.
+    pub loaded: Vec<String>,
+    /// The plugins that failed to load, with the reason.
+    pub failures: Vec<(PathBuf, PluginError)>,
+}
+
+impl LoadReport {
+    /// Returns whether all plugins were loaded.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "loaded {} plugins, {} failed",
+            self.loaded.len(),
+            self.failures.len()
+        )?;
+        for (path, error) in &self.failures {
+            write!(f, "\n\n{}: {error}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// A handle that keeps watching plugin source files for changes, see [`PluginManager::watch`].
+#[derive(Debug)]
+pub struct Watcher(RecommendedWatcher);
@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::value::Value;
+
+/// A function's name and the arguments it was invoked with.
+pub(crate) type Key = (String, Vec<Value>);
+
+/// The results of a plugin's pure functions, of which the least recently used are evicted once
+/// there are more than `capacity`, see [`LoadOptions::memoize`].
+///
+/// [`LoadOptions::memoize`]: crate::LoadOptions::memoize
+#[derive(Debug)]
+pub(crate) struct Memo {
+    capacity: usize,
+    /// The results, with the tick they were last used at.
+    entries: HashMap<Key, (Value, u64)>,
+    /// The keys of the entries, by the tick they were last used at.
+    recency: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+impl Memo {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the result kept for `key`, marking it as the most recently used.
+    pub(crate) fn get(&mut self, key: &Key) -> Option<Value> {
+        let (value, used) = self.entries.get_mut(key)?;
+        let key = self.recency.remove(used).expect("entries have a tick");
+        self.tick += 1;
+        *used = self.tick;
+        let value = value.clone();
+        self.recency.insert(self.tick, key);
+        Some(value)
+    }
+
+    /// Keeps the result for `key`, evicting the least recently used result if there are too
+    /// many. `key` and `value` must be detached, since they outlive the invocation.
+    pub(crate) fn insert(&mut self, key: Key, value: Value) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, key);
+        self.evict();
+    }
+
+    /// Changes how many results are kept, evicting the least recently used ones if necessary.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let (_, key) = self.recency.pop_first().expect("entries have a tick");
+            self.entries.remove(&key);
+        }
+    }
+}
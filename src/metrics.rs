@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::error::PluginError;
+use crate::value::Value;
+
+/// Records what plugins do, for hosts that export metrics, see [`LoadOptions::metrics`].
+///
+/// The methods are called on the threads plugins are compiled and invoked on, so they should
+/// return quickly. [`PrometheusMetrics`] is a recorder that keeps the metrics in memory.
+///
+/// [`LoadOptions::metrics`]: crate::LoadOptions::metrics
+pub trait Metrics: fmt::Debug + Send + Sync {
+    /// Records that a function of `plugin` returned `result` after `latency`, including
+    /// retries.
+    fn invoked(
+        &self,
+        plugin: &str,
+        function: &str,
+        latency: Duration,
+        result: &Result<Value, PluginError>,
+    ) {
+        let _ = (plugin, function, latency, result);
+    }
+
+    /// Records that a function of `plugin` returned a memoized result, see
+    /// [`LoadOptions::memoize`](crate::LoadOptions::memoize).
+    fn cache_hit(&self, plugin: &str, function: &str) {
+        let _ = (plugin, function);
+    }
+
+    /// Records that compiling `plugin` took `duration`, which is short if its library was
+    /// cached.
+    fn compiled(&self, plugin: &str, duration: Duration, succeeded: bool) {
+        let _ = (plugin, duration, succeeded);
+    }
+}
+
+/// The upper bounds of the buckets invocation latencies are counted in, in seconds.
+const INVOKE_BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+/// The upper bounds of the buckets compile times are counted in, in seconds.
+const COMPILE_BUCKETS: [f64; 8] = [0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// A [`Metrics`] recorder that keeps counters and histograms per plugin, which it renders in
+/// the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct PrometheusMetrics {
+    plugins: Mutex<BTreeMap<String, PluginMetrics>>,
+}
+
+#[derive(Debug)]
+struct PluginMetrics {
+    invocations: u64,
+    failures: u64,
+    panics: u64,
+    cache_hits: u64,
+    invoke_seconds: Histogram,
+    compile_seconds: Histogram,
+}
+
+impl Default for PluginMetrics {
+    fn default() -> Self {
+        Self {
+            invocations: 0,
+            failures: 0,
+            panics: 0,
+            cache_hits: 0,
+            invoke_seconds: Histogram::new(&INVOKE_BUCKETS),
+            compile_seconds: Histogram::new(&COMPILE_BUCKETS),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// The number of observations per bucket, not including those of smaller buckets.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = self.bounds.iter().position(|&bound| seconds <= bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+impl PrometheusMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, plugin: &str, f: impl FnOnce(&mut PluginMetrics)) {
+        let mut plugins = self.plugins.lock().unwrap();
+        match plugins.get_mut(plugin) {
+            Some(metrics) => f(metrics),
+            None => f(plugins.entry(plugin.into()).or_default()),
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format, as served at `/metrics`.
+    pub fn render(&self) -> String {
+        let plugins = self.plugins.lock().unwrap();
+        let mut out = String::new();
+        let help = "Invocations of plugin functions.";
+        write_counter(&mut out, "invocations", help, &plugins, |m| m.invocations);
+        let help = "Invocations that failed.";
+        write_counter(&mut out, "failures", help, &plugins, |m| m.failures);
+        let help = "Invocations that panicked.";
+        write_counter(&mut out, "panics", help, &plugins, |m| m.panics);
+        let help = "Invocations that returned a memoized result.";
+        write_counter(&mut out, "cache_hits", help, &plugins, |m| m.cache_hits);
+        let help = "How long invocations took.";
+        write_histogram(&mut out, "invoke_seconds", help, &plugins, |m| {
+            &m.invoke_seconds
+        });
+        let help = "How long compiling plugins took.";
+        write_histogram(&mut out, "compile_seconds", help, &plugins, |m| {
+            &m.compile_seconds
+        });
+        out
+    }
+}
+
+fn write_counter(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    plugins: &BTreeMap<String, PluginMetrics>,
+    value: impl Fn(&PluginMetrics) -> u64,
+) {
+    let name = format!("roc_plugin_{name}_total");
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} counter").unwrap();
+    for (plugin, metrics) in plugins {
+        let plugin = escape(plugin);
+        writeln!(out, "{name}{{plugin=\"{plugin}\"}} {}", value(metrics)).unwrap();
+    }
+}
+
+fn write_histogram(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    plugins: &BTreeMap<String, PluginMetrics>,
+    histogram: impl Fn(&PluginMetrics) -> &Histogram,
+) {
+    let name = format!("roc_plugin_{name}");
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} histogram").unwrap();
+    for (plugin, metrics) in plugins {
+        let plugin = escape(plugin);
+        let histogram = histogram(metrics);
+        // Buckets count the observations of all smaller buckets too.
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+            cumulative += count;
+            let labels = format!("plugin=\"{plugin}\",le=\"{bound}\"");
+            writeln!(out, "{name}_bucket{{{labels}}} {cumulative}").unwrap();
+        }
+        let count = histogram.count;
+        writeln!(
+            out,
+            "{name}_bucket{{plugin=\"{plugin}\",le=\"+Inf\"}} {count}"
+        )
+        .unwrap();
+        writeln!(out, "{name}_sum{{plugin=\"{plugin}\"}} {}", histogram.sum).unwrap();
+        writeln!(out, "{name}_count{{plugin=\"{plugin}\"}} {count}").unwrap();
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn invoked(
+        &self,
+        plugin: &str,
+        _function: &str,
+        latency: Duration,
+        result: &Result<Value, PluginError>,
+    ) {
+        self.update(plugin, |metrics| {
+            metrics.invocations += 1;
+            match result {
+                Ok(_) => {}
+                Err(PluginError::Panic { .. }) => {
+                    metrics.failures += 1;
+                    metrics.panics += 1;
+                }
+                Err(_) => metrics.failures += 1,
+            }
+            metrics.invoke_seconds.observe(latency);
+        });
+    }
+
+    fn cache_hit(&self, plugin: &str, _function: &str) {
+        self.update(plugin, |metrics| metrics.cache_hits += 1);
+    }
+
+    fn compiled(&self, plugin: &str, duration: Duration, _succeeded: bool) {
+        self.update(plugin, |metrics| metrics.compile_seconds.observe(duration));
+    }
+}
+
+/// Escapes `value` for a label, which is quoted.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::error::PluginError;
+use crate::manager::PluginManager;
+use crate::plugin::Plugin;
+use crate::value::{DType, Value};
+
+/// A chain of plugins that each get the result of the previous one, like
+/// `slugify | truncate(80) | uppercase`.
+///
+/// Every stage invokes the first function of a plugin, passing the previous result as its first
+/// argument. The remaining arguments are given in parentheses, as comma-separated Roc literals
+/// like `pad(20, "-")`. Stages that return a `Result` or `Task` end the pipeline if they fail,
+/// and pass on the successful value otherwise.
+///
+/// Pipelines are checked against the signatures of the plugins before they run, so that the
+/// result of each stage has the type the next one takes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Stage {
+    plugin: String,
+    /// The arguments after the first one, as written between the parentheses.
+    args: Option<String>,
+}
+
+/// A stage whose plugin was found, with its arguments parsed.
+struct Resolved<'a> {
+    plugin: &'a Arc<Plugin>,
+    args: Vec<Value>,
+}
+
+impl FromStr for Pipeline {
+    type Err = PluginError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |msg: String| PluginError::Pipeline(format!("`{s}`: {msg}"));
+
+        let mut stages = Vec::new();
+        for stage in split_stages(s) {
+            let stage = stage.trim();
+            let (plugin, args) = match stage.split_once('(') {
+                Some((plugin, rest)) => {
+                    let args = rest
+                        .strip_suffix(')')
+                        .ok_or_else(|| invalid(format!("unclosed `(` in `{stage}`")))?;
+                    (plugin.trim_end(), Some(args.trim().to_owned()))
+                }
+                None => (stage, None),
+            };
+            if plugin.is_empty() || plugin.contains(char::is_whitespace) {
+                return Err(invalid(format!("expected a plugin name, found `{stage}`")));
+            }
+            stages.push(Stage {
+                plugin: plugin.into(),
+                args,
+            });
+        }
+        Ok(Self { stages })
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", stage.plugin)?;
+            if let Some(args) = &stage.args {
+                write!(f, "({args})")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Pipeline {
+    /// Checks that the plugins of all stages are in `manager`, and that each stage's result has
+    /// the type the next stage takes.
+    pub fn check(&self, manager: &PluginManager) -> Result<(), PluginError> {
+        self.resolve(manager).map(drop)
+    }
+
+    /// Returns the type of the input of the pipeline, which is the type of the first argument
+    /// of its first stage.
+    pub fn input_type<'a>(&self, manager: &'a PluginManager) -> Result<&'a DType, PluginError> {
+        let first = &self.resolve(manager)?[0];
+        Ok(&first.plugin.meta().arg_types[0])
+    }
+
+    /// Feeds `input` through the stages of the pipeline, returning the result of the last one.
+    pub fn run(&self, manager: &PluginManager, input: Value) -> Result<Value, PluginError> {
+        let mut value = input;
+        for stage in self.resolve(manager)? {
+            let mut args = vec![value];
+            args.extend(stage.args);
+            value = stage.plugin.invoke_with(&args)?;
+        }
+        Ok(value)
+    }
+
+    /// Looks up the plugins of the stages and parses their arguments, checking their types.
+    fn resolve<'a>(&self, manager: &'a PluginManager) -> Result<Vec<Resolved<'a>>, PluginError> {
+        let mut resolved: Vec<Resolved<'a>> = Vec::new();
+        for stage in &self.stages {
+            let invalid = |msg: String| PluginError::Pipeline(format!("`{self}`: {msg}"));
+            let plugin = manager
+                .get(&stage.plugin)
+                .ok_or_else(|| PluginError::PluginNotFound(stage.plugin.clone()))?;
+            let meta = plugin.meta();
+            let Some((input, rest)) = meta.arg_types.split_first() else {
+                return Err(invalid(format!("`{}` takes no arguments", stage.plugin)));
+            };
+
+            if let Some(previous) = resolved.last() {
+                let output = previous.plugin.meta().ok_type();
+                if output != input {
+                    return Err(invalid(format!(
+                        "`{}` returns {output}, but `{}` takes {input}",
+                        previous.plugin.name(),
+                        stage.plugin
+                    )));
+                }
+            }
+
+            // The arguments are parsed as the tuple of the remaining argument types.
+            let args = match &stage.args {
+                Some(args) if !args.is_empty() => {
+                    let literal = format!("({args})");
+                    match Value::parse(&literal, &DType::Tuple(rest.to_vec())) {
+                        Ok(Value::Tuple(values)) => values,
+                        Ok(_) => unreachable!("tuples are parsed as `Value::Tuple`"),
+                        Err(error) => {
+                            let msg = format!("invalid arguments of `{}`: {error}", stage.plugin);
+                            return Err(invalid(msg));
+                        }
+                    }
+                }
+                _ => Vec::new(),
+            };
+            if args.len() != rest.len() {
+                return Err(invalid(format!(
+                    "`{}` takes {} arguments after its input, found {}",
+                    stage.plugin,
+                    rest.len(),
+                    args.len()
+                )));
+            }
+            resolved.push(Resolved { plugin, args });
+        }
+        Ok(resolved)
+    }
+}
+
+/// Splits a pipeline at the `|`s between its stages, skipping those in string literals.
+fn split_stages(s: &str) -> Vec<&str> {
+    let mut stages = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '|' if !in_string => {
+                stages.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    stages.push(&s[start..]);
+    stages
+}
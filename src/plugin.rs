@@ -1,288 +1,2944 @@
-use std::ffi::c_void;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::{c_void, OsStr};
 use std::fs::{self, File};
-use std::io::Write;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::io::{self, Write};
+use std::iter;
+#[cfg(feature = "tokio")]
 use std::panic;
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::str::FromStr;
-use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, LazyLock, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use libloading::{Library, Symbol};
+use libffi::middle::{Cif, CodePtr, Type};
+use libloading::Library;
+use object::Object;
 use regex::Regex;
-use roc_std::RocStr;
+use serde::Deserialize;
 
-#[derive(Debug)]
-struct Meta {
-    name: String,
-    arg_types: Vec<DType>,
-    return_type: DType,
+use crate::alloc::{self, Allocator};
+use crate::audit::{self, AuditLog};
+use crate::breaker::Breaker;
+use crate::bytes::Bytes;
+use crate::cache;
+use crate::cancel::CancellationToken;
+use crate::clock::Clock;
+use crate::config::PluginOverrides;
+use crate::convert::{FromRocReturn, IntoRocArgs};
+use crate::diagnostics;
+use crate::effects::{self, Capabilities, HostApis, HostEffect};
+use crate::error::{self, PanicKind, PluginError};
+#[cfg(unix)]
+use crate::isolate;
+use crate::logging::{plugin_log, LogLevel};
+use crate::manager::Peers;
+use crate::memo::Memo;
+use crate::metrics::Metrics;
+use crate::report::InvocationReport;
+use crate::roc_host::{self, AllocReport, DbgSink, MemoryLimitExceeded};
+use crate::schedule::Schedule;
+use crate::sidecar;
+use crate::spans::in_span;
+use crate::store::Store;
+use crate::toolchain::{toolchain, Syntax};
+use crate::type_expr::{Signature, TypeError, TypeExpr};
+use crate::value::{self, DType, FfiValue, RocBuf, Value};
+#[cfg(feature = "wasm")]
+use crate::wasm::{self, WasmLimits};
+
+/// The signature of a plugin function, as declared in its `#[plugin]` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Meta {
+    pub name: String,
+    pub arg_types: Vec<DType>,
+    pub return_type: DType,
+    /// How long an invocation may run, set with `#[plugin(timeout = "5s")]`.
+    ///
+    /// Overrides [`LoadOptions::timeout`].
+    pub timeout: Option<Duration>,
+    /// What the function does, set with `#[plugin(description = "slugifies text")]`.
+    pub description: Option<String>,
+    /// The version of the function, set with `#[plugin(version = "1.2")]`.
+    pub version: Option<String>,
+    /// Tags for organizing functions, set with `#[plugin(tags = "text, web")]`.
+    pub tags: Vec<String>,
+    /// The hook point of the host the function implements, set with
+    /// `#[plugin(hook = "on_message")]`, see [`PluginManager::run_hook`].
+    ///
+    /// [`PluginManager::run_hook`]: crate::PluginManager::run_hook
+    pub hook: Option<String>,
+    /// The event the function subscribes to, set with `#[plugin(subscribe = "user_created")]`,
+    /// see [`PluginManager::publish`].
+    ///
+    /// [`PluginManager::publish`]: crate::PluginManager::publish
+    pub subscribe: Option<String>,
+    /// The cron expression of the times the function runs at, set with
+    /// `#[plugin(schedule = "*/5 * * * *")]`, see [`Scheduler`].
+    ///
+    /// [`Scheduler`]: crate::Scheduler
+    pub schedule: Option<String>,
+    /// When the function runs relative to others implementing the same hook point or
+    /// subscribing to the same event, set with `#[plugin(priority = "10")]`.
+    ///
+    /// Functions with higher priorities run first, and those with the same priority in the
+    /// order of their plugins' names. The default priority is 0.
+    pub priority: i32,
+    /// The type of the state of a stateful plugin, for its functions declared like
+    /// `update : State, Msg -> (State, Response)`, see [`Plugin::reset_state`].
+    ///
+    /// The host passes the state along, so it is neither in `arg_types` nor in `return_type`.
+    pub state: Option<DType>,
+}
+
+impl Meta {
+    pub fn signature(&self) -> String {
+        if self.arg_types.is_empty() {
+            return self.return_type.to_string();
+        }
+
+        let arg_types: Vec<_> = self.arg_types.iter().map(|t| t.to_string()).collect();
+        format!("{} -> {}", arg_types.join(", "), self.return_type)
+    }
+
+    /// The type of a successful return value, i.e. without the `Result` or `Task` wrapper, if any.
+    pub fn ok_type(&self) -> &DType {
+        match &self.return_type {
+            DType::Result(ok, _) | DType::Task(ok, _) => ok,
+            t => t,
+        }
+    }
+
+    /// The name of the platform function that exposes this plugin function to the host.
+    fn entry_name(&self) -> String {
+        format!("entry_{}", self.name)
+    }
+
+    /// Returns the function as declared in Roc, taking and returning the plugin's state if it is
+    /// stateful.
+    fn with_state(&self) -> Cow<'_, Meta> {
+        let Some(state) = &self.state else {
+            return Cow::Borrowed(self);
+        };
+        Cow::Owned(Meta {
+            arg_types: iter::once(state.clone())
+                .chain(self.arg_types.iter().cloned())
+                .collect(),
+            return_type: DType::Tuple(vec![state.clone(), self.return_type.clone()]),
+            state: None,
+            ..self.clone()
+        })
+    }
+
+    /// Returns the `init : {} -> State` function of a stateful plugin, as called by the host,
+    /// which is through an entry that takes no arguments.
+    fn init(state: &DType) -> Meta {
+        Meta {
+            name: "init".into(),
+            arg_types: Vec::new(),
+            return_type: state.clone(),
+            timeout: None,
+            description: None,
+            version: None,
+            tags: Vec::new(),
+            hook: None,
+            subscribe: None,
+            schedule: None,
+            priority: 0,
+            state: None,
+        }
+    }
+
+    /// Returns the lifecycle hook with the given name, see [`HOOKS`].
+    fn hook(name: &str) -> Meta {
+        Meta {
+            name: name.into(),
+            arg_types: Vec::new(),
+            return_type: DType::Task(Box::new(DType::Unit), Box::new(DType::Str)),
+            timeout: None,
+            description: None,
+            version: None,
+            tags: Vec::new(),
+            hook: None,
+            subscribe: None,
+            schedule: None,
+            priority: 0,
+            state: None,
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum DType {
-    Str,
-    U64,
+/// The optional `Task {} Str` functions the host calls after loading a plugin's library and
+/// before unloading it, see [`Plugin`].
+const HOOKS: [&str; 2] = ["onLoad", "onUnload"];
+
+/// Options controlling how plugins are compiled and loaded.
+#[derive(Clone, Debug)]
+pub struct LoadOptions {
+    /// Whether to reuse libraries built earlier from the same source and compiler.
+    pub cache: bool,
+    /// The directory built libraries and per-plugin build directories are kept in.
+    pub cache_dir: PathBuf,
+    /// Whether to keep the platform and app generated for a plugin in its build directory after
+    /// building it, for debugging. Compile errors then include the directory's path.
+    pub keep_artifacts: bool,
+    /// Whether to defer compiling a plugin until it is first invoked.
+    ///
+    /// Headers are still parsed at load time, so malformed plugins are reported early.
+    pub lazy: bool,
+    /// How long invocations of functions without a `timeout` attribute may run.
+    pub timeout: Option<Duration>,
+    /// Whether to invoke plugins in a forked worker process, so that crashing plugins can't
+    /// take down the host. See [`Plugin::set_isolated`].
+    pub isolated: bool,
+    /// How many bytes a single invocation may allocate, or `None` for no limit.
+    pub memory_limit: Option<usize>,
+    /// How many results of the plugin's pure functions to keep, so that invoking them again
+    /// with the same arguments returns the kept result without running them, or 0 to run them
+    /// every time. See [`Plugin::set_memoize`].
+    pub memoize: usize,
+    /// How many times to retry invocations that panic, waiting `retry_backoff` before the first
+    /// retry and twice as long before each further one.
+    ///
+    /// Retries perform the effects of a function again, and count as a single invocation for
+    /// `failure_threshold`.
+    pub retries: u32,
+    pub retry_backoff: Duration,
+    /// After how many consecutive failed invocations the plugin is marked unhealthy, or `None`
+    /// to never mark it unhealthy. Failures are panics, timeouts, exceeded memory limits and
+    /// failed worker processes.
+    ///
+    /// Invocations of unhealthy plugins fail with [`PluginError::Unhealthy`] without running,
+    /// until `cooldown` has passed and an invocation succeeds, or [`Plugin::reset_health`] is
+    /// called.
+    pub failure_threshold: Option<u32>,
+    pub cooldown: Duration,
+    /// The directory `Host.readFile` and `Host.writeFile` are confined to, or `None` to deny
+    /// plugins file access.
+    ///
+    /// Each plugin gets its own subdirectory, named after the plugin.
+    pub data_dir: Option<PathBuf>,
+    /// The hosts plugins may send requests to through `Host.httpGet`.
+    ///
+    /// Entries match a host exactly, or any of its subdomains if written as `*.example.com`.
+    /// Plugins can't make requests if this is empty.
+    #[cfg(feature = "http")]
+    pub http_allowlist: Vec<String>,
+    /// The environment variables plugins may read through `Host.envVar`, such as API keys.
+    ///
+    /// No other variables are visible to plugins.
+    pub env_allowlist: Vec<String>,
+    /// The plugins a plugin may invoke through `Host.call`, by name.
+    ///
+    /// Calls only reach plugins added to the same [`PluginManager`](crate::PluginManager).
+    pub call_allowlist: Vec<String>,
+    /// How many `Host.call`s may be nested, so that plugins calling each other can't recurse
+    /// without bound.
+    pub max_call_depth: usize,
+    /// The clock plugins read through `Host.now` and `Host.monotonicMillis`.
+    pub clock: Clock,
+    /// The seed `Host.randomU64` starts from in every invocation, so that plugin behavior can be
+    /// reproduced, or `None` to seed it randomly. Single invocations can be seeded with
+    /// [`with_random_seed`](crate::with_random_seed) instead.
+    pub random_seed: Option<u64>,
+    /// Effects added to the `Host` module of these plugins, in addition to the built-in ones.
+    ///
+    /// The host must export a `roc_fx_<name>` function implementing each effect, or plugins
+    /// using it fail to load.
+    pub effects: Vec<HostEffect>,
+    /// The function that receives the output of `dbg` statements in these plugins.
+    ///
+    /// If this is `None`, `dbg` output is printed to stderr at info level, see
+    /// [`log`](crate::log), or emitted as `tracing` events at debug level if the `tracing`
+    /// feature is enabled and a subscriber is set.
+    pub dbg_sink: Option<DbgSink>,
+    /// Data that the effects performed by these plugins can read through
+    /// [`host_data`](crate::host_data), so that the `roc_fx_*` functions implementing them
+    /// can tell apart plugins of different managers.
+    pub host_data: Option<Arc<dyn Any + Send + Sync>>,
+    /// The implementations of the host API traits whose effects these plugins perform, see
+    /// [`HostApis`](crate::HostApis).
+    pub host_apis: HostApis,
+    /// The allocator behind the memory these plugins allocate.
+    ///
+    /// Plugins with different allocators can be used side by side, since memory is always freed
+    /// by the allocator it came from.
+    pub allocator: Allocator,
+    /// The recorder of these plugins' invocations and compile times, if any.
+    pub metrics: Option<Arc<dyn Metrics>>,
+    /// The log every invocation of these plugins is recorded in, if any.
+    pub audit_log: Option<AuditLog>,
+    /// Glob patterns like `tools/*.roc` selecting which plugin files
+    /// [`PluginManager::scan`](crate::PluginManager::scan) loads, or all if this is empty.
+    ///
+    /// Patterns are matched against paths relative to the scanned directory.
+    pub include: Vec<String>,
+    /// Glob patterns of plugin files that `PluginManager::scan` skips, even if included.
+    pub exclude: Vec<String>,
+    /// The configuration passed to plugins that declare its type in a `#[config]` header line,
+    /// as JSON matching that type. Plugins read it as `Config.config`, after `import pf.Config`.
+    ///
+    /// Loading a plugin that declares a configuration fails if this is `None`.
+    pub config: Option<serde_json::Value>,
+    /// The plugins that may be compiled and invoked, or all if this is empty.
+    ///
+    /// Entries are plugin names like `tools/slugify`, which may be pinned to a version of the
+    /// plugin's source as `tools/slugify@sha256:<hash>`, with the hash printed by `sha256sum`.
+    /// Plugins that aren't allowed are still loaded, but disabled, see [`Plugin::is_disabled`].
+    pub allow: Vec<String>,
+    /// The plugins that are disabled even if allowed, written like the entries of `allow`.
+    pub deny: Vec<String>,
+    /// Settings of individual plugins, keyed by plugin name, that take precedence over these.
+    pub overrides: BTreeMap<String, PluginOverrides>,
+    pub backend: Backend,
+    pub profile: Profile,
+    /// The Roc compiler executable, which is looked up in `PATH` unless this is a path.
+    pub roc_bin: PathBuf,
+    /// The file the platforms of plugins are generated from, or `None` to use
+    /// [`PLATFORM_TEMPLATE`], which lists the placeholders that are replaced.
+    ///
+    /// Replacing the template allows extending the platform, for example with entry functions
+    /// that wrap the plugin's functions.
+    pub platform_template: Option<PathBuf>,
 }
 
-impl DType {
-    fn as_str(&self) -> &str {
+/// The template the platforms of plugins are generated from, unless
+/// [`LoadOptions::platform_template`] is set.
+///
+/// These placeholders are replaced:
+///
+/// - `{name}`: the name of the plugin's first function
+/// - `{requires}`: the plugin's functions with their signatures, like `add : U64, U64 -> U64`
+/// - `{exposes}`: the platform's modules, `Host` and, for configured plugins, `Config`
+/// - `{imports}`: the `imports []` section required by older compilers, or nothing
+/// - `{provides}`: the entry functions called by the host, which the platform must provide
+/// - `{entries}`: the definitions of the entry functions
+/// - `{effects}`: the effects plugins can perform through `Host`, like `Host.log`
+///
+/// Other lowercase names in braces, like `{nmae}`, and placeholders that are never closed are
+/// rejected when a plugin is compiled.
+/// Other text in braces, like Roc's empty records or records written like `{ x }`, is kept as
+/// is.
+pub const PLATFORM_TEMPLATE: &str = r#"
+platform "plugin"
+    requires {} { {requires} }
+    exposes [{exposes}]
+    packages {}{imports}
+    provides [{provides}]
+
+{entries}"#;
+
+/// How optimized compiled plugins are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Build without optimizations, which is fast for iterating on plugins.
+    #[default]
+    Dev,
+    /// Build with `roc build --optimize`, which is slower but makes plugins run faster.
+    Release,
+}
+
+impl Profile {
+    fn name(self) -> &'static str {
         match self {
-            Self::Str => "Str",
-            Self::U64 => "U64",
+            Self::Dev => "dev",
+            Self::Release => "release",
         }
     }
 }
 
-impl FromStr for DType {
+impl FromStr for Profile {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let dtype = match s {
-            "Str" => Self::Str,
-            "U64" => Self::U64,
-            _ => return Err(s.into()),
-        };
-        Ok(dtype)
+        match s {
+            "dev" => Ok(Self::Dev),
+            "release" => Ok(Self::Release),
+            _ => Err(format!(
+                "unknown profile `{s}`, expected `dev` or `release`"
+            )),
+        }
     }
 }
 
-#[derive(Debug)]
-enum Value {
-    Str(RocStr),
-    U64(u64),
+/// How plugins are compiled and run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Compile plugins to native libraries, which are loaded into the host process.
+    ///
+    /// This is only supported on Unix. Native libraries import the host functions they call,
+    /// like `roc_alloc`, from the executable that loads them, but Windows DLLs can only import
+    /// from libraries they were linked against, which `roc build` doesn't allow.
+    #[default]
+    Native,
+    /// Compile plugins to wasm32 and run them in a sandbox with the given limits.
+    ///
+    /// Sandboxed plugins have no access to the host's memory or file system, but the types
+    /// they can take and return are limited to numbers, strings, lists and results.
+    #[cfg(feature = "wasm")]
+    Wasm(WasmLimits),
 }
 
-impl Value {
-    fn as_void_ptr(&self) -> *const c_void {
+impl Backend {
+    /// The name of the compilation target, as passed to `roc build --target` when
+    /// cross-compiling.
+    fn target(self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            #[cfg(feature = "wasm")]
+            Self::Wasm(_) => "wasm32",
+        }
+    }
+
+    /// The file extension of compiled plugins, which is the platform's one for native libraries,
+    /// like `so` on Linux and `dylib` on macOS.
+    fn extension(self) -> &'static str {
         match self {
-            Value::Str(s) => s as *const _ as *const _,
-            Value::U64(n) => *n as *const _,
+            Self::Native => env::consts::DLL_EXTENSION,
+            #[cfg(feature = "wasm")]
+            Self::Wasm(_) => "wasm",
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Returns the build directory of the plugin at `path`, creating it if necessary.
+    fn build_dir(&self, path: &Path) -> Result<PathBuf, PluginError> {
+        let path = fs::canonicalize(path)?;
+        let dir = self
+            .cache_dir
+            .join("build")
+            .join(cache::key(&[&*path.to_string_lossy()]));
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// Returns whether the plugin named `name` with source `code` is disabled by `allow` or
+    /// `deny`.
+    fn disables(&self, name: &str, code: &str) -> bool {
+        let hash = cache::source_hash(code);
+        let matches = |entry: &String| match entry.split_once('@') {
+            Some((entry, pin)) => entry == name && pin.strip_prefix("sha256:") == Some(&*hash),
+            None => entry == name,
+        };
+        let allowed = self.allow.is_empty() || self.allow.iter().any(matches);
+        !allowed || self.deny.iter().any(matches)
+    }
+
+    /// Removes all cached libraries and build directories.
+    pub fn clean(&self) -> Result<(), PluginError> {
+        match fs::remove_dir_all(&self.cache_dir) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            cache: true,
+            cache_dir: cache::default_dir(),
+            keep_artifacts: false,
+            lazy: false,
+            timeout: None,
+            isolated: false,
+            memory_limit: None,
+            memoize: 0,
+            retries: 0,
+            retry_backoff: Duration::from_millis(100),
+            failure_threshold: None,
+            cooldown: Duration::from_secs(30),
+            data_dir: None,
+            #[cfg(feature = "http")]
+            http_allowlist: Vec::new(),
+            env_allowlist: Vec::new(),
+            call_allowlist: Vec::new(),
+            max_call_depth: 8,
+            clock: Clock::System,
+            random_seed: None,
+            effects: Vec::new(),
+            dbg_sink: None,
+            host_data: None,
+            host_apis: HostApis::default(),
+            allocator: Allocator::System,
+            metrics: None,
+            audit_log: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            config: None,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            overrides: BTreeMap::new(),
+            backend: Backend::Native,
+            profile: Profile::Dev,
+            roc_bin: "roc".into(),
+            platform_template: None,
         }
     }
 }
 
+/// A loaded Roc plugin.
+///
+/// Plugins are `Send` and `Sync`, and can be invoked from any number of threads at once.
+/// Invocations share the plugin's library, but not any Roc values: every invocation allocates
+/// its own, and Roc code has no global mutable state. [`Value`]s themselves aren't `Send`,
+/// since they may borrow buffers allocated by Roc, so each thread passes its own arguments.
+///
+/// Reloading while other threads invoke the plugin is safe: [`Plugin::reload`] and
+/// [`Plugin::unload`] wait for running invocations to finish before replacing the library, and
+/// the old library is used until then. Builds of the same plugin are serialized, since they
+/// share a build directory.
+///
+/// Plugins that define `onLoad : Task {} Str` or `onUnload : Task {} Str` have them called
+/// after each of their libraries is loaded and before it is unloaded, for example to warm
+/// caches or flush state. A failing `onLoad` fails the load. When a library is replaced, the
+/// new library's `onLoad` runs first, and the old library's `onUnload` once the invocations
+/// still using it have finished.
 #[derive(Debug)]
 pub struct Plugin {
-    meta: Meta,
-    dylib: Library,
+    name: String,
+    functions: Vec<Meta>,
+    /// The plugin's source file, or its manifest if it was precompiled.
+    path: PathBuf,
+    options: LoadOptions,
+    /// Whether the plugin was loaded from a precompiled library, which is never rebuilt.
+    precompiled: bool,
+    isolated: AtomicBool,
+    /// Whether the plugin is disabled by [`LoadOptions::allow`] or [`LoadOptions::deny`], which
+    /// is decided again whenever its source changes.
+    disabled: AtomicBool,
+    /// What the plugin's effects may access, shared with every library loaded for it.
+    capabilities: Arc<Capabilities>,
+    state: RwLock<State>,
+    /// Held shared by every running invocation, and exclusively while a library is torn down.
+    ///
+    /// When both are needed, this lock is taken before `state`.
+    running: RwLock<()>,
+    /// Held while the plugin is compiled, so that concurrent builds don't clobber each other's
+    /// files in the build directory.
+    ///
+    /// No other lock is taken while holding this one.
+    compiling: Mutex<()>,
+    /// The state of a stateful plugin, or `None` until its `init` is called.
+    ///
+    /// Held while the state is updated, after `running` and `state` are taken.
+    roc_state: Mutex<Option<Detached<Value>>>,
+    /// The kept results of pure functions, see [`LoadOptions::memoize`].
+    ///
+    /// No other lock is taken while holding this one.
+    memo: Mutex<Detached<Memo>>,
+    breaker: Breaker,
+}
+
+// Plugins are shared between threads by `PluginManager::watch` and the async API, so keep
+// them `Send` and `Sync` as fields are added.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Plugin>();
+    assert_send_sync::<crate::PluginManager>();
+};
+
+#[derive(Debug)]
+struct State {
+    code: String,
+    /// The hash of `code`, as recorded in audit logs.
+    sha256: String,
+    /// The compiled library, which lazily loaded plugins only build on first invocation.
+    ///
+    /// Invocations hold on to the library they started with, so that a reload can swap in a
+    /// new one without waiting for them to finish.
+    library: Option<Arc<Loaded>>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    /// The name of the plugin the library belongs to, for error messages.
+    plugin: String,
+    module: Module,
+    /// The file `module` was loaded from.
+    path: PathBuf,
+    /// The exported entrypoint symbol of each function, keyed by function name.
+    symbols: HashMap<String, String>,
+    memory_limit: Option<usize>,
+    capabilities: Arc<Capabilities>,
+    /// Whether `onLoad` succeeded, so that `onUnload` is called when the library is dropped.
+    loaded: bool,
+}
+
+#[derive(Debug)]
+enum Module {
+    Native {
+        dylib: Library,
+        /// The layouts of the values the entrypoints write, keyed by function name.
+        layouts: HashMap<String, EntryLayout>,
+    },
+    #[cfg(feature = "wasm")]
+    Wasm(wasm::Module),
+}
+
+impl Drop for Loaded {
+    fn drop(&mut self) {
+        if self.loaded {
+            if let Err(error) = self.run_hook("onUnload") {
+                plugin_log!(
+                    "roc_plugin::log",
+                    LogLevel::Warn,
+                    &self.plugin,
+                    "onUnload failed: {error}",
+                );
+            }
+        }
+    }
+}
+
+impl Loaded {
+    /// Runs the lifecycle hook with the given name, if the library exports it.
+    fn run_hook(&self, name: &str) -> Result<(), PluginError> {
+        if !self.symbols.contains_key(name) {
+            return Ok(());
+        }
+        invoke_caught(self, &Meta::hook(name), &[], &CancellationToken::new()).map(drop)
+    }
+
+    fn get_entrypoint(&self, dylib: &Library, meta: &Meta) -> Result<CodePtr, PluginError> {
+        let name = &self.symbols[&meta.name];
+        let symbol = unsafe { dylib.get::<*mut c_void>(name.as_bytes()) }.map_err(|_| {
+            PluginError::SymbolNotFound {
+                name: name.clone(),
+                found: Vec::new(),
+            }
+        })?;
+        Ok(CodePtr(*symbol))
+    }
+
+    /// Invokes the function. Only wasm modules can be interrupted by cancelling `token`.
+    #[cfg_attr(not(feature = "wasm"), allow(unused_variables))]
+    fn invoke_entry(
+        &self,
+        meta: &Meta,
+        args: &[Value],
+        token: &CancellationToken,
+    ) -> Result<Value, PluginError> {
+        match &self.module {
+            Module::Native { dylib, layouts } => {
+                self.invoke_native(dylib, &layouts[&meta.name], meta, args)
+            }
+            #[cfg(feature = "wasm")]
+            Module::Wasm(module) => {
+                module.invoke(&self.plugin, &self.symbols[&meta.name], meta, args, token)
+            }
+        }
+    }
+
+    fn invoke_native(
+        &self,
+        dylib: &Library,
+        layout: &EntryLayout,
+        meta: &Meta,
+        args: &[Value],
+    ) -> Result<Value, PluginError> {
+        alloc::scope(|| {
+            let mut temps = Vec::new();
+            let ffi_args = args
+                .iter()
+                .zip(&meta.arg_types)
+                .map(|(arg, dtype)| arg.to_ffi(dtype, &mut temps))
+                .collect::<Result<Vec<_>, _>>()?;
+            let entry = self.get_entrypoint(dylib, meta)?;
+
+            let result = match &meta.return_type {
+                DType::Task(..) => unsafe { self.run_task(dylib, layout, meta, entry, &ffi_args) },
+                dtype => unsafe { call_and_decode(entry, &ffi_args, layout, dtype) },
+            };
+            drop(temps);
+            result
+        })
+    }
+
+    /// Calls the entrypoint of a function that returns a `Task`, then runs the task.
+    ///
+    /// The entrypoint only returns the task's closure data. Running it goes through the caller
+    /// function Roc generates for the closure, which performs the effects and writes the result.
+    unsafe fn run_task(
+        &self,
+        dylib: &Library,
+        layout: &EntryLayout,
+        meta: &Meta,
+        entry: CodePtr,
+        args: &[FfiValue],
+    ) -> Result<Value, PluginError> {
+        let caller_name = format!("roc__{}_0_caller", meta.entry_name());
+        let caller = dylib
+            .get::<unsafe extern "C-unwind" fn(*const u8, *const u8, *mut u8)>(
+                caller_name.as_bytes(),
+            )
+            .map_err(|_| PluginError::SymbolNotFound {
+                name: caller_name,
+                found: Vec::new(),
+            })?;
+
+        let mut closure = RocBuf::with_layout(layout.size, layout.align);
+        let out = FfiValue::Ptr(closure.as_mut_ptr() as *const c_void);
+        let args: Vec<_> = iter::once(out).chain(args.iter().copied()).collect();
+        call::<()>(entry, &args, Type::void());
+
+        let result_size = layout.task_result_size.expect("tasks have a result size");
+        let mut result = RocBuf::with_layout(result_size, meta.return_type.align());
+        alloc::in_roc(|| {
+            caller(
+                &() as *const () as *const u8,
+                closure.as_ptr(),
+                result.as_mut_ptr(),
+            )
+        });
+        Value::read_return(&meta.return_type, result.as_ptr())
+    }
 }
 
 impl Plugin {
+    /// Returns the name of the plugin, which is the name of its first function, prefixed with
+    /// the subdirectory it was discovered in, like `tools/slugify`.
     pub fn name(&self) -> &str {
-        &self.meta.name
+        &self.name
     }
 
-    pub fn load<P: AsRef<Path>>(path: P) -> Self {
-        let code = fs::read_to_string(path).unwrap();
+    /// Returns the signature of the first function of the plugin.
+    pub fn meta(&self) -> &Meta {
+        &self.functions[0]
+    }
 
-        let (header, code) = code.split_once('\n').unwrap();
-        let meta = parse_header(header);
+    /// Returns the names of all functions exposed by the plugin.
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(|m| m.name.as_str())
+    }
 
-        let dylib = compile(&meta, code);
+    /// Returns the signature of the function with the given name.
+    pub fn function(&self, name: &str) -> Result<&Meta, PluginError> {
+        self.functions
+            .iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| PluginError::FunctionNotFound(name.into()))
+    }
 
-        Self { meta, dylib }
+    /// Returns the function implementing the hook point with the given name, if any, see
+    /// [`Meta::hook`].
+    pub fn hook_function(&self, hook: &str) -> Option<&Meta> {
+        self.functions
+            .iter()
+            .find(|m| m.hook.as_deref() == Some(hook))
     }
 
-    pub fn invoke(&self) {
-        let result = catch_unwind_silent(|| match &self.meta.arg_types[..] {
-            [] => self.invoke0(),
-            [t1] => self.invoke1(*t1),
-            [t1, t2] => self.invoke2(*t1, *t2),
-            _ => unimplemented!("more than 2 arguments"),
-        });
+    /// Returns the priority of the plugin when plugins run in a batch, which is the highest
+    /// priority of its functions, see [`Meta::priority`].
+    pub fn priority(&self) -> i32 {
+        self.functions
+            .iter()
+            .map(|m| m.priority)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns the functions that run on a schedule, with their schedules.
+    pub(crate) fn scheduled(&self) -> impl Iterator<Item = (&str, Schedule)> {
+        self.functions.iter().filter_map(|m| {
+            let schedule = Schedule::parse(m.schedule.as_ref()?).expect("headers are validated");
+            Some((m.name.as_str(), schedule))
+        })
+    }
+
+    /// Returns the functions subscribing to the event with the given name, see
+    /// [`Meta::subscribe`].
+    pub fn subscriptions<'a>(&'a self, event: &'a str) -> impl Iterator<Item = &'a Meta> + 'a {
+        self.functions
+            .iter()
+            .filter(move |m| m.subscribe.as_deref() == Some(event))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+        Self::load_with(path, &LoadOptions::default())
+    }
+
+    pub fn load_with<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<Self, PluginError> {
+        Self::load_namespaced(path.as_ref(), None, options)
+    }
+
+    /// Loads the plugin at `path`, prefixing its name with `namespace`, if any.
+    pub(crate) fn load_namespaced(
+        path: &Path,
+        namespace: Option<&str>,
+        options: &LoadOptions,
+    ) -> Result<Self, PluginError> {
+        let path = fs::canonicalize(path)?;
+        let code = fs::read_to_string(&path)?;
+        let headers = declarations(&path, &code)?;
+        let functions = parse_headers(&headers)?;
+        #[cfg(feature = "wasm")]
+        if let Backend::Wasm(_) = options.backend {
+            functions
+                .iter()
+                .try_for_each(|meta| wasm::check(&meta.with_state()))?;
+            lifecycle_hooks(&code).iter().try_for_each(wasm::check)?;
+        }
+
+        let name = match namespace {
+            Some(namespace) => format!("{namespace}/{}", functions[0].name),
+            None => functions[0].name.clone(),
+        };
+        let mut options = options.clone();
+        if let Some(overrides) = options.overrides.get(&name).cloned() {
+            overrides.apply(&mut options);
+        }
+
+        let disabled = options.disables(&name, &code);
+        if !disabled {
+            // Check the configuration now, rather than when the plugin is first compiled.
+            gen_config_module(&headers, &options)?;
+            parse_packages(&headers, &path)?;
+        }
 
-        if let Err(error) = result {
-            let msg = error.downcast::<String>().unwrap();
-            eprintln!("plugin panicked: {}", *msg);
+        let capabilities = Arc::new(Capabilities::new(&name, &options));
+        let lazy = options.lazy || disabled;
+        let plugin = Self {
+            name,
+            functions,
+            path,
+            isolated: AtomicBool::new(options.isolated),
+            disabled: AtomicBool::new(disabled),
+            memo: Mutex::new(Detached(Memo::new(options.memoize))),
+            breaker: Breaker::new(&options),
+            options,
+            state: RwLock::new(State {
+                sha256: cache::source_hash(&code),
+                code,
+                library: None,
+            }),
+            running: RwLock::new(()),
+            compiling: Mutex::new(()),
+            roc_state: Mutex::new(None),
+            precompiled: false,
+            capabilities,
+        };
+        if !lazy {
+            plugin.library()?;
         }
+        Ok(plugin)
     }
 
-    unsafe fn get_entrypoint<F>(&self) -> Symbol<F> {
-        self.dylib.get(b"roc__entry_1_exposed_generic").unwrap()
+    /// Loads a plugin written by [`Plugin::precompile`] from its manifest, without invoking the
+    /// Roc compiler.
+    pub fn load_precompiled<P: AsRef<Path>>(manifest: P) -> Result<Self, PluginError> {
+        let path = fs::canonicalize(manifest)?;
+        let code = fs::read_to_string(&path)?;
+        let functions = parse_headers(&code)?;
+
+        #[allow(unused_mut)]
+        let mut options = LoadOptions::default();
+        #[cfg(feature = "wasm")]
+        if path.with_extension("wasm").exists() {
+            options.backend = Backend::Wasm(WasmLimits::default());
+        }
+
+        let name = functions[0].name.clone();
+        let capabilities = Arc::new(Capabilities::new(&name, &options));
+        let plugin = Self {
+            name,
+            functions,
+            path,
+            memo: Mutex::new(Detached(Memo::new(options.memoize))),
+            breaker: Breaker::new(&options),
+            options,
+            state: RwLock::new(State {
+                sha256: cache::source_hash(&code),
+                code,
+                library: None,
+            }),
+            running: RwLock::new(()),
+            compiling: Mutex::new(()),
+            roc_state: Mutex::new(None),
+            precompiled: true,
+            isolated: AtomicBool::new(false),
+            disabled: AtomicBool::new(false),
+            capabilities,
+        };
+        plugin.library()?;
+        Ok(plugin)
     }
 
-    fn invoke0(&self) {
-        match self.meta.return_type {
-            DType::Str => {
-                let mut result = RocStr::default();
-                unsafe {
-                    let entry = self.get_entrypoint::<unsafe extern "C" fn(*mut RocStr)>();
-                    entry(&mut result);
-                }
-                println!(">>> {result}");
+    /// Writes the plugin's library and a manifest of its functions to `dir`, so that it can be
+    /// loaded with [`Plugin::load_precompiled`] on machines without a Roc compiler.
+    ///
+    /// Returns the path of the manifest, `<name>.manifest`, which the library is placed next to.
+    pub fn precompile<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, PluginError> {
+        let library = self.library()?;
+        let code = self.state.read().unwrap().code.clone();
+        let headers = if self.precompiled {
+            Cow::Borrowed(&*code)
+        } else {
+            declarations(&self.path, &code)?
+        };
+        let extension = self.options.backend.extension();
+        write_precompiled(
+            self.name(),
+            &headers,
+            &library.path,
+            extension,
+            dir.as_ref(),
+        )
+    }
+
+    /// Loads the plugin at `path` without compiling it until it is first invoked.
+    pub fn load_lazy<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+        let options = LoadOptions {
+            lazy: true,
+            ..LoadOptions::default()
+        };
+        Self::load_with(path, &options)
+    }
+
+    /// Returns the path of the plugin's source file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the plugin is disabled by [`LoadOptions::allow`] or [`LoadOptions::deny`].
+    ///
+    /// Disabled plugins are never compiled, and invoking them fails with
+    /// [`PluginError::PluginDisabled`].
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether the plugin is invoked in a forked worker process, which is only supported on
+    /// Unix.
+    ///
+    /// Isolated invocations are slower, but a plugin that segfaults or runs out of memory only
+    /// kills its worker, which is reported as [`PluginError::WorkerFailed`].
+    ///
+    /// The worker only gets a copy of the invoking thread, and would deadlock on locks held by
+    /// the others. So isolated invocations fail with [`PluginError::WorkerFailed`] unless the
+    /// host runs a single thread when they start, which excludes watching plugins, executors and
+    /// async runtimes, and platforms where threads can't be counted, like macOS.
+    pub fn set_isolated(&self, isolated: bool) {
+        self.isolated.store(isolated, Ordering::Relaxed);
+    }
+
+    /// Sets how many results of the plugin's pure functions are kept, see
+    /// [`LoadOptions::memoize`], evicting the least recently used ones if there are more.
+    ///
+    /// Functions are pure if they don't return a `Task` and the plugin isn't stateful. Kept
+    /// results are discarded when the plugin is reloaded.
+    pub fn set_memoize(&self, capacity: usize) {
+        self.memo.lock().unwrap().0.set_capacity(capacity);
+    }
+
+    /// Returns whether the plugin is healthy, i.e. hasn't failed too often in a row, see
+    /// [`LoadOptions::failure_threshold`].
+    pub fn is_healthy(&self) -> bool {
+        !self.breaker.is_open()
+    }
+
+    /// Marks the plugin healthy again, so that it is invoked without waiting for the cooldown.
+    ///
+    /// Plugins are also marked healthy when they are reloaded.
+    pub fn reset_health(&self) {
+        self.breaker.reset();
+    }
+
+    /// Provides the store behind the plugin's `Host.kvGet` and `Host.kvSet` effects.
+    ///
+    /// Stores are only set once, when the plugin is added to a [`PluginManager`].
+    ///
+    /// [`PluginManager`]: crate::PluginManager
+    pub(crate) fn set_store(&self, store: Arc<Store>) {
+        let _ = self.capabilities.store.set(store);
+    }
+
+    /// Provides the plugins the plugin can invoke through its `Host.call` effect.
+    ///
+    /// Like the store, the plugins are only set once, when the plugin is added to a
+    /// [`PluginManager`].
+    ///
+    /// [`PluginManager`]: crate::PluginManager
+    pub(crate) fn set_peers(&self, peers: Arc<Peers>) {
+        let _ = self.capabilities.peers.set(peers);
+    }
+
+    /// Returns the compiled library of the plugin, compiling it if necessary.
+    fn library(&self) -> Result<Arc<Loaded>, PluginError> {
+        if self.is_disabled() {
+            return Err(PluginError::PluginDisabled(self.name.clone()));
+        }
+        if let Some(library) = &self.state.read().unwrap().library {
+            return Ok(Arc::clone(library));
+        }
+
+        let mut state = self.state.write().unwrap();
+        if let Some(library) = &state.library {
+            return Ok(Arc::clone(library));
+        }
+        let library = Arc::new(self.compile(&state.code, &self.options)?);
+        state.library = Some(Arc::clone(&library));
+        Ok(library)
+    }
+
+    /// Type checks the plugin's source with `roc check`, without building it.
+    ///
+    /// This reports the same errors as compiling the plugin, but is much faster. Precompiled
+    /// plugins aren't checked.
+    pub fn check(&self) -> Result<(), PluginError> {
+        if self.precompiled {
+            return Ok(());
+        }
+        let code = self.state.read().unwrap().code.clone();
+        let _compiling = self.compiling.lock().unwrap();
+        let build_dir = self.options.build_dir(&self.path)?;
+        check(
+            &self.functions,
+            &code,
+            &self.path,
+            &build_dir,
+            &self.options,
+        )
+    }
+
+    fn compile(&self, code: &str, options: &LoadOptions) -> Result<Loaded, PluginError> {
+        in_span!(
+            "plugin.load",
+            || self.load_library(code, options),
+            plugin = self.name(),
+            signature = %self.meta().signature(),
+        )
+    }
+
+    fn load_library(&self, code: &str, options: &LoadOptions) -> Result<Loaded, PluginError> {
+        #[cfg(not(unix))]
+        if options.backend == Backend::Native {
+            return Err(PluginError::Config(
+                "native plugins are only supported on Unix, see `Backend::Native`".into(),
+            ));
+        }
+
+        let _compiling = self.compiling.lock().unwrap();
+        let path = if self.precompiled {
+            self.path.with_extension(options.backend.extension())
+        } else {
+            let build_dir = options.build_dir(&self.path)?;
+            let started = Instant::now();
+            let compiled = in_span!(
+                "plugin.compile",
+                || compile(&self.functions, code, &self.path, &build_dir, options),
+                plugin = self.name(),
+                signature = %self.meta().signature(),
+            );
+            if let Some(metrics) = &options.metrics {
+                metrics.compiled(&self.name, started.elapsed(), compiled.is_ok());
             }
-            DType::U64 => {
-                let result = unsafe {
-                    let entry = self.get_entrypoint::<unsafe extern "C" fn() -> u64>();
-                    entry()
-                };
-                println!(">>> {result}");
+            compiled?
+        };
+
+        let (module, symbols) = match options.backend {
+            Backend::Native => {
+                let exports = exported_roc_symbols(&path)?;
+                let dylib = unsafe { Library::new(&path).map_err(PluginError::Load)? };
+                let entries = self.entries();
+                let symbols = resolve_symbols(&entries, &exports)?;
+                let layouts = entry_layouts(&dylib, &entries, &symbols)?;
+                (Module::Native { dylib, layouts }, symbols)
+            }
+            #[cfg(feature = "wasm")]
+            Backend::Wasm(limits) => {
+                let module = wasm::Module::load(&path, limits)?;
+                let symbols = resolve_symbols(&self.entries(), module.exports())?;
+                (Module::Wasm(module), symbols)
             }
+        };
+        let mut loaded = Loaded {
+            plugin: self.name().into(),
+            module,
+            path,
+            symbols,
+            memory_limit: options.memory_limit,
+            capabilities: Arc::clone(&self.capabilities),
+            loaded: false,
+        };
+        loaded.run_hook("onLoad")?;
+        loaded.loaded = true;
+        Ok(loaded)
+    }
+
+    /// Reads the plugin's source file, checking that the signatures of its functions have not
+    /// changed, since callers may rely on them.
+    fn read_source(&self) -> Result<String, PluginError> {
+        let code = fs::read_to_string(&self.path)?;
+        if parse_headers(&declarations(&self.path, &code)?)? != self.functions {
+            return Err(PluginError::HeaderParse(
+                "plugin signatures can't change while the plugin is loaded".into(),
+            ));
         }
+        Ok(code)
+    }
+
+    /// Unloads the plugin's library, waiting for running invocations to finish first.
+    ///
+    /// The plugin is compiled again when it is next invoked. Calling this from within an
+    /// invocation of the same plugin deadlocks.
+    pub fn unload(&self) {
+        let _idle = self.running.write().unwrap();
+        self.state.write().unwrap().library = None;
+    }
+
+    /// Rebuilds the plugin from its source file, bypassing the cache, and replaces its library
+    /// once running invocations have finished.
+    ///
+    /// Precompiled plugins aren't rebuilt, but their library is loaded again.
+    ///
+    /// Calling this from within an invocation of the same plugin deadlocks.
+    pub fn reload(&self) -> Result<(), PluginError> {
+        let code = self.read_source()?;
+        let disabled = self.options.disables(&self.name, &code);
+        let library = if disabled {
+            None
+        } else {
+            let options = LoadOptions {
+                cache: false,
+                ..self.options.clone()
+            };
+            Some(Arc::new(self.compile(&code, &options)?))
+        };
+
+        let _idle = self.running.write().unwrap();
+        let mut state = self.state.write().unwrap();
+        state.sha256 = cache::source_hash(&code);
+        state.code = code;
+        state.library = library;
+        self.disabled.store(disabled, Ordering::Relaxed);
+        drop(state);
+        self.memo.lock().unwrap().0.clear();
+        self.breaker.reset();
+        Ok(())
+    }
+
+    /// Recompiles the plugin from its source file and swaps in the new library.
+    ///
+    /// Unlike [`Plugin::reload`], this doesn't wait for running invocations, which finish with
+    /// the old library instead.
+    pub(crate) fn refresh(&self) -> Result<(), PluginError> {
+        let code = self.read_source()?;
+        let disabled = self.options.disables(&self.name, &code);
+
+        let compiled = self.state.read().unwrap().library.is_some();
+        let library = if compiled && !disabled {
+            Some(Arc::new(self.compile(&code, &self.options)?))
+        } else {
+            None
+        };
+
+        let mut state = self.state.write().unwrap();
+        state.sha256 = cache::source_hash(&code);
+        state.code = code;
+        state.library = library;
+        self.disabled.store(disabled, Ordering::Relaxed);
+        drop(state);
+        self.memo.lock().unwrap().0.clear();
+        self.breaker.reset();
+        Ok(())
+    }
+
+    /// Invokes the first function of the plugin with generated arguments.
+    pub fn invoke(&self) -> Result<Value, PluginError> {
+        self.invoke_function(&self.meta().name)
     }
 
-    fn invoke1(&self, t1: DType) {
-        let a1 = generate_value(t1);
+    pub fn invoke_function(&self, name: &str) -> Result<Value, PluginError> {
+        let args = self.generated_args(name)?;
+        self.invoke_function_with(name, &args)
+    }
+
+    /// Returns the arguments the function is invoked with by [`Plugin::invoke_function`].
+    pub fn generated_args(&self, name: &str) -> Result<Vec<Value>, PluginError> {
+        let meta = self.function(name)?;
+        Ok(meta.arg_types.iter().map(generate_value).collect())
+    }
+
+    /// Invokes the first function of the plugin with the given arguments.
+    pub fn invoke_with(&self, args: &[Value]) -> Result<Value, PluginError> {
+        self.invoke_function_with(&self.meta().name, args)
+    }
+
+    pub fn invoke_function_with(&self, name: &str, args: &[Value]) -> Result<Value, PluginError> {
+        self.invoke_function_cancellable(name, args, &CancellationToken::new())
+    }
 
-        match self.meta.return_type {
-            DType::Str => {
-                let mut result = RocStr::default();
-                unsafe {
-                    let entry =
-                        self.get_entrypoint::<unsafe extern "C" fn(*mut RocStr, *const c_void)>();
-                    entry(&mut result, a1.as_void_ptr());
+    /// Invokes the first function of the plugin with the given arguments, unless `token` is
+    /// cancelled first. See [`CancellationToken`] for which invocations can be interrupted.
+    pub fn invoke_cancellable(
+        &self,
+        args: &[Value],
+        token: &CancellationToken,
+    ) -> Result<Value, PluginError> {
+        self.invoke_function_cancellable(&self.meta().name, args, token)
+    }
+
+    pub fn invoke_function_cancellable(
+        &self,
+        name: &str,
+        args: &[Value],
+        token: &CancellationToken,
+    ) -> Result<Value, PluginError> {
+        // Audit logs record the actual time, rather than the one plugins see.
+        let timestamp_ms = Clock::System.now_millis();
+        let started = Instant::now();
+        let result = in_span!(
+            "plugin.invoke",
+            || self.invoke_retrying(name, args, token),
+            plugin = self.name(),
+            function = name,
+            signature = %self.function(name).map(Meta::signature).unwrap_or_default(),
+        );
+        let duration = started.elapsed();
+        if let Some(metrics) = &self.options.metrics {
+            metrics.invoked(&self.name, name, duration, &result);
+        }
+        if let Some(audit_log) = &self.options.audit_log {
+            let sha256 = self.state.read().unwrap().sha256.clone();
+            audit_log.record(audit::Entry {
+                timestamp_ms,
+                plugin: &self.name,
+                sha256: &sha256,
+                function: name,
+                meta: self.function(name).ok(),
+                args,
+                result: &result,
+                duration,
+            });
+        }
+        result
+    }
+
+    /// Invokes the function, retrying if it panics, see [`LoadOptions::retries`].
+    fn invoke_retrying(
+        &self,
+        name: &str,
+        args: &[Value],
+        token: &CancellationToken,
+    ) -> Result<Value, PluginError> {
+        self.breaker.check(&self.name)?;
+        let mut backoff = self.options.retry_backoff;
+        let mut retries = self.options.retries;
+        loop {
+            let result = self.invoke_once(name, args, token);
+            if let Err(PluginError::Panic { .. }) = result {
+                if retries > 0 && !token.is_cancelled() {
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    retries -= 1;
+                    continue;
                 }
-                println!(">>> {result}");
             }
-            DType::U64 => {
-                let result = unsafe {
-                    let entry = self.get_entrypoint::<unsafe extern "C" fn(*const c_void) -> u64>();
-                    entry(a1.as_void_ptr())
-                };
-                println!(">>> {result}");
+            self.breaker.record(&result);
+            return result;
+        }
+    }
+
+    /// Invokes the first function of the plugin with the given arguments, reporting the memory
+    /// allocated through Roc meanwhile, see [`AllocReport`].
+    pub fn invoke_traced(&self, args: &[Value]) -> (Result<Value, PluginError>, AllocReport) {
+        self.invoke_function_traced(&self.meta().name, args)
+    }
+
+    /// Invokes the function with the given arguments, reporting the memory allocated through
+    /// Roc meanwhile, see [`AllocReport`].
+    ///
+    /// Allocations are tracked on the calling thread, so the function runs on it, in the host
+    /// process, even if the plugin is isolated or has a timeout, and its result isn't taken
+    /// from or added to the memoized results. Wasm plugins allocate in their own memory, which
+    /// isn't tracked.
+    ///
+    /// The report includes the allocations the host makes to pass the arguments and read the
+    /// result, so outstanding allocations that the result doesn't hold on to point at leaks in
+    /// either the plugin or the host.
+    pub fn invoke_function_traced(
+        &self,
+        name: &str,
+        args: &[Value],
+    ) -> (Result<Value, PluginError>, AllocReport) {
+        roc_host::traced(|| self.invoke_function_with(name, args))
+    }
+
+    /// Invokes the function with the given arguments, returning its outcome with how long it
+    /// took, see [`InvocationReport`].
+    ///
+    /// If `trace` is set, the memory allocated through Roc is reported too, with the function
+    /// running like [`Plugin::invoke_function_traced`].
+    pub fn invoke_reported(&self, name: &str, args: &[Value], trace: bool) -> InvocationReport {
+        let started = Instant::now();
+        let (result, allocations) = if trace {
+            let (result, allocations) = self.invoke_function_traced(name, args);
+            (result, Some(allocations))
+        } else {
+            (self.invoke_function_with(name, args), None)
+        };
+        let duration = started.elapsed();
+
+        let mut warnings = Vec::new();
+        if trace {
+            if self.isolated.load(Ordering::Relaxed) {
+                warnings.push("ran in the host process instead of an isolated worker".into());
+            }
+            let timeout = self
+                .function(name)
+                .ok()
+                .and_then(|meta| meta.timeout.or(self.options.timeout));
+            if let Some(timeout) = timeout.filter(|timeout| duration > *timeout) {
+                warnings.push(format!("took longer than its timeout of {timeout:?}"));
+            }
+        }
+        if let (Err(_), Some(allocations)) = (&result, &allocations) {
+            // Failed invocations have no result to hold on to their allocations.
+            if allocations.outstanding > 0 {
+                warnings.push(format!(
+                    "leaked {} allocations of {} bytes",
+                    allocations.outstanding, allocations.outstanding_bytes
+                ));
             }
         }
+
+        InvocationReport {
+            plugin: self.name.clone(),
+            function: name.into(),
+            duration,
+            result,
+            allocations,
+            warnings,
+        }
     }
 
-    fn invoke2(&self, t1: DType, t2: DType) {
-        let a1 = generate_value(t1);
-        let a2 = generate_value(t2);
+    /// Invokes the function without retrying, see [`LoadOptions::retries`].
+    fn invoke_once(
+        &self,
+        name: &str,
+        args: &[Value],
+        token: &CancellationToken,
+    ) -> Result<Value, PluginError> {
+        let meta = self.function(name)?;
+        if args.len() != meta.arg_types.len() {
+            return Err(PluginError::ArgumentCount {
+                expected: meta.arg_types.len(),
+                found: args.len(),
+            });
+        }
 
-        match self.meta.return_type {
-            DType::Str => {
-                let mut result = RocStr::default();
-                unsafe {
-                    let entry =
-                        self.get_entrypoint::<unsafe extern "C" fn(*mut RocStr, *const c_void, *const c_void)>();
-                    entry(&mut result, a1.as_void_ptr(), a2.as_void_ptr());
+        // Functions that don't return a `Task` can't perform effects, so they return the same
+        // result for the same arguments, unless the plugin is stateful.
+        let pure = meta.state.is_none() && !matches!(meta.return_type, DType::Task(..));
+        // Traced invocations must run, on this thread, see `Plugin::invoke_function_traced`.
+        let traced = roc_host::is_traced();
+        let key = if pure && !traced && self.memo.lock().unwrap().0.capacity() > 0 {
+            let key = (name.to_owned(), args.iter().map(Value::detach).collect());
+            if let Some(value) = self.memo.lock().unwrap().0.get(&key) {
+                if let Some(metrics) = &self.options.metrics {
+                    metrics.cache_hit(&self.name, name);
                 }
-                println!(">>> {result}");
+                return Ok(value);
             }
-            DType::U64 => {
-                let mut result = 0;
-                unsafe {
-                    let entry = self.get_entrypoint::<unsafe extern "C" fn(*mut u64, *const c_void, *const c_void)>();
-                    entry(&mut result, a1.as_void_ptr(), a2.as_void_ptr())
-                };
-                println!(">>> {result}");
+            Some(key)
+        } else {
+            None
+        };
+
+        let _running = self.running.read().unwrap();
+        let library = self.library()?;
+        // Compiling may take a while, so check again afterwards.
+        if token.is_cancelled() {
+            return Err(PluginError::Cancelled);
+        }
+        let isolated = self.isolated.load(Ordering::Relaxed) && !traced;
+        let timeout = |meta: &Meta| meta.timeout.or(self.options.timeout).filter(|_| !traced);
+        let run = |meta: &Meta, args: &[Value]| match (isolated, timeout(meta)) {
+            (true, timeout) => invoke_isolated(&library, meta, args, timeout, token),
+            (false, Some(timeout)) => {
+                invoke_with_timeout(Arc::clone(&library), meta, args, timeout, token)
             }
+            (false, None) => invoke_caught(&library, meta, args, token),
+        };
+        let Some(state_type) = &meta.state else {
+            let result = run(meta, args);
+            if let (Some(key), Ok(value)) = (key, &result) {
+                self.memo.lock().unwrap().0.insert(key, value.detach());
+            }
+            return result;
+        };
+
+        // Hold the state until it is replaced, so that concurrent updates aren't lost.
+        let mut state = self.roc_state.lock().unwrap();
+        let current = match state.take() {
+            Some(Detached(current)) => current,
+            None => run(&Meta::init(state_type), &[])?,
+        };
+        let roc_meta = meta.with_state();
+        let roc_args: Vec<_> = iter::once(current.clone())
+            .chain(args.iter().cloned())
+            .collect();
+        match run(&roc_meta, &roc_args) {
+            Ok(Value::Tuple(mut items)) if items.len() == 2 => {
+                let response = items.pop().unwrap();
+                *state = Some(Detached(items.pop().unwrap().detach()));
+                Ok(response)
+            }
+            // Failed updates leave the state as it was.
+            Ok(value) => {
+                *state = Some(Detached(current));
+                Err(PluginError::TypeMismatch {
+                    expected: roc_meta.return_type.to_string(),
+                    found: value.type_name(),
+                })
+            }
+            Err(error) => {
+                *state = Some(Detached(current));
+                Err(error)
+            }
+        }
+    }
+
+    /// Returns whether the plugin is stateful, i.e. declares a `#[state]` that its functions
+    /// take and return, like `update : State, Msg -> (State, Response)`.
+    pub fn is_stateful(&self) -> bool {
+        self.functions.iter().any(|m| m.state.is_some())
+    }
+
+    /// Discards the state of a stateful plugin, so that the next invocation starts over from
+    /// the state returned by its `init : {} -> State`.
+    ///
+    /// The host holds the state between invocations, and passes it to the function invoked,
+    /// keeping the state it returns for the next invocation. Failed invocations leave the state
+    /// as it was. The state is kept when the plugin is reloaded, since its type can't change.
+    pub fn reset_state(&self) {
+        *self.roc_state.lock().unwrap() = None;
+    }
+
+    /// Returns the functions that the plugin's platform provides to the host, which include the
+    /// `init` of stateful plugins.
+    fn entries(&self) -> Vec<Meta> {
+        let state = self.functions.iter().find_map(|m| m.state.as_ref());
+        self.functions
+            .iter()
+            .cloned()
+            .chain(state.map(Meta::init))
+            .collect()
+    }
+
+    /// Invokes the first function of the plugin with the given arguments, making `ctx` available
+    /// to the host effects it performs through [`invocation_context`].
+    ///
+    /// Invocations with a timeout run on a separate thread that may outlive this call, so their
+    /// effects don't see the context.
+    ///
+    /// [`invocation_context`]: crate::invocation_context
+    pub fn invoke_with_ctx(&self, ctx: &dyn Any, args: &[Value]) -> Result<Value, PluginError> {
+        self.invoke_function_with_ctx(&self.meta().name, ctx, args)
+    }
+
+    pub fn invoke_function_with_ctx(
+        &self,
+        name: &str,
+        ctx: &dyn Any,
+        args: &[Value],
+    ) -> Result<Value, PluginError> {
+        effects::with_context(ctx, || self.invoke_function_with(name, args))
+    }
+
+    /// Invokes the first function of the plugin on tokio's blocking thread pool, so that long
+    /// running plugins don't stall the async runtime.
+    ///
+    /// The invocation starts right away, and keeps running if the returned future is dropped.
+    /// This must be called from within a tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn invoke_async(
+        self: &Arc<Self>,
+        args: &[Value],
+    ) -> impl Future<Output = Result<Value, PluginError>> + Send {
+        self.invoke_function_async(&self.meta().name, args)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub fn invoke_function_async(
+        self: &Arc<Self>,
+        name: &str,
+        args: &[Value],
+    ) -> impl Future<Output = Result<Value, PluginError>> + Send {
+        let plugin = Arc::clone(self);
+        let name = name.to_owned();
+        let args = Detached(args.iter().map(Value::detach).collect::<Vec<_>>());
+        let seed = effects::seed();
+        let task = tokio::task::spawn_blocking(move || {
+            // Capture the wrapper as a whole, rather than just its contents.
+            let args = args;
+            let result = effects::with_seed(seed, || plugin.invoke_function_with(&name, &args.0));
+            Detached(result)
+        });
+
+        async move {
+            match task.await {
+                Ok(Detached(result)) => result,
+                Err(error) => match error.try_into_panic() {
+                    Ok(payload) => panic::resume_unwind(payload),
+                    Err(_) => Err(PluginError::WorkerFailed(
+                        "the blocking task was cancelled".into(),
+                    )),
+                },
+            }
+        }
+    }
+
+    /// Invokes the first function of the plugin with typed arguments and return value.
+    ///
+    /// The Rust types are checked against the function's declared signature before calling it.
+    pub fn call<A: IntoRocArgs, R: FromRocReturn>(&self, args: A) -> Result<R, PluginError> {
+        self.call_function(&self.meta().name, args)
+    }
+
+    pub fn call_function<A: IntoRocArgs, R: FromRocReturn>(
+        &self,
+        name: &str,
+        args: A,
+    ) -> Result<R, PluginError> {
+        let meta = self.function(name)?;
+        let expected = Meta {
+            arg_types: A::roc_types(),
+            return_type: R::roc_type(),
+            ..meta.clone()
+        };
+        if meta.arg_types != expected.arg_types || *meta.ok_type() != expected.return_type {
+            return Err(PluginError::TypeMismatch {
+                expected: meta.signature(),
+                found: expected.signature(),
+            });
         }
+
+        let result = self.invoke_function_with(name, &args.into_roc_values())?;
+        let found = result.type_name();
+        R::from_roc_value(result).ok_or_else(|| PluginError::TypeMismatch {
+            expected: expected.return_type.to_string(),
+            found,
+        })
     }
 }
 
-fn parse_header(header: &str) -> Meta {
-    static RE: LazyLock<Regex> = LazyLock::new(|| {
-        Regex::new(r"^#\[plugin\] (?P<name>\w+) : ((?P<args>[\w, ]+) -> )?(?P<ret>\w+)$").unwrap()
-    });
+unsafe fn call_and_decode(
+    entry: CodePtr,
+    args: &[FfiValue],
+    layout: &EntryLayout,
+    return_type: &DType,
+) -> Result<Value, PluginError> {
+    // Generic entrypoints write their return value through an out pointer, whatever its type.
+    let mut buf = RocBuf::with_layout(layout.size, layout.align);
+    let out = FfiValue::Ptr(buf.as_mut_ptr() as *const c_void);
+    let args: Vec<_> = iter::once(out).chain(args.iter().copied()).collect();
+    call::<()>(entry, &args, Type::void());
+    Value::read_return(return_type, buf.as_ptr())
+}
+
+unsafe fn call<R>(entry: CodePtr, args: &[FfiValue], ret: Type) -> R {
+    let cif = Cif::new(args.iter().map(FfiValue::ffi_type), ret);
+    let args: Vec<_> = args.iter().map(FfiValue::as_arg).collect();
+    alloc::in_roc(|| cif.call(entry, &args))
+}
 
-    let caps = RE.captures(header).unwrap();
-    let name = &caps["name"];
-    let args = caps.name("args").map_or("", |m| m.as_str());
-    let ret = &caps["ret"];
+/// Compiles the plugin at `path` into `dir` like [`Plugin::precompile`], without loading it.
+///
+/// This is meant for build scripts, which can't load plugin libraries since they don't provide
+/// the host functions plugins link against.
+pub fn precompile<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    dir: Q,
+    options: &LoadOptions,
+) -> Result<PathBuf, PluginError> {
+    let path = path.as_ref();
+    let code = fs::read_to_string(path)?;
+    let headers = declarations(path, &code)?;
+    let functions = parse_headers(&headers)?;
+    let build_dir = options.build_dir(path)?;
+    let library = compile(&functions, &code, path, &build_dir, options)?;
+    let extension = options.backend.extension();
+    write_precompiled(
+        &functions[0].name,
+        &headers,
+        &library,
+        extension,
+        dir.as_ref(),
+    )
+}
 
-    let arg_types = args
-        .split_terminator(", ")
-        .map(|x| x.parse().unwrap())
+/// Copies a plugin's library to `dir` and writes its manifest, which lists the plugin's headers.
+///
+/// `headers` are the plugin's [`declarations`].
+fn write_precompiled(
+    name: &str,
+    headers: &str,
+    library: &Path,
+    extension: &str,
+    dir: &Path,
+) -> Result<PathBuf, PluginError> {
+    let headers: Vec<_> = headers
+        .lines()
+        .filter(|l| is_header(l) || l.starts_with("#[state] "))
         .collect();
-    let return_type = ret.parse().unwrap();
 
-    Meta {
+    // Namespaced plugins are placed in subdirectories.
+    let manifest_path = dir.join(name).with_extension("manifest");
+    fs::create_dir_all(manifest_path.parent().unwrap_or(dir))?;
+    fs::copy(library, manifest_path.with_extension(extension))?;
+    fs::write(&manifest_path, headers.join("\n") + "\n")?;
+    Ok(manifest_path)
+}
+
+/// Returns the header lines declaring the plugin whose source `code` was read from `path`, which
+/// are either part of the source or generated from its sidecar, see [`sidecar::read`].
+fn declarations<'a>(path: &Path, code: &'a str) -> Result<Cow<'a, str>, PluginError> {
+    let declared = code.lines().any(|l| {
+        is_header(l)
+            || ["#[config] ", "#[package] ", "#[state] "]
+                .iter()
+                .any(|p| l.starts_with(p))
+    });
+    match sidecar::read(path)? {
+        Some(_) if declared => Err(PluginError::HeaderParse(format!(
+            "the plugin is declared by both header lines and {}",
+            sidecar::path(path).display()
+        ))),
+        Some(headers) => Ok(Cow::Owned(headers)),
+        None => Ok(Cow::Borrowed(code)),
+    }
+}
+
+fn parse_headers(code: &str) -> Result<Vec<Meta>, PluginError> {
+    let state = parse_state(code)?;
+    let mut functions: Vec<Meta> = Vec::new();
+    for line in code.lines().filter(|l| is_header(l)) {
+        let meta = parse_header(line, state.as_ref())?;
+        if functions.iter().any(|m| m.name == meta.name) {
+            let msg = format!("duplicate plugin function `{}`", meta.name);
+            return Err(PluginError::HeaderParse(msg));
+        }
+        let same_hook = |m: &Meta| meta.hook.is_some() && m.hook == meta.hook;
+        if let Some(other) = functions.iter().find(|m| same_hook(m)) {
+            return Err(PluginError::HeaderParse(format!(
+                "`{}` and `{}` both implement hook `{}`",
+                other.name,
+                meta.name,
+                meta.hook.as_deref().unwrap_or_default()
+            )));
+        }
+        if HOOKS.contains(&meta.name.as_str()) {
+            let msg = format!(
+                "`{}` is a lifecycle hook, so it can't be a plugin function",
+                meta.name
+            );
+            return Err(PluginError::HeaderParse(msg));
+        }
+        if state.is_some() && meta.name == "init" {
+            return Err(PluginError::HeaderParse(
+                "`init` is called by the host, so it can't be a plugin function".into(),
+            ));
+        }
+        functions.push(meta);
+    }
+    if state.is_some() && functions.iter().all(|m| m.state.is_none()) {
+        return Err(PluginError::HeaderParse(
+            "the plugin declares a `#[state]` but none of its functions take it".into(),
+        ));
+    }
+
+    if functions.is_empty() {
+        return Err(PluginError::HeaderParse(
+            "no `#[plugin]` header found".into(),
+        ));
+    }
+    check_annotations(code, &functions)?;
+    Ok(functions)
+}
+
+/// Checks that the Roc annotations of the plugin's functions agree with their headers, since
+/// the host marshals values as the types in the headers.
+///
+/// Annotations that can't be resolved, like ones using type aliases or spanning several lines,
+/// are left to the Roc compiler.
+fn check_annotations(code: &str, functions: &[Meta]) -> Result<(), PluginError> {
+    for meta in functions.iter().filter(|m| m.state.is_none()) {
+        let annotation = code.lines().find_map(|line| {
+            line.strip_prefix(meta.name.as_str())?
+                .trim_start()
+                .strip_prefix(':')
+        });
+        let Some(annotation) = annotation else {
+            continue;
+        };
+        let Ok(signature) = Signature::parse(annotation) else {
+            continue;
+        };
+        let resolved = signature
+            .args
+            .iter()
+            .map(TypeExpr::to_dtype)
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|args| Ok((args, signature.ret.to_dtype()?)));
+        let Ok((arg_types, return_type)) = resolved else {
+            continue;
+        };
+        if arg_types != meta.arg_types || return_type != meta.return_type {
+            return Err(PluginError::HeaderParse(format!(
+                "the header of `{}` declares `{}`, but its annotation is `{}`",
+                meta.name,
+                meta.signature(),
+                annotation.trim()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn is_header(line: &str) -> bool {
+    line.starts_with("#[plugin]") || line.starts_with("#[plugin(")
+}
+
+/// Parses the type of the state declared by a `#[state]` header line, for stateful plugins.
+///
+/// Stateful plugins define `init : {} -> State`, and functions like
+/// `update : State, Msg -> (State, Response)`, where `State` is an alias of the declared type.
+fn parse_state(code: &str) -> Result<Option<DType>, PluginError> {
+    let mut headers = code.lines().filter_map(|l| l.strip_prefix("#[state] "));
+    let Some(header) = headers.next() else {
+        return Ok(None);
+    };
+    if headers.next().is_some() {
+        return Err(PluginError::HeaderParse(
+            "duplicate `#[state]` header".into(),
+        ));
+    }
+
+    let dtype = parse_dtype(header)?;
+    let unsupported = |t: &DType| {
+        matches!(
+            t,
+            DType::Result(..) | DType::Task(..) | DType::Unit | DType::Dict(..)
+        )
+    };
+    if dtype.contains(unsupported) {
+        return Err(PluginError::HeaderParse(format!(
+            "unsupported state type `{dtype}`"
+        )));
+    }
+    Ok(Some(dtype))
+}
+
+/// Parses a header like `#[plugin(timeout = "5s")] greet : Str -> Str`, of a plugin whose
+/// `#[state]` is `state`, if it is stateful.
+fn parse_header(header: &str, state: Option<&DType>) -> Result<Meta, PluginError> {
+    let malformed = || PluginError::HeaderParse(format!("malformed header `{header}`"));
+
+    let rest = header.strip_prefix("#[plugin").ok_or_else(malformed)?;
+    let (attrs, rest) = match rest.strip_prefix('(') {
+        Some(rest) => parse_attrs(rest)?,
+        None => (
+            Attrs::default(),
+            rest.strip_prefix(']').ok_or_else(malformed)?,
+        ),
+    };
+    let (name, sig) = rest.split_once(':').ok_or_else(malformed)?;
+    let name = name.trim();
+    let valid_name = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Err(malformed());
+    }
+
+    // Point errors at the header as a whole, rather than just the signature.
+    let offset = header.len() - sig.len();
+    let type_error =
+        |error: TypeError| PluginError::HeaderParse(error.offset(offset).render(header));
+    let signature = Signature::parse(sig).map_err(type_error)?;
+
+    // Functions of stateful plugins take the state first, and return it along with their result.
+    let is_state = |t: &TypeExpr| match t {
+        TypeExpr::Apply { name, args, .. } => name == "State" && args.is_empty(),
+        _ => false,
+    };
+    let (state, args, ret) = match (state, &signature.args[..], &signature.ret) {
+        (Some(state), [first, args @ ..], TypeExpr::Tuple { elems, .. })
+            if is_state(first) && elems.len() == 2 && is_state(&elems[0]) =>
+        {
+            (Some(state.clone()), args, &elems[1])
+        }
+        _ => (None, &signature.args[..], &signature.ret),
+    };
+    let arg_types: Vec<_> = args
+        .iter()
+        .map(TypeExpr::to_dtype)
+        .collect::<Result<_, _>>()
+        .map_err(type_error)?;
+    let return_type = ret.to_dtype().map_err(type_error)?;
+    let passed: Vec<_> = state.iter().chain(&arg_types).cloned().collect();
+    value::check_dec_args(&passed)
+        .map_err(|error| PluginError::HeaderParse(format!("`{name}`: {error}")))?;
+    if attrs.schedule.is_some() && !arg_types.is_empty() {
+        return Err(PluginError::HeaderParse(format!(
+            "`{name}` runs on a schedule, so it can't take arguments"
+        )));
+    }
+    if attrs.subscribe.is_some() && arg_types.len() != 1 {
+        return Err(PluginError::HeaderParse(format!(
+            "`{name}` subscribes to an event, so it must take the event's payload as its only \
+             argument"
+        )));
+    }
+
+    let unsupported_response = |t: &DType| {
+        matches!(
+            t,
+            DType::Result(..) | DType::Task(..) | DType::Unit | DType::Dict(..)
+        )
+    };
+    if state.is_some() && return_type.contains(unsupported_response) {
+        return Err(PluginError::HeaderParse(format!(
+            "unsupported result type `{return_type}` of a stateful function"
+        )));
+    }
+
+    let is_result = |t: &DType| matches!(t, DType::Result(..) | DType::Task(..));
+    let nested_result = match &return_type {
+        DType::Result(ok, err) | DType::Task(ok, err) => {
+            ok.contains(is_result) || err.contains(is_result)
+        }
+        dtype => dtype.contains(is_result),
+    };
+    if nested_result || arg_types.iter().any(|t| t.contains(is_result)) {
+        return Err(PluginError::HeaderParse(
+            "`Result` and `Task` are only supported as the return type".into(),
+        ));
+    }
+
+    let is_unit = |t: &DType| matches!(t, DType::Unit);
+    let nested_unit = |t: &DType| !is_unit(t) && t.contains(is_unit);
+    let invalid_unit = match &return_type {
+        DType::Result(ok, err) | DType::Task(ok, err) => nested_unit(ok) || nested_unit(err),
+        dtype => nested_unit(dtype),
+    };
+    if invalid_unit || arg_types.iter().any(|t| t.contains(is_unit)) {
+        return Err(PluginError::HeaderParse(
+            "`{}` is only supported as the return type".into(),
+        ));
+    }
+
+    // Dicts are converted from and to lists by the generated entry functions, which only
+    // works at the top level.
+    let is_dict = |t: &DType| matches!(t, DType::Dict(..));
+    let nested_dict = |t: &DType| !is_dict(t) && t.contains(is_dict);
+    if nested_dict(&return_type) || arg_types.iter().any(nested_dict) {
+        return Err(PluginError::HeaderParse(
+            "`Dict` is only supported as an argument or return type".into(),
+        ));
+    }
+
+    Ok(Meta {
         name: name.into(),
         arg_types,
         return_type,
+        timeout: attrs.timeout,
+        description: attrs.description,
+        version: attrs.version,
+        tags: attrs.tags,
+        hook: attrs.hook,
+        subscribe: attrs.subscribe,
+        schedule: attrs.schedule,
+        priority: attrs.priority,
+        state,
+    })
+}
+
+/// Parses the signature of a hook point defined by the host, like `Str -> Str`, returning it as
+/// the signature of a function named like the hook.
+pub(crate) fn parse_hook(name: &str, signature: &str) -> Result<Meta, PluginError> {
+    parse_header(&format!("#[plugin] {name} : {signature}"), None)
+}
+
+/// The attributes set in `#[plugin(...)]`.
+#[derive(Default)]
+struct Attrs {
+    timeout: Option<Duration>,
+    description: Option<String>,
+    version: Option<String>,
+    tags: Vec<String>,
+    hook: Option<String>,
+    subscribe: Option<String>,
+    schedule: Option<String>,
+    priority: i32,
+}
+
+/// Parses attributes like `timeout = "5s", version = "1.2")]`, the part of a header following
+/// `#[plugin(`, returning them along with the rest of the header.
+///
+/// Values are parsed up to the closing quote, so they may contain commas and parentheses.
+fn parse_attrs(s: &str) -> Result<(Attrs, &str), PluginError> {
+    let malformed = |attr: &str| PluginError::HeaderParse(format!("malformed attribute `{attr}`"));
+
+    let mut attrs = Attrs::default();
+    let mut seen = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.starts_with(")]") {
+        if rest.is_empty() {
+            return Err(PluginError::HeaderParse("unclosed `#[plugin(`".into()));
+        }
+        let attr = rest;
+        let (key, value) = attr.split_once('=').ok_or_else(|| malformed(attr))?;
+        let key = key.trim();
+        let value = value
+            .trim_start()
+            .strip_prefix('"')
+            .ok_or_else(|| malformed(attr))?;
+        let (value, after) = value.split_once('"').ok_or_else(|| malformed(attr))?;
+        let attr = &attr[..attr.len() - after.len()];
+        if seen.contains(&key) {
+            return Err(PluginError::HeaderParse(format!(
+                "duplicate attribute `{key}`"
+            )));
+        }
+        seen.push(key);
+
+        match key {
+            "timeout" => {
+                attrs.timeout = Some(parse_duration(value).ok_or_else(|| malformed(attr))?)
+            }
+            "description" => attrs.description = Some(value.into()),
+            "version" => attrs.version = Some(value.into()),
+            "hook" => attrs.hook = Some(value.into()),
+            "subscribe" => attrs.subscribe = Some(value.into()),
+            "schedule" => {
+                Schedule::parse(value).map_err(|msg| {
+                    PluginError::HeaderParse(format!("invalid schedule `{value}`: {msg}"))
+                })?;
+                attrs.schedule = Some(value.into());
+            }
+            "priority" => attrs.priority = value.parse().map_err(|_| malformed(attr))?,
+            "tags" => {
+                attrs.tags = value.split(',').map(str::trim).map(String::from).collect();
+                if attrs.tags.iter().any(String::is_empty) {
+                    return Err(malformed(attr));
+                }
+            }
+            _ => {
+                return Err(PluginError::HeaderParse(format!(
+                    "unknown attribute `{key}`"
+                )))
+            }
+        }
+
+        let after = after.trim_start();
+        rest = match after.strip_prefix(',') {
+            Some(after) => after.trim_start(),
+            None if after.starts_with(")]") => after,
+            None => return Err(malformed(attr)),
+        };
     }
+    Ok((attrs, &rest[2..]))
 }
 
-fn compile(meta: &Meta, code: &str) -> Library {
-    let tmpdir = tempfile::tempdir().unwrap();
-    let platform_file_path = tmpdir.path().join("platform.roc");
-    let app_file_path = tmpdir.path().join("plugin.roc");
-    let dylib_file_path = tmpdir.path().join("plugin.dylib");
-
-    let platform_file = File::create(&platform_file_path).unwrap();
-    let platform_code = gen_platform_code(&meta);
-    write!(&platform_file, "{platform_code}").unwrap();
-
-    let app_file = File::create(&app_file_path).unwrap();
-    let app_header = format!(
-        r#"app [{name}] {{ pf: platform "{path}" }}"#,
-        name = meta.name,
-        path = platform_file_path.to_str().unwrap(),
-    );
-    write!(&app_file, "{app_header}\n").unwrap();
-    write!(&app_file, "{code}").unwrap();
-
-    let status = Command::new("roc")
-        .args(["build", "--lib"])
-        .args(["--output", dylib_file_path.to_str().unwrap()])
-        .arg(app_file_path)
-        .stdout(Stdio::null())
-        .status()
-        .unwrap();
+/// Parses the packages declared with header lines like `#[package] json "https://..."`,
+/// returning their shorthands and locations in the order they are declared.
+///
+/// Packages are either URLs of released packages, which name a hash of their contents, or
+/// directories relative to the plugin at `source`.
+fn parse_packages(headers: &str, source: &Path) -> Result<Vec<(String, String)>, PluginError> {
+    let mut packages: Vec<(String, String)> = Vec::new();
+    for header in headers
+        .lines()
+        .filter_map(|l| l.strip_prefix("#[package] "))
+    {
+        let malformed = || PluginError::HeaderParse(format!("malformed package `{header}`"));
+        let (name, location) = header.split_once(' ').ok_or_else(malformed)?;
+        let location = location
+            .trim()
+            .strip_prefix('"')
+            .and_then(|l| l.strip_suffix('"'))
+            .filter(|l| !l.is_empty() && !l.contains('"'))
+            .ok_or_else(malformed)?;
+        let valid_name = name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name.chars().all(|c| c.is_ascii_alphanumeric());
+        if !valid_name {
+            return Err(malformed());
+        }
+        if name == "pf" {
+            return Err(PluginError::HeaderParse(
+                "the package shorthand `pf` is reserved for the platform".into(),
+            ));
+        }
+        if packages.iter().any(|(n, _)| n == name) {
+            let msg = format!("duplicate package `{name}`");
+            return Err(PluginError::HeaderParse(msg));
+        }
 
-    if !status.success() {
-        panic!("roc compile failed: {status}");
+        // The app is built in the plugin's build directory, so local packages are referred to by
+        // their absolute path.
+        let location = if location.contains("://") {
+            location.to_owned()
+        } else {
+            let dir = source.parent().unwrap_or(Path::new("."));
+            dir.join(location).display().to_string()
+        };
+        packages.push((name.into(), location));
     }
+    Ok(packages)
+}
 
-    unsafe { Library::new(&dylib_file_path).unwrap() }
+/// Reads the helper modules that the plugin at `source` with source `code` imports, directly or
+/// through other helper modules. Helper modules are in the same directory as the plugin, see
+/// [`is_module`](crate::is_module).
+fn read_siblings(source: &Path, code: &str) -> Result<Vec<(String, String)>, PluginError> {
+    let dir = source.parent().unwrap_or(Path::new("."));
+    let mut siblings: Vec<(String, String)> = Vec::new();
+    let mut pending = local_imports(code);
+    while let Some(module) = pending.pop() {
+        let name = format!("{module}.roc");
+        if siblings.iter().any(|(n, _)| *n == name) {
+            continue;
+        }
+        let path = dir.join(&name);
+        if !path.is_file() {
+            // Missing modules are reported by the compiler.
+            continue;
+        }
+        if ["Host.roc", "Config.roc"].contains(&name.as_str()) {
+            return Err(PluginError::Compile {
+                stderr: format!(
+                    "the helper module {} is named like a module of the platform",
+                    path.display()
+                ),
+                artifacts: None,
+            });
+        }
+        let code = fs::read_to_string(&path)?;
+        pending.extend(local_imports(&code));
+        siblings.push((name, code));
+    }
+    siblings.sort();
+    Ok(siblings)
 }
 
-fn gen_platform_code(meta: &Meta) -> String {
-    if meta.arg_types.is_empty() {
-        format!(
-            r#"
-platform "plugin"
-    requires {{}} {{ {name} : {return_type} }}
-    exposes []
-    packages {{}}
-    imports []
-    provides [entry]
-
-entry = {name}"#,
-            name = meta.name,
-            return_type = meta.return_type.as_str(),
-        )
+/// Returns the modules imported by `code` like `import Helpers`, as opposed to modules of the
+/// platform or packages, like `import pf.Host`.
+fn local_imports(code: &str) -> Vec<String> {
+    code.lines()
+        .filter_map(|line| line.strip_prefix("import "))
+        .filter_map(|import| import.split_whitespace().next())
+        .filter(|module| {
+            module.starts_with(|c: char| c.is_ascii_uppercase())
+                && module.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+        .map(Into::into)
+        .collect()
+}
+
+/// Parses a duration like `500ms`, `5s`, `2m` or `1h`, returning `None` if it is malformed or
+/// too long to represent.
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let amount: u64 = s[..split].parse().ok()?;
+    match &s[split..] {
+        "ms" => Some(Duration::from_millis(amount)),
+        "s" => Some(Duration::from_secs(amount)),
+        "m" => amount.checked_mul(60).map(Duration::from_secs),
+        "h" => amount.checked_mul(60 * 60).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+fn parse_dtype(s: &str) -> Result<DType, PluginError> {
+    s.parse().map_err(PluginError::HeaderParse)
+}
+
+/// Compiles the plugin, whose source was read from `source`, returning the path of its library.
+///
+/// With caching enabled, a library built earlier from the same inputs is reused instead.
+fn compile(
+    functions: &[Meta],
+    code: &str,
+    source: &Path,
+    build_dir: &Path,
+    options: &LoadOptions,
+) -> Result<PathBuf, PluginError> {
+    let modules = Modules::generate(functions, code, source, options)?;
+
+    if options.cache {
+        let backend = options.backend;
+        let packages: Vec<_> = modules
+            .packages
+            .iter()
+            .map(|(name, location)| format!("{name}: {location}"))
+            .collect();
+        let siblings: Vec<_> = modules
+            .siblings
+            .iter()
+            .map(|(name, code)| format!("{name}\n{code}"))
+            .collect();
+        let key = cache::key(&[
+            toolchain(&options.roc_bin).version.as_str(),
+            backend.target(),
+            options.profile.name(),
+            &modules.platform,
+            &modules.host,
+            modules.config.as_deref().unwrap_or_default(),
+            &packages.join("\n"),
+            &siblings.join("\0"),
+            code,
+        ]);
+        let path = options
+            .cache_dir
+            .join(key)
+            .with_extension(backend.extension());
+        if !path.exists() {
+            let built = build(functions, code, source, &modules, build_dir, options)?;
+            // Copy next to the final location first, so that the cache never contains partially
+            // written libraries.
+            fs::create_dir_all(&options.cache_dir)?;
+            let partial = tempfile::NamedTempFile::new_in(&options.cache_dir)?;
+            fs::copy(built, partial.path())?;
+            partial.persist(&path).map_err(io::Error::from)?;
+        }
+        Ok(path)
     } else {
-        let arg_types: String = meta
-            .arg_types
+        build(functions, code, source, &modules, build_dir, options)
+    }
+}
+
+/// Type checks the plugin, whose source was read from `source`, without building it.
+fn check(
+    functions: &[Meta],
+    code: &str,
+    source: &Path,
+    build_dir: &Path,
+    options: &LoadOptions,
+) -> Result<(), PluginError> {
+    let modules = Modules::generate(functions, code, source, options)?;
+    let app_file_path = modules.write(functions, code, build_dir)?;
+    let mut command = toolchain(&options.roc_bin).command();
+    command.arg("check").arg(app_file_path);
+    run_roc(
+        &mut command,
+        &modules,
+        build_dir,
+        source,
+        options.keep_artifacts,
+    )
+}
+
+/// The generated modules a plugin is built against.
+struct Modules {
+    platform: String,
+    host: String,
+    /// The `Config` module, for plugins that declare a configuration.
+    config: Option<String>,
+    /// The packages the app depends on, see [`parse_packages`].
+    packages: Vec<(String, String)>,
+    /// The helper modules next to the plugin's source, as file names and contents, which are
+    /// copied next to the app so that it can import them.
+    siblings: Vec<(String, String)>,
+    /// The lifecycle hooks the plugin defines, which the app exposes too.
+    hooks: Vec<Meta>,
+}
+
+impl Modules {
+    /// Generates the modules of the plugin whose source `code` was read from `source`.
+    fn generate(
+        functions: &[Meta],
+        code: &str,
+        source: &Path,
+        options: &LoadOptions,
+    ) -> Result<Self, PluginError> {
+        let syntax = toolchain(&options.roc_bin).syntax;
+        let headers = declarations(source, code)?;
+        let config = gen_config_module(&headers, options)?;
+        let template = match &options.platform_template {
+            Some(path) => Cow::Owned(fs::read_to_string(path)?),
+            None => Cow::Borrowed(PLATFORM_TEMPLATE),
+        };
+        let hooks = lifecycle_hooks(code);
+        let entries = [functions, &hooks].concat();
+        Ok(Self {
+            platform: gen_platform_code(
+                &entries,
+                config.is_some(),
+                &options.effects,
+                syntax,
+                &template,
+            )?,
+            host: effects::host_module(syntax, &options.effects),
+            config,
+            packages: parse_packages(&headers, source)?,
+            siblings: read_siblings(source, code)?,
+            hooks,
+        })
+    }
+
+    /// Writes the modules and the app made of the plugin's code to `dir`, returning the path of
+    /// the app.
+    ///
+    /// Files written for an earlier build are removed first, in case the plugin's helper modules
+    /// changed since.
+    fn write(&self, functions: &[Meta], code: &str, dir: &Path) -> Result<PathBuf, PluginError> {
+        Self::remove(dir)?;
+        let platform_file_path = dir.join("platform.roc");
+        let app_file_path = dir.join(diagnostics::APP_FILE);
+
+        let platform_file = File::create(&platform_file_path)?;
+        write!(&platform_file, "{}", self.platform)?;
+        fs::write(dir.join("Host.roc"), &self.host)?;
+        if let Some(config_code) = &self.config {
+            fs::write(dir.join("Config.roc"), config_code)?;
+        }
+        for (name, code) in &self.siblings {
+            fs::write(dir.join(name), code)?;
+        }
+
+        let app_file = File::create(&app_file_path)?;
+        let mut names: Vec<_> = functions.iter().map(|m| m.name.as_str()).collect();
+        if functions.iter().any(|m| m.state.is_some()) {
+            names.push("init");
+        }
+        names.extend(self.hooks.iter().map(|m| m.name.as_str()));
+        let packages: String = self
+            .packages
             .iter()
-            .map(|t| t.as_str())
-            .collect::<Vec<_>>()
-            .join(", ");
-        let arg_vars = ('a'..)
-            .map(|x| x.to_string())
-            .take(meta.arg_types.len())
-            .collect::<Vec<_>>();
+            .map(|(name, location)| format!(r#", {name}: "{location}""#))
+            .collect();
+        let app_header = format!(
+            r#"app [{names}] {{ pf: platform "{path}"{packages} }}"#,
+            names = names.join(", "),
+            path = platform_file_path.display(),
+        );
+        writeln!(&app_file, "{app_header}")?;
+        write!(&app_file, "{code}")?;
+        Ok(app_file_path)
+    }
+
+    /// Removes the files written by [`Modules::write`] from `dir`, which are all of its `.roc`
+    /// files.
+    fn remove(dir: &Path) -> Result<(), PluginError> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() == Some(OsStr::new("roc")) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
 
+/// Builds the plugin in `dir`, returning the path of the produced library.
+///
+/// `dir` is kept across runs, so that roc can reuse intermediate artifacts from earlier builds.
+fn build(
+    functions: &[Meta],
+    code: &str,
+    source: &Path,
+    modules: &Modules,
+    dir: &Path,
+    options: &LoadOptions,
+) -> Result<PathBuf, PluginError> {
+    let backend = options.backend;
+    // roc picks the kind of library to build by the extension of the output file.
+    let dylib_file_path = dir.join("plugin").with_extension(backend.extension());
+    let app_file_path = modules.write(functions, code, dir)?;
+
+    let mut command = toolchain(&options.roc_bin).command();
+    command.args(["build", "--lib"]);
+    if backend != Backend::Native {
+        command.arg(format!("--target={}", backend.target()));
+    }
+    if options.profile == Profile::Release {
+        command.arg("--optimize");
+    }
+    command
+        .arg("--output")
+        .arg(&dylib_file_path)
+        .arg(app_file_path);
+    run_roc(&mut command, modules, dir, source, options.keep_artifacts)?;
+    Ok(dylib_file_path)
+}
+
+/// Runs the Roc compiler on the app in `dir`, mapping its diagnostics back to `source`, see
+/// [`diagnostics::map`].
+///
+/// The generated files are removed afterwards, unless `keep_artifacts` is set.
+fn run_roc(
+    command: &mut Command,
+    modules: &Modules,
+    dir: &Path,
+    source: &Path,
+    keep_artifacts: bool,
+) -> Result<(), PluginError> {
+    let output = command.output();
+    if !keep_artifacts {
+        Modules::remove(dir)?;
+    }
+    let output = output?;
+    if !output.status.success() {
+        // Depending on the version, roc prints its reports to stdout or stderr.
+        let mut stderr = String::from_utf8_lossy(&output.stdout).into_owned();
+        stderr.push_str(&String::from_utf8_lossy(&output.stderr));
+        if stderr.trim().is_empty() {
+            stderr = format!("roc exited with {}", output.status);
+        }
+        let siblings: Vec<_> = modules
+            .siblings
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let stderr = diagnostics::map(stderr.trim_end(), dir, source, &siblings);
+        return Err(PluginError::Compile {
+            stderr,
+            artifacts: keep_artifacts.then(|| dir.to_owned()),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the names of all `roc__*` symbols exported by the library at `path`.
+fn exported_roc_symbols(path: &Path) -> Result<Vec<String>, PluginError> {
+    let data = fs::read(path)?;
+    let invalid = |error| io::Error::new(io::ErrorKind::InvalidData, error);
+    let file = object::File::parse(&*data).map_err(invalid)?;
+    let exports = file.exports().map_err(invalid)?;
+
+    let names = exports
+        .iter()
+        .filter_map(|export| std::str::from_utf8(export.name()).ok())
+        // Mach-O prefixes C symbols with an underscore, which `dlsym` adds implicitly.
+        .map(|name| {
+            name.strip_prefix('_')
+                .filter(|n| n.starts_with("roc__"))
+                .unwrap_or(name)
+        })
+        .filter(|name| name.starts_with("roc__"))
+        .map(String::from)
+        .collect();
+    Ok(names)
+}
+
+/// Matches the functions of a plugin against the generic entrypoints exported by its library.
+fn resolve_symbols(
+    functions: &[Meta],
+    exports: &[String],
+) -> Result<HashMap<String, String>, PluginError> {
+    static RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^roc__(?P<entry>\w+?)_\d+_exposed_generic$").unwrap());
+
+    let mut symbols = HashMap::new();
+    for meta in functions {
+        let entry = meta.entry_name();
+        let symbol = exports
+            .iter()
+            .find(|name| RE.captures(name).is_some_and(|caps| caps["entry"] == entry))
+            .ok_or_else(|| PluginError::SymbolNotFound {
+                name: entry,
+                found: exports.to_vec(),
+            })?;
+        symbols.insert(meta.name.clone(), symbol.clone());
+    }
+    // Lifecycle hooks are optional, so they are only called if the library exports them.
+    for hook in HOOKS {
+        let entry = Meta::hook(hook).entry_name();
+        if let Some(symbol) = exports
+            .iter()
+            .find(|name| RE.captures(name).is_some_and(|caps| caps["entry"] == entry))
+        {
+            symbols.insert(hook.into(), symbol.clone());
+        }
+    }
+    Ok(symbols)
+}
+
+/// The layout of the value a function's entrypoint writes through its out pointer, see
+/// [`entry_layouts`].
+#[derive(Clone, Copy, Debug)]
+struct EntryLayout {
+    /// The size of the value, which for tasks is the closure they run.
+    size: usize,
+    /// The alignment of the value. Roc doesn't report alignments, so this follows from the
+    /// signature, and from the largest alignment of any Roc value for closures.
+    align: usize,
+    /// The size of the result a task writes when it is run.
+    task_result_size: Option<usize>,
+}
+
+/// Returns the layouts of the values written by the entrypoints of `functions`, and of the
+/// lifecycle hooks among `symbols`, keyed by function name.
+///
+/// Sizes are queried from the `roc__*_exposed_size` symbols of the library, and from
+/// `roc__*_0_result_size` for the results of tasks. The sizes of return values are checked
+/// against the signatures, so that a signature that doesn't match the code, like in the header
+/// of a precompiled plugin, fails to load instead of corrupting memory when the function is
+/// called. Compilers that don't export these symbols leave the sizes to the signatures, except
+/// for the closures of tasks, which have no size in the signature.
+///
+/// Roc doesn't report the layouts of arguments, so these aren't checked.
+fn entry_layouts(
+    dylib: &Library,
+    functions: &[Meta],
+    symbols: &HashMap<String, String>,
+) -> Result<HashMap<String, EntryLayout>, PluginError> {
+    let query = |name: &str| {
+        let size = unsafe { dylib.get::<unsafe extern "C" fn() -> i64>(name.as_bytes()) };
+        size.ok().map(|size| unsafe { size() } as usize)
+    };
+    let checked = |meta: &Meta, name: &str, expected: usize| match query(name) {
+        Some(found) if found != expected => Err(PluginError::AbiMismatch {
+            function: meta.name.clone(),
+            expected,
+            found,
+        }),
+        _ => Ok(expected),
+    };
+
+    let hooks = HOOKS
+        .iter()
+        .filter(|hook| symbols.contains_key(**hook))
+        .map(|hook| Meta::hook(hook));
+    let mut layouts = HashMap::new();
+    for meta in functions.iter().cloned().chain(hooks) {
+        let meta = meta.with_state();
+        let size_name = symbols[&meta.name].replace("_exposed_generic", "_exposed_size");
+        let layout = match &meta.return_type {
+            DType::Task(..) => {
+                let closure_name = format!("roc__{}_0_size", meta.entry_name());
+                let size = query(&size_name).or_else(|| query(&closure_name)).ok_or(
+                    PluginError::SymbolNotFound {
+                        name: closure_name,
+                        found: Vec::new(),
+                    },
+                )?;
+                let result_name = format!("roc__{}_0_result_size", meta.entry_name());
+                EntryLayout {
+                    size,
+                    align: RocBuf::ALIGN,
+                    task_result_size: Some(checked(&meta, &result_name, meta.return_type.size())?),
+                }
+            }
+            dtype => EntryLayout {
+                size: checked(&meta, &size_name, dtype.size())?,
+                align: dtype.align(),
+                task_result_size: None,
+            },
+        };
+        layouts.insert(meta.name.clone(), layout);
+    }
+    Ok(layouts)
+}
+
+/// Returns the lifecycle hooks defined by the plugin's source `code`, see [`HOOKS`].
+fn lifecycle_hooks(code: &str) -> Vec<Meta> {
+    let defines = |name: &str| {
+        code.lines().any(|line| {
+            line.strip_prefix(name)
+                .is_some_and(|rest| rest.trim_start().starts_with([':', '=']))
+        })
+    };
+    HOOKS
+        .into_iter()
+        .filter(|name| defines(name))
+        .map(Meta::hook)
+        .collect()
+}
+
+/// Generates the platform of a plugin from `template`, see [`PLATFORM_TEMPLATE`].
+fn gen_platform_code(
+    functions: &[Meta],
+    configured: bool,
+    extra_effects: &[HostEffect],
+    syntax: Syntax,
+    template: &str,
+) -> Result<String, PluginError> {
+    let roc_functions: Vec<_> = functions.iter().map(Meta::with_state).collect();
+    let mut requires: Vec<_> = roc_functions
+        .iter()
+        .map(|m| format!("{} : {}", m.name, m.signature()))
+        .collect();
+    let mut provides: Vec<_> = functions.iter().map(Meta::entry_name).collect();
+    let mut entries: Vec<_> = roc_functions.iter().map(|m| gen_entry(m)).collect();
+    if let Some(state) = functions.iter().find_map(|m| m.state.as_ref()) {
+        let init = Meta::init(state);
+        requires.push(format!("init : {{}} -> {state}"));
+        entries.push(format!("{} = init {{}}", init.entry_name()));
+        provides.push(init.entry_name());
+    }
+    let imports = match syntax {
+        Syntax::Legacy => "\n    imports []",
+        Syntax::Modern => "",
+    };
+    let exposes = if configured { "Host, Config" } else { "Host" };
+
+    let variables = [
+        ("name", functions[0].name.clone()),
+        ("requires", requires.join(", ")),
+        ("exposes", exposes.into()),
+        ("imports", imports.into()),
+        ("provides", provides.join(", ")),
+        ("entries", entries.join("\n\n")),
+        ("effects", effects::names(extra_effects).join(", ")),
+    ];
+    render_template(template, &variables)
+}
+
+/// Replaces the placeholders like `{name}` in `template` with the value of the variable they
+/// name, failing on placeholders that don't name a variable or aren't closed, so that typos in
+/// custom templates surface. Text in braces that isn't a lowercase identifier, like `{}` or
+/// `{ x: 1 }`, is kept.
+///
+/// The template is only scanned once, so placeholders in the values of variables are kept too.
+fn render_template(template: &str, variables: &[(&str, String)]) -> Result<String, PluginError> {
+    let is_name = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+    };
+
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest[1..].find('}') else {
+            let name = rest[1..]
+                .split(|c: char| !c.is_ascii_lowercase() && c != '_')
+                .next()
+                .unwrap_or_default();
+            if is_name(name) {
+                return Err(PluginError::Config(format!(
+                    "unterminated placeholder `{{{name}` in the platform template"
+                )));
+            }
+            break;
+        };
+        let placeholder = Some(&rest[1..end + 1]).filter(|name| is_name(name));
+        match placeholder {
+            Some(name) => {
+                let Some((_, value)) = variables.iter().find(|(n, _)| *n == name) else {
+                    return Err(PluginError::Config(format!(
+                        "unknown placeholder `{{{name}}}` in the platform template"
+                    )));
+                };
+                rendered.push_str(value);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Generates the `Config` module exposing the configuration to plugins that declare one with a
+/// `#[config]` header line, or returns `None` for other plugins.
+fn gen_config_module(headers: &str, options: &LoadOptions) -> Result<Option<String>, PluginError> {
+    let mut headers = headers.lines().filter_map(|l| l.strip_prefix("#[config] "));
+    let Some(header) = headers.next() else {
+        return Ok(None);
+    };
+    if headers.next().is_some() {
+        return Err(PluginError::HeaderParse(
+            "duplicate `#[config]` header".into(),
+        ));
+    }
+
+    let dtype = parse_dtype(header)?;
+    if dtype.contains(|t| matches!(t, DType::Result(..) | DType::Task(..) | DType::Unit)) {
+        return Err(PluginError::HeaderParse(format!(
+            "unsupported configuration type `{dtype}`"
+        )));
+    }
+    let json = options.config.as_ref().ok_or_else(|| {
+        PluginError::Config(format!(
+            "the plugin requires a configuration of type {dtype}"
+        ))
+    })?;
+    let value = Value::from_json(json, &dtype).map_err(PluginError::Config)?;
+
+    let header = match toolchain(&options.roc_bin).syntax {
+        Syntax::Legacy => "interface Config\n    exposes [config]\n    imports []",
+        Syntax::Modern => "module [config]",
+    };
+    Ok(Some(format!(
+        "{header}\n\nconfig : {dtype}\nconfig = {}\n",
+        value.to_roc_literal()
+    )))
+}
+
+fn gen_entry(meta: &Meta) -> String {
+    let arg_vars = ('a'..)
+        .map(|x| x.to_string())
+        .take(meta.arg_types.len())
+        .collect::<Vec<_>>();
+
+    let mut call = vec![meta.name.clone()];
+    for (var, dtype) in arg_vars.iter().zip(&meta.arg_types) {
+        call.push(match dtype {
+            DType::Dict(..) => format!("(Dict.fromList {var})"),
+            _ => var.clone(),
+        });
+    }
+    let mut body = call.join(" ");
+    if let DType::Dict(..) = meta.return_type {
+        body.push_str(" |> Dict.toList");
+    }
+
+    if arg_vars.is_empty() {
+        format!("{entry} = {body}", entry = meta.entry_name())
+    } else {
         format!(
-            r#"
-platform "plugin"
-    requires {{}} {{ {name} : {arg_types} -> {return_type} }}
-    exposes []
-    packages {{}}
-    imports []
-    provides [entry]
-
-entry = \{args1} -> {name} {args2}"#,
-            name = meta.name,
-            return_type = meta.return_type.as_str(),
-            args1 = arg_vars.join(", "),
-            args2 = arg_vars.join(" "),
+            r#"{entry} = \{args} -> {body}"#,
+            entry = meta.entry_name(),
+            args = arg_vars.join(", "),
         )
     }
 }
 
-fn generate_value(t: DType) -> Value {
+fn generate_value(t: &DType) -> Value {
     match t {
+        DType::Bool => Value::Bool(true),
         DType::Str => Value::Str("foo".into()),
+        DType::U8 => Value::U8(42),
         DType::U64 => Value::U64(42),
+        DType::I8 => Value::I8(-42),
+        DType::I16 => Value::I16(-42),
+        DType::I32 => Value::I32(-42),
+        DType::I64 => Value::I64(-42),
+        DType::F32 => Value::F32(4.2),
+        DType::F64 => Value::F64(4.2),
+        DType::Dec => Value::Dec("4.2".parse().unwrap()),
+        DType::Bytes => Value::Bytes(Bytes::from(&b"foo"[..])),
+        DType::List(elem) => Value::List((0..3).map(|_| generate_value(elem)).collect()),
+        DType::Record(fields) => Value::Record(
+            fields
+                .iter()
+                .map(|(name, t)| (name.clone(), generate_value(t)))
+                .collect(),
+        ),
+        DType::Tuple(elems) => Value::Tuple(elems.iter().map(generate_value).collect()),
+        DType::Dict(key, value) => Value::Dict(vec![(generate_value(key), generate_value(value))]),
+        DType::Option(inner) => Value::Option(Some(Box::new(generate_value(inner)))),
+        DType::Unit => unreachable!("`{{}}` is only supported as the return type"),
+        DType::Result(..) | DType::Task(..) => {
+            unreachable!("`Result` and `Task` are only supported as the return type")
+        }
+    }
+}
+
+/// Moves values to a worker thread and back, or holds the state of a stateful plugin.
+#[derive(Debug)]
+pub(crate) struct Detached<T>(pub(crate) T);
+
+// SAFETY: The only values that aren't `Send` are Roc buffers, whose refcounts aren't atomic.
+// Arguments are detached before being sent, so they share no buffers with the caller's values.
+// Results may share buffers with the arguments they were computed from, so workers drop the
+// arguments and any temporary values before sending a result back, leaving the receiver as the
+// only owner of its buffers. The states of stateful plugins and memoized results, along with
+// their arguments, are detached before they are stored.
+unsafe impl<T> Send for Detached<T> {}
+
+/// Invokes the function on a worker thread, giving up on it once `timeout` has passed.
+///
+/// Timed out invocations are killed if they run under the wasm backend. Threads can't be
+/// killed, so other invocations keep running in the background, holding on to their library
+/// until they finish. Isolated invocations enforce their timeout in [`invoke_isolated`] instead.
+fn invoke_with_timeout(
+    library: Arc<Loaded>,
+    meta: &Meta,
+    args: &[Value],
+    timeout: Duration,
+    token: &CancellationToken,
+) -> Result<Value, PluginError> {
+    // The worker gets its own token, so that timing out can kill it without cancelling the
+    // caller's token.
+    let worker_token = CancellationToken::new();
+    let _linked = token.on_cancel({
+        let worker_token = worker_token.clone();
+        move || worker_token.cancel()
+    });
+
+    let meta = meta.clone();
+    let args = Detached(args.iter().map(Value::detach).collect::<Vec<_>>());
+    // The worker continues the `Host.call`s of this thread, which count towards its limit, and
+    // its seed.
+    let calls = effects::calls();
+    let seed = effects::seed();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn({
+        let worker_token = worker_token.clone();
+        move || {
+            // Capture the wrapper as a whole, rather than just its contents.
+            let args = args;
+            let result = effects::with_calls(calls, || {
+                effects::with_seed(seed, || {
+                    invoke_caught(&library, &meta, &args.0, &worker_token)
+                })
+            });
+            // The result may share buffers with the arguments, see `Detached`.
+            drop(args);
+            let _ = tx.send(Detached(result));
+        }
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(Detached(result)) => result,
+        Err(_) => {
+            worker_token.cancel();
+            Err(PluginError::Timeout(timeout))
+        }
+    }
+}
+
+/// Invokes the function in the host process, turning panics into errors.
+fn invoke_caught(
+    library: &Loaded,
+    meta: &Meta,
+    args: &[Value],
+    token: &CancellationToken,
+) -> Result<Value, PluginError> {
+    roc_host::take_panic();
+    // Panics leave nothing of the library half updated, and the host's callbacks in its
+    // capabilities, like its `dbg` sink, are expected to cope with plugins that panic.
+    let result = roc_host::catch_unwind_silent(AssertUnwindSafe(|| {
+        roc_host::with_plugin(&library.plugin, || {
+            effects::with_capabilities(&library.capabilities, || match library.memory_limit {
+                Some(limit) => {
+                    roc_host::with_memory_limit(limit, || library.invoke_entry(meta, args, token))
+                }
+                None => library.invoke_entry(meta, args, token),
+            })
+        })
+    }));
+
+    match result {
+        Ok(result) => result,
+        Err(error) if error.is::<MemoryLimitExceeded>() => Err(PluginError::MemoryLimit(
+            library.memory_limit.unwrap_or_default(),
+        )),
+        Err(error) => {
+            // Panics of the host itself, like in effects, are reported as runtime errors.
+            let (message, kind) = roc_host::take_panic()
+                .unwrap_or_else(|| (error::panic_message(&*error), PanicKind::Runtime));
+            Err(PluginError::Panic {
+                plugin: library.plugin.clone(),
+                entrypoint: library
+                    .symbols
+                    .get(&meta.name)
+                    .unwrap_or(&meta.name)
+                    .clone(),
+                message,
+                kind,
+            })
+        }
     }
 }
 
-fn catch_unwind_silent<F: FnOnce() -> R + panic::UnwindSafe, R>(f: F) -> std::thread::Result<R> {
-    let prev_hook = panic::take_hook();
-    panic::set_hook(Box::new(|_| {}));
-    let result = panic::catch_unwind(f);
-    panic::set_hook(prev_hook);
-    result
+/// Invokes the function in a forked worker process, killing it once `timeout` has passed.
+#[cfg(unix)]
+fn invoke_isolated(
+    library: &Loaded,
+    meta: &Meta,
+    args: &[Value],
+    timeout: Option<Duration>,
+    token: &CancellationToken,
+) -> Result<Value, PluginError> {
+    isolate::run(|| invoke_caught(library, meta, args, token), timeout, token)
+}
+
+/// Isolation relies on `fork`, so other platforms always fail isolated invocations.
+#[cfg(not(unix))]
+fn invoke_isolated(
+    _library: &Loaded,
+    _meta: &Meta,
+    _args: &[Value],
+    _timeout: Option<Duration>,
+    _token: &CancellationToken,
+) -> Result<Value, PluginError> {
+    Err(PluginError::WorkerFailed(
+        "isolated invocations are only supported on Unix".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(&'static str, String)> {
+        vec![("name", "greet".into()), ("provides", "entry_greet".into())]
+    }
+
+    /// Returns the message of the header error `s` fails to parse with, see [`parse_attrs`].
+    fn attrs_error(s: &str) -> String {
+        match parse_attrs(s) {
+            Err(PluginError::HeaderParse(msg)) => msg,
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(_) => panic!("`{s}` parsed"),
+        }
+    }
+
+    #[test]
+    fn parse_attrs_accepts_attributes() {
+        let (attrs, rest) = parse_attrs(
+            r#"timeout = "5s", description = "Says hi, (politely)",version="1.2",
+               tags = "a, b", priority = "-3", hook = "onLoad")] greet : Str"#,
+        )
+        .unwrap();
+        assert_eq!(attrs.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(attrs.description.as_deref(), Some("Says hi, (politely)"));
+        assert_eq!(attrs.version.as_deref(), Some("1.2"));
+        assert_eq!(attrs.tags, ["a", "b"]);
+        assert_eq!(attrs.priority, -3);
+        assert_eq!(attrs.hook.as_deref(), Some("onLoad"));
+        assert_eq!(rest, " greet : Str");
+
+        let (attrs, _) =
+            parse_attrs(r#"schedule = "*/5 * * * *", subscribe = "user.created")]"#).unwrap();
+        assert_eq!(attrs.schedule.as_deref(), Some("*/5 * * * *"));
+        assert_eq!(attrs.subscribe.as_deref(), Some("user.created"));
+
+        let (attrs, rest) = parse_attrs(")] f : Str").unwrap();
+        assert_eq!((attrs.timeout, attrs.priority, rest), (None, 0, " f : Str"));
+    }
+
+    #[test]
+    fn parse_attrs_rejects_duplicate_and_unknown_keys() {
+        assert_eq!(
+            attrs_error(r#"timeout = "1s", timeout = "2s")]"#),
+            "duplicate attribute `timeout`"
+        );
+        assert_eq!(
+            attrs_error(r#"retries = "3")]"#),
+            "unknown attribute `retries`"
+        );
+    }
+
+    #[test]
+    fn parse_attrs_rejects_malformed_attributes() {
+        assert_eq!(
+            attrs_error(r#"timeout = "5x")]"#),
+            r#"malformed attribute `timeout = "5x"`"#
+        );
+        assert_eq!(
+            attrs_error(r#"priority = "high")]"#),
+            r#"malformed attribute `priority = "high"`"#
+        );
+        assert_eq!(
+            attrs_error(r#"tags = "a,,b")]"#),
+            r#"malformed attribute `tags = "a,,b"`"#
+        );
+        assert_eq!(
+            attrs_error(r#"timeout = "5s""#),
+            r#"malformed attribute `timeout = "5s"`"#
+        );
+        assert_eq!(
+            attrs_error(r#"timeout = "5s", version = "1""#),
+            r#"malformed attribute `version = "1"`"#
+        );
+        assert_eq!(attrs_error(r#"timeout = "5s","#), "unclosed `#[plugin(`");
+        for s in [
+            "timeout = 5s)]",
+            r#"timeout "5s")]"#,
+            r#"timeout = "5s)]"#,
+            r#"timeout = "5s" version = "1")]"#,
+            "]",
+        ] {
+            assert!(parse_attrs(s).is_err(), "{s:?}");
+        }
+        assert_eq!(
+            attrs_error(r#"schedule = "* * *")]"#),
+            "invalid schedule `* * *`: expected 5 fields, found 3"
+        );
+    }
+
+    #[test]
+    fn parse_duration_accepts_units() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("0s"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn parse_duration_rejects_bad_input() {
+        for s in [
+            "", "5", "s", "ms", "5 s", "-5s", "+5s", "1.5s", "5S", "5sec", "5d", "s5", " 5s",
+        ] {
+            assert_eq!(parse_duration(s), None, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_duration_rejects_overflow() {
+        assert_eq!(parse_duration("18446744073709551616ms"), None);
+        assert_eq!(parse_duration("18446744073709551615m"), None);
+        assert_eq!(parse_duration("5124095576030432h"), None);
+        assert_eq!(
+            parse_duration("5124095576030431h"),
+            Some(Duration::from_secs(5_124_095_576_030_431 * 3600))
+        );
+    }
+
+    #[test]
+    fn render_template_substitutes_variables() {
+        let rendered = render_template("app {name} provides [{provides}] {name}", &vars());
+        assert_eq!(rendered.unwrap(), "app greet provides [entry_greet] greet");
+    }
+
+    #[test]
+    fn render_template_keeps_other_braces() {
+        let template = "requires {} { x : Str, Y } {Upper} {a1} { name } {";
+        assert_eq!(render_template(template, &vars()).unwrap(), template);
+        // Placeholders in the values of variables are kept as well.
+        let variables = [("name", "{provides}".to_owned())];
+        assert_eq!(render_template("{name}", &variables).unwrap(), "{provides}");
+    }
+
+    #[test]
+    fn render_template_renders_the_default_template() {
+        let names = [
+            "name", "requires", "exposes", "imports", "provides", "entries",
+        ];
+        let variables: Vec<_> = names.iter().map(|&n| (n, n.to_uppercase())).collect();
+        let rendered = render_template(PLATFORM_TEMPLATE, &variables).unwrap();
+        assert!(rendered.contains("requires {} { REQUIRES }"));
+        assert!(rendered.contains("provides [PROVIDES]"));
+    }
+
+    #[test]
+    fn render_template_rejects_unknown_placeholders() {
+        let error = render_template("app {nmae}", &vars()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid configuration: unknown placeholder `{nmae}` in the platform template"
+        );
+    }
+
+    #[test]
+    fn render_template_rejects_unterminated_placeholders() {
+        let error = render_template("provides [{provides", &vars()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid configuration: unterminated placeholder `{provides` in the platform \
+             template"
+        );
+    }
 }
@@ -0,0 +1,326 @@
+#[cfg(unix)]
+use std::ffi::{c_void, CStr};
+#[cfg(unix)]
+use std::mem;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use pyo3::exceptions::PyImportError;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+
+use crate::bytes::Bytes;
+use crate::config::{Config, CONFIG_FILE};
+use crate::error::PluginError;
+use crate::manager::PluginManager;
+use crate::plugin::{Detached, LoadOptions, Plugin};
+use crate::roc_host;
+use crate::value::{DType, Value};
+
+mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+
+    create_exception!(roc_plugin, PluginError, PyException);
+}
+
+impl From<PluginError> for PyErr {
+    fn from(error: PluginError) -> Self {
+        exceptions::PluginError::new_err(error.to_string())
+    }
+}
+
+/// The `roc_plugin` Python module, built with `maturin` from `pyproject.toml`.
+///
+/// ```python
+/// import roc_plugin
+///
+/// mgr = roc_plugin.PluginManager()
+/// mgr.load_dir("plugins")
+/// mgr.invoke("slugify", "Hello World")  # "hello-world"
+/// ```
+#[pymodule]
+fn roc_plugin(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    export_host_functions()?;
+    roc_host::init();
+    m.add_class::<PyPluginManager>()?;
+    m.add(
+        "PluginError",
+        m.py().get_type_bound::<exceptions::PluginError>(),
+    )?;
+    Ok(())
+}
+
+/// Makes the host functions of the extension, like `roc_alloc`, visible to the plugins it loads.
+///
+/// Python loads extensions with `RTLD_LOCAL`, so their symbols aren't used to resolve those of
+/// libraries loaded later. Opening the extension again with `RTLD_GLOBAL` promotes them.
+#[cfg(unix)]
+fn export_host_functions() -> PyResult<()> {
+    let mut info: libc::Dl_info = unsafe { mem::zeroed() };
+    let found = unsafe { libc::dladdr(roc_host::init as *const c_void, &mut info) } != 0;
+    if !found || info.dli_fname.is_null() {
+        return Err(PyImportError::new_err(
+            "failed to find the library of the roc_plugin extension",
+        ));
+    }
+    // The extension is already loaded, so this only changes its flags. The handle is never
+    // closed, since the extension stays loaded anyway.
+    let flags = libc::RTLD_NOW | libc::RTLD_NOLOAD | libc::RTLD_GLOBAL;
+    if unsafe { libc::dlopen(info.dli_fname, flags) }.is_null() {
+        let error = unsafe { CStr::from_ptr(libc::dlerror()) };
+        return Err(PyImportError::new_err(format!(
+            "failed to export the host functions of the roc_plugin extension: {}",
+            error.to_string_lossy()
+        )));
+    }
+    Ok(())
+}
+
+/// Native plugins are only supported on Unix, and wasm plugins get their host functions from
+/// the runtime, so there is nothing to export elsewhere.
+#[cfg(not(unix))]
+fn export_host_functions() -> PyResult<()> {
+    Ok(())
+}
+
+/// A [`PluginManager`] for Python, which converts arguments and return values between Python
+/// and Roc types, following the signatures of the plugins.
+///
+/// Records and dicts are Python dicts, lists and tuples are lists and tuples, `Dec`s are
+/// `decimal.Decimal`s, `List U8`s are `bytes` and missing optional values are `None`.
+/// Invocations release the GIL, so that other Python threads run meanwhile.
+#[pyclass(name = "PluginManager", module = "roc_plugin")]
+struct PyPluginManager {
+    manager: PluginManager,
+}
+
+#[pymethods]
+impl PyPluginManager {
+    #[new]
+    fn new() -> Self {
+        Self {
+            manager: PluginManager::new(),
+        }
+    }
+
+    /// Creates a manager as described by the configuration file at `path`, or the one in the
+    /// current directory if there is one, and loads its plugins.
+    #[staticmethod]
+    #[pyo3(signature = (path=None))]
+    fn from_config(py: Python<'_>, path: Option<PathBuf>) -> PyResult<Self> {
+        let config = match path {
+            Some(path) => Config::load(path)?,
+            None if Path::new(CONFIG_FILE).exists() => Config::load(CONFIG_FILE)?,
+            None => Config::from_env()?,
+        };
+        let (manager, report) = py.allow_threads(|| PluginManager::from_config(&config))?;
+        if !report.is_ok() {
+            return Err(exceptions::PluginError::new_err(report.to_string()));
+        }
+        Ok(Self { manager })
+    }
+
+    /// Loads the plugins in `dir`, returning their names, see [`PluginManager::scan`].
+    ///
+    /// Raises `PluginError` if any of them failed to load, though the others are loaded
+    /// regardless.
+    fn load_dir(&mut self, py: Python<'_>, dir: PathBuf) -> PyResult<Vec<String>> {
+        let manager = &mut self.manager;
+        let report = py.allow_threads(|| manager.load_all([dir], &LoadOptions::default()));
+        if !report.is_ok() {
+            return Err(exceptions::PluginError::new_err(report.to_string()));
+        }
+        Ok(report.loaded)
+    }
+
+    /// Returns the names of the plugins.
+    fn plugins(&self) -> Vec<String> {
+        self.manager.list().map(String::from).collect()
+    }
+
+    /// Returns the signature of the plugin's first function, or the one named `function`.
+    #[pyo3(signature = (plugin, function=None))]
+    fn signature(&self, plugin: &str, function: Option<&str>) -> PyResult<String> {
+        let plugin = self.plugin(plugin)?;
+        let function = function.unwrap_or(&plugin.meta().name);
+        Ok(plugin.function(function)?.signature())
+    }
+
+    /// Invokes the plugin's first function, or the one named `function`, with `args`.
+    #[pyo3(signature = (plugin, *args, function=None))]
+    fn invoke(
+        &self,
+        py: Python<'_>,
+        plugin: &str,
+        args: &Bound<'_, PyTuple>,
+        function: Option<&str>,
+    ) -> PyResult<PyObject> {
+        let plugin = self.plugin(plugin)?;
+        let function = function.unwrap_or(&plugin.meta().name);
+        let meta = plugin.function(function)?;
+        if args.len() != meta.arg_types.len() {
+            let error = PluginError::ArgumentCount {
+                expected: meta.arg_types.len(),
+                found: args.len(),
+            };
+            return Err(PyTypeError::new_err(error.to_string()));
+        }
+        let args = args
+            .iter()
+            .zip(&meta.arg_types)
+            .map(|(arg, dtype)| from_py(&arg, dtype))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let args = Detached(args);
+        let Detached(result) = py.allow_threads(move || {
+            // Capture the wrapper as a whole, rather than just its contents.
+            let args = args;
+            Detached(plugin.invoke_function_with(function, &args.0))
+        });
+        to_py(py, &result?)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("PluginManager({} plugins)", self.manager.plugins().len())
+    }
+}
+
+impl PyPluginManager {
+    fn plugin(&self, name: &str) -> Result<&Plugin, PluginError> {
+        self.manager
+            .get(name)
+            .map(|plugin| &**plugin)
+            .ok_or_else(|| PluginError::PluginNotFound(name.into()))
+    }
+}
+
+/// Converts a Python object to a value of type `dtype`.
+fn from_py(obj: &Bound<'_, PyAny>, dtype: &DType) -> PyResult<Value> {
+    let value =
+        match dtype {
+            DType::Unit => Value::Unit,
+            DType::Bool => Value::Bool(obj.extract()?),
+            DType::Str => Value::Str(obj.extract()?),
+            DType::U8 => Value::U8(obj.extract()?),
+            DType::U64 => Value::U64(obj.extract()?),
+            DType::I8 => Value::I8(obj.extract()?),
+            DType::I16 => Value::I16(obj.extract()?),
+            DType::I32 => Value::I32(obj.extract()?),
+            DType::I64 => Value::I64(obj.extract()?),
+            DType::F32 => Value::F32(obj.extract()?),
+            DType::F64 => Value::F64(obj.extract()?),
+            // Decimals, ints, floats and strings all have their value as their `str`.
+            DType::Dec => {
+                let s = obj.str()?;
+                let s = s.to_str()?;
+                Value::Dec(s.parse().map_err(|error| {
+                    PyValueError::new_err(format!("invalid Dec `{s}`: {error}"))
+                })?)
+            }
+            DType::Bytes => Value::Bytes(Bytes::from(obj.extract::<Vec<u8>>()?)),
+            DType::List(item) => Value::List(
+                obj.iter()?
+                    .map(|item_obj| from_py(&item_obj?, item))
+                    .collect::<PyResult<_>>()?,
+            ),
+            DType::Record(fields) => {
+                let dict = obj.downcast::<PyDict>()?;
+                let values = fields
+                    .iter()
+                    .map(|(name, dtype)| {
+                        let field = dict.get_item(name)?.ok_or_else(|| {
+                            PyValueError::new_err(format!("missing record field `{name}`"))
+                        })?;
+                        Ok((name.clone(), from_py(&field, dtype)?))
+                    })
+                    .collect::<PyResult<_>>()?;
+                Value::Record(values)
+            }
+            DType::Tuple(dtypes) => {
+                let items: Vec<_> = obj.iter()?.collect::<PyResult<_>>()?;
+                if items.len() != dtypes.len() {
+                    return Err(PyValueError::new_err(format!(
+                        "expected a tuple of {} items, found {}",
+                        dtypes.len(),
+                        items.len()
+                    )));
+                }
+                Value::Tuple(
+                    items
+                        .iter()
+                        .zip(dtypes)
+                        .map(|(item, dtype)| from_py(item, dtype))
+                        .collect::<PyResult<_>>()?,
+                )
+            }
+            DType::Dict(key, value) => Value::Dict(
+                obj.downcast::<PyDict>()?
+                    .iter()
+                    .map(|(k, v)| Ok((from_py(&k, key)?, from_py(&v, value)?)))
+                    .collect::<PyResult<_>>()?,
+            ),
+            DType::Option(_) if obj.is_none() => Value::Option(None),
+            DType::Option(dtype) => Value::Option(Some(Box::new(from_py(obj, dtype)?))),
+            DType::Result(..) | DType::Task(..) => {
+                return Err(PyTypeError::new_err(format!(
+                    "{dtype} is only supported as the return type"
+                )));
+            }
+        };
+    Ok(value)
+}
+
+/// Converts a value to a Python object.
+fn to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    let obj = match value {
+        Value::Unit => py.None(),
+        Value::Bool(b) => b.to_object(py),
+        Value::Str(s) => s.to_object(py),
+        Value::U8(n) => n.to_object(py),
+        Value::U64(n) => n.to_object(py),
+        Value::I8(n) => n.to_object(py),
+        Value::I16(n) => n.to_object(py),
+        Value::I32(n) => n.to_object(py),
+        Value::I64(n) => n.to_object(py),
+        Value::F32(x) => x.to_object(py),
+        Value::F64(x) => x.to_object(py),
+        Value::Dec(d) => {
+            let decimal = py.import_bound("decimal")?.getattr("Decimal")?;
+            decimal.call1((d.to_string(),))?.unbind()
+        }
+        Value::Bytes(bytes) => PyBytes::new_bound(py, bytes.as_slice()).into_any().unbind(),
+        Value::List(items) => {
+            let items = items
+                .iter()
+                .map(|item| to_py(py, item))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, items).into_any().unbind()
+        }
+        Value::Tuple(items) => {
+            let items = items
+                .iter()
+                .map(|item| to_py(py, item))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyTuple::new_bound(py, items).into_any().unbind()
+        }
+        Value::Record(fields) => {
+            let dict = PyDict::new_bound(py);
+            for (name, value) in fields {
+                dict.set_item(name, to_py(py, value)?)?;
+            }
+            dict.into_any().unbind()
+        }
+        Value::Dict(entries) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in entries {
+                dict.set_item(to_py(py, key)?, to_py(py, value)?)?;
+            }
+            dict.into_any().unbind()
+        }
+        Value::Option(None) => py.None(),
+        Value::Option(Some(value)) => to_py(py, value)?,
+    };
+    Ok(obj)
+}
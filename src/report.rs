@@ -0,0 +1,89 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde_json::Map;
+
+use crate::error::PluginError;
+use crate::roc_host::AllocReport;
+use crate::value::Value;
+
+/// The outcome of an invocation, see [`Plugin::invoke_reported`].
+///
+/// [`Plugin::invoke_reported`]: crate::Plugin::invoke_reported
+#[derive(Debug)]
+pub struct InvocationReport {
+    /// The name of the invoked plugin.
+    pub plugin: String,
+    /// The name of the invoked function.
+    pub function: String,
+    /// How long the invocation took, including retries.
+    pub duration: Duration,
+    pub result: Result<Value, PluginError>,
+    /// The memory allocated through Roc, if the invocation was traced.
+    pub allocations: Option<AllocReport>,
+    /// Problems that didn't make the invocation fail, like leaked allocations.
+    pub warnings: Vec<String>,
+}
+
+impl InvocationReport {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// Converts the report to a JSON object, with the result's value converted by
+    /// [`Value::to_json`], or its error as a message.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::{json, Value as Json};
+
+        let mut map = Map::new();
+        map.insert("plugin".into(), Json::from(self.plugin.as_str()));
+        map.insert("function".into(), Json::from(self.function.as_str()));
+        map.insert("ok".into(), Json::Bool(self.is_ok()));
+        match &self.result {
+            Ok(value) => map.insert("value".into(), value.to_json()),
+            Err(error) => map.insert("error".into(), Json::from(error.to_string())),
+        };
+        let duration_ms = self.duration.as_secs_f64() * 1000.0;
+        map.insert("duration_ms".into(), Json::from(duration_ms));
+        if let Some(allocations) = &self.allocations {
+            let allocations = json!({
+                "allocations": allocations.allocations,
+                "deallocations": allocations.deallocations,
+                "allocated_bytes": allocations.allocated_bytes,
+                "peak_bytes": allocations.peak_bytes,
+                "outstanding": allocations.outstanding,
+                "outstanding_bytes": allocations.outstanding_bytes,
+            });
+            map.insert("allocations".into(), allocations);
+        }
+        let warnings = self
+            .warnings
+            .iter()
+            .map(|w| Json::from(w.as_str()))
+            .collect();
+        map.insert("warnings".into(), Json::Array(warnings));
+        Json::Object(map)
+    }
+}
+
+impl fmt::Display for InvocationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.plugin, self.function)?;
+        match &self.result {
+            Ok(value) => write!(f, " returned {value}")?,
+            Err(error) => write!(f, " failed: {error}")?,
+        }
+        write!(f, "\ntook {:?}", self.duration)?;
+        if let Some(allocations) = &self.allocations {
+            write!(
+                f,
+                ", allocated {} bytes in {} allocations, at most {} bytes at once",
+                allocations.allocated_bytes, allocations.allocations, allocations.peak_bytes
+            )?;
+        }
+        for warning in &self.warnings {
+            write!(f, "\nwarning: {warning}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,37 +1,390 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::panic;
+use std::path::Path;
+use std::sync::{Arc, Once};
+use std::thread;
+
 use libc::c_void;
 use roc_std::RocStr;
 
+use crate::alloc;
+use crate::effects;
+use crate::error::PanicKind;
+use crate::logging::{self, LogLevel};
+use crate::toolchain::toolchain;
+
+/// Prepares the process for loading plugins, keeping the host functions they call, like
+/// `roc_alloc`, from being stripped from the executable.
+///
+/// This also installs the panic hook which keeps panics of plugins from being printed, while
+/// passing other panics on to the previous hook. Panic hooks set afterwards replace it.
+///
+/// The executable must also export these functions. On Linux, that requires linking it with
+/// `-rdynamic`, e.g. with `cargo:rustc-link-arg-bins=-rdynamic` in its build script.
+///
+/// This may be called any number of times. The host functions dispatch to the plugin running
+/// on the calling thread, so managers whose plugins were loaded with different options, like
+/// different [`LoadOptions::effects`], [`LoadOptions::dbg_sink`]s or
+/// [`LoadOptions::allocator`]s, can be used side by side.
+///
+/// [`LoadOptions::effects`]: crate::LoadOptions::effects
+/// [`LoadOptions::dbg_sink`]: crate::LoadOptions::dbg_sink
+/// [`LoadOptions::allocator`]: crate::LoadOptions::allocator
 pub fn init() {
+    // Probe the default compiler up front, so that loading the first plugin doesn't pay for it.
+    toolchain(Path::new("roc"));
+    install_panic_hook();
+
     let funcs: &[*const extern "C" fn()] = &[
         roc_alloc as _,
         roc_realloc as _,
         roc_dealloc as _,
         roc_panic as _,
+        roc_dbg as _,
+        effects::roc_fx_log as _,
+        effects::roc_fx_readFile as _,
+        effects::roc_fx_writeFile as _,
+        effects::roc_fx_httpGet as _,
+        effects::roc_fx_kvGet as _,
+        effects::roc_fx_kvSet as _,
+        effects::roc_fx_envVar as _,
+        effects::roc_fx_now as _,
+        effects::roc_fx_monotonicMillis as _,
+        effects::roc_fx_randomU64 as _,
+        effects::roc_fx_call as _,
     ];
     std::hint::black_box(funcs);
 }
 
+/// The memory budget of the plugin invocation running on a thread, see [`with_memory_limit`].
+#[derive(Clone, Copy)]
+struct Budget {
+    used: usize,
+    limit: usize,
+}
+
+thread_local! {
+    static BUDGET: Cell<Option<Budget>> = const { Cell::new(None) };
+    /// The message and kind of the last `roc_panic` on this thread.
+    static PANIC: Cell<Option<(String, PanicKind)>> = const { Cell::new(None) };
+    /// The name of the plugin running on this thread, see [`with_plugin`].
+    static PLUGIN: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// Whether this thread is in a plugin call, whose panics aren't printed, see
+    /// [`catch_unwind_silent`].
+    static SILENT: Cell<bool> = const { Cell::new(false) };
+    /// The allocations of the traced invocation running on this thread, see [`traced`].
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+}
+
+/// The memory a traced invocation allocated through Roc, see [`Plugin::invoke_traced`].
+///
+/// [`Plugin::invoke_traced`]: crate::Plugin::invoke_traced
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocReport {
+    /// The number of allocations, not counting reallocations.
+    pub allocations: usize,
+    /// The number of allocations freed, including ones made before the invocation.
+    pub deallocations: usize,
+    /// The number of bytes allocated, with reallocations counting as much as they grew.
+    pub allocated_bytes: usize,
+    /// The most bytes allocated during the invocation that were in use at once.
+    pub peak_bytes: usize,
+    /// The number of allocations made during the invocation that were still in use after it
+    /// returned, either held on to by the returned value or leaked.
+    pub outstanding: usize,
+    /// The number of bytes of the outstanding allocations.
+    pub outstanding_bytes: usize,
+}
+
+#[derive(Default)]
+struct Trace {
+    report: AllocReport,
+    /// The sizes of the allocations made during the invocation that are in use, by address.
+    live: HashMap<usize, usize>,
+    /// The sum of the sizes in `live`.
+    in_use: usize,
+}
+
+impl Trace {
+    fn alloc(&mut self, ptr: *mut c_void, size: usize) {
+        self.report.allocations += 1;
+        self.report.allocated_bytes += size;
+        self.live.insert(ptr as usize, size);
+        self.grow(size);
+    }
+
+    fn realloc(&mut self, old: *mut c_void, new: *mut c_void, old_size: usize, new_size: usize) {
+        self.report.allocated_bytes += new_size.saturating_sub(old_size);
+        // Allocations made before the invocation stay untracked.
+        if self.live.remove(&(old as usize)).is_some() {
+            self.live.insert(new as usize, new_size);
+            self.in_use -= old_size;
+            self.grow(new_size);
+        }
+    }
+
+    fn dealloc(&mut self, ptr: *mut c_void) {
+        self.report.deallocations += 1;
+        if let Some(size) = self.live.remove(&(ptr as usize)) {
+            self.in_use -= size;
+        }
+    }
+
+    fn grow(&mut self, size: usize) {
+        self.in_use += size;
+        self.report.peak_bytes = self.report.peak_bytes.max(self.in_use);
+    }
+}
+
+/// Runs `f`, reporting the memory allocated through Roc on this thread meanwhile.
+pub(crate) fn traced<R>(f: impl FnOnce() -> R) -> (R, AllocReport) {
+    struct Reset(Option<Trace>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            TRACE.set(self.0.take());
+        }
+    }
+
+    let reset = Reset(TRACE.replace(Some(Trace::default())));
+    let result = f();
+    let trace = TRACE.take().expect("traces are only removed by `Reset`");
+    drop(reset);
+    let report = AllocReport {
+        outstanding: trace.live.len(),
+        outstanding_bytes: trace.in_use,
+        ..trace.report
+    };
+    (result, report)
+}
+
+/// Returns whether an invocation on this thread is traced, see [`traced`].
+pub(crate) fn is_traced() -> bool {
+    TRACE.with_borrow(Option::is_some)
+}
+
+/// Records an allocation event in the trace of this thread, if it is traced.
+fn trace(f: impl FnOnce(&mut Trace)) {
+    // Threads that are exiting have lost their trace, but aren't traced either.
+    let _ = TRACE.try_with(|trace| {
+        if let Some(trace) = &mut *trace.borrow_mut() {
+            f(trace);
+        }
+    });
+}
+
+/// Installs a panic hook that ignores panics in plugin calls and passes all others on to the
+/// previous hook, unless it was installed before.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // Threads that are exiting have lost their flag, but aren't in plugin calls either.
+            if !SILENT.try_with(Cell::get).unwrap_or(false) {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Runs the plugin call `f`, catching its panics without printing them.
+///
+/// Rather than swapping the process-wide panic hook, which races with other threads, this marks
+/// the thread as being in a plugin call for the hook installed by [`init`], so that panics of
+/// the host outside of plugin calls are still printed. The hook is installed now if `init`
+/// wasn't called.
+pub(crate) fn catch_unwind_silent<R>(
+    f: impl FnOnce() -> R + panic::UnwindSafe,
+) -> thread::Result<R> {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SILENT.set(self.0);
+        }
+    }
+
+    install_panic_hook();
+    let _reset = Reset(SILENT.replace(true));
+    panic::catch_unwind(f)
+}
+
+/// The panic payload used to abort an invocation that exceeds its memory limit.
+pub(crate) struct MemoryLimitExceeded;
+
+/// Runs `f`, aborting it with a [`MemoryLimitExceeded`] panic once the memory it allocates
+/// through Roc on this thread exceeds `limit` bytes.
+///
+/// Memory allocated before the limit is hit is leaked, since Roc code doesn't clean up when it
+/// is unwound.
+pub(crate) fn with_memory_limit<R>(limit: usize, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<Budget>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            BUDGET.set(self.0);
+        }
+    }
+
+    let _reset = Reset(BUDGET.replace(Some(Budget { used: 0, limit })));
+    f()
+}
+
+/// Runs `f`, attributing output of the plugin code it calls to `plugin`.
+pub(crate) fn with_plugin<R>(plugin: &str, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<String>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            PLUGIN.set(self.0.take());
+        }
+    }
+
+    let _reset = Reset(PLUGIN.replace(Some(plugin.into())));
+    f()
+}
+
+/// Returns the name of the plugin running on this thread, if any.
+pub(crate) fn current_plugin() -> Option<String> {
+    PLUGIN.with_borrow(Clone::clone)
+}
+
+/// Calls `f` with the name of the plugin running on this thread.
+pub(crate) fn with_current_plugin<R>(f: impl FnOnce(&str) -> R) -> R {
+    PLUGIN.with_borrow(|plugin| f(plugin.as_deref().unwrap_or("unknown")))
+}
+
+/// Accounts for `grown` newly allocated and `shrunk` released bytes.
+fn account(grown: usize, shrunk: usize) {
+    let Some(mut budget) = BUDGET.get() else {
+        return;
+    };
+    budget.used = (budget.used + grown).saturating_sub(shrunk);
+    if budget.used > budget.limit {
+        BUDGET.set(None);
+        panic::panic_any(MemoryLimitExceeded);
+    }
+    BUDGET.set(Some(budget));
+}
+
 #[no_mangle]
-pub unsafe extern "C" fn roc_alloc(size: usize, _alignment: u32) -> *mut c_void {
-    libc::malloc(size)
+pub unsafe extern "C-unwind" fn roc_alloc(size: usize, _alignment: u32) -> *mut c_void {
+    account(size, 0);
+    let ptr: *mut c_void = alloc::alloc(size).cast();
+    if !ptr.is_null() {
+        trace(|trace| trace.alloc(ptr, size));
+    }
+    ptr
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn roc_realloc(
+pub unsafe extern "C-unwind" fn roc_realloc(
     c_ptr: *mut c_void,
     new_size: usize,
     _old_size: usize,
     _alignment: u32,
 ) -> *mut c_void {
-    libc::realloc(c_ptr, new_size)
+    let old_size = alloc::size_of(c_ptr.cast());
+    account(new_size, old_size);
+    let ptr: *mut c_void = alloc::realloc(c_ptr.cast(), new_size).cast();
+    if !ptr.is_null() {
+        trace(|trace| trace.realloc(c_ptr, ptr, old_size, new_size));
+    }
+    ptr
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn roc_dealloc(c_ptr: *mut c_void, _alignment: u32) {
-    libc::free(c_ptr)
+    account(0, alloc::size_of(c_ptr.cast()));
+    trace(|trace| trace.dealloc(c_ptr));
+    alloc::dealloc(c_ptr.cast());
+}
+
+/// Returns the panic recorded by the last `roc_panic` on this thread, clearing it.
+pub(crate) fn take_panic() -> Option<(String, PanicKind)> {
+    PANIC.take()
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn roc_panic(msg: *const RocStr, tag_id: u32) {
+    let msg = (*msg).as_str().to_owned();
+    PANIC.set(Some((msg.clone(), PanicKind::from_tag(tag_id))));
+    panic!("{msg}");
+}
+
+/// A `dbg` statement evaluated by a plugin.
+#[derive(Debug)]
+pub struct Dbg<'a> {
+    /// The name of the plugin.
+    pub plugin: &'a str,
+    /// The location of the statement, as `file:line`.
+    pub location: &'a str,
+    /// The source code of the inspected expression.
+    pub source: &'a str,
+    /// The inspected value.
+    pub value: &'a str,
+}
+
+/// A function that receives the output of `dbg` statements in plugins, see
+/// [`LoadOptions::dbg_sink`].
+///
+/// [`LoadOptions::dbg_sink`]: crate::LoadOptions::dbg_sink
+#[derive(Clone)]
+pub struct DbgSink(Arc<dyn Fn(&Dbg) + Send + Sync>);
+
+impl DbgSink {
+    pub fn new<F: Fn(&Dbg) + Send + Sync + 'static>(sink: F) -> Self {
+        Self(Arc::new(sink))
+    }
+}
+
+impl fmt::Debug for DbgSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DbgSink(..)")
+    }
+}
+
+/// Passes the output of a `dbg` statement to the sink of the running plugin, see
+/// [`LoadOptions::dbg_sink`].
+///
+/// [`LoadOptions::dbg_sink`]: crate::LoadOptions::dbg_sink
+pub(crate) fn emit_dbg(dbg: &Dbg) {
+    match effects::dbg_sink() {
+        Some(DbgSink(sink)) => sink(dbg),
+        None => default_dbg_sink(dbg),
+    }
+}
+
+fn default_dbg_sink(dbg: &Dbg) {
+    #[cfg(feature = "tracing")]
+    if logging::has_subscriber() {
+        tracing::debug!(
+            target: "roc_plugin::dbg",
+            plugin = dbg.plugin,
+            location = dbg.location,
+            source = dbg.source,
+            "{}",
+            dbg.value,
+        );
+        return;
+    }
+    // `dbg` statements are meant to be seen, so they are printed unless the output is quiet.
+    let (location, source, value) = (dbg.location, dbg.source, dbg.value);
+    logging::print(
+        LogLevel::Info,
+        dbg.plugin,
+        format_args!("[{location}] {source} = {value}"),
+    );
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn roc_panic(msg: *const RocStr, _tag_id: u32) {
-    panic!("{}", *msg);
+pub unsafe extern "C" fn roc_dbg(loc: *const RocStr, msg: *const RocStr, src: *const RocStr) {
+    with_current_plugin(|plugin| {
+        let dbg = Dbg {
+            plugin,
+            location: (*loc).as_str(),
+            source: (*src).as_str(),
+            value: (*msg).as_str(),
+        };
+        emit_dbg(&dbg);
+    });
 }
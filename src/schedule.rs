@@ -0,0 +1,390 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::logging::{plugin_log, LogLevel};
+use crate::manager::PluginManager;
+use crate::plugin::Plugin;
+
+/// When a scheduled function runs, parsed from a cron expression like `*/5 * * * *`.
+///
+/// The five fields are the minute, hour, day of the month, month and day of the week, where
+/// Sunday is 0 or 7. Fields are `*`, numbers, ranges like `1-5` and lists like `1,15`, all of
+/// which may be followed by a step like `/5`. Like in cron, a time matches if the other fields
+/// and either of the day fields match when both are restricted. Times are in UTC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month or of the week is `*`, see [`Schedule::matches`].
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    pub(crate) fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<_> = expr.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        };
+        let mut weekdays_set = parse_field(weekdays, 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if weekdays_set & (1 << 7) != 0 {
+            weekdays_set = (weekdays_set | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekdays_set,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+
+    /// Returns whether the function runs in the minute starting `unix_minute` minutes after the
+    /// Unix epoch.
+    fn matches(&self, unix_minute: u64) -> bool {
+        let time = Time::from_unix_minute(unix_minute);
+        let bit = |set: u64, value: u32| set & (1 << value) != 0;
+        let day = bit(self.days, time.day);
+        let weekday = bit(self.weekdays, time.weekday);
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        bit(self.minutes, time.minute)
+            && bit(self.hours, time.hour)
+            && bit(self.months, time.month)
+            && day_matches
+    }
+}
+
+/// Parses a field of a cron expression into the set of values it matches, as a bitset.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid field `{field}`");
+    let number = |s: &str| match s.parse::<u32>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n),
+        _ => Err(format!("`{s}` is not between {min} and {max}")),
+    };
+
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // A step after a single value runs from it to the end, like in `5/15`.
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// A point in time in UTC, to the minute.
+struct Time {
+    minute: u32,
+    hour: u32,
+    day: u32,
+    month: u32,
+    /// The day of the week, where Sunday is 0.
+    weekday: u32,
+}
+
+impl Time {
+    fn from_unix_minute(unix_minute: u64) -> Self {
+        let days = unix_minute / (24 * 60);
+        let minute_of_day = unix_minute % (24 * 60);
+
+        // The civil date of a day count, see http://howardhinnant.github.io/date_algorithms.html.
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+
+        Self {
+            minute: (minute_of_day % 60) as u32,
+            hour: (minute_of_day / 60) as u32,
+            day: day as u32,
+            month: month as u32,
+            // The Unix epoch was a Thursday.
+            weekday: ((days + 4) % 7) as u32,
+        }
+    }
+}
+
+/// Runs the scheduled functions of a manager's plugins on time, see [`Scheduler::start`].
+///
+/// Scheduling stops when the scheduler is dropped, though runs that already started finish.
+#[derive(Debug)]
+pub struct Scheduler {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// A scheduled function of a plugin.
+struct Job {
+    plugin: Arc<Plugin>,
+    function: String,
+    schedule: Schedule,
+    /// Whether the job is running, so that a run is skipped while the previous one continues.
+    running: Arc<AtomicBool>,
+}
+
+impl Scheduler {
+    /// Starts running the functions of `manager`'s plugins that declare a schedule with
+    /// `#[plugin(schedule = "*/5 * * * *")]`, on a background thread.
+    ///
+    /// Every run is invoked on a thread of its own, so that slow functions don't delay the
+    /// others, and is logged along with its outcome. Runs are skipped while the previous run of
+    /// the same function continues, and disabled plugins are skipped.
+    pub fn start(manager: &PluginManager) -> Self {
+        let jobs: Vec<_> = manager
+            .plugins()
+            .iter()
+            .flat_map(|plugin| {
+                plugin.scheduled().map(|(function, schedule)| Job {
+                    plugin: Arc::clone(plugin),
+                    function: function.into(),
+                    schedule,
+                    running: Arc::default(),
+                })
+            })
+            .collect();
+
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || loop {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let minute = now.as_secs() / 60 + 1;
+            let until_next_minute = Duration::from_secs(minute * 60).saturating_sub(now);
+            if stopped.recv_timeout(until_next_minute) != Err(mpsc::RecvTimeoutError::Timeout) {
+                return;
+            }
+            for job in jobs.iter().filter(|job| job.schedule.matches(minute)) {
+                job.run();
+            }
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Job {
+    /// Invokes the function on a new thread, unless its previous run continues.
+    fn run(&self) {
+        let (plugin, function) = (Arc::clone(&self.plugin), self.function.clone());
+        if plugin.is_disabled() {
+            return;
+        }
+        if self.running.swap(true, Ordering::Acquire) {
+            plugin_log!(
+                "roc_plugin::schedule",
+                LogLevel::Warn,
+                plugin.name(),
+                "skipping scheduled {function}, its previous run continues",
+            );
+            return;
+        }
+
+        let running = Arc::clone(&self.running);
+        thread::spawn(move || {
+            plugin_log!(
+                "roc_plugin::schedule",
+                LogLevel::Debug,
+                plugin.name(),
+                "running scheduled {function}",
+            );
+            let started = Instant::now();
+            match plugin.invoke_function_with(&function, &[]) {
+                Ok(_) => plugin_log!(
+                    "roc_plugin::schedule",
+                    LogLevel::Info,
+                    plugin.name(),
+                    "scheduled {function} finished in {:?}",
+                    started.elapsed(),
+                ),
+                Err(error) => plugin_log!(
+                    "roc_plugin::schedule",
+                    LogLevel::Error,
+                    plugin.name(),
+                    "scheduled {function} failed: {error}",
+                ),
+            }
+            running.store(false, Ordering::Release);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the minutes since the Unix epoch of a time in UTC, see `Time::from_unix_minute`.
+    fn unix_minute(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> u64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        (days * 24 * 60 + hour * 60 + minute) as u64
+    }
+
+    /// Returns the values of a field's bitset.
+    fn values(set: u64) -> Vec<u32> {
+        (0..64).filter(|v| set & (1 << v) != 0).collect()
+    }
+
+    #[test]
+    fn civil_time() {
+        let time = Time::from_unix_minute(0);
+        let fields = [time.minute, time.hour, time.day, time.month, time.weekday];
+        assert_eq!(fields, [0, 0, 1, 1, 4]);
+
+        let time = Time::from_unix_minute(unix_minute(2023, 10, 15, 13, 37));
+        let fields = [time.minute, time.hour, time.day, time.month, time.weekday];
+        assert_eq!(fields, [37, 13, 15, 10, 0]);
+    }
+
+    #[test]
+    fn leap_days() {
+        let time = Time::from_unix_minute(unix_minute(2024, 2, 29, 23, 59));
+        assert_eq!([time.day, time.month, time.weekday], [29, 2, 4]);
+        let time = Time::from_unix_minute(unix_minute(2024, 2, 29, 23, 59) + 1);
+        assert_eq!([time.day, time.month, time.weekday], [1, 3, 5]);
+
+        let time = Time::from_unix_minute(unix_minute(2000, 2, 29, 0, 0));
+        assert_eq!([time.day, time.month], [29, 2]);
+        // 2100 is divisible by 100, but not by 400, so it isn't a leap year.
+        let time = Time::from_unix_minute(unix_minute(2100, 2, 28, 0, 0) + 24 * 60);
+        assert_eq!([time.day, time.month], [1, 3]);
+
+        let schedule = Schedule::parse("0 12 29 2 *").unwrap();
+        assert!(schedule.matches(unix_minute(2024, 2, 29, 12, 0)));
+        assert!(!schedule.matches(unix_minute(2024, 3, 1, 12, 0)));
+    }
+
+    #[test]
+    fn steps_ranges_and_lists() {
+        let minutes = |field| {
+            values(
+                Schedule::parse(&format!("{field} * * * *"))
+                    .unwrap()
+                    .minutes,
+            )
+        };
+        assert_eq!(minutes("*/15"), [0, 15, 30, 45]);
+        assert_eq!(minutes("5/15"), [5, 20, 35, 50]);
+        assert_eq!(minutes("1-5"), [1, 2, 3, 4, 5]);
+        assert_eq!(minutes("1-10/3"), [1, 4, 7, 10]);
+        assert_eq!(minutes("1,15,30-32"), [1, 15, 30, 31, 32]);
+        assert_eq!(minutes("7"), [7]);
+        assert_eq!(minutes("*").len(), 60);
+
+        let schedule = Schedule::parse("*/5 * * * *").unwrap();
+        assert!(schedule.matches(unix_minute(2023, 10, 15, 13, 35)));
+        assert!(!schedule.matches(unix_minute(2023, 10, 15, 13, 36)));
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        let schedule = Schedule::parse("0 0 * * 0").unwrap();
+        assert_eq!(schedule, Schedule::parse("0 0 * * 7").unwrap());
+        assert_eq!(values(schedule.weekdays), [0]);
+        assert!(schedule.matches(unix_minute(2023, 10, 15, 0, 0)));
+        assert!(!schedule.matches(unix_minute(2023, 10, 16, 0, 0)));
+
+        let weekend = Schedule::parse("0 0 * * 6-7").unwrap();
+        assert_eq!(values(weekend.weekdays), [0, 6]);
+        let every_day = Schedule::parse("0 0 * * 0-7").unwrap();
+        assert_eq!(values(every_day.weekdays), [0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either_day() {
+        // The 13th of every month, and every Friday.
+        let schedule = Schedule::parse("0 0 13 * 5").unwrap();
+        assert!(schedule.matches(unix_minute(2023, 10, 13, 0, 0)));
+        assert!(schedule.matches(unix_minute(2023, 10, 20, 0, 0)));
+        assert!(schedule.matches(unix_minute(2023, 11, 13, 0, 0)));
+        assert!(!schedule.matches(unix_minute(2023, 10, 14, 0, 0)));
+
+        // Only one day field is restricted, so it alone decides.
+        let thirteenth = Schedule::parse("0 0 13 * *").unwrap();
+        assert!(!thirteenth.matches(unix_minute(2023, 10, 20, 0, 0)));
+        let fridays = Schedule::parse("0 0 * * 5").unwrap();
+        assert!(!fridays.matches(unix_minute(2023, 11, 13, 0, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert_eq!(
+            Schedule::parse("* * * *").unwrap_err(),
+            "expected 5 fields, found 4"
+        );
+        assert_eq!(
+            Schedule::parse("* * * * * *").unwrap_err(),
+            "expected 5 fields, found 6"
+        );
+        assert_eq!(
+            Schedule::parse("60 * * * *").unwrap_err(),
+            "`60` is not between 0 and 59"
+        );
+        for expr in [
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * 32 * *",
+            "* * * 0 *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "1,,2 * * * *",
+            "*/x * * * *",
+            "",
+        ] {
+            assert!(Schedule::parse(expr).is_err(), "{expr:?}");
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::{self, Read};
+use std::net::ToSocketAddrs;
+use std::thread;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::error::PluginError;
+use crate::logging::{plugin_log, LogLevel};
+use crate::manager::PluginManager;
+use crate::metrics::PrometheusMetrics;
+use crate::plugin::Plugin;
+use crate::value::Value;
+
+/// The largest request body the server accepts, so that clients can't make the host allocate
+/// without bound.
+const MAX_BODY_LEN: u64 = 16 << 20;
+
+/// A response to a request, before it's serialized.
+struct Reply {
+    status: u16,
+    body: serde_json::Value,
+}
+
+impl Reply {
+    fn ok(body: serde_json::Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, error: impl ToString) -> Self {
+        let body = serde_json::json!({ "error": error.to_string() });
+        Self { status, body }
+    }
+}
+
+impl PluginManager {
+    /// Serves the manager's plugins over HTTP at `addr`, until accepting a request fails.
+    ///
+    /// The endpoints are:
+    ///
+    /// - `GET /plugins`, which responds with the plugins and their functions
+    /// - `POST /plugins/<name>`, which invokes the plugin's first function, or the one named by
+    ///   a `function` query parameter, with the arguments in the JSON body, and responds with
+    ///   `{"value": ...}`
+    ///
+    /// The arguments are a JSON array, or a JSON object for functions taking a single record,
+    /// see [`Value::to_json`] for how values are represented. Failed requests get a response
+    /// like `{"error": "..."}`, with status 400 for invalid arguments, 404 for unknown plugins,
+    /// 503 for unhealthy plugins and 500 for failed invocations. Requests are served
+    /// concurrently.
+    pub fn serve_http<A: ToSocketAddrs>(&self, addr: A) -> Result<(), PluginError> {
+        let server =
+            Server::http(addr).map_err(|error| PluginError::Io(io::Error::other(error)))?;
+        thread::scope(|scope| {
+            for request in server.incoming_requests() {
+                scope.spawn(move || self.serve_request(request));
+            }
+        });
+        Ok(())
+    }
+
+    fn serve_request(&self, mut request: Request) {
+        let url = request.url().to_owned();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let reply = match (request.method(), path.strip_prefix("/plugins")) {
+            (Method::Get, Some("" | "/")) => self.discover(),
+            (Method::Post, Some(name)) if name.len() > 1 && name.starts_with('/') => {
+                let function = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("function="));
+                let mut body = String::new();
+                match request
+                    .as_reader()
+                    .take(MAX_BODY_LEN + 1)
+                    .read_to_string(&mut body)
+                {
+                    Ok(len) if len as u64 > MAX_BODY_LEN => Reply::error(413, "body is too long"),
+                    Ok(_) => self.invoke_endpoint(&name[1..], function, &body),
+                    Err(error) => Reply::error(400, format!("unreadable body: {error}")),
+                }
+            }
+            (_, Some("" | "/")) => Reply::error(405, "expected GET"),
+            (_, Some(name)) if name.starts_with('/') => Reply::error(405, "expected POST"),
+            _ => Reply::error(404, format!("no endpoint at `{path}`")),
+        };
+
+        let header =
+            Header::from_bytes("Content-Type", "application/json").expect("the header is valid");
+        let response = Response::from_string(reply.body.to_string())
+            .with_status_code(reply.status)
+            .with_header(header);
+        if let Err(error) = request.respond(response) {
+            plugin_log!(
+                "roc_plugin::server",
+                LogLevel::Error,
+                "http",
+                "failed to respond to HTTP request: {error}",
+            );
+        }
+    }
+
+    /// Responds to `GET /plugins`.
+    fn discover(&self) -> Reply {
+        let plugins: Vec<_> = self.plugins().iter().map(|p| describe(p)).collect();
+        Reply::ok(serde_json::json!({ "plugins": plugins }))
+    }
+
+    /// Responds to `POST /plugins/<name>`.
+    fn invoke_endpoint(&self, name: &str, function: Option<&str>, body: &str) -> Reply {
+        let Some(plugin) = self.get(name) else {
+            return Reply::error(404, PluginError::PluginNotFound(name.into()));
+        };
+        let function = function.unwrap_or(&plugin.meta().name);
+        let meta = match plugin.function(function) {
+            Ok(meta) => meta,
+            Err(error) => return Reply::error(404, error),
+        };
+        // An empty body stands for no arguments.
+        let json = match body.trim() {
+            "" => serde_json::Value::Array(Vec::new()),
+            body => match serde_json::from_str(body) {
+                Ok(json) => json,
+                Err(error) => return Reply::error(400, format!("malformed body: {error}")),
+            },
+        };
+        let args = match Value::args_from_json(&json, &meta.arg_types) {
+            Ok(args) => args,
+            Err(error) => return Reply::error(400, error),
+        };
+
+        match plugin.invoke_function_with(function, &args) {
+            Ok(value) => Reply::ok(serde_json::json!({ "value": value.to_json() })),
+            Err(error @ PluginError::Unhealthy { .. }) => Reply::error(503, error),
+            Err(error) => Reply::error(500, error),
+        }
+    }
+}
+
+impl PrometheusMetrics {
+    /// Serves the metrics over HTTP at `addr`, in the Prometheus text format at `GET /metrics`,
+    /// until accepting a request fails.
+    pub fn serve_http<A: ToSocketAddrs>(&self, addr: A) -> Result<(), PluginError> {
+        let server =
+            Server::http(addr).map_err(|error| PluginError::Io(io::Error::other(error)))?;
+        for request in server.incoming_requests() {
+            let response = match (request.method(), request.url()) {
+                (Method::Get, "/metrics") => {
+                    let header = Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+                        .expect("the header is valid");
+                    Response::from_string(self.render()).with_header(header)
+                }
+                (_, "/metrics") => Response::from_string("expected GET").with_status_code(405),
+                (_, path) => {
+                    Response::from_string(format!("no endpoint at `{path}`")).with_status_code(404)
+                }
+            };
+            if let Err(error) = request.respond(response) {
+                plugin_log!(
+                    "roc_plugin::server",
+                    LogLevel::Error,
+                    "metrics",
+                    "failed to respond to HTTP request: {error}",
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describes `plugin` in the response to `GET /plugins`.
+fn describe(plugin: &Plugin) -> serde_json::Value {
+    let functions: Vec<_> = plugin
+        .functions()
+        .filter_map(|name| plugin.function(name).ok())
+        .map(|meta| {
+            let arg_types: Vec<_> = meta.arg_types.iter().map(ToString::to_string).collect();
+            serde_json::json!({
+                "name": meta.name,
+                "arguments": arg_types,
+                "returns": meta.return_type.to_string(),
+                "description": meta.description,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "name": plugin.name(),
+        "endpoint": format!("/plugins/{}", plugin.name()),
+        "disabled": plugin.is_disabled(),
+        "functions": functions,
+    })
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::PluginError;
+
+/// The declarations of a plugin in a `<name>.toml` file next to its source, for plugins that
+/// don't declare their functions in header lines.
+///
+/// ```toml
+/// config = "{ greeting : Str }"
+/// state = "{ count : U64 }"
+/// packages = { json = "https://example.com/roc-json/0.10.0/KbIfTNbx.tar.br" }
+///
+/// [[functions]]
+/// name = "slugify"
+/// signature = "Str -> Str"
+/// timeout = "2s"
+/// description = "slugifies text"
+/// version = "1.2"
+/// tags = ["text"]
+/// hook = "on_message"
+/// subscribe = "user_created"
+/// priority = 10
+/// schedule = "*/5 * * * *"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Sidecar {
+    /// The type of the plugin's configuration, like in a `#[config]` header line.
+    #[serde(default)]
+    config: Option<String>,
+    /// The type of the plugin's state, like in a `#[state]` header line.
+    #[serde(default)]
+    state: Option<String>,
+    /// The packages the plugin depends on, keyed by shorthand, like in `#[package]` header lines.
+    #[serde(default)]
+    packages: BTreeMap<String, String>,
+    functions: Vec<Function>,
+}
+
+/// A function declared in a sidecar, like in a `#[plugin]` header line.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Function {
+    name: String,
+    signature: String,
+    #[serde(default)]
+    timeout: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    hook: Option<String>,
+    #[serde(default)]
+    subscribe: Option<String>,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    schedule: Option<String>,
+}
+
+/// Returns the path of the sidecar of the plugin whose source is at `source`.
+pub(crate) fn path(source: &Path) -> PathBuf {
+    source.with_extension("toml")
+}
+
+/// Reads the sidecar of the plugin whose source is at `source`, returning the header lines
+/// equivalent to its declarations, or `None` if the plugin has no sidecar.
+pub(crate) fn read(source: &Path) -> Result<Option<String>, PluginError> {
+    let path = path(source);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let invalid = |msg: String| PluginError::HeaderParse(format!("in {}: {msg}", path.display()));
+    let sidecar: Sidecar = toml::from_str(&text).map_err(|error| invalid(error.to_string()))?;
+
+    let mut headers = Vec::new();
+    if let Some(config) = &sidecar.config {
+        headers.push(format!("#[config] {config}"));
+    }
+    if let Some(state) = &sidecar.state {
+        headers.push(format!("#[state] {state}"));
+    }
+    for (name, location) in &sidecar.packages {
+        headers.push(format!(r#"#[package] {name} "{location}""#));
+    }
+    for function in &sidecar.functions {
+        // Attribute values are quoted in headers, and tags separated by commas.
+        let values = [
+            &function.timeout,
+            &function.description,
+            &function.version,
+            &function.hook,
+            &function.subscribe,
+            &function.schedule,
+        ];
+        if values
+            .into_iter()
+            .flatten()
+            .any(|value| value.contains('"'))
+            || function.tags.iter().any(|tag| tag.contains([',', '"']))
+        {
+            return Err(invalid(format!(
+                "attributes of `{}` can't contain quotes, and tags can't contain commas",
+                function.name
+            )));
+        }
+
+        let mut attrs = Vec::new();
+        for (key, value) in [
+            ("timeout", &function.timeout),
+            ("description", &function.description),
+            ("version", &function.version),
+            ("hook", &function.hook),
+            ("subscribe", &function.subscribe),
+            ("schedule", &function.schedule),
+        ] {
+            if let Some(value) = value {
+                attrs.push(format!(r#"{key} = "{value}""#));
+            }
+        }
+        if function.priority != 0 {
+            attrs.push(format!(r#"priority = "{}""#, function.priority));
+        }
+        if !function.tags.is_empty() {
+            attrs.push(format!(r#"tags = "{}""#, function.tags.join(", ")));
+        }
+        let annotation = if attrs.is_empty() {
+            "#[plugin]".to_owned()
+        } else {
+            format!("#[plugin({})]", attrs.join(", "))
+        };
+        headers.push(format!(
+            "{annotation} {} : {}",
+            function.name, function.signature
+        ));
+    }
+    Ok(Some(headers.join("\n") + "\n"))
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
+
+use crate::error::PluginError;
+use crate::logging::{plugin_log, LogLevel};
+use crate::manager::PluginManager;
+use crate::plugin::Plugin;
+use crate::value::Value;
+
+/// The longest message the control socket accepts, so that a bad length prefix can't make the
+/// host allocate without bound.
+const MAX_MESSAGE_LEN: usize = 16 << 20;
+
+impl PluginManager {
+    /// Serves requests to the manager's plugins over a Unix domain socket at `path`, until
+    /// accepting a connection fails.
+    ///
+    /// Messages in both directions are JSON objects, each preceded by its length in bytes as a
+    /// 4-byte big-endian integer. Connections may send any number of requests, which each get a
+    /// response, and are served concurrently. The requests are:
+    ///
+    /// - `{"op": "invoke", "plugin": "slugify", "args": ["Hello"]}`, which invokes the plugin's
+    ///   first function, or the one named by an optional `function`, and responds with
+    ///   `{"ok": true, "value": ...}`
+    /// - `{"op": "reload", "plugin": "slugify"}`, which reloads the plugin, or all plugins if
+    ///   `plugin` is missing, see [`Plugin::reload`]
+    /// - `{"op": "status"}`, which responds with the plugins and their functions
+    ///
+    /// Failed requests get a response like `{"ok": false, "error": "..."}`. A socket left at
+    /// `path` by an earlier run is replaced.
+    pub fn serve_socket<P: AsRef<Path>>(&self, path: P) -> Result<(), PluginError> {
+        let path = path.as_ref();
+        if path
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_socket())
+        {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        let started = Instant::now();
+
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                let stream = stream?;
+                scope.spawn(move || {
+                    if let Err(error) = self.serve_connection(stream, started) {
+                        plugin_log!(
+                            "roc_plugin::socket",
+                            LogLevel::Error,
+                            "socket",
+                            "control socket connection failed: {error}",
+                        );
+                    }
+                });
+            }
+            Ok(())
+        })
+    }
+
+    /// Responds to the requests sent over `stream` until the other end closes it.
+    fn serve_connection(&self, mut stream: UnixStream, started: Instant) -> io::Result<()> {
+        while let Some(message) = read_message(&mut stream)? {
+            let response = match serde_json::from_str(&message) {
+                Ok(request) => self.respond(&request, started),
+                Err(error) => Err(format!("malformed request: {error}")),
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(error) => serde_json::json!({ "ok": false, "error": error }),
+            };
+            write_message(&mut stream, &response.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn respond(
+        &self,
+        request: &serde_json::Value,
+        started: Instant,
+    ) -> Result<serde_json::Value, String> {
+        let field = |name: &str| request.get(name).and_then(|value| value.as_str());
+        let plugin = |name: &str| {
+            self.get(name)
+                .ok_or_else(|| PluginError::PluginNotFound(name.into()).to_string())
+        };
+
+        match field("op").ok_or("missing `op`")? {
+            "invoke" => {
+                let plugin = plugin(field("plugin").ok_or("missing `plugin`")?)?;
+                let function = field("function").unwrap_or(&plugin.meta().name);
+                let meta = plugin.function(function).map_err(|e| e.to_string())?;
+                let no_args = serde_json::Value::Array(Vec::new());
+                let args = request.get("args").unwrap_or(&no_args);
+                let args = Value::args_from_json(args, &meta.arg_types)?;
+
+                let value = plugin
+                    .invoke_function_with(function, &args)
+                    .map_err(|e| e.to_string())?;
+                Ok(serde_json::json!({ "ok": true, "value": value.to_json() }))
+            }
+            "reload" => {
+                let plugins = match field("plugin") {
+                    Some(name) => vec![plugin(name)?],
+                    None => self.plugins().iter().collect(),
+                };
+                let failures: Vec<_> = plugins
+                    .iter()
+                    .filter_map(|plugin| {
+                        let error = plugin.reload().err()?;
+                        Some(format!("{}: {error}", plugin.name()))
+                    })
+                    .collect();
+                if !failures.is_empty() {
+                    return Err(failures.join("\n"));
+                }
+                Ok(serde_json::json!({ "ok": true }))
+            }
+            "status" => {
+                let plugins: Vec<_> = self.plugins().iter().map(|p| status(p)).collect();
+                Ok(serde_json::json!({
+                    "ok": true,
+                    "uptime_ms": started.elapsed().as_millis() as u64,
+                    "plugins": plugins,
+                }))
+            }
+            op => Err(format!("unknown op `{op}`")),
+        }
+    }
+}
+
+/// Describes `plugin` in the response to a `status` request.
+fn status(plugin: &Plugin) -> serde_json::Value {
+    let functions: Vec<_> = plugin
+        .functions()
+        .filter_map(|name| plugin.function(name).ok())
+        .map(|meta| {
+            serde_json::json!({
+                "name": meta.name,
+                "signature": meta.signature(),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "name": plugin.name(),
+        "path": plugin.path().display().to_string(),
+        "disabled": plugin.is_disabled(),
+        "stateful": plugin.is_stateful(),
+        "functions": functions,
+    })
+}
+
+/// Reads a length-prefixed message, or returns `None` if the other end closed the connection.
+fn read_message(stream: &mut UnixStream) -> io::Result<Option<String>> {
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {len} bytes is too long"),
+        ));
+    }
+
+    let mut message = vec![0; len];
+    stream.read_exact(&mut message)?;
+    String::from_utf8(message)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn write_message(stream: &mut UnixStream, message: &str) -> io::Result<()> {
+    let len = u32::try_from(message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "response is too long"))?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(message.as_bytes())
+}
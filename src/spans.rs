@@ -0,0 +1,47 @@
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+use crate::error::PluginError;
+
+/// Runs `$f` in a `tracing` span called `$name` with the given fields, if the `tracing` feature
+/// is enabled, see [`record`].
+///
+/// The fields are only evaluated if the span is created.
+macro_rules! in_span {
+    ($name:literal, $f:expr, $($field:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let result = $crate::spans::record(
+            tracing::info_span!(
+                target: "roc_plugin",
+                $name,
+                duration_ms = tracing::field::Empty,
+                outcome = tracing::field::Empty,
+                $($field)*
+            ),
+            $f,
+        );
+        #[cfg(not(feature = "tracing"))]
+        let result = $f();
+        result
+    }};
+}
+
+pub(crate) use in_span;
+
+/// Runs `f` in `span`, recording how long it took in `duration_ms`, and its outcome, `ok` or
+/// the error, in `outcome`.
+#[cfg(feature = "tracing")]
+pub(crate) fn record<R>(
+    span: tracing::Span,
+    f: impl FnOnce() -> Result<R, PluginError>,
+) -> Result<R, PluginError> {
+    let started = Instant::now();
+    let result = span.in_scope(f);
+    span.record("duration_ms", started.elapsed().as_secs_f64() * 1000.0);
+    match &result {
+        Ok(_) => span.record("outcome", "ok"),
+        Err(error) => span.record("outcome", tracing::field::display(error)),
+    };
+    result
+}
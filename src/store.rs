@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::PluginError;
+
+/// The key-value store plugins access through `Host.kvGet` and `Host.kvSet`.
+///
+/// Every plugin has its own namespace, so plugins can't see or overwrite each other's entries.
+#[derive(Debug, Default)]
+pub(crate) struct Store {
+    entries: Mutex<BTreeMap<(String, String), String>>,
+    /// The file the store is persisted to after every change, if any.
+    path: Option<PathBuf>,
+}
+
+impl Store {
+    /// Opens the store persisted at `path`, which is created on the first change if it doesn't
+    /// exist yet.
+    pub(crate) fn open(path: &Path) -> Result<Self, PluginError> {
+        let entries = match fs::read_to_string(path) {
+            Ok(contents) => parse(&contents).ok_or_else(|| {
+                let msg = format!("malformed key-value store `{}`", path.display());
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            })?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Self {
+            entries: Mutex::new(entries),
+            path: Some(path.to_owned()),
+        })
+    }
+
+    pub(crate) fn get(&self, namespace: &str, key: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(&(namespace.into(), key.into())).cloned()
+    }
+
+    pub(crate) fn set(&self, namespace: &str, key: &str, value: &str) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert((namespace.into(), key.into()), value.into());
+        match &self.path {
+            Some(path) => persist(path, &entries),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes `entries` to `path`, one tab-separated `namespace key value` line per entry.
+fn persist(path: &Path, entries: &BTreeMap<(String, String), String>) -> io::Result<()> {
+    let mut contents = String::new();
+    for ((namespace, key), value) in entries {
+        let fields = [namespace, key, value].map(|s| escape(s));
+        contents.push_str(&fields.join("\t"));
+        contents.push('\n');
+    }
+
+    // Write next to the store first, so that a crash can't leave it partially written.
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+    let dir = dir.unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let partial = tempfile::NamedTempFile::new_in(dir)?;
+    fs::write(partial.path(), contents)?;
+    partial.persist(path).map_err(io::Error::from)?;
+    Ok(())
+}
+
+fn parse(contents: &str) -> Option<BTreeMap<(String, String), String>> {
+    contents
+        .lines()
+        .map(|line| {
+            let [namespace, key, value] = line.split('\t').collect::<Vec<_>>()[..] else {
+                return None;
+            };
+            Some(((unescape(namespace)?, unescape(key)?), unescape(value)?))
+        })
+        .collect()
+}
+
+/// Escapes the characters that separate fields and entries.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
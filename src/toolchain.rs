@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{LazyLock, Mutex};
+
+use regex::Regex;
+
+/// The platform header syntax understood by a Roc compiler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Syntax {
+    /// Headers with an `imports []` section, as accepted by older nightlies.
+    Legacy,
+    /// Headers without `imports`, which newer nightlies require.
+    Modern,
+}
+
+/// A Roc compiler, as reported by `roc version`.
+#[derive(Debug)]
+pub(crate) struct Toolchain {
+    /// The compiler executable, see [`LoadOptions::roc_bin`](crate::LoadOptions::roc_bin).
+    pub(crate) bin: PathBuf,
+    /// The output of `roc version`, which is empty if the compiler couldn't be run.
+    pub(crate) version: String,
+    pub(crate) syntax: Syntax,
+}
+
+impl Toolchain {
+    /// Returns a command running the compiler.
+    pub(crate) fn command(&self) -> Command {
+        Command::new(&self.bin)
+    }
+}
+
+/// The first nightly that rejects `imports []` in platform headers.
+const MODERN_SYNTAX_SINCE: (u32, u32, u32) = (2024, 8, 1);
+
+/// The oldest nightly whose builtin `Task` the generated platform relies on.
+pub(crate) const OLDEST_SUPPORTED: (u32, u32, u32) = (2024, 6, 1);
+
+/// The first nightly that replaced `Task` with effectful functions, which plugins can't use yet.
+pub(crate) const UNSUPPORTED_SINCE: (u32, u32, u32) = (2025, 1, 1);
+
+/// Returns the Roc compiler `bin`, probing it on first use.
+pub(crate) fn toolchain(bin: &Path) -> &'static Toolchain {
+    static TOOLCHAINS: LazyLock<Mutex<HashMap<PathBuf, &'static Toolchain>>> =
+        LazyLock::new(Default::default);
+
+    let mut toolchains = TOOLCHAINS.lock().unwrap();
+    if let Some(toolchain) = toolchains.get(bin) {
+        return toolchain;
+    }
+    // Only a handful of compilers are ever used, so keep them for the rest of the process.
+    let toolchain = Box::leak(Box::new(probe(bin)));
+    toolchains.insert(bin.to_owned(), toolchain);
+    toolchain
+}
+
+fn probe(bin: &Path) -> Toolchain {
+    let version = Command::new(bin)
+        .arg("version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        .unwrap_or_default();
+
+    // Compilers we can't date are assumed to be recent.
+    let syntax = match build_date(&version) {
+        Some(date) if date < MODERN_SYNTAX_SINCE => Syntax::Legacy,
+        _ => Syntax::Modern,
+    };
+    Toolchain {
+        bin: bin.to_owned(),
+        version,
+        syntax,
+    }
+}
+
+/// Extracts the build date from a version string like
+/// `roc nightly pre-release, built from commit 1a2b3c4 on Mon Aug 26 09:03:15 UTC 2024`.
+pub(crate) fn build_date(version: &str) -> Option<(u32, u32, u32)> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let re =
+        Regex::new(r" on \w{3} (?P<month>\w{3}) +(?P<day>\d+) [\d:]+ \w+ (?P<year>\d{4})").unwrap();
+    let caps = re.captures(version)?;
+    let month = MONTHS.iter().position(|m| *m == &caps["month"])? as u32 + 1;
+    Some((caps["year"].parse().ok()?, month, caps["day"].parse().ok()?))
+}
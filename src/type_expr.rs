@@ -0,0 +1,725 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::value::DType;
+
+/// A Roc type annotation, as written in a plugin header, before it is resolved to a [`DType`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum TypeExpr {
+    /// A type name, possibly applied to arguments, like `Str` or `Result Str U64`.
+    Apply {
+        name: String,
+        args: Vec<TypeExpr>,
+        span: Range<usize>,
+    },
+    /// A record type, like `{ name : Str }`, or `{}`.
+    Record {
+        fields: Vec<(String, Range<usize>, TypeExpr)>,
+        span: Range<usize>,
+    },
+    /// A tuple type, like `(Str, U64)`.
+    Tuple {
+        elems: Vec<TypeExpr>,
+        span: Range<usize>,
+    },
+    /// A tag union, like `[None, Some Str]`.
+    Tags {
+        tags: Vec<(String, Vec<TypeExpr>)>,
+        span: Range<usize>,
+    },
+}
+
+/// The signature of a plugin function, like `Str, U64 -> Str`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Signature {
+    pub(crate) args: Vec<TypeExpr>,
+    pub(crate) ret: TypeExpr,
+}
+
+/// An error in a type annotation, pointing at the offending part of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TypeError {
+    pub(crate) message: String,
+    /// The byte range of the annotation the error refers to.
+    pub(crate) span: Range<usize>,
+}
+
+impl TypeError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Renders the error with `source`, the annotation it was found in, underlining the
+    /// offending part:
+    ///
+    /// ```text
+    /// unknown type `Strng`
+    ///     Strng -> Str
+    ///     ^^^^^
+    /// ```
+    pub(crate) fn render(&self, source: &str) -> String {
+        let start = source[..self.span.start.min(source.len())].chars().count();
+        let len = source
+            .get(self.span.clone())
+            .map_or(1, |s| s.chars().count().max(1));
+        format!(
+            "{}\n    {source}\n    {}{}",
+            self.message,
+            " ".repeat(start),
+            "^".repeat(len)
+        )
+    }
+
+    /// Shifts the error by `offset` bytes, for annotations that are part of a longer line.
+    pub(crate) fn offset(mut self, offset: usize) -> Self {
+        self.span = self.span.start + offset..self.span.end + offset;
+        self
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl TypeExpr {
+    /// Parses a type annotation that makes up the whole of `s`.
+    pub(crate) fn parse(s: &str) -> Result<Self, TypeError> {
+        let mut parser = Parser::new(s)?;
+        let expr = parser.type_expr()?;
+        parser.finish()?;
+        Ok(expr)
+    }
+
+    fn span(&self) -> Range<usize> {
+        match self {
+            Self::Apply { span, .. }
+            | Self::Record { span, .. }
+            | Self::Tuple { span, .. }
+            | Self::Tags { span, .. } => span.clone(),
+        }
+    }
+
+    /// Resolves the annotation to the type values are marshalled as.
+    pub(crate) fn to_dtype(&self) -> Result<DType, TypeError> {
+        match self {
+            Self::Apply { name, args, span } => {
+                if name == "Option" {
+                    return Err(TypeError::new(
+                        "unknown type `Option`, optional values are written `[None, Some a]`",
+                        span.clone(),
+                    ));
+                }
+                let arity = match name.as_str() {
+                    "List" => 1,
+                    "Result" | "Task" | "Dict" => 2,
+                    _ => 0,
+                };
+                if args.len() != arity {
+                    let message = match arity {
+                        0 => format!("`{name}` takes no type arguments"),
+                        1 => format!("`{name}` takes 1 type argument, found {}", args.len()),
+                        n => format!("`{name}` takes {n} type arguments, found {}", args.len()),
+                    };
+                    return Err(TypeError::new(message, span.clone()));
+                }
+                let args = args
+                    .iter()
+                    .map(TypeExpr::to_dtype)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut args = args.into_iter();
+                let mut arg = || Box::new(args.next().expect("arity was checked"));
+
+                let dtype = match name.as_str() {
+                    "Bool" => DType::Bool,
+                    "Str" => DType::Str,
+                    "U8" => DType::U8,
+                    "U64" => DType::U64,
+                    "I8" => DType::I8,
+                    "I16" => DType::I16,
+                    "I32" => DType::I32,
+                    "I64" => DType::I64,
+                    "F32" => DType::F32,
+                    "F64" => DType::F64,
+                    "Dec" => DType::Dec,
+                    "Bytes" => {
+                        return Err(TypeError::new(
+                            "unknown type `Bytes`, byte buffers are written `List U8`",
+                            span.clone(),
+                        ));
+                    }
+                    "List" => {
+                        let elem = arg();
+                        // Byte lists are passed as `Bytes`, without copying them.
+                        if *elem == DType::U8 {
+                            return Ok(DType::Bytes);
+                        }
+                        if let DType::Unit
+                        | DType::Bytes
+                        | DType::List(_)
+                        | DType::Record(_)
+                        | DType::Tuple(_)
+                        | DType::Result(..)
+                        | DType::Task(..)
+                        | DType::Dict(..)
+                        | DType::Option(_) = *elem
+                        {
+                            let span = self.args_span();
+                            return Err(TypeError::new(
+                                format!("unsupported list element type `{elem}`"),
+                                span,
+                            ));
+                        }
+                        DType::List(elem)
+                    }
+                    "Result" => DType::Result(arg(), arg()),
+                    "Task" => DType::Task(arg(), arg()),
+                    "Dict" => {
+                        let (key, value) = (arg(), arg());
+                        if *key != DType::Str || *value != DType::Str {
+                            return Err(TypeError::new(
+                                "unsupported dict type, only `Dict Str Str` is supported",
+                                span.clone(),
+                            ));
+                        }
+                        DType::Dict(key, value)
+                    }
+                    _ if name.starts_with(|c: char| c.is_ascii_lowercase()) => {
+                        return Err(TypeError::new(
+                            format!("type variables like `{name}` are not supported"),
+                            span.clone(),
+                        ));
+                    }
+                    _ => {
+                        return Err(TypeError::new(
+                            format!("unknown type `{name}`"),
+                            span.clone(),
+                        ));
+                    }
+                };
+                Ok(dtype)
+            }
+            Self::Record { fields, .. } if fields.is_empty() => Ok(DType::Unit),
+            Self::Record { fields, .. } => {
+                let mut resolved: Vec<(String, DType)> = Vec::new();
+                for (name, span, expr) in fields {
+                    if resolved.iter().any(|(n, _)| n == name) {
+                        return Err(TypeError::new(
+                            format!("duplicate record field `{name}`"),
+                            span.clone(),
+                        ));
+                    }
+                    resolved.push((name.clone(), expr.to_dtype()?));
+                }
+                Ok(DType::Record(resolved))
+            }
+            Self::Tuple { elems, .. } => Ok(DType::Tuple(
+                elems
+                    .iter()
+                    .map(TypeExpr::to_dtype)
+                    .collect::<Result<_, _>>()?,
+            )),
+            Self::Tags { tags, span } => {
+                // The only supported tag union is the optional value `[None, Some a]`.
+                let some = match &tags[..] {
+                    [(none, no_payload), (some, payload)]
+                    | [(some, payload), (none, no_payload)]
+                        if none == "None" && no_payload.is_empty() && some == "Some" =>
+                    {
+                        payload
+                    }
+                    _ => {
+                        return Err(TypeError::new(
+                            "unsupported tag union, only `[None, Some a]` is supported",
+                            span.clone(),
+                        ));
+                    }
+                };
+                match &some[..] {
+                    [payload] => Ok(DType::Option(Box::new(payload.to_dtype()?))),
+                    _ => Err(TypeError::new(
+                        "`Some` takes exactly one payload",
+                        span.clone(),
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Returns the span of the arguments of a type application.
+    fn args_span(&self) -> Range<usize> {
+        match self {
+            Self::Apply { args, span, .. } => match (args.first(), args.last()) {
+                (Some(first), Some(last)) => first.span().start..last.span().end,
+                _ => span.clone(),
+            },
+            expr => expr.span(),
+        }
+    }
+}
+
+impl Signature {
+    /// Parses a function signature like `Str, U64 -> Str`, or just the return type for
+    /// functions without arguments.
+    pub(crate) fn parse(s: &str) -> Result<Self, TypeError> {
+        let mut parser = Parser::new(s)?;
+        let mut types = vec![parser.type_expr()?];
+        while parser.eat(&Token::Comma) {
+            types.push(parser.type_expr()?);
+        }
+
+        let signature = if parser.eat(&Token::Arrow) {
+            Signature {
+                args: types,
+                ret: parser.type_expr()?,
+            }
+        } else if let [_] = &types[..] {
+            Signature {
+                args: Vec::new(),
+                ret: types.pop().expect("there is one type"),
+            }
+        } else {
+            return Err(parser.unexpected("`->`"));
+        };
+        parser.finish()?;
+        Ok(signature)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    /// A type or tag name, like `Str`.
+    Upper(String),
+    /// A record field name or type variable, like `name`.
+    Lower(String),
+    Arrow,
+    Comma,
+    Colon,
+    Open(char),
+    Close(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Upper(name) | Self::Lower(name) => write!(f, "`{name}`"),
+            Self::Arrow => f.write_str("`->`"),
+            Self::Comma => f.write_str("`,`"),
+            Self::Colon => f.write_str("`:`"),
+            Self::Open(c) | Self::Close(c) => write!(f, "`{c}`"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<(Token, Range<usize>)>, TypeError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '(' | '{' | '[' => Token::Open(c),
+            ')' | '}' | ']' => Token::Close(c),
+            '-' if chars.next_if(|&(_, c)| c == '>').is_some() => Token::Arrow,
+            c if c.is_ascii_alphabetic() => {
+                let mut end = start + 1;
+                // Qualified names like `Str.Str` are read as a single name.
+                while let Some((i, c)) =
+                    chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+                {
+                    end = i + c.len_utf8();
+                }
+                let name = s[start..end].to_owned();
+                let token = if c.is_ascii_uppercase() {
+                    Token::Upper(name)
+                } else {
+                    Token::Lower(name)
+                };
+                tokens.push((token, start..end));
+                continue;
+            }
+            c => {
+                return Err(TypeError::new(
+                    format!("unexpected `{c}`"),
+                    start..start + c.len_utf8(),
+                ));
+            }
+        };
+        let end = chars.peek().map_or(s.len(), |&(i, _)| i);
+        tokens.push((token, start..end));
+    }
+    Ok(tokens)
+}
+
+/// A recursive-descent parser of type annotations.
+///
+/// ```text
+/// type  = Name atom* | atom
+/// atom  = Name | "(" type ("," type)* ")" | "{" (field ("," field)*)? "}" | "[" tag ("," tag)* "]"
+/// field = name ":" type
+/// tag   = Name atom*
+/// ```
+struct Parser {
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+    /// The length of the input, where errors about its end point.
+    len: usize,
+}
+
+impl Parser {
+    fn new(s: &str) -> Result<Self, TypeError> {
+        Ok(Self {
+            tokens: tokenize(s)?,
+            pos: 0,
+            len: s.len(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    /// The span of the next token, or of the end of the input.
+    fn span(&self) -> Range<usize> {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.len..self.len, |(_, span)| span.clone())
+    }
+
+    /// The end of the last consumed token.
+    fn end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .map_or(0, |pos| self.tokens[pos].1.end)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matches = self.peek() == Some(token);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), TypeError> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&token.to_string()))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> TypeError {
+        let message = match self.peek() {
+            Some(token) => format!("expected {expected}, found {token}"),
+            None => format!("expected {expected}, found the end of the type"),
+        };
+        TypeError::new(message, self.span())
+    }
+
+    fn finish(&self) -> Result<(), TypeError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(TypeError::new(format!("unexpected {token}"), self.span())),
+        }
+    }
+
+    fn type_expr(&mut self) -> Result<TypeExpr, TypeError> {
+        match self.peek() {
+            Some(Token::Upper(_)) => {
+                let (name, start) = self.name()?;
+                let args = self.atoms()?;
+                let span = start..self.end();
+                Ok(TypeExpr::Apply { name, args, span })
+            }
+            _ => self.atom(),
+        }
+    }
+
+    /// Parses the arguments of a type application or the payload of a tag.
+    fn atoms(&mut self) -> Result<Vec<TypeExpr>, TypeError> {
+        let mut atoms = Vec::new();
+        while let Some(Token::Upper(_) | Token::Lower(_) | Token::Open(_)) = self.peek() {
+            atoms.push(self.atom()?);
+        }
+        Ok(atoms)
+    }
+
+    fn name(&mut self) -> Result<(String, usize), TypeError> {
+        let start = self.span().start;
+        match self.peek().cloned() {
+            Some(Token::Upper(name) | Token::Lower(name)) => {
+                self.pos += 1;
+                Ok((name, start))
+            }
+            _ => Err(self.unexpected("a type")),
+        }
+    }
+
+    fn atom(&mut self) -> Result<TypeExpr, TypeError> {
+        let start = self.span().start;
+        match self.peek() {
+            Some(Token::Upper(_) | Token::Lower(_)) => {
+                let (name, start) = self.name()?;
+                let span = start..self.end();
+                Ok(TypeExpr::Apply {
+                    name,
+                    args: Vec::new(),
+                    span,
+                })
+            }
+            Some(Token::Open('(')) => {
+                self.pos += 1;
+                let mut elems = vec![self.type_expr()?];
+                while self.eat(&Token::Comma) {
+                    elems.push(self.type_expr()?);
+                }
+                self.expect(Token::Close(')'))?;
+                if elems.len() == 1 {
+                    return Ok(elems.pop().expect("there is one element"));
+                }
+                let span = start..self.end();
+                Ok(TypeExpr::Tuple { elems, span })
+            }
+            Some(Token::Open('{')) => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                while !self.eat(&Token::Close('}')) {
+                    if !fields.is_empty() {
+                        self.expect(Token::Comma)?;
+                        // Allow a trailing comma.
+                        if self.eat(&Token::Close('}')) {
+                            break;
+                        }
+                    }
+                    let field_span = self.span();
+                    let name = match self.peek().cloned() {
+                        Some(Token::Lower(name)) if !name.contains('.') => name,
+                        _ => return Err(self.unexpected("a record field name")),
+                    };
+                    self.pos += 1;
+                    self.expect(Token::Colon)?;
+                    fields.push((name, field_span, self.type_expr()?));
+                }
+                let span = start..self.end();
+                Ok(TypeExpr::Record { fields, span })
+            }
+            Some(Token::Open('[')) => {
+                self.pos += 1;
+                let mut tags = Vec::new();
+                while !self.eat(&Token::Close(']')) {
+                    if !tags.is_empty() {
+                        self.expect(Token::Comma)?;
+                        if self.eat(&Token::Close(']')) {
+                            break;
+                        }
+                    }
+                    let tag = match self.peek().cloned() {
+                        Some(Token::Upper(tag)) => tag,
+                        _ => return Err(self.unexpected("a tag")),
+                    };
+                    self.pos += 1;
+                    tags.push((tag, self.atoms()?));
+                }
+                let span = start..self.end();
+                if tags.is_empty() {
+                    return Err(TypeError::new("empty tag unions are not supported", span));
+                }
+                Ok(TypeExpr::Tags { tags, span })
+            }
+            _ => Err(self.unexpected("a type")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dtype(s: &str) -> DType {
+        TypeExpr::parse(s).and_then(|expr| expr.to_dtype()).unwrap()
+    }
+
+    /// Returns the message and span of the error `s` fails to parse or resolve with.
+    fn error(s: &str) -> (String, Range<usize>) {
+        let error = TypeExpr::parse(s)
+            .and_then(|expr| expr.to_dtype())
+            .unwrap_err();
+        (error.message, error.span)
+    }
+
+    fn boxed(dtype: DType) -> Box<DType> {
+        Box::new(dtype)
+    }
+
+    #[test]
+    fn nested_types() {
+        assert_eq!(dtype("List Str"), DType::List(boxed(DType::Str)));
+        assert_eq!(dtype("List U8"), DType::Bytes);
+        assert_eq!(
+            dtype("Result (List I64) Str"),
+            DType::Result(boxed(DType::List(boxed(DType::I64))), boxed(DType::Str))
+        );
+        assert_eq!(
+            dtype("Task {} Str"),
+            DType::Task(boxed(DType::Unit), boxed(DType::Str))
+        );
+        assert_eq!(
+            dtype("{ name : Str, scores : List U64, meta : { age : U8 }, }"),
+            DType::Record(vec![
+                ("name".into(), DType::Str),
+                ("scores".into(), DType::List(boxed(DType::U64))),
+                (
+                    "meta".into(),
+                    DType::Record(vec![("age".into(), DType::U8)])
+                ),
+            ])
+        );
+        assert_eq!(
+            dtype("(Str, { x : F64 }, (Bool, Dec))"),
+            DType::Tuple(vec![
+                DType::Str,
+                DType::Record(vec![("x".into(), DType::F64)]),
+                DType::Tuple(vec![DType::Bool, DType::Dec]),
+            ])
+        );
+        assert_eq!(
+            dtype("[Some (List Str), None]"),
+            DType::Option(boxed(DType::List(boxed(DType::Str))))
+        );
+        assert_eq!(dtype("((Str))"), DType::Str);
+        assert_eq!(
+            dtype("Dict Str Str"),
+            DType::Dict(boxed(DType::Str), boxed(DType::Str))
+        );
+    }
+
+    #[test]
+    fn signatures() {
+        let signature = Signature::parse("Str, List U64 -> { ok : Bool }").unwrap();
+        assert_eq!(signature.args.len(), 2);
+        assert_eq!(
+            signature.args[1].to_dtype(),
+            Ok(DType::List(boxed(DType::U64)))
+        );
+        assert_eq!(
+            signature.ret.to_dtype(),
+            Ok(DType::Record(vec![("ok".into(), DType::Bool)]))
+        );
+
+        let signature = Signature::parse("Str").unwrap();
+        assert!(signature.args.is_empty());
+        assert_eq!(signature.ret.to_dtype(), Ok(DType::Str));
+
+        let error = Signature::parse("Str, U64").unwrap_err();
+        assert_eq!(error.message, "expected `->`, found the end of the type");
+        assert_eq!(error.span, 8..8);
+    }
+
+    #[test]
+    fn unknown_types() {
+        assert_eq!(error("Strng"), ("unknown type `Strng`".into(), 0..5));
+        assert_eq!(error("List Strng"), ("unknown type `Strng`".into(), 5..10));
+        assert_eq!(
+            error("{ a : Str, b : Strng }"),
+            ("unknown type `Strng`".into(), 15..20)
+        );
+        assert_eq!(
+            error("a"),
+            ("type variables like `a` are not supported".into(), 0..1)
+        );
+        assert_eq!(error("Option Str").1, 0..10);
+        assert_eq!(error("Bytes").1, 0..5);
+    }
+
+    #[test]
+    fn wrong_arity() {
+        assert_eq!(
+            error("Result Str"),
+            ("`Result` takes 2 type arguments, found 1".into(), 0..10)
+        );
+        assert_eq!(
+            error("List"),
+            ("`List` takes 1 type argument, found 0".into(), 0..4)
+        );
+        assert_eq!(
+            error("Str U64"),
+            ("`Str` takes no type arguments".into(), 0..7)
+        );
+        assert_eq!(
+            error("List (Result Str)").1,
+            6..16,
+            "errors of nested types point at them"
+        );
+    }
+
+    #[test]
+    fn unsupported_types() {
+        assert_eq!(
+            error("List (List Str)"),
+            ("unsupported list element type `List Str`".into(), 6..14)
+        );
+        assert_eq!(error("Dict Str U64").1, 0..12);
+        assert_eq!(
+            error("[]"),
+            ("empty tag unions are not supported".into(), 0..2)
+        );
+        assert_eq!(
+            error("[A, B]"),
+            (
+                "unsupported tag union, only `[None, Some a]` is supported".into(),
+                0..6
+            )
+        );
+        assert_eq!(
+            error("[None, Some Str U64]"),
+            ("`Some` takes exactly one payload".into(), 0..20)
+        );
+        assert_eq!(
+            error("{ a : Str, a : U64 }"),
+            ("duplicate record field `a`".into(), 11..12)
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            error("{ a Str }"),
+            ("expected `:`, found `Str`".into(), 4..7)
+        );
+        assert_eq!(
+            error("(Str, U64"),
+            ("expected `)`, found the end of the type".into(), 9..9)
+        );
+        assert_eq!(error("Str ->"), ("unexpected `->`".into(), 4..6));
+        assert_eq!(error("Str $"), ("unexpected `$`".into(), 4..5));
+        assert_eq!(
+            error("{ A : Str }"),
+            ("expected a record field name, found `A`".into(), 2..3)
+        );
+        assert_eq!(error("()"), ("expected a type, found `)`".into(), 1..2));
+        assert_eq!(
+            error(""),
+            ("expected a type, found the end of the type".into(), 0..0)
+        );
+    }
+
+    #[test]
+    fn renders_errors_with_their_span() {
+        let error = TypeExpr::parse("Strng").unwrap().to_dtype().unwrap_err();
+        assert_eq!(
+            error.offset(4).render("f : Strng"),
+            "unknown type `Strng`\n    f : Strng\n        ^^^^^"
+        );
+        let error = Signature::parse("Str,").unwrap_err();
+        assert_eq!(
+            error.render("Str,"),
+            "expected a type, found the end of the type\n    Str,\n        ^"
+        );
+    }
+}
@@ -0,0 +1,959 @@
+use std::any::Any;
+use std::ffi::c_void;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::ptr;
+use std::str::FromStr;
+
+use libffi::middle::{Arg, Type};
+use roc_std::{RocList, RocStr};
+
+use crate::alloc;
+use crate::bytes::Bytes;
+use crate::dec::Dec;
+use crate::error::PluginError;
+use crate::type_expr::TypeExpr;
+
+/// The type of a value passed to or returned from a plugin function.
+#[derive(Clone, Debug, Eq)]
+pub enum DType {
+    /// The empty record `{}`, returned by plugins that are only called for their effects.
+    Unit,
+    Bool,
+    Str,
+    U8,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Dec,
+    /// A `List U8`, which is passed as [`Bytes`] rather than as a list of [`Value::U8`]s.
+    Bytes,
+    List(Box<DType>),
+    Record(Vec<(String, DType)>),
+    Tuple(Vec<DType>),
+    Result(Box<DType>, Box<DType>),
+    Dict(Box<DType>, Box<DType>),
+    /// An optional value, passed to Roc as a `[None, Some a]` tag union.
+    Option(Box<DType>),
+    /// An effectful computation that the host runs, resulting in `ok` or `err`. Like `Result`,
+    /// this is only supported as the return type.
+    Task(Box<DType>, Box<DType>),
+}
+
+impl PartialEq for DType {
+    /// Compares two types, ignoring the order of record fields like Roc does.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::List(a), Self::List(b)) | (Self::Option(a), Self::Option(b)) => a == b,
+            (Self::Bytes, Self::List(elem)) | (Self::List(elem), Self::Bytes) => **elem == Self::U8,
+            (Self::Record(a), Self::Record(b)) => {
+                a.len() == b.len() && a.iter().all(|f| b.contains(f))
+            }
+            (Self::Tuple(a), Self::Tuple(b)) => a == b,
+            (Self::Result(a, b), Self::Result(c, d))
+            | (Self::Task(a, b), Self::Task(c, d))
+            | (Self::Dict(a, b), Self::Dict(c, d)) => a == c && b == d,
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl DType {
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            Self::Unit => 0,
+            Self::Bool | Self::U8 | Self::I8 => 1,
+            Self::I16 => 2,
+            Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 => 8,
+            Self::Dec => 16,
+            Self::Str => mem::size_of::<RocStr>(),
+            // Dicts cross the FFI boundary as lists of key-value tuples.
+            Self::Bytes | Self::List(_) | Self::Dict(..) => mem::size_of::<RocList<u8>>(),
+            Self::Record(fields) => {
+                let end = record_offsets(fields)
+                    .last()
+                    .map_or(0, |((_, t), offset)| offset + t.size());
+                end.next_multiple_of(self.align())
+            }
+            Self::Tuple(elems) => {
+                let end = tuple_offsets(elems)
+                    .last()
+                    .map_or(0, |&(i, offset)| offset + elems[i].size());
+                end.next_multiple_of(self.align())
+            }
+            // Tasks are read in the form of the result they produce.
+            Self::Result(ok, err) | Self::Task(ok, err) => {
+                (result_tag_offset(ok, err) + 1).next_multiple_of(self.align())
+            }
+            Self::Option(inner) => (inner.size() + 1).next_multiple_of(self.align()),
+        }
+    }
+
+    pub(crate) fn align(&self) -> usize {
+        match self {
+            Self::Str => mem::align_of::<RocStr>(),
+            Self::Bytes | Self::List(_) | Self::Dict(..) => mem::align_of::<RocList<u8>>(),
+            Self::Record(fields) => fields.iter().map(|(_, t)| t.align()).max().unwrap_or(1),
+            Self::Tuple(elems) => elems.iter().map(DType::align).max().unwrap_or(1),
+            Self::Result(ok, err) | Self::Task(ok, err) => ok.align().max(err.align()),
+            Self::Option(inner) => inner.align(),
+            Self::Unit => 1,
+            scalar => scalar.size(),
+        }
+    }
+
+    /// Returns whether the type or any type nested in it satisfies `pred`.
+    pub(crate) fn contains(&self, pred: fn(&DType) -> bool) -> bool {
+        pred(self)
+            || match self {
+                Self::List(elem) | Self::Option(elem) => elem.contains(pred),
+                Self::Record(fields) => fields.iter().any(|(_, t)| t.contains(pred)),
+                Self::Tuple(elems) => elems.iter().any(|t| t.contains(pred)),
+                Self::Result(a, b) | Self::Task(a, b) | Self::Dict(a, b) => {
+                    a.contains(pred) || b.contains(pred)
+                }
+                _ => false,
+            }
+    }
+}
+
+/// Returns the fields of a record in memory order, together with their offsets.
+///
+/// Roc orders record fields by decreasing alignment, then by name.
+fn record_offsets(fields: &[(String, DType)]) -> Vec<(&(String, DType), usize)> {
+    let mut sorted: Vec<_> = fields.iter().collect();
+    sorted.sort_by(|(n1, t1), (n2, t2)| t2.align().cmp(&t1.align()).then(n1.cmp(n2)));
+
+    let offsets = layout(sorted.iter().map(|(_, t)| t));
+    sorted.into_iter().zip(offsets).collect()
+}
+
+/// Returns the indices of the elements of a tuple in memory order, together with their offsets.
+///
+/// Roc orders tuple elements by decreasing alignment, then by position.
+fn tuple_offsets(elems: &[DType]) -> Vec<(usize, usize)> {
+    let mut sorted: Vec<_> = (0..elems.len()).collect();
+    sorted.sort_by(|&i, &j| elems[j].align().cmp(&elems[i].align()).then(i.cmp(&j)));
+
+    let offsets = layout(sorted.iter().map(|&i| &elems[i]));
+    sorted.into_iter().zip(offsets).collect()
+}
+
+/// Returns the offsets of consecutive, suitably aligned values of the given types.
+fn layout<'a>(types: impl Iterator<Item = &'a DType>) -> Vec<usize> {
+    let mut offset = 0_usize;
+    let mut offsets = Vec::new();
+    for dtype in types {
+        offset = offset.next_multiple_of(dtype.align());
+        offsets.push(offset);
+        offset += dtype.size();
+    }
+    offsets
+}
+
+/// Returns the offset of the tag of a `Result ok err`, which follows the payload.
+///
+/// Roc numbers tags alphabetically, so `Err` is 0 and `Ok` is 1. Likewise, the `None` tag of an
+/// optional value is 0 and `Some` is 1, with the tag following the payload of `Some`.
+fn result_tag_offset(ok: &DType, err: &DType) -> usize {
+    let align = ok.align().max(err.align());
+    ok.size().max(err.size()).next_multiple_of(align)
+}
+
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unit => f.write_str("{}"),
+            Self::Bool => f.write_str("Bool"),
+            Self::Str => f.write_str("Str"),
+            Self::U8 => f.write_str("U8"),
+            Self::U64 => f.write_str("U64"),
+            Self::I8 => f.write_str("I8"),
+            Self::I16 => f.write_str("I16"),
+            Self::I32 => f.write_str("I32"),
+            Self::I64 => f.write_str("I64"),
+            Self::F32 => f.write_str("F32"),
+            Self::F64 => f.write_str("F64"),
+            Self::Dec => f.write_str("Dec"),
+            Self::Bytes => f.write_str("List U8"),
+            Self::List(elem) => write!(f, "List {elem}"),
+            Self::Record(fields) => {
+                f.write_str("{ ")?;
+                for (i, (name, dtype)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{name} : {dtype}")?;
+                }
+                f.write_str(" }")
+            }
+            Self::Tuple(elems) => {
+                let elems: Vec<_> = elems.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Self::Result(ok, err) => {
+                f.write_str("Result ")?;
+                fmt_type_arg(ok, f)?;
+                f.write_str(" ")?;
+                fmt_type_arg(err, f)
+            }
+            Self::Task(ok, err) => {
+                f.write_str("Task ")?;
+                fmt_type_arg(ok, f)?;
+                f.write_str(" ")?;
+                fmt_type_arg(err, f)
+            }
+            Self::Dict(key, value) => {
+                f.write_str("Dict ")?;
+                fmt_type_arg(key, f)?;
+                f.write_str(" ")?;
+                fmt_type_arg(value, f)
+            }
+            Self::Option(inner) => {
+                f.write_str("[None, Some ")?;
+                fmt_type_arg(inner, f)?;
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+/// Formats the argument of a type constructor, parenthesizing it if necessary.
+fn fmt_type_arg(dtype: &DType, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match dtype {
+        DType::Bytes | DType::List(_) | DType::Result(..) | DType::Task(..) | DType::Dict(..) => {
+            write!(f, "({dtype})")
+        }
+        dtype => write!(f, "{dtype}"),
+    }
+}
+
+impl FromStr for DType {
+    type Err = String;
+
+    /// Parses a Roc type annotation like `List Str` or `{ name : Str, age : U64 }`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TypeExpr::parse(s)
+            .and_then(|expr| expr.to_dtype())
+            .map_err(|error| error.render(s))
+    }
+}
+
+/// A value passed to or returned from a plugin function.
+///
+/// Values can be compared and hashed, where floats are compared by their bits, so that `NaN`
+/// equals itself and `0.0` differs from `-0.0`. Records and dicts are compared in the order of
+/// their fields and entries.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Str(String),
+    U8(u8),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Dec(Dec),
+    Bytes(Bytes),
+    List(Vec<Value>),
+    Record(Vec<(String, Value)>),
+    Tuple(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+    Option(Option<Box<Value>>),
+}
+
+impl From<Option<Value>> for Value {
+    fn from(value: Option<Value>) -> Self {
+        Value::Option(value.map(Box::new))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::U8(a), Value::U8(b)) => a == b,
+            (Value::U64(a), Value::U64(b)) => a == b,
+            (Value::I8(a), Value::I8(b)) => a == b,
+            (Value::I16(a), Value::I16(b)) => a == b,
+            (Value::I32(a), Value::I32(b)) => a == b,
+            (Value::I64(a), Value::I64(b)) => a == b,
+            (Value::F32(a), Value::F32(b)) => a.to_bits() == b.to_bits(),
+            (Value::F64(a), Value::F64(b)) => a.to_bits() == b.to_bits(),
+            (Value::Dec(a), Value::Dec(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Record(a), Value::Record(b)) => a == b,
+            (Value::Dict(a), Value::Dict(b)) => a == b,
+            (Value::Option(a), Value::Option(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Value::Unit => {}
+            Value::Bool(b) => b.hash(state),
+            Value::Str(s) => s.hash(state),
+            Value::U8(n) => n.hash(state),
+            Value::U64(n) => n.hash(state),
+            Value::I8(n) => n.hash(state),
+            Value::I16(n) => n.hash(state),
+            Value::I32(n) => n.hash(state),
+            Value::I64(n) => n.hash(state),
+            Value::F32(x) => x.to_bits().hash(state),
+            Value::F64(x) => x.to_bits().hash(state),
+            Value::Dec(d) => d.hash(state),
+            Value::Bytes(bytes) => bytes.as_slice().hash(state),
+            Value::List(items) | Value::Tuple(items) => items.hash(state),
+            Value::Record(fields) => fields.hash(state),
+            Value::Dict(entries) => entries.hash(state),
+            Value::Option(value) => value.hash(state),
+        }
+    }
+}
+
+/// A Rust type that can be stored in a `RocList`.
+pub(crate) trait RocElem: Clone + 'static {
+    const DTYPE: DType;
+
+    fn from_value(value: &Value) -> Option<Self>;
+    fn to_value(&self) -> Value;
+}
+
+macro_rules! roc_elem {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl RocElem for $ty {
+                const DTYPE: DType = DType::$variant;
+
+                fn from_value(value: &Value) -> Option<Self> {
+                    match value {
+                        Value::$variant(x) => Some(x.clone()),
+                        _ => None,
+                    }
+                }
+
+                fn to_value(&self) -> Value {
+                    Value::$variant(self.clone())
+                }
+            }
+        )*
+    };
+}
+
+roc_elem! {
+    bool => Bool,
+    u8 => U8,
+    u64 => U64,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+    Dec => Dec,
+}
+
+impl RocElem for RocStr {
+    const DTYPE: DType = DType::Str;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Str(s) => Some(s.as_str().into()),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Str(self.as_str().into())
+    }
+}
+
+/// Invokes a generic function with the `RocElem` type corresponding to a list element `DType`.
+macro_rules! dispatch_elem {
+    ($elem:expr, $func:ident($($arg:expr),*)) => {
+        match $elem {
+            DType::Bool => $func::<bool>($($arg),*),
+            DType::Str => $func::<RocStr>($($arg),*),
+            DType::U8 => $func::<u8>($($arg),*),
+            DType::U64 => $func::<u64>($($arg),*),
+            DType::I8 => $func::<i8>($($arg),*),
+            DType::I16 => $func::<i16>($($arg),*),
+            DType::I32 => $func::<i32>($($arg),*),
+            DType::I64 => $func::<i64>($($arg),*),
+            DType::F32 => $func::<f32>($($arg),*),
+            DType::F64 => $func::<f64>($($arg),*),
+            DType::Dec => $func::<Dec>($($arg),*),
+            DType::Unit
+            | DType::Bytes
+            | DType::List(_)
+            | DType::Record(_)
+            | DType::Tuple(_)
+            | DType::Result(..)
+            | DType::Task(..)
+            | DType::Dict(..)
+            | DType::Option(_) => {
+                unreachable!("unsupported list element types are rejected by the header parser")
+            }
+        }
+    };
+}
+
+impl Value {
+    /// Returns a copy of the value that shares no Roc-managed memory with it.
+    ///
+    /// Roc reference counts aren't atomic, so values moved to another thread must not share
+    /// buffers with values that stay behind.
+    pub(crate) fn detach(&self) -> Value {
+        match self {
+            Value::Bytes(bytes) => Value::Bytes(Bytes::from(bytes.as_slice())),
+            Value::List(items) => Value::List(items.iter().map(Value::detach).collect()),
+            Value::Record(fields) => Value::Record(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.detach()))
+                    .collect(),
+            ),
+            Value::Tuple(items) => Value::Tuple(items.iter().map(Value::detach).collect()),
+            Value::Dict(entries) => Value::Dict(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.detach(), value.detach()))
+                    .collect(),
+            ),
+            Value::Option(value) => Value::Option(value.as_ref().map(|v| Box::new(v.detach()))),
+            value => value.clone(),
+        }
+    }
+
+    /// Converts the value into the representation it is passed across the FFI boundary.
+    ///
+    /// Arguments that are passed by reference may need temporary Roc values; these are pushed
+    /// into `temps` and must be kept alive until the call returns.
+    ///
+    /// Plugins own their arguments and release them when they are done, so the reference
+    /// counted values among them are passed with a reference of their own, see [`pass_owned`].
+    pub(crate) fn to_ffi(
+        &self,
+        dtype: &DType,
+        temps: &mut Vec<Box<dyn Any>>,
+    ) -> Result<FfiValue, PluginError> {
+        let ffi = match (dtype, self) {
+            (DType::Bool, Value::Bool(b)) => FfiValue::U8(*b as u8),
+            (DType::Str, Value::Str(s)) => pass_owned(RocStr::from(s.as_str()), temps),
+            (DType::U8, Value::U8(n)) => FfiValue::U8(*n),
+            (DType::U64, Value::U64(n)) => FfiValue::U64(*n),
+            (DType::I8, Value::I8(n)) => FfiValue::I8(*n),
+            (DType::I16, Value::I16(n)) => FfiValue::I16(*n),
+            (DType::I32, Value::I32(n)) => FfiValue::I32(*n),
+            (DType::I64, Value::I64(n)) => FfiValue::I64(*n),
+            (DType::F32, Value::F32(x)) => FfiValue::F32(*x),
+            (DType::F64, Value::F64(x)) => FfiValue::F64(*x),
+            (DType::Dec, Value::Dec(d)) => FfiValue::Dec(d.to_raw()),
+            (DType::Bytes, Value::Bytes(bytes)) => bytes_to_ffi(bytes, temps),
+            (DType::List(elem), Value::Bytes(bytes)) if **elem == DType::U8 => {
+                bytes_to_ffi(bytes, temps)
+            }
+            (DType::Bytes, Value::List(items)) => list_to_ffi::<u8>(items, temps)?,
+            (DType::List(elem), Value::List(items)) => {
+                dispatch_elem!(&**elem, list_to_ffi(items, temps))?
+            }
+            (DType::Record(fields), Value::Record(values)) => {
+                let mut buf = RocBuf::new(dtype.size());
+                for ((name, field_type), offset) in record_offsets(fields) {
+                    let value = values
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, v)| v)
+                        .ok_or_else(|| mismatch(dtype, self))?;
+                    let ffi = value.to_ffi(field_type, temps)?;
+                    unsafe { ffi.write_to(buf.as_mut_ptr().add(offset), field_type.size()) };
+                }
+
+                let ptr = buf.as_ptr() as *const c_void;
+                temps.push(Box::new(buf));
+                FfiValue::Ptr(ptr)
+            }
+            (DType::Tuple(elems), Value::Tuple(values)) if elems.len() == values.len() => {
+                let mut buf = RocBuf::new(dtype.size());
+                for (i, offset) in tuple_offsets(elems) {
+                    let ffi = values[i].to_ffi(&elems[i], temps)?;
+                    unsafe { ffi.write_to(buf.as_mut_ptr().add(offset), elems[i].size()) };
+                }
+
+                let ptr = buf.as_ptr() as *const c_void;
+                temps.push(Box::new(buf));
+                FfiValue::Ptr(ptr)
+            }
+            (DType::Dict(..), Value::Dict(entries)) => dict_to_ffi(dtype, self, entries, temps)?,
+            (DType::Option(inner), Value::Option(value)) => {
+                let mut buf = RocBuf::new(dtype.size());
+                if let Some(value) = value {
+                    let ffi = value.to_ffi(inner, temps)?;
+                    unsafe {
+                        ffi.write_to(buf.as_mut_ptr(), inner.size());
+                        buf.as_mut_ptr().add(inner.size()).write(1);
+                    }
+                }
+
+                let ptr = buf.as_ptr() as *const c_void;
+                temps.push(Box::new(buf));
+                FfiValue::Ptr(ptr)
+            }
+            _ => return Err(mismatch(dtype, self)),
+        };
+        Ok(ffi)
+    }
+
+    /// Reads a value of type `dtype` that a plugin wrote to `src`, taking ownership of it.
+    ///
+    /// The Roc values in it are copied into the returned value or shared by it, and the
+    /// references read from `src` are released, so `src` must not be read again.
+    pub(crate) unsafe fn read_from(dtype: &DType, src: *const u8) -> Value {
+        match dtype {
+            DType::Unit => Value::Unit,
+            DType::Bool => Value::Bool(src.read() != 0),
+            DType::Str => Value::Str(src.cast::<RocStr>().read().as_str().into()),
+            DType::U8 => Value::U8(src.read()),
+            DType::U64 => Value::U64(src.cast::<u64>().read()),
+            DType::I8 => Value::I8(src.cast::<i8>().read()),
+            DType::I16 => Value::I16(src.cast::<i16>().read()),
+            DType::I32 => Value::I32(src.cast::<i32>().read()),
+            DType::I64 => Value::I64(src.cast::<i64>().read()),
+            DType::F32 => Value::F32(src.cast::<f32>().read()),
+            DType::F64 => Value::F64(src.cast::<f64>().read()),
+            DType::Dec => Value::Dec(Dec::from_raw(src.cast::<i128>().read())),
+            DType::Bytes => {
+                let list = src.cast::<RocList<u8>>().read();
+                // Arena memory is freed once the invocation returns, so it can't be shared.
+                if alloc::is_arena() {
+                    Value::Bytes(Bytes::from(list.as_slice()))
+                } else {
+                    Value::Bytes(Bytes::from_roc_list(list))
+                }
+            }
+            DType::List(elem) => dispatch_elem!(&**elem, list_from_roc(src)),
+            DType::Record(fields) => Value::Record(
+                record_offsets(fields)
+                    .into_iter()
+                    .map(|((name, t), offset)| (name.clone(), Self::read_from(t, src.add(offset))))
+                    .collect(),
+            ),
+            DType::Tuple(elems) => {
+                let mut values: Vec<_> = tuple_offsets(elems)
+                    .into_iter()
+                    .map(|(i, offset)| (i, Self::read_from(&elems[i], src.add(offset))))
+                    .collect();
+                values.sort_by_key(|&(i, _)| i);
+                Value::Tuple(values.into_iter().map(|(_, v)| v).collect())
+            }
+            DType::Dict(..) => dict_from_roc(src),
+            DType::Option(inner) => {
+                let value = (src.add(inner.size()).read() == 1)
+                    .then(|| Box::new(Self::read_from(inner, src)));
+                Value::Option(value)
+            }
+            DType::Result(..) | DType::Task(..) => {
+                unreachable!("`Result` and `Task` are only supported as the return type")
+            }
+        }
+    }
+
+    /// Reads the return value of a plugin from `src`, turning `Err` results into errors.
+    ///
+    /// For tasks, `src` must hold the result the task produced when it was run.
+    pub(crate) unsafe fn read_return(dtype: &DType, src: *const u8) -> Result<Value, PluginError> {
+        match dtype {
+            DType::Result(ok, err) | DType::Task(ok, err) => {
+                let tag = src.add(result_tag_offset(ok, err)).read();
+                if tag == 1 {
+                    Ok(Self::read_from(ok, src))
+                } else {
+                    let payload = Self::read_from(err, src);
+                    Err(PluginError::PluginFailed(payload.to_string()))
+                }
+            }
+            dtype => Ok(Self::read_from(dtype, src)),
+        }
+    }
+
+    pub(crate) fn type_name(&self) -> String {
+        match self {
+            Value::Unit => "{}".into(),
+            Value::Bool(_) => "Bool".into(),
+            Value::Str(_) => "Str".into(),
+            Value::U8(_) => "U8".into(),
+            Value::U64(_) => "U64".into(),
+            Value::I8(_) => "I8".into(),
+            Value::I16(_) => "I16".into(),
+            Value::I32(_) => "I32".into(),
+            Value::I64(_) => "I64".into(),
+            Value::F32(_) => "F32".into(),
+            Value::F64(_) => "F64".into(),
+            Value::Dec(_) => "Dec".into(),
+            Value::Bytes(_) => "List U8".into(),
+            Value::List(items) => match items.first() {
+                Some(item) => format!("List {}", item.type_name()),
+                None => "List *".into(),
+            },
+            Value::Record(values) => {
+                let fields: Vec<_> = values
+                    .iter()
+                    .map(|(name, v)| format!("{name} : {}", v.type_name()))
+                    .collect();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            Value::Tuple(values) => {
+                let elems: Vec<_> = values.iter().map(Value::type_name).collect();
+                format!("({})", elems.join(", "))
+            }
+            Value::Dict(entries) => match entries.first() {
+                Some((k, v)) => format!("Dict {} {}", k.type_name(), v.type_name()),
+                None => "Dict * *".into(),
+            },
+            Value::Option(value) => match value {
+                Some(value) => format!("[None, Some {}]", value.type_name()),
+                None => "[None, Some *]".into(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => f.write_str("{}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Str(s) => write!(f, "{s}"),
+            Value::U8(n) => write!(f, "{n}"),
+            Value::U64(n) => write!(f, "{n}"),
+            Value::I8(n) => write!(f, "{n}"),
+            Value::I16(n) => write!(f, "{n}"),
+            Value::I32(n) => write!(f, "{n}"),
+            Value::I64(n) => write!(f, "{n}"),
+            Value::F32(x) => write!(f, "{x}"),
+            Value::F64(x) => write!(f, "{x}"),
+            Value::Dec(d) => write!(f, "{d}"),
+            Value::Bytes(bytes) => write!(f, "{:?}", bytes.as_slice()),
+            Value::List(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    fmt_nested(item, f)?;
+                }
+                f.write_str("]")
+            }
+            Value::Record(values) => {
+                f.write_str("{ ")?;
+                for (i, (name, value)) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{name}: ")?;
+                    fmt_nested(value, f)?;
+                }
+                f.write_str(" }")
+            }
+            Value::Tuple(values) => {
+                f.write_str("(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    fmt_nested(value, f)?;
+                }
+                f.write_str(")")
+            }
+            Value::Dict(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    fmt_nested(key, f)?;
+                    f.write_str(": ")?;
+                    fmt_nested(value, f)?;
+                }
+                f.write_str("}")
+            }
+            Value::Option(Some(value)) => {
+                f.write_str("Some ")?;
+                fmt_nested(value, f)
+            }
+            Value::Option(None) => f.write_str("None"),
+        }
+    }
+}
+
+/// Formats a value nested in a collection, quoting strings.
+fn fmt_nested(value: &Value, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match value {
+        Value::Str(s) => write!(f, "{s:?}"),
+        value => write!(f, "{value}"),
+    }
+}
+
+fn mismatch(expected: &DType, found: &Value) -> PluginError {
+    PluginError::TypeMismatch {
+        expected: expected.to_string(),
+        found: found.type_name(),
+    }
+}
+
+fn list_to_ffi<T: RocElem>(
+    items: &[Value],
+    temps: &mut Vec<Box<dyn Any>>,
+) -> Result<FfiValue, PluginError> {
+    let elems = items
+        .iter()
+        .map(|item| T::from_value(item).ok_or_else(|| mismatch(&T::DTYPE, item)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(pass_owned(RocList::from_slice(&elems), temps))
+}
+
+/// Passes a byte buffer to a plugin by sharing it rather than copying it.
+fn bytes_to_ffi(bytes: &Bytes, temps: &mut Vec<Box<dyn Any>>) -> FfiValue {
+    pass_owned(bytes.as_roc_list().clone(), temps)
+}
+
+/// Passes a reference counted Roc value to a plugin, keeping it in `temps` until the call
+/// returns.
+///
+/// The plugin takes ownership of its arguments and decrements their reference counts when it
+/// is done with them, so the value's count is incremented for it first, by leaking a clone.
+/// Otherwise, the plugin would free memory that `temps` or a shared [`Bytes`] still refers
+/// to, and then `temps` would free it again. The extra reference also keeps the plugin from
+/// mutating the value in place, which would be visible to the host.
+fn pass_owned<T: Clone + 'static>(value: T, temps: &mut Vec<Box<dyn Any>>) -> FfiValue {
+    let value = Box::new(value);
+    mem::forget(T::clone(&value));
+    let ptr = &*value as *const T as *const c_void;
+    temps.push(value);
+    FfiValue::Ptr(ptr)
+}
+
+/// Converts a list returned by a plugin into a `Value`, releasing the plugin's reference.
+unsafe fn list_from_roc<T: RocElem>(src: *const u8) -> Value {
+    let list = src.cast::<RocList<T>>().read();
+    Value::List(list.iter().map(T::to_value).collect())
+}
+
+/// An element of the `List (Str, Str)` that a `Dict Str Str` is passed as.
+#[derive(Clone)]
+#[repr(C)]
+struct StrPair(RocStr, RocStr);
+
+fn dict_to_ffi(
+    dtype: &DType,
+    value: &Value,
+    entries: &[(Value, Value)],
+    temps: &mut Vec<Box<dyn Any>>,
+) -> Result<FfiValue, PluginError> {
+    let pairs = entries
+        .iter()
+        .map(|entry| match entry {
+            (Value::Str(k), Value::Str(v)) => Ok(StrPair(k.as_str().into(), v.as_str().into())),
+            _ => Err(mismatch(dtype, value)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(pass_owned(RocList::from_slice(&pairs), temps))
+}
+
+/// Converts a dict returned by a plugin into a `Value`, releasing the plugin's reference.
+unsafe fn dict_from_roc(src: *const u8) -> Value {
+    let list = src.cast::<RocList<StrPair>>().read();
+    let entries = list
+        .iter()
+        .map(|StrPair(k, v)| (Value::Str(k.as_str().into()), Value::Str(v.as_str().into())))
+        .collect();
+    Value::Dict(entries)
+}
+
+/// Zero-initialized scratch memory, aligned for any Roc value.
+pub(crate) struct RocBuf(Vec<u128>);
+
+impl RocBuf {
+    /// The largest alignment of any Roc value, which that of the buffers is at least.
+    pub(crate) const ALIGN: usize = 16;
+
+    pub(crate) fn new(size: usize) -> Self {
+        Self(vec![0; size.div_ceil(mem::size_of::<u128>())])
+    }
+
+    /// Returns a buffer for a value of `size` bytes, aligned to `align` bytes.
+    pub(crate) fn with_layout(size: usize, align: usize) -> Self {
+        assert!(
+            align <= Self::ALIGN,
+            "Roc values are at most 16-byte aligned"
+        );
+        Self::new(size)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.0.as_ptr().cast()
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.0.as_mut_ptr().cast()
+    }
+}
+
+/// A value in the representation it is passed across the FFI boundary.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum FfiValue {
+    Ptr(*const c_void),
+    U8(u8),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Dec(i128),
+}
+
+impl FfiValue {
+    pub(crate) fn ffi_type(&self) -> Type {
+        match self {
+            Self::Ptr(_) => Type::pointer(),
+            Self::U8(_) => Type::u8(),
+            Self::U64(_) => Type::u64(),
+            Self::I8(_) => Type::i8(),
+            Self::I16(_) => Type::i16(),
+            Self::I32(_) => Type::i32(),
+            Self::I64(_) => Type::i64(),
+            Self::F32(_) => Type::f32(),
+            Self::F64(_) => Type::f64(),
+            Self::Dec(_) => dec_ffi_type(),
+        }
+    }
+
+    /// Writes the value to `dst`, where it occupies `size` bytes.
+    ///
+    /// Values passed by reference are copied bitwise, so `dst` borrows them rather than taking
+    /// ownership.
+    unsafe fn write_to(self, dst: *mut u8, size: usize) {
+        match self {
+            Self::Ptr(p) => ptr::copy_nonoverlapping(p.cast::<u8>(), dst, size),
+            Self::U8(n) => dst.write(n),
+            Self::U64(n) => dst.cast::<u64>().write(n),
+            Self::I8(n) => dst.cast::<i8>().write(n),
+            Self::I16(n) => dst.cast::<i16>().write(n),
+            Self::I32(n) => dst.cast::<i32>().write(n),
+            Self::I64(n) => dst.cast::<i64>().write(n),
+            Self::F32(x) => dst.cast::<f32>().write(x),
+            Self::F64(x) => dst.cast::<f64>().write(x),
+            Self::Dec(n) => dst.cast::<i128>().write(n),
+        }
+    }
+
+    pub(crate) fn as_arg(&self) -> Arg {
+        match self {
+            Self::Ptr(p) => Arg::new(p),
+            Self::U8(n) => Arg::new(n),
+            Self::U64(n) => Arg::new(n),
+            Self::I8(n) => Arg::new(n),
+            Self::I16(n) => Arg::new(n),
+            Self::I32(n) => Arg::new(n),
+            Self::I64(n) => Arg::new(n),
+            Self::F32(x) => Arg::new(x),
+            Self::F64(x) => Arg::new(x),
+            Self::Dec(n) => Arg::new(n),
+        }
+    }
+}
+
+/// The FFI type of a Roc `Dec`.
+///
+/// libffi has no 128-bit integer type, so a `Dec` is passed as a pair of 64-bit integers. This
+/// is only where the entrypoints expect it on x86-64, as long as both fit into registers, since
+/// a `Dec` is aligned to 16 bytes and the pair to 8, see [`check_dec_args`].
+pub(crate) fn dec_ffi_type() -> Type {
+    Type::structure([Type::u64(), Type::u64()])
+}
+
+/// Checks that the `Dec`s among arguments of types `args` can be passed, see [`dec_ffi_type`].
+///
+/// On x86-64, the pair and the `Dec` are passed in the same two integer registers, but
+/// differently on the stack. Elsewhere, they are passed in different registers, like on
+/// aarch64, which passes a `Dec` in an even and odd register.
+pub(crate) fn check_dec_args(args: &[DType]) -> Result<(), String> {
+    if !args.contains(&DType::Dec) {
+        return Ok(());
+    }
+    if !cfg!(target_arch = "x86_64") {
+        return Err("`Dec` arguments are only supported on x86-64".into());
+    }
+
+    // Generic entrypoints take their out pointer in the first of the six integer registers.
+    let mut registers = 1;
+    for dtype in args {
+        match dtype {
+            DType::F32 | DType::F64 => {}
+            DType::Dec => {
+                registers += 2;
+                if registers > 6 {
+                    return Err(
+                        "`Dec` arguments must be passed in registers, which hold at \
+                         most five arguments that aren't floats up to the last `Dec`, \
+                         with each `Dec` counting as two"
+                            .into(),
+                    );
+                }
+            }
+            _ => registers += 1,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn dec_args_in_registers() {
+        assert!(check_dec_args(&[DType::Dec, DType::Dec]).is_ok());
+        assert!(check_dec_args(&[DType::Str, DType::U64, DType::Dec]).is_ok());
+        let mut floats = vec![DType::F64; 10];
+        floats.push(DType::Dec);
+        assert!(check_dec_args(&floats).is_ok());
+        assert!(check_dec_args(&[DType::Dec, DType::Dec, DType::Dec]).is_err());
+        assert!(check_dec_args(&[DType::U64, DType::U64, DType::U64, DType::Dec]).is_ok());
+        let args = [DType::U64, DType::U64, DType::U64, DType::U64, DType::Dec];
+        assert!(check_dec_args(&args).is_err());
+        // Arguments after the last `Dec` may be passed on the stack.
+        assert!(check_dec_args(&[DType::Dec, DType::Dec, DType::U64, DType::U64]).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "x86_64"))]
+    fn dec_args_unsupported() {
+        assert!(check_dec_args(&[DType::U64]).is_ok());
+        assert!(check_dec_args(&[DType::Dec]).is_err());
+    }
+}
@@ -0,0 +1,610 @@
+use std::fmt;
+use std::path::Path;
+
+use wasmtime::{
+    AsContext, AsContextMut, Caller, Config, Engine, InstancePre, Linker, Memory, Store,
+    StoreLimits, StoreLimitsBuilder, UpdateDeadline, Val,
+};
+
+use crate::bytes::Bytes;
+use crate::cancel::CancellationToken;
+use crate::error::{PanicKind, PluginError};
+use crate::plugin::Meta;
+use crate::roc_host::{self, Dbg};
+use crate::value::{DType, Value};
+
+const PAGE_SIZE: u64 = 64 * 1024;
+
+/// Resource limits for plugins run by the wasm backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WasmLimits {
+    /// The maximum size of a plugin's linear memory, in bytes.
+    pub memory: usize,
+    /// How much fuel a single invocation may consume, or `None` to not meter invocations.
+    pub fuel: Option<u64>,
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        Self {
+            memory: 64 << 20,
+            fuel: None,
+        }
+    }
+}
+
+/// A plugin compiled to wasm32.
+///
+/// Every invocation runs in a fresh instance, so plugins can't keep state between invocations,
+/// and memory allocated by one invocation is released when it returns.
+pub(crate) struct Module {
+    instance: InstancePre<HostState>,
+    limits: WasmLimits,
+    exports: Vec<String>,
+}
+
+struct HostState {
+    /// The name of the plugin, for `dbg` output.
+    plugin: String,
+    limits: StoreLimits,
+    /// The end of the allocated part of the instance's memory.
+    heap: u32,
+}
+
+/// A panic raised by a plugin through `roc_panic`.
+#[derive(Debug)]
+struct RocPanic {
+    message: String,
+    kind: PanicKind,
+}
+
+impl fmt::Display for RocPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RocPanic {}
+
+/// The trap raised when an invocation is cancelled.
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the invocation was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl Module {
+    pub(crate) fn load(path: &Path, limits: WasmLimits) -> Result<Self, PluginError> {
+        let mut config = Config::new();
+        config.consume_fuel(limits.fuel.is_some());
+        // Cancelled invocations are interrupted by bumping the epoch, see `Module::invoke`.
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).map_err(PluginError::Wasm)?;
+        let module = wasmtime::Module::from_file(&engine, path).map_err(PluginError::Wasm)?;
+
+        let mut linker = Linker::new(&engine);
+        define_host_functions(&mut linker).map_err(PluginError::Wasm)?;
+        linker
+            .define_unknown_imports_as_traps(&module)
+            .map_err(PluginError::Wasm)?;
+
+        let exports = module
+            .exports()
+            .map(|export| export.name().to_owned())
+            .filter(|name| name.starts_with("roc__"))
+            .collect();
+        let instance = linker.instantiate_pre(&module).map_err(PluginError::Wasm)?;
+        Ok(Self {
+            instance,
+            limits,
+            exports,
+        })
+    }
+
+    /// Returns the names of all `roc__*` functions exported by the module.
+    pub(crate) fn exports(&self) -> &[String] {
+        &self.exports
+    }
+
+    pub(crate) fn invoke(
+        &self,
+        plugin: &str,
+        symbol: &str,
+        meta: &Meta,
+        args: &[Value],
+        token: &CancellationToken,
+    ) -> Result<Value, PluginError> {
+        let state = HostState {
+            plugin: plugin.into(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.limits.memory)
+                .build(),
+            heap: 0,
+        };
+        let engine = self.instance.module().engine();
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+        if let Some(fuel) = self.limits.fuel {
+            store.set_fuel(fuel).map_err(PluginError::Wasm)?;
+        }
+
+        // Cancelling bumps the epoch, which makes every running invocation of the module check
+        // whether its own token was cancelled.
+        store.set_epoch_deadline(1);
+        let cancelled = token.clone();
+        store.epoch_deadline_callback(move |_| {
+            if cancelled.is_cancelled() {
+                Err(Cancelled.into())
+            } else {
+                Ok(UpdateDeadline::Continue(1))
+            }
+        });
+        let engine = engine.clone();
+        let _on_cancel = token.on_cancel(move || engine.increment_epoch());
+
+        let instance = self
+            .instance
+            .instantiate(&mut store)
+            .map_err(PluginError::Wasm)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| invalid_module("the module doesn't export its memory"))?;
+        store.data_mut().heap = memory.data_size(&store) as u32;
+        let func =
+            instance
+                .get_func(&mut store, symbol)
+                .ok_or_else(|| PluginError::SymbolNotFound {
+                    name: symbol.into(),
+                    found: Vec::new(),
+                })?;
+
+        // Like natively, values that don't fit into a register are returned through an out
+        // pointer passed as the first argument.
+        let ty = func.ty(&store);
+        let mut params = Vec::new();
+        let out = if ty.params().len() == args.len() + 1 {
+            let (size, align) = layout(&meta.return_type);
+            let ptr = alloc(&mut store, memory, size, align).map_err(PluginError::Wasm)?;
+            params.push(Val::I32(ptr as i32));
+            Some(ptr)
+        } else {
+            None
+        };
+        for (arg, dtype) in args.iter().zip(&meta.arg_types) {
+            params.push(to_val(&mut store, memory, dtype, arg)?);
+        }
+
+        let mut results = vec![Val::I32(0); ty.results().len()];
+        func.call(&mut store, &params, &mut results)
+            .map_err(|error| match error.downcast::<RocPanic>() {
+                Ok(RocPanic { message, kind }) => PluginError::Panic {
+                    plugin: plugin.into(),
+                    entrypoint: symbol.into(),
+                    message,
+                    kind,
+                },
+                Err(error) if error.downcast_ref::<Cancelled>().is_some() => PluginError::Cancelled,
+                Err(error) => PluginError::Wasm(error),
+            })?;
+
+        let data = memory.data(&store);
+        let result = match (out, results.first()) {
+            (Some(ptr), _) => read_return(data, &meta.return_type, ptr),
+            (None, Some(val)) => from_val(&meta.return_type, val).map(Ok),
+            (None, None) => Some(Ok(Value::Unit)),
+        };
+        result.unwrap_or_else(|| Err(invalid_module("the plugin returned an invalid value")))
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Module")
+            .field("limits", &self.limits)
+            .field("exports", &self.exports)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Checks that the wasm backend can pass all argument and return types of `meta`.
+pub(crate) fn check(meta: &Meta) -> Result<(), PluginError> {
+    fn supported(dtype: &DType) -> bool {
+        match dtype {
+            DType::Dec
+            | DType::Record(_)
+            | DType::Tuple(_)
+            | DType::Dict(..)
+            | DType::Option(_)
+            | DType::Task(..) => false,
+            DType::List(elem) => supported(elem),
+            DType::Result(ok, err) => supported(ok) && supported(err),
+            _ => true,
+        }
+    }
+
+    let types = meta.arg_types.iter().chain([&meta.return_type]);
+    match types.into_iter().find(|t| !supported(t)) {
+        Some(dtype) => Err(PluginError::HeaderParse(format!(
+            "`{dtype}` is not supported by the wasm backend"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Defines the functions Roc expects the host platform to provide.
+fn define_host_functions(linker: &mut Linker<HostState>) -> wasmtime::Result<()> {
+    linker.func_wrap(
+        "env",
+        "roc_alloc",
+        |mut caller: Caller<'_, HostState>, size: u32, align: u32| {
+            let memory = caller_memory(&mut caller)?;
+            alloc(&mut caller, memory, size, align)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "roc_realloc",
+        |mut caller: Caller<'_, HostState>, ptr: u32, new_size: u32, old_size: u32, align: u32| {
+            let memory = caller_memory(&mut caller)?;
+            let new_ptr = alloc(&mut caller, memory, new_size, align)?;
+            let (start, len) = (ptr as usize, old_size.min(new_size) as usize);
+            let data = memory.data_mut(&mut caller);
+            if start + len > data.len() {
+                return Err(wasmtime::Error::msg("invalid pointer"));
+            }
+            data.copy_within(start..start + len, new_ptr as usize);
+            Ok(new_ptr)
+        },
+    )?;
+    // Memory is only released when the instance is dropped.
+    linker.func_wrap(
+        "env",
+        "roc_dealloc",
+        |_: Caller<'_, HostState>, _ptr: u32, _align: u32| {},
+    )?;
+    linker.func_wrap(
+        "env",
+        "roc_dbg",
+        |mut caller: Caller<'_, HostState>, loc: u32, msg: u32, src: u32| {
+            let memory = caller_memory(&mut caller)?;
+            let data = memory.data(&caller);
+            let read = |ptr| read_str(data, ptr).unwrap_or_default();
+            let (location, value, source) = (read(loc), read(msg), read(src));
+            roc_host::emit_dbg(&Dbg {
+                plugin: &caller.data().plugin,
+                location: &location,
+                source: &source,
+                value: &value,
+            });
+            Ok::<_, wasmtime::Error>(())
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "roc_panic",
+        |mut caller: Caller<'_, HostState>, msg: u32, tag_id: u32| {
+            let memory = caller_memory(&mut caller)?;
+            let message = read_str(memory.data(&caller), msg).unwrap_or_default();
+            let kind = PanicKind::from_tag(tag_id);
+            Err::<(), _>(wasmtime::Error::new(RocPanic { message, kind }))
+        },
+    )?;
+    Ok(())
+}
+
+fn invalid_module(msg: &'static str) -> PluginError {
+    PluginError::Wasm(wasmtime::Error::msg(msg))
+}
+
+fn caller_memory(caller: &mut Caller<'_, HostState>) -> wasmtime::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| wasmtime::Error::msg("the module doesn't export its memory"))
+}
+
+/// Allocates `size` bytes at the end of the instance's memory, growing it as necessary.
+fn alloc<C>(ctx: &mut C, memory: Memory, size: u32, align: u32) -> wasmtime::Result<u32>
+where
+    C: AsContextMut<Data = HostState>,
+{
+    let out_of_memory = || wasmtime::Error::msg("plugin exceeded its memory limit");
+
+    let ptr = ctx.as_context().data().heap.next_multiple_of(align.max(1));
+    let end = ptr.checked_add(size).ok_or_else(out_of_memory)?;
+    let available = memory.data_size(&*ctx) as u64;
+    if u64::from(end) > available {
+        let pages = (u64::from(end) - available).div_ceil(PAGE_SIZE);
+        memory.grow(&mut *ctx, pages).map_err(|_| out_of_memory())?;
+    }
+    ctx.as_context_mut().data_mut().heap = end;
+    Ok(ptr)
+}
+
+/// Returns the size and alignment of values of type `dtype` on wasm32.
+fn layout(dtype: &DType) -> (u32, u32) {
+    match dtype {
+        DType::Unit => (0, 1),
+        DType::Bool | DType::U8 | DType::I8 => (1, 1),
+        DType::I16 => (2, 2),
+        DType::I32 | DType::F32 => (4, 4),
+        DType::U64 | DType::I64 | DType::F64 => (8, 8),
+        DType::Str | DType::Bytes | DType::List(_) => (12, 4),
+        DType::Result(ok, err) => {
+            let align = layout(ok).1.max(layout(err).1);
+            (
+                (result_tag_offset(ok, err) + 1).next_multiple_of(align),
+                align,
+            )
+        }
+        _ => unreachable!("unsupported types are rejected when loading the plugin"),
+    }
+}
+
+fn result_tag_offset(ok: &DType, err: &DType) -> u32 {
+    let (ok_size, ok_align) = layout(ok);
+    let (err_size, err_align) = layout(err);
+    ok_size
+        .max(err_size)
+        .next_multiple_of(ok_align.max(err_align))
+}
+
+fn to_val(
+    store: &mut Store<HostState>,
+    memory: Memory,
+    dtype: &DType,
+    value: &Value,
+) -> Result<Val, PluginError> {
+    let val = match (dtype, value) {
+        (DType::Bool, Value::Bool(b)) => Val::I32(i32::from(*b)),
+        (DType::U8, Value::U8(n)) => Val::I32(i32::from(*n)),
+        (DType::I8, Value::I8(n)) => Val::I32(i32::from(*n)),
+        (DType::I16, Value::I16(n)) => Val::I32(i32::from(*n)),
+        (DType::I32, Value::I32(n)) => Val::I32(*n),
+        (DType::U64, Value::U64(n)) => Val::I64(*n as i64),
+        (DType::I64, Value::I64(n)) => Val::I64(*n),
+        (DType::F32, Value::F32(n)) => Val::F32(n.to_bits()),
+        (DType::F64, Value::F64(n)) => Val::F64(n.to_bits()),
+        (DType::Str | DType::Bytes | DType::List(_), _) => {
+            let (size, align) = layout(dtype);
+            let ptr = alloc(store, memory, size, align).map_err(PluginError::Wasm)?;
+            write(store, memory, dtype, value, ptr)?;
+            Val::I32(ptr as i32)
+        }
+        _ => return Err(mismatch(dtype, value)),
+    };
+    Ok(val)
+}
+
+fn mismatch(dtype: &DType, value: &Value) -> PluginError {
+    PluginError::TypeMismatch {
+        expected: dtype.to_string(),
+        found: value.type_name(),
+    }
+}
+
+/// Writes `value` to the instance's memory at `ptr`.
+fn write(
+    store: &mut Store<HostState>,
+    memory: Memory,
+    dtype: &DType,
+    value: &Value,
+    ptr: u32,
+) -> Result<(), PluginError> {
+    let bytes = match (dtype, value) {
+        (DType::Bool, Value::Bool(b)) => vec![u8::from(*b)],
+        (DType::U8, Value::U8(n)) => vec![*n],
+        (DType::I8, Value::I8(n)) => n.to_le_bytes().to_vec(),
+        (DType::I16, Value::I16(n)) => n.to_le_bytes().to_vec(),
+        (DType::I32, Value::I32(n)) => n.to_le_bytes().to_vec(),
+        (DType::U64, Value::U64(n)) => n.to_le_bytes().to_vec(),
+        (DType::I64, Value::I64(n)) => n.to_le_bytes().to_vec(),
+        (DType::F32, Value::F32(n)) => n.to_le_bytes().to_vec(),
+        (DType::F64, Value::F64(n)) => n.to_le_bytes().to_vec(),
+        (DType::Str, Value::Str(s)) if s.len() < 12 => {
+            // Small strings are stored inline, with their length in the last byte.
+            let mut bytes = [0; 12];
+            bytes[..s.len()].copy_from_slice(s.as_bytes());
+            bytes[11] = 0x80 | s.len() as u8;
+            bytes.to_vec()
+        }
+        (DType::Str, Value::Str(s)) => write_list(store, memory, &DType::U8, s.len(), |i| {
+            Value::U8(s.as_bytes()[i])
+        })?,
+        (DType::Bytes, Value::Bytes(bytes)) => {
+            write_list(store, memory, &DType::U8, bytes.len(), |i| {
+                Value::U8(bytes[i])
+            })?
+        }
+        (DType::List(elem), Value::List(items)) => {
+            write_list(store, memory, elem, items.len(), |i| items[i].clone())?
+        }
+        _ => return Err(mismatch(dtype, value)),
+    };
+    memory
+        .write(store, ptr as usize, &bytes)
+        .map_err(|error| PluginError::Wasm(error.into()))
+}
+
+/// Writes the elements of a list to a new allocation and returns the list's header.
+///
+/// The allocation is marked as read-only, so that the plugin never frees or mutates it.
+fn write_list(
+    store: &mut Store<HostState>,
+    memory: Memory,
+    elem: &DType,
+    len: usize,
+    item: impl Fn(usize) -> Value,
+) -> Result<Vec<u8>, PluginError> {
+    let (size, align) = layout(elem);
+    // The reference count lives right in front of the elements.
+    let prefix = align.max(4);
+    let data_size = u32::try_from(len)
+        .ok()
+        .and_then(|len| len.checked_mul(size))
+        .ok_or_else(|| invalid_module("list too large"))?;
+    let alloc_size = data_size
+        .checked_add(prefix)
+        .ok_or_else(|| invalid_module("list too large"))?;
+    let base = alloc(store, memory, alloc_size, prefix).map_err(PluginError::Wasm)?;
+    let data = base + prefix;
+    memory
+        .write(&mut *store, (data - 4) as usize, &0_i32.to_le_bytes())
+        .map_err(|error| PluginError::Wasm(error.into()))?;
+    for i in 0..len {
+        write(store, memory, elem, &item(i), data + i as u32 * size)?;
+    }
+
+    let len = len as u32;
+    Ok([data, len, len]
+        .into_iter()
+        .flat_map(u32::to_le_bytes)
+        .collect())
+}
+
+fn from_val(dtype: &DType, val: &Val) -> Option<Value> {
+    let value = match (dtype, val) {
+        (DType::Bool, Val::I32(n)) => Value::Bool(*n & 0xff != 0),
+        (DType::U8, Val::I32(n)) => Value::U8(*n as u8),
+        (DType::I8, Val::I32(n)) => Value::I8(*n as i8),
+        (DType::I16, Val::I32(n)) => Value::I16(*n as i16),
+        (DType::I32, Val::I32(n)) => Value::I32(*n),
+        (DType::U64, Val::I64(n)) => Value::U64(*n as u64),
+        (DType::I64, Val::I64(n)) => Value::I64(*n),
+        (DType::F32, Val::F32(bits)) => Value::F32(f32::from_bits(*bits)),
+        (DType::F64, Val::F64(bits)) => Value::F64(f64::from_bits(*bits)),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Reads the return value of a plugin from `ptr`, turning `Err` results into errors.
+fn read_return(data: &[u8], dtype: &DType, ptr: u32) -> Option<Result<Value, PluginError>> {
+    match dtype {
+        DType::Result(ok, err) => {
+            let [tag] = read_bytes(data, ptr.checked_add(result_tag_offset(ok, err))?)?;
+            if tag == 1 {
+                Some(Ok(read(data, ok, ptr)?))
+            } else {
+                let payload = read(data, err, ptr)?;
+                Some(Err(PluginError::PluginFailed(payload.to_string())))
+            }
+        }
+        dtype => Some(Ok(read(data, dtype, ptr)?)),
+    }
+}
+
+fn read(data: &[u8], dtype: &DType, ptr: u32) -> Option<Value> {
+    let value = match dtype {
+        DType::Unit => Value::Unit,
+        DType::Bool => Value::Bool(read_bytes::<1>(data, ptr)?[0] != 0),
+        DType::U8 => Value::U8(u8::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::I8 => Value::I8(i8::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::I16 => Value::I16(i16::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::I32 => Value::I32(i32::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::U64 => Value::U64(u64::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::I64 => Value::I64(i64::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::F32 => Value::F32(f32::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::F64 => Value::F64(f64::from_le_bytes(read_bytes(data, ptr)?)),
+        DType::Str => Value::Str(read_str(data, ptr)?),
+        DType::Bytes => {
+            let (elems, len) = read_list_header(data, ptr)?;
+            Value::Bytes(Bytes::from(read_slice(data, elems, len)?))
+        }
+        DType::List(elem) => {
+            let (elems, len) = read_list_header(data, ptr)?;
+            let size = layout(elem).0;
+            // The elements must fit into the memory, and lists of zero-sized elements, like
+            // `List {}`, can't be longer than it either, so that their length is bounded too.
+            read_slice(data, elems, len.checked_mul(size)?)?;
+            if len as usize > data.len() {
+                return None;
+            }
+            let items = (0..len)
+                .map(|i| read(data, elem, elems.checked_add(i * size)?))
+                .collect::<Option<_>>()?;
+            Value::List(items)
+        }
+        _ => unreachable!("unsupported types are rejected when loading the plugin"),
+    };
+    Some(value)
+}
+
+fn read_str(data: &[u8], ptr: u32) -> Option<String> {
+    let header: [u8; 12] = read_bytes(data, ptr)?;
+    let bytes = if header[11] & 0x80 != 0 {
+        // Small strings are stored inline, with their length in the last byte.
+        &header[..usize::from(header[11] & 0x7f)]
+    } else {
+        let (elems, len) = read_list_header(data, ptr)?;
+        // The high bit of the length marks seamless slices.
+        read_slice(data, elems, len & 0x7fff_ffff)?
+    };
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Reads the element pointer and length of the list at `ptr`.
+fn read_list_header(data: &[u8], ptr: u32) -> Option<(u32, u32)> {
+    let elems = u32::from_le_bytes(read_bytes(data, ptr)?);
+    let len = u32::from_le_bytes(read_bytes(data, ptr.checked_add(4)?)?);
+    Some((elems, len))
+}
+
+fn read_slice(data: &[u8], ptr: u32, len: u32) -> Option<&[u8]> {
+    data.get(ptr as usize..)?.get(..len as usize)
+}
+
+fn read_bytes<const N: usize>(data: &[u8], ptr: u32) -> Option<[u8; N]> {
+    data.get(ptr as usize..)?.first_chunk().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a memory holding the list header `[elems, len, len]` at 0, followed by `rest`.
+    fn list(elems: u32, len: u32, rest: &[u8]) -> Vec<u8> {
+        let mut data: Vec<_> = [elems, len, len]
+            .into_iter()
+            .flat_map(u32::to_le_bytes)
+            .collect();
+        data.extend_from_slice(rest);
+        data
+    }
+
+    #[test]
+    fn reads_lists() {
+        let data = list(12, 2, &[1, 0, 0, 0, 2, 0, 0, 0]);
+        let dtype = DType::List(Box::new(DType::I32));
+        let items = vec![Value::I32(1), Value::I32(2)];
+        assert_eq!(read(&data, &dtype, 0), Some(Value::List(items)));
+    }
+
+    #[test]
+    fn rejects_lists_outside_the_memory() {
+        let dtype = DType::List(Box::new(DType::I32));
+        assert_eq!(read(&list(12, 3, &[0; 8]), &dtype, 0), None);
+        assert_eq!(read(&list(u32::MAX - 2, 2, &[]), &dtype, 0), None);
+        assert_eq!(read(&list(12, u32::MAX, &[]), &dtype, 0), None);
+        assert_eq!(read(&list(0, 0, &[]), &dtype, u32::MAX - 2), None);
+    }
+
+    #[test]
+    fn bounds_lists_of_zero_sized_elements() {
+        let dtype = DType::List(Box::new(DType::Unit));
+        assert_eq!(read(&list(0, u32::MAX, &[]), &dtype, 0), None);
+        let units = vec![Value::Unit; 3];
+        assert_eq!(read(&list(0, 3, &[]), &dtype, 0), Some(Value::List(units)));
+    }
+
+    #[test]
+    fn rejects_result_tags_outside_the_memory() {
+        let dtype = DType::Result(Box::new(DType::I64), Box::new(DType::I64));
+        assert!(read_return(&[0; 16], &dtype, u32::MAX - 4).is_none());
+    }
+}
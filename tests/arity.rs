@@ -0,0 +1,79 @@
+//! Invokes plugins taking from zero to eight arguments.
+
+mod common;
+
+use roc_plugin::{FromRocReturn, IntoRocArgs, Plugin};
+
+/// Loads the fixture `name` and calls its function with `args`, or returns `None` if the test
+/// is skipped, see [`common::options`].
+fn call<A: IntoRocArgs, R: FromRocReturn>(name: &str, args: A) -> Option<R> {
+    let options = common::options()?;
+    let plugin = Plugin::load_with(common::fixture(name), &options).unwrap();
+    Some(plugin.call(args).unwrap())
+}
+
+#[test]
+fn zero_arguments() {
+    if let Some(result) = call::<_, String>("hello_world", ()) {
+        assert_eq!(result, "Hello world!");
+    }
+}
+
+#[test]
+fn one_argument() {
+    if let Some(result) = call::<_, i64>("double", (-21i64,)) {
+        assert_eq!(result, -42);
+    }
+}
+
+#[test]
+fn two_arguments() {
+    if let Some(result) = call::<_, u64>("add", (2u64, 3u64)) {
+        assert_eq!(result, 5);
+    }
+}
+
+#[test]
+fn three_arguments() {
+    if let Some(result) = call::<_, String>("concat3", ("a", "b", "c")) {
+        assert_eq!(result, "a-b-c");
+    }
+}
+
+#[test]
+fn four_arguments() {
+    if let Some(result) = call::<_, u64>("sum4", (1u64, 2u64, 3u64, 4u64)) {
+        assert_eq!(result, 10);
+    }
+}
+
+#[test]
+fn five_arguments() {
+    if let Some(result) = call::<_, String>("describe5", ("a", 1u64, "b", 2u64, "c")) {
+        assert_eq!(result, "a 1 b 2 c");
+    }
+}
+
+#[test]
+fn six_arguments() {
+    let args = (1u64, 2u64, 3u64, 4u64, 5u64, 6u64);
+    if let Some(result) = call::<_, u64>("sum6", args) {
+        assert_eq!(result, 21);
+    }
+}
+
+#[test]
+fn seven_arguments() {
+    let args = ("a", "b", "c", 4u64, "e", "f", "g");
+    if let Some(result) = call::<_, String>("describe7", args) {
+        assert_eq!(result, "a b c 4 e f g");
+    }
+}
+
+#[test]
+fn eight_arguments() {
+    let args = (1u64, 2u64, 3u64, 4u64, 5u64, 6u64, 7u64, 8u64);
+    if let Some(result) = call::<_, u64>("sum8", args) {
+        assert_eq!(result, 36);
+    }
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by the integration tests, which compile the plugins in `plugins/`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use roc_plugin::LoadOptions;
+
+/// Prepares the host and returns the options tests load plugins with, or `None` if no Roc
+/// compiler is installed, in which case the calling test is skipped.
+pub fn options() -> Option<LoadOptions> {
+    if Command::new("roc").arg("version").output().is_err() {
+        eprintln!("skipping: compiling plugins requires `roc` in PATH");
+        return None;
+    }
+    roc_plugin::init();
+    Some(LoadOptions {
+        cache_dir: Path::new(env!("CARGO_TARGET_TMPDIR")).join("roc-plugins"),
+        ..LoadOptions::default()
+    })
+}
+
+/// Returns the path of the fixture plugin `plugins/<name>.roc`.
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("plugins")
+        .join(name)
+        .with_extension("roc")
+}
@@ -0,0 +1,61 @@
+//! Invokes plugins shared by many threads at once.
+
+mod common;
+
+use std::thread;
+
+use roc_plugin::{Plugin, PluginManager, Value};
+
+const THREADS: u64 = 16;
+const INVOCATIONS: u64 = 100;
+
+#[test]
+fn shared_plugin() {
+    let Some(options) = common::options() else {
+        return;
+    };
+    let plugin = Plugin::load_with(common::fixture("add"), &options).unwrap();
+
+    thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let plugin = &plugin;
+            scope.spawn(move || {
+                for i in 0..INVOCATIONS {
+                    let sum: u64 = plugin.call((thread, i)).unwrap();
+                    assert_eq!(sum, thread + i);
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn shared_manager() {
+    let Some(options) = common::options() else {
+        return;
+    };
+    let mut manager = PluginManager::new();
+    for name in ["add", "concat3"] {
+        let plugin = Plugin::load_with(common::fixture(name), &options).unwrap();
+        manager.add(plugin).unwrap();
+    }
+
+    thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let manager = &manager;
+            scope.spawn(move || {
+                for i in 0..INVOCATIONS {
+                    let sum = manager
+                        .invoke("add", &[Value::U64(thread), Value::U64(i)])
+                        .unwrap();
+                    assert_eq!(sum, Value::U64(thread + i));
+
+                    let args = [thread, i, thread * i].map(|n| Value::Str(n.to_string()));
+                    let joined = manager.invoke("concat3", &args).unwrap();
+                    let expected = format!("{thread}-{i}-{}", thread * i);
+                    assert_eq!(joined, Value::Str(expected));
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,28 @@
+//! Passes `Dec`s to and from plugins.
+
+mod common;
+
+use roc_plugin::{Dec, Plugin};
+
+#[test]
+fn dec_arguments_and_result() {
+    let Some(options) = common::options() else {
+        return;
+    };
+    let plugin = Plugin::load_with(common::fixture("add_tax"), &options).unwrap();
+
+    let cases = [
+        ("10", "0.25", "12.5"),
+        ("-2.5", "0.1", "-2.75"),
+        ("0", "1", "0"),
+    ];
+    for (price, rate, total) in cases {
+        let args = (price.parse::<Dec>().unwrap(), rate.parse::<Dec>().unwrap());
+        let result: Dec = plugin.call(args).unwrap();
+        assert_eq!(result.to_string(), total);
+    }
+
+    let small = Dec::from_raw(1);
+    let result: Dec = plugin.call((small, Dec::from(1_u64))).unwrap();
+    assert_eq!(result, Dec::from_raw(2));
+}
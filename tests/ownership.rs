@@ -0,0 +1,97 @@
+//! Checks that the memory plugins allocate for owned arguments and results is freed.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use roc_plugin::{Allocator, Bytes, LoadOptions, Plugin};
+
+/// An allocator counting the allocations made and freed through `roc_alloc` and `roc_dealloc`.
+struct Counting {
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.deallocations.fetch_add(1, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+impl Counting {
+    fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of allocations that weren't freed yet.
+    fn outstanding(&self) -> usize {
+        self.allocations() - self.deallocations.load(Ordering::SeqCst)
+    }
+}
+
+static COUNTING: Counting = Counting {
+    allocations: AtomicUsize::new(0),
+    deallocations: AtomicUsize::new(0),
+};
+
+/// Strings this long don't fit into a `RocStr` inline, so they are allocated.
+fn long(s: &str) -> String {
+    s.repeat(32)
+}
+
+fn load(name: &str, options: &LoadOptions) -> Plugin {
+    Plugin::load_with(common::fixture(name), options).unwrap()
+}
+
+// The counts are shared, so the plugins are invoked one after another by a single test.
+#[test]
+fn allocations_balance() {
+    let Some(options) = common::options() else {
+        return;
+    };
+    let options = LoadOptions {
+        allocator: Allocator::Custom(&COUNTING),
+        ..options
+    };
+    let concat3 = load("concat3", &options);
+    let exclaim_all = load("exclaim_all", &options);
+    let to_utf8 = load("to_utf8", &options);
+    let allocations = COUNTING.allocations();
+    let outstanding = COUNTING.outstanding();
+
+    // Strings are copied out of the results, so nothing is outstanding once the calls return.
+    for _ in 0..10 {
+        let joined: String = concat3
+            .call((long("a"), long("b").as_str(), long("c")))
+            .unwrap();
+        assert_eq!(joined, [long("a"), long("b"), long("c")].join("-"));
+        assert_eq!(COUNTING.outstanding(), outstanding);
+
+        let strs = vec![long("x"), long("y")];
+        let exclaimed: Vec<String> = exclaim_all.call((strs,)).unwrap();
+        assert_eq!(exclaimed, [long("x") + "!", long("y") + "!"]);
+        assert_eq!(COUNTING.outstanding(), outstanding);
+    }
+
+    // Byte buffers are taken over, so their allocation is outstanding until they are dropped.
+    for _ in 0..10 {
+        let bytes: Bytes = to_utf8.call((long("z"),)).unwrap();
+        assert_eq!(&*bytes, long("z").as_bytes());
+        assert_eq!(COUNTING.outstanding(), outstanding + 1);
+        drop(bytes);
+        assert_eq!(COUNTING.outstanding(), outstanding);
+    }
+
+    assert!(COUNTING.allocations() > allocations);
+}